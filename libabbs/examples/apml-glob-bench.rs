@@ -0,0 +1,177 @@
+use std::{
+	env::{self},
+	fs,
+	path::Path,
+	sync::Arc,
+	time::Instant,
+};
+
+use libabbs::apml::{
+	ApmlContext,
+	ast::{
+		ApmlAst, ArrayElement, AstNode, ExpansionModifier, Text, VariableValue,
+		Word,
+	},
+	lst::ApmlLst,
+	pattern::{BashPattern, GlobMatcher, PatternCache},
+};
+
+fn collect_apml(path: &Path, result: &mut Vec<String>) {
+	for entry in path.read_dir().unwrap() {
+		let entry = entry.unwrap();
+		if entry.file_name() == "spec"
+			|| entry
+				.file_name()
+				.to_str()
+				.unwrap_or_default()
+				.starts_with("defines")
+		{
+			result.push(fs::read_to_string(entry.path()).unwrap());
+		} else if entry.file_type().unwrap().is_dir() {
+			collect_apml(&entry.path(), result);
+		}
+	}
+}
+
+fn collect_patterns<'a>(
+	text: &Text<'a>,
+	result: &mut Vec<Arc<BashPattern<'a>>>,
+) {
+	for word in &text.0 {
+		let Word::Variable(expansion) = word else {
+			continue;
+		};
+		match &expansion.modifier {
+			Some(
+				ExpansionModifier::StripShortestPrefix(pattern)
+				| ExpansionModifier::StripLongestPrefix(pattern)
+				| ExpansionModifier::StripShortestSuffix(pattern)
+				| ExpansionModifier::StripLongestSuffix(pattern)
+				| ExpansionModifier::UpperOnce(pattern)
+				| ExpansionModifier::UpperAll(pattern)
+				| ExpansionModifier::LowerOnce(pattern)
+				| ExpansionModifier::LowerAll(pattern),
+			) => result.push(pattern.clone()),
+			Some(
+				ExpansionModifier::ReplaceOnce { pattern, string }
+				| ExpansionModifier::ReplaceAll { pattern, string }
+				| ExpansionModifier::ReplacePrefix { pattern, string }
+				| ExpansionModifier::ReplaceSuffix { pattern, string },
+			) => {
+				result.push(pattern.clone());
+				collect_patterns(string, result);
+			}
+			Some(
+				ExpansionModifier::ErrorOnUnset(text)
+				| ExpansionModifier::WhenUnset(text)
+				| ExpansionModifier::WhenSet(text),
+			) => collect_patterns(text, result),
+			_ => {}
+		}
+	}
+}
+
+fn main() {
+	let tree = env::var("TREE").expect("TREE env var must be set");
+	let mut srcs = Vec::new();
+	collect_apml(Path::new(&tree), &mut srcs);
+
+	// pairs of patterns and values of the file they appear in
+	let mut cases = Vec::new();
+	let lsts = srcs
+		.iter()
+		.map(|src| ApmlLst::parse(src).expect(src))
+		.collect::<Vec<_>>();
+	for (src, lst) in srcs.iter().zip(&lsts) {
+		let ast = ApmlAst::emit_from(lst).expect(src);
+		let mut patterns = Vec::new();
+		for def in &ast.0 {
+			match &def.value {
+				VariableValue::String(text) => {
+					collect_patterns(text, &mut patterns)
+				}
+				VariableValue::Array(elements) => {
+					for element in elements {
+						if let ArrayElement::Text(text, _) = element {
+							collect_patterns(text, &mut patterns);
+						}
+					}
+				}
+			}
+		}
+		if patterns.is_empty() {
+			continue;
+		}
+		let values = ApmlContext::eval_ast(&ast)
+			.expect(src)
+			.iter()
+			.map(|(_, value)| value.as_string())
+			.collect::<Vec<_>>();
+		cases.push((patterns, values));
+	}
+	let count = cases
+		.iter()
+		.map(|(patterns, _)| patterns.len())
+		.sum::<usize>();
+
+	let start = Instant::now();
+	let mut matched = 0;
+	for _ in 0..10 {
+		for (patterns, values) in &cases {
+			for pattern in patterns {
+				// extglob negations are not representable as regex
+				let Ok(regex) = pattern.to_regex("", "", true) else {
+					continue;
+				};
+				matched += values.iter().filter(|v| regex.is_match(v)).count();
+			}
+		}
+	}
+	println!(
+		"regex: matched {} patterns ({} hits) in {:?}",
+		count,
+		matched,
+		start.elapsed()
+	);
+
+	let start = Instant::now();
+	let mut matched = 0;
+	for _ in 0..10 {
+		for (patterns, values) in &cases {
+			for pattern in patterns {
+				let matcher = GlobMatcher::new(pattern);
+				matched += values
+					.iter()
+					.filter(|v| matcher.find(v, 0).is_some())
+					.count();
+			}
+		}
+	}
+	println!(
+		"native: matched {} patterns ({} hits) in {:?}",
+		count,
+		matched,
+		start.elapsed()
+	);
+
+	let cache = PatternCache::new();
+	let start = Instant::now();
+	let mut matched = 0;
+	for _ in 0..10 {
+		for (patterns, values) in &cases {
+			for pattern in patterns {
+				let matcher = cache.get(pattern);
+				matched += values
+					.iter()
+					.filter(|v| matcher.find(v, 0).is_some())
+					.count();
+			}
+		}
+	}
+	println!(
+		"cached: matched {} patterns ({} hits) in {:?}",
+		count,
+		matched,
+		start.elapsed()
+	);
+}
@@ -0,0 +1,9 @@
+use std::{env::args, fs};
+
+fn main() {
+	let file = args().nth(1).expect("Usage: apml-json <PATH>");
+	let src = fs::read_to_string(&file).unwrap();
+	let ctx = libabbs::apml::ApmlContext::eval_source(&src).unwrap();
+
+	println!("{}", serde_json::to_string_pretty(&ctx).unwrap());
+}
@@ -0,0 +1,405 @@
+//! Static analysis of variable references.
+//!
+//! [`ReferenceGraph`] records which variables each definition of a
+//! [`ApmlAst`] reads and which definition every read resolves to,
+//! without evaluating the file. It is used to find suspicious
+//! references, see [`ReferenceGraph::issues`].
+
+use std::collections::HashSet;
+
+use super::{
+	ApmlContext,
+	ast::{ApmlAst, ArrayElement, ExpansionModifier, VariableExpansion, Word},
+	span::Span,
+	visit::{
+		AstVisitor,
+		ast::{walk_array_element, walk_variable_expansion, walk_word},
+	},
+};
+
+/// A graph of variable references in a APML file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReferenceGraph {
+	definitions: Vec<DefinitionNode>,
+}
+
+/// A variable definition in a [`ReferenceGraph`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DefinitionNode {
+	/// Name of the defined variable.
+	pub name: String,
+	/// Span of the definition.
+	pub span: Span,
+	/// Variables read by the definition, in source order.
+	pub reads: Vec<Reference>,
+}
+
+/// A read of a variable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+	/// Name of the read variable.
+	pub name: String,
+	/// Span of the expansion or the array inclusion.
+	///
+	/// Variables in arithmetic expansions have no span of their own, so
+	/// the span of the whole arithmetic expansion is used.
+	pub span: Span,
+	/// Where the read resolves to.
+	pub target: Target,
+	/// Whether the expansion handles unset variables, such as
+	/// `${NAME:-default}` and `${NAME:?message}`.
+	pub guarded: bool,
+}
+
+/// Resolution of a [`Reference`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+	/// An earlier definition in the file, by index.
+	Definition(usize),
+	/// A variable of the parent context.
+	Parent,
+	/// A variable only defined later in the file, by index of the
+	/// first definition. The read expands to the parent value or to an
+	/// empty string.
+	Later(usize),
+	/// A variable never defined in the file or the parent context.
+	Undefined,
+}
+
+/// A suspicious reference found by [`ReferenceGraph::issues`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReferenceIssue {
+	/// A definition reads a variable which is never defined.
+	///
+	/// [Guarded][Reference::guarded] reads are not reported.
+	Undefined {
+		/// Index of the reading definition.
+		def: usize,
+		/// The reference.
+		reference: Reference,
+	},
+	/// A definition is overwritten before being read.
+	Overwritten {
+		/// Index of the overwritten definition.
+		def: usize,
+		/// Index of the overwriting definition.
+		by: usize,
+	},
+	/// A definition reads itself, such as `NAME+=VALUE`, but the
+	/// variable has no previous value.
+	///
+	/// Following definitions appending to the same variable form a
+	/// chain, which is reported as a whole.
+	SelfReference {
+		/// Indexes of definitions in the chain.
+		chain: Vec<usize>,
+	},
+}
+
+impl ReferenceGraph {
+	/// Builds the reference graph of a AST.
+	///
+	/// If a parent context is given, such as the context of `spec` for a
+	/// `defines` file, reads of variables defined there are resolved to
+	/// [`Target::Parent`].
+	pub fn build(ast: &ApmlAst, parent: Option<&ApmlContext>) -> Self {
+		let mut definitions = Vec::with_capacity(ast.0.len());
+		for (idx, def) in ast.0.iter().enumerate() {
+			let mut collector = ReadCollector { reads: Vec::new() };
+			collector.visit_variable_value(&def.value);
+			let reads = collector
+				.reads
+				.into_iter()
+				.map(|(name, span, guarded)| {
+					let target = ast.0[..idx]
+						.iter()
+						.rposition(|prev| prev.name == name)
+						.map(Target::Definition)
+						.or_else(|| {
+							parent
+								.is_some_and(|parent| {
+									parent.contains_var(&name)
+								})
+								.then_some(Target::Parent)
+						})
+						.or_else(|| {
+							ast.0[idx..]
+								.iter()
+								.position(|later| later.name == name)
+								.map(|later| Target::Later(idx + later))
+						})
+						.unwrap_or(Target::Undefined);
+					Reference {
+						name,
+						span,
+						target,
+						guarded,
+					}
+				})
+				.collect();
+			definitions.push(DefinitionNode {
+				name: def.name.to_string(),
+				span: def.span,
+				reads,
+			});
+		}
+		Self { definitions }
+	}
+
+	/// Returns all definitions.
+	pub fn definitions(&self) -> &[DefinitionNode] {
+		&self.definitions
+	}
+
+	/// Returns the definition at an index.
+	pub fn definition(&self, def: usize) -> Option<&DefinitionNode> {
+		self.definitions.get(def)
+	}
+
+	/// Returns indexes of definitions of a variable.
+	pub fn definitions_of<'a>(
+		&'a self,
+		name: &'a str,
+	) -> impl Iterator<Item = usize> + 'a {
+		(0..self.definitions.len())
+			.filter(move |&idx| self.definitions[idx].name == name)
+	}
+
+	/// Returns indexes of definitions reading a variable.
+	pub fn readers_of<'a>(
+		&'a self,
+		name: &'a str,
+	) -> impl Iterator<Item = usize> + 'a {
+		(0..self.definitions.len()).filter(move |&idx| {
+			self.definitions[idx]
+				.reads
+				.iter()
+				.any(|reference| reference.name == name)
+		})
+	}
+
+	/// Returns indexes of definitions reading a definition.
+	pub fn dependents_of(&self, def: usize) -> impl Iterator<Item = usize> {
+		(0..self.definitions.len()).filter(move |&idx| {
+			self.definitions[idx]
+				.reads
+				.iter()
+				.any(|reference| reference.target == Target::Definition(def))
+		})
+	}
+
+	/// Returns indexes of definitions a definition depends on, directly
+	/// or transitively.
+	pub fn dependencies_of(&self, def: usize) -> Vec<usize> {
+		let mut result = HashSet::new();
+		let mut queue = vec![def];
+		while let Some(def) = queue.pop() {
+			for reference in &self.definitions[def].reads {
+				if let Target::Definition(target) = reference.target
+					&& result.insert(target)
+				{
+					queue.push(target);
+				}
+			}
+		}
+		let mut result = result.into_iter().collect::<Vec<_>>();
+		result.sort_unstable();
+		result
+	}
+
+	/// Returns all suspicious references, in source order.
+	pub fn issues(&self) -> Vec<ReferenceIssue> {
+		let mut issues = Vec::new();
+		let mut chained = HashSet::new();
+		for (idx, def) in self.definitions.iter().enumerate() {
+			for reference in &def.reads {
+				if reference.name == def.name {
+					if matches!(
+						reference.target,
+						Target::Later(_) | Target::Undefined
+					) && chained.insert(idx)
+					{
+						let chain = self.self_reference_chain(idx);
+						chained.extend(chain.iter().copied());
+						issues.push(ReferenceIssue::SelfReference { chain });
+					}
+				} else if reference.target == Target::Undefined
+					&& !reference.guarded
+				{
+					issues.push(ReferenceIssue::Undefined {
+						def: idx,
+						reference: reference.clone(),
+					});
+				}
+			}
+
+			if let Some(by) = self.definitions[idx + 1..]
+				.iter()
+				.position(|later| later.name == def.name)
+				.map(|offset| idx + 1 + offset)
+				&& self.dependents_of(idx).next().is_none()
+			{
+				issues.push(ReferenceIssue::Overwritten { def: idx, by });
+			}
+		}
+		issues
+	}
+
+	/// Returns the chain of definitions appending to the variable defined
+	/// at `start`.
+	fn self_reference_chain(&self, start: usize) -> Vec<usize> {
+		let mut chain = vec![start];
+		let mut last = start;
+		while let Some(next) = self.dependents_of(last).find(|&next| {
+			next > last
+				&& self.definitions[next].name == self.definitions[start].name
+		}) {
+			chain.push(next);
+			last = next;
+		}
+		chain
+	}
+}
+
+/// Collects variables read by a definition.
+struct ReadCollector {
+	reads: Vec<(String, Span, bool)>,
+}
+
+impl<'a> AstVisitor<'a> for ReadCollector {
+	fn visit_array_element(&mut self, element: &ArrayElement<'a>) {
+		if let ArrayElement::ArrayInclusion(name, span) = element {
+			self.reads.push((name.to_string(), *span, false));
+		}
+		walk_array_element(self, element);
+	}
+
+	fn visit_variable_expansion(&mut self, exp: &VariableExpansion<'a>) {
+		let guarded = matches!(
+			exp.modifier,
+			Some(
+				ExpansionModifier::WhenUnset(_)
+					| ExpansionModifier::ErrorOnUnset(_)
+			)
+		);
+		self.reads.push((exp.name.to_string(), exp.span, guarded));
+		walk_variable_expansion(self, exp);
+	}
+
+	fn visit_word(&mut self, word: &Word<'a>) {
+		if let Word::Arithmetic(expr, span) = word {
+			self.reads.extend(
+				expr.variables()
+					.into_iter()
+					.map(|name| (name.to_string(), *span, false)),
+			);
+		} else {
+			walk_word(self, word);
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::apml::{ast::AstNode, lst::ApmlLst};
+
+	fn build_graph(src: &str, parent: Option<&ApmlContext>) -> ReferenceGraph {
+		let lst = ApmlLst::parse(src).unwrap();
+		ReferenceGraph::build(&ApmlAst::emit_from(&lst).unwrap(), parent)
+	}
+
+	#[test]
+	fn test_reference_graph() {
+		let parent = ApmlContext::eval_source("VER=1").unwrap();
+		let src =
+			"A=$VER\nB=\"${A:-$C} $((D + 1))\"\nC=(\"${B[@]}\")\nD=1\nB+=x";
+		let graph = build_graph(src, Some(&parent));
+		let reads = |def: usize| {
+			graph
+				.definition(def)
+				.unwrap()
+				.reads
+				.iter()
+				.map(|reference| (reference.name.as_str(), reference.target))
+		};
+		assert_eq!(reads(0).collect::<Vec<_>>(), vec![("VER", Target::Parent)]);
+		assert_eq!(
+			reads(1).collect::<Vec<_>>(),
+			vec![
+				("A", Target::Definition(0)),
+				("C", Target::Later(2)),
+				("D", Target::Later(3)),
+			]
+		);
+		assert_eq!(
+			reads(2).collect::<Vec<_>>(),
+			vec![("B", Target::Definition(1))]
+		);
+		assert_eq!(
+			reads(4).collect::<Vec<_>>(),
+			vec![("B", Target::Definition(1))]
+		);
+		assert_eq!(graph.definition(0).unwrap().reads[0].span, Span::new(2, 6));
+		let span = |def: usize, read: usize| {
+			graph.definition(def).unwrap().reads[read].span.slice(src)
+		};
+		assert_eq!(span(1, 2), Some("$((D + 1))"));
+		assert_eq!(span(2, 0), Some("\"${B[@]}\""));
+		assert_eq!(span(4, 0), Some("B"));
+		assert_eq!(graph.definitions_of("B").collect::<Vec<_>>(), vec![1, 4]);
+		assert_eq!(graph.readers_of("B").collect::<Vec<_>>(), vec![2, 4]);
+		assert_eq!(graph.dependents_of(1).collect::<Vec<_>>(), vec![2, 4]);
+		assert_eq!(graph.dependencies_of(2), vec![0, 1]);
+		assert!(graph.issues().is_empty());
+	}
+
+	#[test]
+	fn test_issues() {
+		let graph = build_graph(
+			"A=$PKGVER\nB=1\nB=2\nC=$B\nD+=a\nD+=b\nD=\"$D c\"\nE=(\"${E[@]}\")",
+			None,
+		);
+		assert_eq!(
+			graph.issues(),
+			vec![
+				ReferenceIssue::Undefined {
+					def: 0,
+					reference: Reference {
+						name: "PKGVER".to_string(),
+						span: Span::new(2, 9),
+						target: Target::Undefined,
+						guarded: false,
+					},
+				},
+				ReferenceIssue::Overwritten { def: 1, by: 2 },
+				ReferenceIssue::SelfReference {
+					chain: vec![4, 5, 6]
+				},
+				ReferenceIssue::SelfReference { chain: vec![7] },
+			]
+		);
+
+		let parent = ApmlContext::eval_source("PKGVER=1\nD=1").unwrap();
+		let graph = build_graph("A=$PKGVER\nD+=a", Some(&parent));
+		assert!(graph.issues().is_empty());
+
+		let graph =
+			build_graph("A=${X:-a}\nB=${Y:?unset}\nC=${Z:+c}\nD=${W}", None);
+		assert!(graph.definition(0).unwrap().reads[0].guarded);
+		assert!(graph.definition(1).unwrap().reads[0].guarded);
+		assert!(!graph.definition(2).unwrap().reads[0].guarded);
+		assert_eq!(
+			graph
+				.issues()
+				.into_iter()
+				.map(|issue| match issue {
+					ReferenceIssue::Undefined { reference, .. } =>
+						reference.name,
+					_ => unreachable!(),
+				})
+				.collect::<Vec<_>>(),
+			vec!["Z", "W"]
+		);
+	}
+}
@@ -0,0 +1,153 @@
+//! Architecture-specific overrides of variables.
+//!
+//! Autobuild allows to override a variable for a target architecture with
+//! `NAME__ARCH`, such as `PKGDEP__AMD64`, or for a group of architectures
+//! with `NAME__GROUP`, such as `PKGDEP__RETRO`. For a given architecture,
+//! the effective value of `NAME` is resolved with the following precedence:
+//!
+//! 1. The override for the architecture (`NAME__ARCH`).
+//! 2. The override for the first arch-group including the architecture
+//!    (`NAME__GROUP`), in the given order of groups.
+//! 3. The plain variable (`NAME`).
+//!
+//! Arch-groups are defined by Autobuild4, which are not known by this
+//! module. Callers must provide names of groups including the target
+//! architecture.
+
+use crate::Architecture;
+
+use super::{ApmlContext, VariableValue};
+
+/// Returns the suffix of overrides for an architecture or arch-group.
+#[must_use]
+pub fn override_suffix(target: &str) -> String {
+	target.to_ascii_uppercase()
+}
+
+/// Splits an override variable name into the base name and the suffix.
+///
+/// Returns [None] if the name is not in form of `NAME__SUFFIX`.
+#[must_use]
+pub fn split_override(name: &str) -> Option<(&str, &str)> {
+	name.rsplit_once("__")
+		.filter(|(base, suffix)| !base.is_empty() && !suffix.is_empty())
+}
+
+/// Returns the name of the variable providing the effective value of a
+/// variable for an architecture.
+///
+/// `groups` are names of arch-groups including the architecture, in
+/// order of precedence. Returns [None] if neither the variable nor any
+/// override is defined.
+#[must_use]
+pub fn effective_name(
+	ctx: &ApmlContext,
+	name: &str,
+	arch: Architecture,
+	groups: &[&str],
+) -> Option<String> {
+	std::iter::once(arch.ident())
+		.chain(groups.iter().copied())
+		.map(|target| format!("{}__{}", name, override_suffix(target)))
+		.find(|name| ctx.contains_var(name))
+		.or_else(|| ctx.contains_var(name).then(|| name.to_string()))
+}
+
+/// Returns the effective value of a variable for an architecture.
+///
+/// See [`effective_name`] for details.
+#[must_use]
+pub fn resolve_var<'a>(
+	ctx: &'a ApmlContext,
+	name: &str,
+	arch: Architecture,
+	groups: &[&str],
+) -> Option<&'a VariableValue> {
+	ctx.get(&effective_name(ctx, name, arch, groups)?)
+}
+
+/// Returns the effective context for an architecture.
+///
+/// The returned context is flattened, with every variable overridden for
+/// the architecture replaced by its effective value. Override variables
+/// themselves are kept untouched.
+#[must_use]
+pub fn resolve_context(
+	ctx: &ApmlContext,
+	arch: Architecture,
+	groups: &[&str],
+) -> ApmlContext {
+	let suffixes = std::iter::once(arch.ident())
+		.chain(groups.iter().copied())
+		.map(override_suffix)
+		.collect::<Vec<_>>();
+	let mut result = ctx.flatten();
+	for name in ctx.keys() {
+		let Some((base, suffix)) = split_override(name) else {
+			continue;
+		};
+		if !suffixes.iter().any(|target| target == suffix) {
+			continue;
+		}
+		let Some(source) = effective_name(ctx, base, arch, groups) else {
+			continue;
+		};
+		if source == base {
+			continue;
+		}
+		let unevaluable = ctx.is_unevaluable(&source);
+		result.insert(base.to_string(), ctx[&source].clone());
+		result.mark_unevaluable(base, unevaluable);
+	}
+	result
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_split_override() {
+		assert_eq!(split_override("PKGDEP__AMD64"), Some(("PKGDEP", "AMD64")));
+		assert_eq!(split_override("A__B__C"), Some(("A__B", "C")));
+		assert_eq!(split_override("PKGDEP"), None);
+		assert_eq!(split_override("__AMD64"), None);
+		assert_eq!(split_override("PKGDEP__"), None);
+	}
+
+	#[test]
+	fn test_resolve() {
+		let ctx = ApmlContext::eval_source(
+			"PKGDEP=\"a\"\nPKGDEP__AMD64=\"${PKGDEP} b\"\n\
+			PKGDEP__RETRO=\"c\"\nPKGDEP__OCS2=\"d\"\n\
+			BUILDDEP__RETRO=\"e\"\nPKGSUG__ARM64=\"f\"",
+		)
+		.unwrap();
+		let groups = ["ocs2", "retro"];
+		let resolve = |name, arch| {
+			resolve_var(&ctx, name, arch, &groups).map(VariableValue::as_string)
+		};
+		assert_eq!(resolve("PKGDEP", Architecture::Amd64).unwrap(), "a b");
+		assert_eq!(resolve("PKGDEP", Architecture::Loongson3).unwrap(), "d");
+		assert_eq!(resolve("BUILDDEP", Architecture::Riscv).unwrap(), "e");
+		assert!(resolve("PKGSUG", Architecture::Amd64).is_none());
+		assert_eq!(
+			effective_name(&ctx, "PKGDEP", Architecture::Amd64, &[]).unwrap(),
+			"PKGDEP__AMD64"
+		);
+		assert_eq!(
+			effective_name(&ctx, "PKGDEP", Architecture::Riscv, &[]).unwrap(),
+			"PKGDEP"
+		);
+
+		let resolved = resolve_context(&ctx, Architecture::Loongson3, &groups);
+		assert_eq!(resolved["PKGDEP"].as_string(), "d");
+		assert_eq!(resolved["BUILDDEP"].as_string(), "e");
+		assert_eq!(resolved["PKGDEP__AMD64"].as_string(), "a b");
+		assert!(!resolved.contains_var("PKGSUG"));
+		let resolved = resolve_context(&ctx, Architecture::Arm64, &[]);
+		assert_eq!(resolved["PKGDEP"].as_string(), "a");
+		assert_eq!(resolved["PKGSUG"].as_string(), "f");
+		assert!(!resolved.contains_var("BUILDDEP"));
+	}
+}
@@ -25,12 +25,28 @@
 //!
 //! Although not all LST nodes can be represented in AST form, all AST
 //! nodes must have a valid LST form.
+//!
+//! Variable definitions, words and array elements carry a [`Span`] of the
+//! LST token they are emitted from, so diagnostics can point back to the
+//! source. Spans are ignored when comparing or hashing AST nodes, and nodes
+//! created without a LST have empty spans.
 
-use std::{borrow::Cow, cmp::max, num::ParseIntError, sync::Arc};
+use std::{
+	borrow::Cow,
+	cmp::max,
+	fmt::Display,
+	hash::{Hash, Hasher},
+	num::ParseIntError,
+	sync::Arc,
+};
 
 use thiserror::Error;
 
-use super::{lst, pattern::BashPattern};
+use super::{
+	lst::{self, source_len},
+	pattern::BashPattern,
+	span::Span,
+};
 
 /// Trait for AST nodes.
 ///
@@ -40,7 +56,13 @@ pub trait AstNode: Sized {
 	type LST;
 
 	/// Emits a LST node into AST node.
-	fn emit_from(lst: &Self::LST) -> EmitResult<Self>;
+	///
+	/// Spans of the emitted nodes are relative to the start of the LST node.
+	fn emit_from(lst: &Self::LST) -> EmitResult<Self> {
+		Self::emit_at(lst, 0)
+	}
+	/// Emits a LST node starting at the given byte offset into AST node.
+	fn emit_at(lst: &Self::LST, offset: usize) -> EmitResult<Self>;
 	/// Lowers a AST node into LST node.
 	fn lower(&self) -> Self::LST;
 }
@@ -66,7 +88,7 @@ pub struct ApmlAst<'a>(pub Vec<VariableDefinition<'a>>);
 impl<'a> AstNode for ApmlAst<'a> {
 	type LST = lst::ApmlLst<'a>;
 
-	fn emit_from(lst: &Self::LST) -> EmitResult<Self> {
+	fn emit_at(lst: &Self::LST, mut offset: usize) -> EmitResult<Self> {
 		enum State {
 			/// Ready for elements
 			Ready,
@@ -84,13 +106,14 @@ impl<'a> AstNode for ApmlAst<'a> {
 				lst::Token::Comment(_) => state = State::NeedNewline,
 				lst::Token::Variable(def) => {
 					if matches!(state, State::Ready) {
-						result.push(VariableDefinition::emit_from(def)?);
+						result.push(VariableDefinition::emit_at(def, offset)?);
 						state = State::NeedDelimiter;
 					} else {
 						return Err(EmitError::MissingRootElementDelimiter);
 					}
 				}
 			}
+			offset += source_len(token);
 		}
 		Ok(Self(result))
 	}
@@ -111,20 +134,50 @@ impl<'a> AstNode for ApmlAst<'a> {
 /// When emitted from [`lst::VariableDefinition`], the variable operator
 /// is omitted. All appending-to operations are desugared. `NAME+="VALUE"`
 /// are desugared into `NAME="${NAME}VALUE"` and `NAME+=(VALUES)` are desugared
-/// into `NAME=("${NAME[@]}" VALUES)`.
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// into `NAME=("${NAME[@]}" VALUES)`. The desugared expansion of `NAME`
+/// spans the variable name.
+#[derive(Debug, Clone)]
 pub struct VariableDefinition<'a> {
 	/// Name of the variable.
 	pub name: Cow<'a, str>,
 	/// Value of the variable.
 	pub value: VariableValue<'a>,
+	/// Span of the whole definition.
+	pub span: Span,
+}
+
+impl PartialEq for VariableDefinition<'_> {
+	fn eq(&self, other: &Self) -> bool {
+		self.name == other.name && self.value == other.value
+	}
+}
+
+impl Eq for VariableDefinition<'_> {}
+
+impl Hash for VariableDefinition<'_> {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.name.hash(state);
+		self.value.hash(state);
+	}
+}
+
+impl<'a> VariableDefinition<'a> {
+	/// Creates a variable definition without span.
+	pub fn new(name: Cow<'a, str>, value: VariableValue<'a>) -> Self {
+		Self {
+			name,
+			value,
+			span: Span::default(),
+		}
+	}
 }
 
 impl<'a> AstNode for VariableDefinition<'a> {
 	type LST = lst::VariableDefinition<'a>;
 
-	fn emit_from(lst: &Self::LST) -> EmitResult<Self> {
-		let mut value = VariableValue::emit_from(&lst.value)?;
+	fn emit_at(lst: &Self::LST, offset: usize) -> EmitResult<Self> {
+		let value_offset = offset + lst.name.len() + source_len(&lst.op);
+		let mut value = VariableValue::emit_at(&lst.value, value_offset)?;
 		match lst.op {
 			lst::VariableOp::Assignment => {}
 			lst::VariableOp::Append => match &mut value {
@@ -134,13 +187,17 @@ impl<'a> AstNode for VariableDefinition<'a> {
 						Word::Variable(VariableExpansion {
 							name: lst.name.clone(),
 							modifier: None,
+							span: Span::new(offset, offset + lst.name.len()),
 						}),
 					);
 				}
 				VariableValue::Array(elements) => {
 					elements.insert(
 						0,
-						ArrayElement::ArrayInclusion(lst.name.clone()),
+						ArrayElement::ArrayInclusion(
+							lst.name.clone(),
+							Span::new(offset, offset + lst.name.len()),
+						),
 					);
 				}
 			},
@@ -148,6 +205,7 @@ impl<'a> AstNode for VariableDefinition<'a> {
 		Ok(Self {
 			name: lst.name.clone(),
 			value,
+			span: Span::new(offset, offset + source_len(lst)),
 		})
 	}
 
@@ -172,12 +230,14 @@ pub enum VariableValue<'a> {
 impl<'a> AstNode for VariableValue<'a> {
 	type LST = lst::VariableValue<'a>;
 
-	fn emit_from(lst: &Self::LST) -> EmitResult<Self> {
+	fn emit_at(lst: &Self::LST, offset: usize) -> EmitResult<Self> {
 		match lst {
 			lst::VariableValue::String(text) => {
-				Ok(Self::String(Text::emit_from(text)?))
+				Ok(Self::String(Text::emit_at(text, offset)?))
 			}
 			lst::VariableValue::Array(tokens) => {
+				// skip the opening parenthesis
+				let mut offset = offset + 1;
 				enum State {
 					/// Ready for elements
 					Ready,
@@ -204,7 +264,9 @@ impl<'a> AstNode for VariableValue<'a> {
 						}
 						lst::ArrayToken::Element(_) => {
 							if matches!(state, State::Ready) {
-								result.push(ArrayElement::emit_from(token)?);
+								result.push(ArrayElement::emit_at(
+									token, offset,
+								)?);
 								state = State::NeedDelimiter;
 							} else {
 								return Err(
@@ -213,6 +275,7 @@ impl<'a> AstNode for VariableValue<'a> {
 							}
 						}
 					}
+					offset += source_len(token);
 				}
 				Ok(Self::Array(result))
 			}
@@ -244,10 +307,11 @@ pub struct Text<'a>(pub Vec<Word<'a>>);
 impl<'a> AstNode for Text<'a> {
 	type LST = lst::Text<'a>;
 
-	fn emit_from(lst: &Self::LST) -> EmitResult<Self> {
+	fn emit_at(lst: &Self::LST, mut offset: usize) -> EmitResult<Self> {
 		let mut result = Vec::new();
 		for unit in &lst.0 {
-			result.append(&mut emit_text_unit(unit)?);
+			result.append(&mut emit_text_unit(unit, offset)?);
+			offset += source_len(unit);
 		}
 		Ok(Self(result))
 	}
@@ -260,30 +324,41 @@ impl<'a> AstNode for Text<'a> {
 }
 
 /// Emits a LST literal string part as string.
-fn emit_text_unit<'a>(lst: &lst::TextUnit<'a>) -> EmitResult<Vec<Word<'a>>> {
+fn emit_text_unit<'a>(
+	lst: &lst::TextUnit<'a>,
+	offset: usize,
+) -> EmitResult<Vec<Word<'a>>> {
 	match lst {
 		lst::TextUnit::Unquoted(words) | lst::TextUnit::DoubleQuote(words) => {
+			// skip the opening quote
+			let mut offset = if matches!(lst, lst::TextUnit::DoubleQuote(_)) {
+				offset + 1
+			} else {
+				offset
+			};
 			let mut result = Vec::new();
 			for word in words {
-				result.push(Word::emit_from(word)?);
+				result.push(Word::emit_at(word, offset)?);
+				offset += source_len(word);
 			}
 			Ok(result)
 		}
-		lst::TextUnit::SingleQuote(text) => {
-			Ok(vec![Word::Literal(text.clone())])
-		}
+		lst::TextUnit::SingleQuote(text) => Ok(vec![Word::Literal(
+			text.clone(),
+			Span::new(offset, offset + source_len(lst)),
+		)]),
 	}
 }
 
 impl From<String> for Text<'_> {
 	fn from(value: String) -> Self {
-		Self(vec![Word::Literal(value.into())])
+		Self(vec![Word::Literal(value.into(), Span::default())])
 	}
 }
 
 impl From<&'static str> for Text<'_> {
 	fn from(value: &'static str) -> Self {
-		Self(vec![Word::Literal(value.into())])
+		Self(vec![Word::Literal(value.into(), Span::default())])
 	}
 }
 
@@ -292,34 +367,79 @@ impl From<&'static str> for Text<'_> {
 /// When emitted from [`lst::Word`], the subcommand variant is emitted as a literal,
 /// literal strings are concatenated as one string, and unbraced and braced variable expansions
 /// are unified.
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+///
+/// Every word carries the span of its LST word, see [`Word::span`].
+#[derive(Debug, Clone)]
 pub enum Word<'a> {
 	/// A literal string.
-	Literal(Cow<'a, str>),
+	Literal(Cow<'a, str>, Span),
 	/// A variable expansion.
 	Variable(VariableExpansion<'a>),
 	/// A complete subcommand string, including `$(` and `)`.
 	///
 	/// The inner string is escaped.
-	Subcommand(Cow<'a, str>),
+	Subcommand(Cow<'a, str>, Span),
+	/// A arithmetic expansion.
+	Arithmetic(Arc<ArithmeticExpr>, Span),
+}
+
+impl PartialEq for Word<'_> {
+	fn eq(&self, other: &Self) -> bool {
+		match (self, other) {
+			(Word::Literal(a, _), Word::Literal(b, _))
+			| (Word::Subcommand(a, _), Word::Subcommand(b, _)) => a == b,
+			(Word::Variable(a), Word::Variable(b)) => a == b,
+			(Word::Arithmetic(a, _), Word::Arithmetic(b, _)) => a == b,
+			_ => false,
+		}
+	}
+}
+
+impl Eq for Word<'_> {}
+
+impl Hash for Word<'_> {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		std::mem::discriminant(self).hash(state);
+		match self {
+			Word::Literal(text, _) | Word::Subcommand(text, _) => {
+				text.hash(state)
+			}
+			Word::Variable(expansion) => expansion.hash(state),
+			Word::Arithmetic(expr, _) => expr.hash(state),
+		}
+	}
+}
+
+impl Word<'_> {
+	/// Returns the span of the word.
+	#[must_use]
+	pub fn span(&self) -> Span {
+		match self {
+			Word::Variable(expansion) => expansion.span,
+			Word::Literal(_, span)
+			| Word::Subcommand(_, span)
+			| Word::Arithmetic(_, span) => *span,
+		}
+	}
 }
 
 impl<'a> AstNode for Word<'a> {
 	type LST = lst::Word<'a>;
 
-	fn emit_from(lst: &Self::LST) -> EmitResult<Self> {
+	fn emit_at(lst: &Self::LST, offset: usize) -> EmitResult<Self> {
+		let span = Span::new(offset, offset + source_len(lst));
 		match lst {
 			lst::Word::Literal(parts) => {
 				if parts.len() == 1 {
 					match &parts[0] {
 						lst::LiteralPart::String(str) => {
-							Ok(Self::Literal(str.clone()))
+							Ok(Self::Literal(str.clone(), span))
 						}
 						lst::LiteralPart::Escaped(ch) => {
-							Ok(Self::Literal(ch.to_string().into()))
+							Ok(Self::Literal(ch.to_string().into(), span))
 						}
 						lst::LiteralPart::LineContinuation => {
-							Ok(Self::Literal(Cow::Borrowed("")))
+							Ok(Self::Literal(Cow::Borrowed(""), span))
 						}
 					}
 				} else {
@@ -331,35 +451,49 @@ impl<'a> AstNode for Word<'a> {
 						}
 						emit_literal_part(part, &mut result);
 					}
-					Ok(Self::Literal(result.into()))
+					Ok(Self::Literal(result.into(), span))
 				}
 			}
 			lst::Word::UnbracedVariable(name) => {
 				Ok(Self::Variable(VariableExpansion {
 					name: name.clone(),
 					modifier: None,
+					span,
 				}))
 			}
 			lst::Word::BracedVariable(expansion) => {
-				Ok(Self::Variable(VariableExpansion::emit_from(expansion)?))
+				// skip `${`
+				let mut expansion =
+					VariableExpansion::emit_at(expansion, offset + 2)?;
+				expansion.span = span;
+				Ok(Self::Variable(expansion))
 			}
 			lst::Word::Subcommand(_) => {
-				Ok(Self::Subcommand(lst.to_string().into()))
+				Ok(Self::Subcommand(lst.to_string().into(), span))
 			}
+			// unsupported expressions are kept as opaque sub-commands
+			lst::Word::Arithmetic(expr) => Ok(ArithmeticExpr::parse(expr)
+				.map_or_else(
+					|| Self::Subcommand(lst.to_string().into(), span),
+					|expr| Self::Arithmetic(Arc::new(expr), span),
+				)),
 		}
 	}
 
 	fn lower(&self) -> Self::LST {
 		match self {
-			Word::Literal(text) => {
+			Word::Literal(text, _) => {
 				lst::Word::Literal(lst::LiteralPart::escape(text))
 			}
 			Word::Variable(expansion) => {
 				lst::Word::BracedVariable(expansion.lower())
 			}
-			Word::Subcommand(text) => {
+			Word::Subcommand(text, _) => {
 				lst::Word::Literal(vec![lst::LiteralPart::String(text.clone())])
 			}
+			Word::Arithmetic(expr, _) => {
+				lst::Word::Arithmetic(expr.to_string().into())
+			}
 		}
 	}
 }
@@ -373,19 +507,335 @@ fn emit_literal_part(lst: &lst::LiteralPart, result: &mut String) {
 	}
 }
 
-/// A variable expansion.
+/// A arithmetic expression, evaluated with bash integer semantics.
+///
+/// Variables can be referred as `NAME`, `$NAME` or `${NAME}`. Only
+/// a side-effect-free subset of operators is supported, so assignments
+/// and increments are not representable.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ArithmeticExpr {
+	/// A integer literal.
+	Number(i64),
+	/// A variable reference.
+	Variable(String),
+	/// A unary operation.
+	Unary(UnaryOperator, Box<ArithmeticExpr>),
+	/// A binary operation.
+	Binary(BinaryOperator, Box<ArithmeticExpr>, Box<ArithmeticExpr>),
+}
+
+/// Unary arithmetic operators.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnaryOperator {
+	/// `+`
+	Plus,
+	/// `-`
+	Minus,
+	/// `!`
+	Not,
+}
+
+impl UnaryOperator {
+	/// Returns the operator token.
+	#[must_use]
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			UnaryOperator::Plus => "+",
+			UnaryOperator::Minus => "-",
+			UnaryOperator::Not => "!",
+		}
+	}
+}
+
+/// Binary arithmetic operators.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinaryOperator {
+	/// `*`
+	Mul,
+	/// `/`
+	Div,
+	/// `%`
+	Rem,
+	/// `+`
+	Add,
+	/// `-`
+	Sub,
+	/// `<`
+	Lt,
+	/// `<=`
+	Le,
+	/// `>`
+	Gt,
+	/// `>=`
+	Ge,
+	/// `==`
+	Eq,
+	/// `!=`
+	Ne,
+	/// `&&`
+	And,
+	/// `||`
+	Or,
+}
+
+impl BinaryOperator {
+	const ALL: [Self; 13] = [
+		Self::Mul,
+		Self::Div,
+		Self::Rem,
+		Self::Add,
+		Self::Sub,
+		Self::Lt,
+		Self::Le,
+		Self::Gt,
+		Self::Ge,
+		Self::Eq,
+		Self::Ne,
+		Self::And,
+		Self::Or,
+	];
+
+	/// Returns the operator token.
+	#[must_use]
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			BinaryOperator::Mul => "*",
+			BinaryOperator::Div => "/",
+			BinaryOperator::Rem => "%",
+			BinaryOperator::Add => "+",
+			BinaryOperator::Sub => "-",
+			BinaryOperator::Lt => "<",
+			BinaryOperator::Le => "<=",
+			BinaryOperator::Gt => ">",
+			BinaryOperator::Ge => ">=",
+			BinaryOperator::Eq => "==",
+			BinaryOperator::Ne => "!=",
+			BinaryOperator::And => "&&",
+			BinaryOperator::Or => "||",
+		}
+	}
+
+	/// Returns the precedence of the operator, higher binds tighter.
+	fn precedence(&self) -> u8 {
+		match self {
+			BinaryOperator::Or => 1,
+			BinaryOperator::And => 2,
+			BinaryOperator::Eq | BinaryOperator::Ne => 3,
+			BinaryOperator::Lt
+			| BinaryOperator::Le
+			| BinaryOperator::Gt
+			| BinaryOperator::Ge => 4,
+			BinaryOperator::Add | BinaryOperator::Sub => 5,
+			BinaryOperator::Mul | BinaryOperator::Div | BinaryOperator::Rem => {
+				6
+			}
+		}
+	}
+}
+
+impl ArithmeticExpr {
+	/// Parses a arithmetic expression.
+	///
+	/// Returns [None] if the expression is invalid or unsupported.
+	#[must_use]
+	pub fn parse(src: &str) -> Option<Self> {
+		let mut parser = ArithmeticParser { rest: src };
+		let expr = parser.binary(0)?;
+		parser.rest.trim_start().is_empty().then_some(expr)
+	}
+
+	/// Returns names of all referred variables.
+	#[must_use]
+	pub fn variables(&self) -> Vec<&str> {
+		match self {
+			ArithmeticExpr::Number(_) => vec![],
+			ArithmeticExpr::Variable(name) => vec![name],
+			ArithmeticExpr::Unary(_, expr) => expr.variables(),
+			ArithmeticExpr::Binary(_, lhs, rhs) => {
+				let mut result = lhs.variables();
+				result.append(&mut rhs.variables());
+				result
+			}
+		}
+	}
+
+	/// Formats a operand, adding parentheses if needed.
+	fn fmt_operand(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ArithmeticExpr::Binary(..) => f.write_fmt(format_args!("({self})")),
+			ArithmeticExpr::Number(num) if *num < 0 => {
+				f.write_fmt(format_args!("({num})"))
+			}
+			_ => Display::fmt(self, f),
+		}
+	}
+}
+
+impl Display for ArithmeticExpr {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ArithmeticExpr::Number(num) => Display::fmt(num, f),
+			ArithmeticExpr::Variable(name) => f.write_str(name),
+			ArithmeticExpr::Unary(op, expr) => {
+				f.write_str(op.as_str())?;
+				expr.fmt_operand(f)
+			}
+			ArithmeticExpr::Binary(op, lhs, rhs) => {
+				lhs.fmt_operand(f)?;
+				f.write_fmt(format_args!(" {} ", op.as_str()))?;
+				rhs.fmt_operand(f)
+			}
+		}
+	}
+}
+
+/// Precedence-climbing parser of arithmetic expressions.
+struct ArithmeticParser<'a> {
+	rest: &'a str,
+}
+
+impl ArithmeticParser<'_> {
+	fn binary(&mut self, min_precedence: u8) -> Option<ArithmeticExpr> {
+		let mut lhs = self.unary()?;
+		loop {
+			self.rest = self.rest.trim_start();
+			let Some(op) = BinaryOperator::ALL
+				.into_iter()
+				.filter(|op| self.rest.starts_with(op.as_str()))
+				.max_by_key(|op| op.as_str().len())
+			else {
+				break;
+			};
+			if op.precedence() < min_precedence {
+				break;
+			}
+			self.rest = &self.rest[op.as_str().len()..];
+			let rhs = self.binary(op.precedence() + 1)?;
+			lhs = ArithmeticExpr::Binary(op, Box::new(lhs), Box::new(rhs));
+		}
+		Some(lhs)
+	}
+
+	fn unary(&mut self) -> Option<ArithmeticExpr> {
+		self.rest = self.rest.trim_start();
+		let mut chars = self.rest.chars();
+		let op = match chars.next()? {
+			// increments and decrements are not supported
+			'+' | '-' if self.rest[1..].starts_with(&self.rest[..1]) => {
+				return None;
+			}
+			'+' => UnaryOperator::Plus,
+			'-' => UnaryOperator::Minus,
+			'!' => UnaryOperator::Not,
+			'(' => {
+				self.rest = chars.as_str();
+				let expr = self.binary(0)?;
+				self.rest = self.rest.trim_start().strip_prefix(')')?;
+				return Some(expr);
+			}
+			'0'..='9' => return self.number(),
+			'$' => {
+				self.rest = chars.as_str();
+				if let Some(rest) = self.rest.strip_prefix('{') {
+					self.rest = rest;
+					let name = self.name()?;
+					self.rest = self.rest.strip_prefix('}')?;
+					return Some(name);
+				}
+				return self.name();
+			}
+			_ => return self.name(),
+		};
+		self.rest = chars.as_str();
+		Some(ArithmeticExpr::Unary(op, Box::new(self.unary()?)))
+	}
+
+	fn number(&mut self) -> Option<ArithmeticExpr> {
+		let end = self
+			.rest
+			.find(|ch: char| !ch.is_ascii_alphanumeric())
+			.unwrap_or(self.rest.len());
+		let (literal, rest) = self.rest.split_at(end);
+		self.rest = rest;
+		let num = if let Some(hex) = literal
+			.strip_prefix("0x")
+			.or_else(|| literal.strip_prefix("0X"))
+		{
+			i64::from_str_radix(hex, 16)
+		} else if literal.len() > 1
+			&& let Some(oct) = literal.strip_prefix('0')
+		{
+			i64::from_str_radix(oct, 8)
+		} else {
+			literal.parse()
+		};
+		num.ok().map(ArithmeticExpr::Number)
+	}
+
+	fn name(&mut self) -> Option<ArithmeticExpr> {
+		if !self
+			.rest
+			.starts_with(|ch: char| ch.is_ascii_alphabetic() || ch == '_')
+		{
+			return None;
+		}
+		let end = self
+			.rest
+			.find(|ch: char| !ch.is_ascii_alphanumeric() && ch != '_')
+			.unwrap_or(self.rest.len());
+		let (name, rest) = self.rest.split_at(end);
+		self.rest = rest;
+		Some(ArithmeticExpr::Variable(name.to_string()))
+	}
+}
+
+/// A variable expansion.
+#[derive(Debug, Clone)]
 pub struct VariableExpansion<'a> {
 	/// Name of the variable.
 	pub name: Cow<'a, str>,
 	/// Modifier to apply to the expanded value.
 	pub modifier: Option<ExpansionModifier<'a>>,
+	/// Span of the expansion.
+	///
+	/// When emitted as a part of [`Word`], this covers the whole `$NAME`
+	/// or `${...}` word. Otherwise, it covers only the inner part of braces.
+	pub span: Span,
+}
+
+impl PartialEq for VariableExpansion<'_> {
+	fn eq(&self, other: &Self) -> bool {
+		self.name == other.name && self.modifier == other.modifier
+	}
+}
+
+impl Eq for VariableExpansion<'_> {}
+
+impl Hash for VariableExpansion<'_> {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.name.hash(state);
+		self.modifier.hash(state);
+	}
+}
+
+impl<'a> VariableExpansion<'a> {
+	/// Creates a variable expansion without span.
+	pub fn new(
+		name: Cow<'a, str>,
+		modifier: Option<ExpansionModifier<'a>>,
+	) -> Self {
+		Self {
+			name,
+			modifier,
+			span: Span::default(),
+		}
+	}
 }
 
 impl<'a> AstNode for VariableExpansion<'a> {
 	type LST = lst::BracedExpansion<'a>;
 
-	fn emit_from(lst: &Self::LST) -> EmitResult<Self> {
+	fn emit_at(lst: &Self::LST, offset: usize) -> EmitResult<Self> {
 		let modifier = lst.modifier.as_ref().take_if(|modifier| {
 			!matches!(
 				modifier,
@@ -394,13 +844,17 @@ impl<'a> AstNode for VariableExpansion<'a> {
 			)
 		});
 		let modifier = if let Some(modifier) = modifier {
-			Some(ExpansionModifier::emit_from(modifier)?)
+			Some(ExpansionModifier::emit_at(
+				modifier,
+				offset + lst.name.len(),
+			)?)
 		} else {
 			None
 		};
 		Ok(Self {
 			name: lst.name.clone(),
 			modifier,
+			span: Span::new(offset, offset + source_len(lst)),
 		})
 	}
 
@@ -481,7 +935,11 @@ pub enum ExpansionModifier<'a> {
 impl<'a> AstNode for ExpansionModifier<'a> {
 	type LST = lst::ExpansionModifier<'a>;
 
-	fn emit_from(lst: &Self::LST) -> EmitResult<Self> {
+	fn emit_at(lst: &Self::LST, offset: usize) -> EmitResult<Self> {
+		// offset of the replacement string, after the pattern and `/`
+		let string_offset = |prefix: usize, pattern: &BashPattern| {
+			offset + prefix + source_len(pattern) + 1
+		};
 		match lst {
 			lst::ExpansionModifier::Substring { offset, length } => {
 				Ok(Self::Substring {
@@ -510,7 +968,7 @@ impl<'a> AstNode for ExpansionModifier<'a> {
 				Ok(Self::ReplaceOnce {
 					pattern: pattern.clone(),
 					string: Arc::new(if let Some(text) = string {
-						Text::emit_from(text)?
+						Text::emit_at(text, string_offset(1, pattern))?
 					} else {
 						Text::default()
 					}),
@@ -520,7 +978,7 @@ impl<'a> AstNode for ExpansionModifier<'a> {
 				Ok(Self::ReplaceAll {
 					pattern: pattern.clone(),
 					string: Arc::new(if let Some(text) = string {
-						Text::emit_from(text)?
+						Text::emit_at(text, string_offset(2, pattern))?
 					} else {
 						Text::default()
 					}),
@@ -530,7 +988,7 @@ impl<'a> AstNode for ExpansionModifier<'a> {
 				Ok(Self::ReplacePrefix {
 					pattern: pattern.clone(),
 					string: Arc::new(if let Some(text) = string {
-						Text::emit_from(text)?
+						Text::emit_at(text, string_offset(2, pattern))?
 					} else {
 						Text::default()
 					}),
@@ -540,7 +998,7 @@ impl<'a> AstNode for ExpansionModifier<'a> {
 				Ok(Self::ReplaceSuffix {
 					pattern: pattern.clone(),
 					string: Arc::new(if let Some(text) = string {
-						Text::emit_from(text)?
+						Text::emit_at(text, string_offset(2, pattern))?
 					} else {
 						Text::default()
 					}),
@@ -558,15 +1016,15 @@ impl<'a> AstNode for ExpansionModifier<'a> {
 			lst::ExpansionModifier::LowerAll(pattern) => {
 				Ok(Self::LowerAll(pattern.clone()))
 			}
-			lst::ExpansionModifier::ErrorOnUnset(text) => {
-				Ok(Self::ErrorOnUnset(Arc::new(Text::emit_from(text)?)))
-			}
+			lst::ExpansionModifier::ErrorOnUnset(text) => Ok(
+				Self::ErrorOnUnset(Arc::new(Text::emit_at(text, offset + 2)?)),
+			),
 			lst::ExpansionModifier::Length => Ok(Self::Length),
 			lst::ExpansionModifier::WhenUnset(text) => {
-				Ok(Self::WhenUnset(Arc::new(Text::emit_from(text)?)))
+				Ok(Self::WhenUnset(Arc::new(Text::emit_at(text, offset + 2)?)))
 			}
 			lst::ExpansionModifier::WhenSet(text) => {
-				Ok(Self::WhenSet(Arc::new(Text::emit_from(text)?)))
+				Ok(Self::WhenSet(Arc::new(Text::emit_at(text, offset + 2)?)))
 			}
 			lst::ExpansionModifier::ArrayElements => {
 				Err(EmitError::Unrepresentable)
@@ -649,19 +1107,57 @@ impl<'a> AstNode for ExpansionModifier<'a> {
 
 /// A element of an array.
 ///
-/// Spacy tokens, newline and comments are discarded
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// Spacy tokens, newline and comments are discarded. Every element
+/// carries the span of its LST token, see [`ArrayElement::span`].
+#[derive(Debug, Clone)]
 pub enum ArrayElement<'a> {
 	/// A element expanding to all elements of another array.
-	ArrayInclusion(Cow<'a, str>),
+	ArrayInclusion(Cow<'a, str>, Span),
 	/// A text element.
-	Text(Arc<Text<'a>>),
+	Text(Arc<Text<'a>>, Span),
+}
+
+impl PartialEq for ArrayElement<'_> {
+	fn eq(&self, other: &Self) -> bool {
+		match (self, other) {
+			(
+				ArrayElement::ArrayInclusion(a, _),
+				ArrayElement::ArrayInclusion(b, _),
+			) => a == b,
+			(ArrayElement::Text(a, _), ArrayElement::Text(b, _)) => a == b,
+			_ => false,
+		}
+	}
+}
+
+impl Eq for ArrayElement<'_> {}
+
+impl Hash for ArrayElement<'_> {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		std::mem::discriminant(self).hash(state);
+		match self {
+			ArrayElement::ArrayInclusion(name, _) => name.hash(state),
+			ArrayElement::Text(text, _) => text.hash(state),
+		}
+	}
+}
+
+impl ArrayElement<'_> {
+	/// Returns the span of the element.
+	#[must_use]
+	pub fn span(&self) -> Span {
+		match self {
+			ArrayElement::ArrayInclusion(_, span)
+			| ArrayElement::Text(_, span) => *span,
+		}
+	}
 }
 
 impl<'a> AstNode for ArrayElement<'a> {
 	type LST = lst::ArrayToken<'a>;
 
-	fn emit_from(lst: &Self::LST) -> EmitResult<Self> {
+	fn emit_at(lst: &Self::LST, offset: usize) -> EmitResult<Self> {
+		let span = Span::new(offset, offset + source_len(lst));
 		match lst {
 			lst::ArrayToken::Spacy(_)
 			| lst::ArrayToken::Newline
@@ -679,21 +1175,21 @@ impl<'a> AstNode for ArrayElement<'a> {
 									&& word.modifier == Some(lst::ExpansionModifier::ArrayElements)
 									{
 										// expand array elements
-										return Ok(Self::ArrayInclusion(word.name.clone()));
+										return Ok(Self::ArrayInclusion(word.name.clone(), span));
 									}
 							}
 						}
 						lst::TextUnit::SingleQuote(_) => {}
 					}
 				}
-				Ok(Self::Text(Arc::new(Text::emit_from(text)?)))
+				Ok(Self::Text(Arc::new(Text::emit_at(text, offset)?), span))
 			}
 		}
 	}
 
 	fn lower(&self) -> Self::LST {
 		match self {
-			ArrayElement::ArrayInclusion(name) => {
+			ArrayElement::ArrayInclusion(name, _) => {
 				lst::ArrayToken::Element(Arc::new(lst::Text(vec![
 					lst::TextUnit::DoubleQuote(vec![
 						lst::Word::BracedVariable(lst::BracedExpansion {
@@ -705,7 +1201,7 @@ impl<'a> AstNode for ArrayElement<'a> {
 					]),
 				])))
 			}
-			ArrayElement::Text(text) => {
+			ArrayElement::Text(text, _) => {
 				lst::ArrayToken::Element(Arc::new(text.lower()))
 			}
 		}
@@ -744,7 +1240,8 @@ mod test {
 		let text_lst = Arc::new(lst::Text(vec![lst::TextUnit::SingleQuote(
 			"foo$\\".into(),
 		)]));
-		let text_ast = Text(vec![Word::Literal("foo$\\".into())]);
+		let text_ast =
+			Text(vec![Word::Literal("foo$\\".into(), Span::default())]);
 		let def_lst = lst::VariableDefinition {
 			name: "test".into(),
 			op: lst::VariableOp::Assignment,
@@ -753,6 +1250,7 @@ mod test {
 		let def_ast = VariableDefinition {
 			name: "test".into(),
 			value: VariableValue::String(text_ast.clone()),
+			span: Span::default(),
 		};
 		assert_emit_lower(
 			lst::ApmlLst(vec![
@@ -784,12 +1282,72 @@ mod test {
 		]));
 	}
 
+	#[test]
+	fn test_span() {
+		let src = "A=1 # a\nB+=\"x${A:-$C}\"\nD=(a \"$B\")\nE=${A//x/$D}\n\
+			D+=($((A+1)) 'b')";
+		let ast =
+			ApmlAst::emit_from(&lst::ApmlLst::parse(src).unwrap()).unwrap();
+		assert_eq!(ast.0[0].span.slice(src), Some("A=1"));
+		assert_eq!(ast.0[1].span.slice(src), Some("B+=\"x${A:-$C}\""));
+		let VariableValue::String(text) = &ast.0[1].value else {
+			panic!()
+		};
+		assert_eq!(text.0[0].span().slice(src), Some("B"));
+		assert_eq!(text.0[1].span().slice(src), Some("x"));
+		assert_eq!(text.0[2].span().slice(src), Some("${A:-$C}"));
+		let Word::Variable(VariableExpansion {
+			modifier: Some(ExpansionModifier::WhenUnset(text)),
+			..
+		}) = &text.0[2]
+		else {
+			panic!()
+		};
+		assert_eq!(text.0[0].span().slice(src), Some("$C"));
+		let VariableValue::Array(elements) = &ast.0[2].value else {
+			panic!()
+		};
+		assert_eq!(elements[0].span().slice(src), Some("a"));
+		assert_eq!(elements[1].span().slice(src), Some("\"$B\""));
+		let ArrayElement::Text(text, _) = &elements[1] else {
+			panic!()
+		};
+		assert_eq!(text.0[0].span().slice(src), Some("$B"));
+		let VariableValue::String(text) = &ast.0[3].value else {
+			panic!()
+		};
+		let Word::Variable(VariableExpansion {
+			modifier: Some(ExpansionModifier::ReplaceAll { string, .. }),
+			..
+		}) = &text.0[0]
+		else {
+			panic!()
+		};
+		assert_eq!(string.0[0].span().slice(src), Some("$D"));
+		let VariableValue::Array(elements) = &ast.0[4].value else {
+			panic!()
+		};
+		assert_eq!(elements[0].span().slice(src), Some("D"));
+		assert_eq!(elements[1].span().slice(src), Some("$((A+1))"));
+		assert_eq!(elements[2].span().slice(src), Some("'b'"));
+		let ArrayElement::Text(text, _) = &elements[1] else {
+			panic!()
+		};
+		assert!(matches!(text.0[0], Word::Arithmetic(..)));
+		assert_eq!(text.0[0].span().slice(src), Some("$((A+1))"));
+		let ArrayElement::Text(text, _) = &elements[2] else {
+			panic!()
+		};
+		assert_eq!(text.0[0].span().slice(src), Some("'b'"));
+	}
+
 	#[test]
 	fn test_variable_definition() {
 		let text_lst = Arc::new(lst::Text(vec![lst::TextUnit::SingleQuote(
 			"foo$\\".into(),
 		)]));
-		let text_ast = Text(vec![Word::Literal("foo$\\".into())]);
+		let text_ast =
+			Text(vec![Word::Literal("foo$\\".into(), Span::default())]);
 		assert_emit_lower(
 			lst::VariableDefinition {
 				name: "test".into(),
@@ -799,6 +1357,7 @@ mod test {
 			VariableDefinition {
 				name: "test".into(),
 				value: VariableValue::String(text_ast.clone()),
+				span: Span::default(),
 			},
 			"test=\"foo\\$\\\\\"",
 		);
@@ -814,9 +1373,11 @@ mod test {
 					Word::Variable(VariableExpansion {
 						name: "test".into(),
 						modifier: None,
+						span: Span::default(),
 					}),
-					Word::Literal("foo$\\".into()),
+					Word::Literal("foo$\\".into(), Span::default()),
 				])),
+				span: Span::default(),
 			},
 			"test=\"${test}foo\\$\\\\\"",
 		);
@@ -832,7 +1393,9 @@ mod test {
 				name: "test".into(),
 				value: VariableValue::Array(vec![ArrayElement::Text(
 					Arc::new(text_ast.clone()),
+					Span::default(),
 				)]),
+				span: Span::default(),
 			},
 			"test=(\"foo\\$\\\\\")",
 		);
@@ -847,9 +1410,16 @@ mod test {
 			VariableDefinition {
 				name: "test".into(),
 				value: VariableValue::Array(vec![
-					ArrayElement::ArrayInclusion("test".into()),
-					ArrayElement::Text(Arc::new(text_ast.clone())),
+					ArrayElement::ArrayInclusion(
+						"test".into(),
+						Span::default(),
+					),
+					ArrayElement::Text(
+						Arc::new(text_ast.clone()),
+						Span::default(),
+					),
 				]),
+				span: Span::default(),
 			},
 			"test=(\"${test[@]}\" \"foo\\$\\\\\")",
 		);
@@ -860,7 +1430,8 @@ mod test {
 		let text_lst = Arc::new(lst::Text(vec![lst::TextUnit::SingleQuote(
 			"foo$\\".into(),
 		)]));
-		let text_ast = Text(vec![Word::Literal("foo$\\".into())]);
+		let text_ast =
+			Text(vec![Word::Literal("foo$\\".into(), Span::default())]);
 		assert_emit_lower(
 			lst::VariableValue::String(text_lst.clone()),
 			VariableValue::String(text_ast.clone()),
@@ -876,9 +1447,9 @@ mod test {
 				lst::ArrayToken::Element(text_lst.clone()),
 			]),
 			VariableValue::Array(vec![
-				ArrayElement::Text(Arc::new(text_ast.clone())),
-				ArrayElement::Text(Arc::new(text_ast.clone())),
-				ArrayElement::Text(Arc::new(text_ast.clone())),
+				ArrayElement::Text(Arc::new(text_ast.clone()), Span::default()),
+				ArrayElement::Text(Arc::new(text_ast.clone()), Span::default()),
+				ArrayElement::Text(Arc::new(text_ast.clone()), Span::default()),
 			]),
 			"(\"foo\\$\\\\\" \"foo\\$\\\\\" \"foo\\$\\\\\")",
 		);
@@ -904,8 +1475,8 @@ mod test {
 				)]),
 			]),
 			Text(vec![
-				Word::Literal("test".into()),
-				Word::Literal("test$$".into()),
+				Word::Literal("test".into(), Span::default()),
+				Word::Literal("test$$".into(), Span::default()),
 			]),
 			"\"testtest\\$\\$\"",
 		);
@@ -915,7 +1486,7 @@ mod test {
 	fn test_word() {
 		assert_emit_lower(
 			lst::Word::Literal(lst::LiteralPart::escape("test$$")),
-			Word::Literal("test$$".into()),
+			Word::Literal("test$$".into(), Span::default()),
 			"test\\$\\$",
 		);
 		assert_emit_lower(
@@ -923,6 +1494,7 @@ mod test {
 			Word::Variable(VariableExpansion {
 				name: "a".into(),
 				modifier: None,
+				span: Span::default(),
 			}),
 			"${a}",
 		);
@@ -934,6 +1506,7 @@ mod test {
 			Word::Variable(VariableExpansion {
 				name: "a".into(),
 				modifier: None,
+				span: Span::default(),
 			}),
 			"${a}",
 		);
@@ -952,12 +1525,15 @@ mod test {
 					]),
 				]))),
 			]),
-			Word::Subcommand("$('true' \"foo$\\$asdf\")".into()),
+			Word::Subcommand(
+				"$('true' \"foo$\\$asdf\")".into(),
+				Span::default(),
+			),
 			"$('true' \"foo$\\$asdf\")",
 		);
 		assert_emit_lower(
 			lst::Word::Literal(lst::LiteralPart::escape("test$$\n")),
-			Word::Literal("test$$\n".into()),
+			Word::Literal("test$$\n".into(), Span::default()),
 			"test\\$\\$\n",
 		);
 		assert_emit_lower(
@@ -966,9 +1542,50 @@ mod test {
 				lst::LiteralPart::LineContinuation,
 				lst::LiteralPart::String("test\ntest".into()),
 			]),
-			Word::Literal("testtest\ntest".into()),
+			Word::Literal("testtest\ntest".into(), Span::default()),
 			"testtest\ntest",
 		);
+		assert_emit_lower(
+			lst::Word::Arithmetic(" ${a} +-(b*2)>=0x1 ".into()),
+			Word::Arithmetic(
+				Arc::new(ArithmeticExpr::Binary(
+					BinaryOperator::Ge,
+					Box::new(ArithmeticExpr::Binary(
+						BinaryOperator::Add,
+						Box::new(ArithmeticExpr::Variable("a".into())),
+						Box::new(ArithmeticExpr::Unary(
+							UnaryOperator::Minus,
+							Box::new(ArithmeticExpr::Binary(
+								BinaryOperator::Mul,
+								Box::new(ArithmeticExpr::Variable("b".into())),
+								Box::new(ArithmeticExpr::Number(2)),
+							)),
+						)),
+					)),
+					Box::new(ArithmeticExpr::Number(1)),
+				)),
+				Span::default(),
+			),
+			"$(((a + -(b * 2)) >= 1))",
+		);
+	}
+
+	#[test]
+	fn test_arithmetic_expr() {
+		for src in ["a++", "a = 1", "1 << 2", "(1", "1)", "", "08", "$"] {
+			assert_eq!(ArithmeticExpr::parse(src), None, "{src}");
+		}
+		let expr = ArithmeticExpr::parse("a || b && !c == $d - -1").unwrap();
+		assert_eq!(expr.to_string(), "a || (b && (!c == (d - -1)))");
+		assert_eq!(
+			ArithmeticExpr::parse(&expr.to_string()),
+			Some(expr.clone())
+		);
+		assert_eq!(expr.variables(), vec!["a", "b", "c", "d"]);
+		assert_eq!(
+			Word::emit_from(&lst::Word::Arithmetic("1<<2".into())).unwrap(),
+			Word::Subcommand("$((1<<2))".into(), Span::default())
+		);
 	}
 
 	#[test]
@@ -981,6 +1598,7 @@ mod test {
 			VariableExpansion {
 				name: "test".into(),
 				modifier: None,
+				span: Span::default(),
 			},
 			"test",
 		);
@@ -992,6 +1610,7 @@ mod test {
 			VariableExpansion {
 				name: "test".into(),
 				modifier: Some(ExpansionModifier::Length),
+				span: Span::default(),
 			},
 			"#test",
 		);
@@ -1003,6 +1622,7 @@ mod test {
 			VariableExpansion {
 				name: "test".into(),
 				modifier: None,
+				span: Span::default(),
 			},
 			"test",
 		);
@@ -1014,6 +1634,7 @@ mod test {
 			VariableExpansion {
 				name: "test".into(),
 				modifier: None,
+				span: Span::default(),
 			},
 			"test",
 		);
@@ -1028,7 +1649,10 @@ mod test {
 		let text_lst = Arc::new(lst::Text(vec![lst::TextUnit::SingleQuote(
 			"foo$\\".into(),
 		)]));
-		let text_ast = Arc::new(Text(vec![Word::Literal("foo$\\".into())]));
+		let text_ast = Arc::new(Text(vec![Word::Literal(
+			"foo$\\".into(),
+			Span::default(),
+		)]));
 		assert_emit_lower(
 			lst::ExpansionModifier::Substring {
 				offset: "10".into(),
@@ -1235,14 +1859,20 @@ mod test {
 					},
 				)]),
 			]))),
-			ArrayElement::ArrayInclusion("a".into()),
+			ArrayElement::ArrayInclusion("a".into(), Span::default()),
 			"\"${a[@]}\"",
 		);
 		assert_emit_lower(
 			lst::ArrayToken::Element(Arc::new(lst::Text(vec![
 				lst::TextUnit::SingleQuote("a".into()),
 			]))),
-			ArrayElement::Text(Arc::new(Text(vec![Word::Literal("a".into())]))),
+			ArrayElement::Text(
+				Arc::new(Text(vec![Word::Literal(
+					"a".into(),
+					Span::default(),
+				)])),
+				Span::default(),
+			),
 			"\"a\"",
 		);
 		assert_emit_fail::<ArrayElement, _>(lst::ArrayToken::Spacy(' '));
@@ -0,0 +1,519 @@
+//! Deserializing typed structures from [`ApmlContext`].
+//!
+//! A context is deserialized as a map from variable names to values, so
+//! structs with fields named after variables can be read directly:
+//!
+//! ```
+//! use libabbs::apml::{ApmlContext, de::from_context};
+//!
+//! #[derive(serde::Deserialize)]
+//! #[allow(non_snake_case)]
+//! struct Defines {
+//!     PKGNAME: String,
+//!     PKGDEP: Vec<String>,
+//!     ABHOST: Option<String>,
+//! }
+//!
+//! let ctx = ApmlContext::eval_source("PKGNAME=foo\nPKGDEP=\"a b\"").unwrap();
+//! let defines: Defines = from_context(&ctx).unwrap();
+//! assert_eq!(defines.PKGNAME, "foo");
+//! assert_eq!(defines.PKGDEP, vec!["a", "b"]);
+//! assert_eq!(defines.ABHOST, None);
+//! ```
+//!
+//! Values are coerced following the rules of [`VariableValue`]:
+//! - Strings are read with [`VariableValue::as_string`], joining arrays.
+//! - Sequences are read with [`VariableValue::as_array`], splitting strings.
+//! - Options are `None` if the variable is unset or empty.
+//! - Numbers, booleans and unit enum variants are parsed from strings.
+
+use std::{borrow::Cow, fmt::Display};
+
+use serde::de::{
+	self, Deserialize, Deserializer, IntoDeserializer, MapAccess, Visitor,
+	value::{BorrowedStrDeserializer, SeqDeserializer},
+};
+use thiserror::Error;
+
+use super::{ApmlContext, VariableValue};
+
+/// Error from deserializing a [`ApmlContext`].
+#[derive(Debug, Error)]
+pub enum DeError {
+	#[error("{0}")]
+	Custom(String),
+	#[error("Invalid value {value:?}, expected {expected}")]
+	InvalidValue {
+		/// The value failed to be parsed.
+		value: String,
+		/// Description of the expected value.
+		expected: &'static str,
+	},
+	#[error("Invalid variable {name}: {source}")]
+	Variable {
+		/// Name of the variable.
+		name: String,
+		/// The error from deserializing its value.
+		source: Box<DeError>,
+	},
+}
+
+impl de::Error for DeError {
+	fn custom<T: Display>(msg: T) -> Self {
+		Self::Custom(msg.to_string())
+	}
+}
+
+type Result<T> = std::result::Result<T, DeError>;
+
+/// Deserializes a typed structure from variables of a context.
+///
+/// Variables from parent contexts are included.
+pub fn from_context<'de, T: Deserialize<'de>>(
+	ctx: &'de ApmlContext,
+) -> Result<T> {
+	T::deserialize(ContextDeserializer::new(ctx))
+}
+
+/// Deserializes a typed value from a variable value.
+pub fn from_value<'de, T: Deserialize<'de>>(
+	value: &'de VariableValue,
+) -> Result<T> {
+	T::deserialize(ValueDeserializer::new(value))
+}
+
+/// A [`Deserializer`] reading variables of a [`ApmlContext`] as a map.
+#[derive(Debug, Clone, Copy)]
+pub struct ContextDeserializer<'de> {
+	ctx: &'de ApmlContext,
+}
+
+impl<'de> ContextDeserializer<'de> {
+	/// Creates a deserializer over a context.
+	pub fn new(ctx: &'de ApmlContext) -> Self {
+		Self { ctx }
+	}
+}
+
+impl<'de> Deserializer<'de> for ContextDeserializer<'de> {
+	type Error = DeError;
+
+	fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+		let mut entries = self
+			.ctx
+			.iter()
+			.map(|(name, value)| (name.as_str(), value))
+			.collect::<Vec<_>>();
+		entries.sort_unstable_by_key(|(name, _)| *name);
+		visitor.visit_map(ContextAccess {
+			entries: entries.into_iter(),
+			value: None,
+		})
+	}
+
+	fn deserialize_struct<V: Visitor<'de>>(
+		self,
+		_name: &'static str,
+		fields: &'static [&'static str],
+		visitor: V,
+	) -> Result<V::Value> {
+		// only expose requested variables so `deny_unknown_fields` works
+		let entries = fields
+			.iter()
+			.filter_map(|field| Some((*field, self.ctx.get(field)?)))
+			.collect::<Vec<_>>();
+		visitor.visit_map(ContextAccess {
+			entries: entries.into_iter(),
+			value: None,
+		})
+	}
+
+	fn deserialize_option<V: Visitor<'de>>(
+		self,
+		visitor: V,
+	) -> Result<V::Value> {
+		visitor.visit_some(self)
+	}
+
+	fn deserialize_newtype_struct<V: Visitor<'de>>(
+		self,
+		_name: &'static str,
+		visitor: V,
+	) -> Result<V::Value> {
+		visitor.visit_newtype_struct(self)
+	}
+
+	serde::forward_to_deserialize_any! {
+		bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+		bytes byte_buf unit unit_struct seq tuple tuple_struct map enum
+		identifier ignored_any
+	}
+}
+
+struct ContextAccess<'de> {
+	entries: std::vec::IntoIter<(&'de str, &'de VariableValue)>,
+	value: Option<(&'de str, &'de VariableValue)>,
+}
+
+impl<'de> MapAccess<'de> for ContextAccess<'de> {
+	type Error = DeError;
+
+	fn next_key_seed<K: de::DeserializeSeed<'de>>(
+		&mut self,
+		seed: K,
+	) -> Result<Option<K::Value>> {
+		match self.entries.next() {
+			Some((name, value)) => {
+				self.value = Some((name, value));
+				seed.deserialize(BorrowedStrDeserializer::new(name))
+					.map(Some)
+			}
+			None => Ok(None),
+		}
+	}
+
+	fn next_value_seed<V: de::DeserializeSeed<'de>>(
+		&mut self,
+		seed: V,
+	) -> Result<V::Value> {
+		let (name, value) =
+			self.value.take().expect("value requested before key");
+		seed.deserialize(ValueDeserializer::new(value))
+			.map_err(|err| DeError::Variable {
+				name: name.to_string(),
+				source: Box::new(err),
+			})
+	}
+
+	fn size_hint(&self) -> Option<usize> {
+		Some(self.entries.len())
+	}
+}
+
+/// A [`Deserializer`] reading a [`VariableValue`].
+#[derive(Debug, Clone, Copy)]
+pub struct ValueDeserializer<'de> {
+	value: &'de VariableValue,
+}
+
+impl<'de> ValueDeserializer<'de> {
+	/// Creates a deserializer over a variable value.
+	pub fn new(value: &'de VariableValue) -> Self {
+		Self { value }
+	}
+
+	fn scalar(self) -> ScalarDeserializer<'de> {
+		match self.value {
+			VariableValue::String(text) => {
+				ScalarDeserializer(Cow::Borrowed(text))
+			}
+			VariableValue::Array(_) => {
+				ScalarDeserializer(Cow::Owned(self.value.as_string()))
+			}
+		}
+	}
+}
+
+macro_rules! forward_to_scalar {
+	($($method:ident)*) => {
+		$(
+			fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+				self.scalar().$method(visitor)
+			}
+		)*
+	};
+}
+
+impl<'de> Deserializer<'de> for ValueDeserializer<'de> {
+	type Error = DeError;
+
+	fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+		match self.value {
+			VariableValue::String(text) => visitor.visit_borrowed_str(text),
+			VariableValue::Array(_) => self.deserialize_seq(visitor),
+		}
+	}
+
+	fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+		let elements = match self.value {
+			VariableValue::String(_) => self
+				.value
+				.as_array()
+				.into_iter()
+				.map(|el| ScalarDeserializer(Cow::Owned(el)))
+				.collect::<Vec<_>>(),
+			VariableValue::Array(els) => els
+				.iter()
+				.map(|el| ScalarDeserializer(Cow::Borrowed(el)))
+				.collect(),
+		};
+		let mut seq = SeqDeserializer::new(elements.into_iter());
+		let value = visitor.visit_seq(&mut seq)?;
+		seq.end()?;
+		Ok(value)
+	}
+
+	fn deserialize_tuple<V: Visitor<'de>>(
+		self,
+		_len: usize,
+		visitor: V,
+	) -> Result<V::Value> {
+		self.deserialize_seq(visitor)
+	}
+
+	fn deserialize_tuple_struct<V: Visitor<'de>>(
+		self,
+		_name: &'static str,
+		_len: usize,
+		visitor: V,
+	) -> Result<V::Value> {
+		self.deserialize_seq(visitor)
+	}
+
+	fn deserialize_option<V: Visitor<'de>>(
+		self,
+		visitor: V,
+	) -> Result<V::Value> {
+		if self.value.is_empty() {
+			visitor.visit_none()
+		} else {
+			visitor.visit_some(self)
+		}
+	}
+
+	fn deserialize_newtype_struct<V: Visitor<'de>>(
+		self,
+		_name: &'static str,
+		visitor: V,
+	) -> Result<V::Value> {
+		visitor.visit_newtype_struct(self)
+	}
+
+	fn deserialize_enum<V: Visitor<'de>>(
+		self,
+		name: &'static str,
+		variants: &'static [&'static str],
+		visitor: V,
+	) -> Result<V::Value> {
+		self.scalar().deserialize_enum(name, variants, visitor)
+	}
+
+	forward_to_scalar! {
+		deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32
+		deserialize_i64 deserialize_i128 deserialize_u8 deserialize_u16
+		deserialize_u32 deserialize_u64 deserialize_u128 deserialize_f32
+		deserialize_f64 deserialize_char deserialize_str deserialize_string
+		deserialize_bytes deserialize_byte_buf deserialize_unit
+		deserialize_identifier
+	}
+
+	serde::forward_to_deserialize_any! {
+		unit_struct map struct ignored_any
+	}
+}
+
+/// A [`Deserializer`] reading a single string, parsing it if needed.
+struct ScalarDeserializer<'de>(Cow<'de, str>);
+
+impl ScalarDeserializer<'_> {
+	fn parse<T: std::str::FromStr>(&self, expected: &'static str) -> Result<T> {
+		self.0.trim().parse().map_err(|_| DeError::InvalidValue {
+			value: self.0.to_string(),
+			expected,
+		})
+	}
+}
+
+macro_rules! deserialize_parsed {
+	($($method:ident => $visit:ident, $expected:literal;)*) => {
+		$(
+			fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+				visitor.$visit(self.parse($expected)?)
+			}
+		)*
+	};
+}
+
+impl<'de> Deserializer<'de> for ScalarDeserializer<'de> {
+	type Error = DeError;
+
+	fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+		match self.0 {
+			Cow::Borrowed(text) => visitor.visit_borrowed_str(text),
+			Cow::Owned(text) => visitor.visit_string(text),
+		}
+	}
+
+	fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+		match self.0.trim() {
+			"1" | "y" | "yes" | "true" | "on" => visitor.visit_bool(true),
+			"" | "0" | "n" | "no" | "false" | "off" => {
+				visitor.visit_bool(false)
+			}
+			_ => Err(DeError::InvalidValue {
+				value: self.0.to_string(),
+				expected: "a boolean",
+			}),
+		}
+	}
+
+	deserialize_parsed! {
+		deserialize_i8 => visit_i8, "an integer";
+		deserialize_i16 => visit_i16, "an integer";
+		deserialize_i32 => visit_i32, "an integer";
+		deserialize_i64 => visit_i64, "an integer";
+		deserialize_i128 => visit_i128, "an integer";
+		deserialize_u8 => visit_u8, "an unsigned integer";
+		deserialize_u16 => visit_u16, "an unsigned integer";
+		deserialize_u32 => visit_u32, "an unsigned integer";
+		deserialize_u64 => visit_u64, "an unsigned integer";
+		deserialize_u128 => visit_u128, "an unsigned integer";
+		deserialize_f32 => visit_f32, "a number";
+		deserialize_f64 => visit_f64, "a number";
+		deserialize_char => visit_char, "a character";
+	}
+
+	fn deserialize_option<V: Visitor<'de>>(
+		self,
+		visitor: V,
+	) -> Result<V::Value> {
+		if self.0.is_empty() {
+			visitor.visit_none()
+		} else {
+			visitor.visit_some(self)
+		}
+	}
+
+	fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+		visitor.visit_unit()
+	}
+
+	fn deserialize_newtype_struct<V: Visitor<'de>>(
+		self,
+		_name: &'static str,
+		visitor: V,
+	) -> Result<V::Value> {
+		visitor.visit_newtype_struct(self)
+	}
+
+	fn deserialize_enum<V: Visitor<'de>>(
+		self,
+		_name: &'static str,
+		_variants: &'static [&'static str],
+		visitor: V,
+	) -> Result<V::Value> {
+		let variant: Cow<'de, str> = match self.0 {
+			Cow::Borrowed(text) => Cow::Borrowed(text.trim()),
+			Cow::Owned(text) => Cow::Owned(text.trim().to_string()),
+		};
+		visitor.visit_enum(variant.into_deserializer())
+	}
+
+	serde::forward_to_deserialize_any! {
+		str string bytes byte_buf unit_struct seq tuple tuple_struct map
+		struct identifier ignored_any
+	}
+}
+
+impl<'de> IntoDeserializer<'de, DeError> for ScalarDeserializer<'de> {
+	type Deserializer = Self;
+
+	fn into_deserializer(self) -> Self::Deserializer {
+		self
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use serde::Deserialize;
+
+	use super::*;
+
+	#[derive(Debug, Deserialize, PartialEq, Eq)]
+	#[serde(rename_all = "lowercase")]
+	enum AbType {
+		Autotools,
+		Cmakeninja,
+	}
+
+	#[derive(Debug, Deserialize, PartialEq)]
+	#[allow(non_snake_case)]
+	struct Defines {
+		PKGNAME: String,
+		PKGDEP: Vec<String>,
+		BUILDDEP: Vec<String>,
+		ABHOST: Option<String>,
+		PKGSEC: Option<String>,
+		ABTYPE: AbType,
+		NOSTATIC: bool,
+		REL: u32,
+		#[serde(default)]
+		ABSPLITDBG: bool,
+		SRCS: String,
+	}
+
+	#[test]
+	fn test_from_context() {
+		let ctx = ApmlContext::eval_source(
+			"PKGNAME=foo\nPKGDEP=\"a  b \\\nc\"\nBUILDDEP=()\nPKGSEC=\n\
+			ABTYPE=cmakeninja\nNOSTATIC=0\nREL=2\nSRCS=(tbl::a git::b)",
+		)
+		.unwrap();
+		assert_eq!(
+			from_context::<Defines>(&ctx).unwrap(),
+			Defines {
+				PKGNAME: "foo".to_string(),
+				PKGDEP: vec!["a".to_string(), "b".to_string(), "c".to_string()],
+				BUILDDEP: vec![],
+				ABHOST: None,
+				PKGSEC: None,
+				ABTYPE: AbType::Cmakeninja,
+				NOSTATIC: false,
+				REL: 2,
+				ABSPLITDBG: false,
+				SRCS: "tbl::a git::b".to_string(),
+			}
+		);
+
+		let map =
+			from_context::<std::collections::BTreeMap<String, Vec<String>>>(
+				&ctx,
+			)
+			.unwrap();
+		assert_eq!(map["SRCS"], vec!["tbl::a", "git::b"]);
+		assert_eq!(map["PKGSEC"], Vec::<String>::new());
+	}
+
+	#[test]
+	fn test_from_context_error() {
+		let ctx = ApmlContext::eval_source("PKGNAME=foo").unwrap();
+		assert_eq!(
+			from_context::<Defines>(&ctx).unwrap_err().to_string(),
+			"missing field `PKGDEP`"
+		);
+
+		#[derive(Debug, Deserialize)]
+		#[allow(non_snake_case, dead_code)]
+		struct Spec {
+			REL: u32,
+		}
+		let ctx = ApmlContext::eval_source("REL=a").unwrap();
+		assert_eq!(
+			from_context::<Spec>(&ctx).unwrap_err().to_string(),
+			"Invalid variable REL: Invalid value \"a\", expected an unsigned integer"
+		);
+	}
+
+	#[test]
+	fn test_from_value() {
+		let value = VariableValue::String(" 1 2 3 ".to_string());
+		assert_eq!(from_value::<Vec<u8>>(&value).unwrap(), vec![1, 2, 3]);
+		assert_eq!(
+			from_value::<(String, String, String)>(&value).unwrap(),
+			("1".to_string(), "2".to_string(), "3".to_string())
+		);
+		let value = VariableValue::Array(vec!["a".into(), "b".into()]);
+		assert_eq!(from_value::<String>(&value).unwrap(), "a b");
+		assert!(from_value::<(String,)>(&value).is_err());
+	}
+}
@@ -8,6 +8,16 @@
 //! not exposing too much about styling details.
 //! It basically just allows to add, rewrite and remove existing variable
 //! definitions.
+//!
+//! Comments attached to a definition, as described in
+//! [`DefinitionBlock`][lst::DefinitionBlock], are kept together with it
+//! when it is moved or removed.
+//!
+//! Items of list variables, which are either arrays or strings delimited
+//! with spaces like `PKGDEP`, can be edited in place without touching the
+//! rest of the list, see [`ApmlEditor::insert_list_item`].
+
+use std::sync::Arc;
 
 use super::{
 	ast::{self, AstNode},
@@ -148,24 +158,11 @@ impl<'b> ApmlEditor<'_, 'b> {
 			value: value.lower(),
 		};
 		let token = lst::Token::Variable(definition);
-		if let Some(after) = after
-			&& let Some((index, _)) = self.find_var(after)
-		{
-			let after = self
-				.lst_tokens_iter()
-				.skip(index)
-				.take_while(|token| !matches!(token, lst::Token::Newline))
-				.count();
-			let index = index + after + 1;
-			if index <= self.lst_tokens().len() {
-				self.lst_tokens_mut().insert(index, lst::Token::Newline);
-				self.lst_tokens_mut().insert(index, token);
-				return;
-			}
-		}
-		self.ensure_end_newline();
-		self.lst_tokens_mut().push(token);
-		self.lst_tokens_mut().push(lst::Token::Newline);
+		let at = after
+			.and_then(|after| self.find_var_index(after))
+			.and_then(|index| self.definition_block(index))
+			.map_or(self.lst_tokens().len(), |block| block.trailing.end);
+		self.insert_lines(at, vec![token, lst::Token::Newline]);
 	}
 
 	/// Replace a variable definition.
@@ -203,43 +200,148 @@ impl<'b> ApmlEditor<'_, 'b> {
 	/// The given index must points to a variable definition token.
 	/// After a removal, all indexes are invalidated.
 	///
-	/// Spaces and comments before the most near following newline will
-	/// be stripped. If there is comment before the line of variable definition
-	/// and the variable definition has been followed by two newlines,
-	/// All preceding comments will be stripped as well.
+	/// The whole line of the definition is removed, including inline
+	/// comments, together with its leading and trailing comments.
+	/// Leading comments are always removed, even if the definition is
+	/// directly followed by another one.
+	/// If the definition is surrounded by blank lines, one of them is
+	/// removed as well.
+	///
+	/// If other definitions are on the same line, only the definition
+	/// and the spaces separating it from its neighbour are removed,
+	/// keeping the line and its comments.
 	pub fn remove_var(&mut self, index: usize) {
-		// scan of following spaces and newline
-		let after = self
-			.lst_tokens_iter()
-			.skip(index)
-			.take_while(|token| !matches!(token, lst::Token::Newline))
-			.count();
-		let mut start = index;
+		let Some(block) = self.definition_block(index) else {
+			return;
+		};
+		if block.shared {
+			let tokens = self.lst_tokens();
+			let is_space =
+				|token: &lst::Token| matches!(token, lst::Token::Spacy(_));
+			let after = tokens[index + 1..block.line.end]
+				.iter()
+				.take_while(|token| is_space(token))
+				.count();
+			let next_is_var = matches!(
+				tokens.get(index + 1 + after),
+				Some(lst::Token::Variable(_))
+			);
+			let range = if next_is_var {
+				index..index + 1 + after
+			} else {
+				let before = tokens[block.line.start..index]
+					.iter()
+					.rev()
+					.take_while(|token| is_space(token))
+					.count();
+				index - before..index + 1
+			};
+			self.lst_tokens_mut().drain(range);
+			return;
+		}
+		let mut range = block.range();
 		let tokens = self.lst_tokens();
-		if matches!(tokens.get(index - 2), Some(lst::Token::Comment(_))) {
-			// scan for next line
-			if !tokens
+		let blank_before = range.start == 0
+			|| tokens[..range.start - 1]
 				.iter()
-				.skip(index)
-				.skip_while(|token| !matches!(token, lst::Token::Newline))
-				.skip(1)
+				.rev()
 				.take_while(|token| !matches!(token, lst::Token::Newline))
-				.any(|token| matches!(token, lst::Token::Variable(_)))
-			{
-				// next line is empty, scan for removable comments
-				while matches!(tokens.get(start - 1), Some(lst::Token::Newline))
-					&& matches!(
-						tokens.get(start - 2),
-						Some(lst::Token::Comment(_))
-					) && matches!(
-					tokens.get(start - 3),
-					Some(lst::Token::Newline)
-				) {
-					start -= 2;
-				}
-			}
+				.all(|token| matches!(token, lst::Token::Spacy(_)));
+		if blank_before
+			&& let Some(blank) = tokens[range.end..]
+				.iter()
+				.position(|token| !matches!(token, lst::Token::Spacy(_)))
+			&& matches!(tokens[range.end + blank], lst::Token::Newline)
+		{
+			range.end += blank + 1;
+		}
+		self.lst_tokens_mut().drain(range);
+	}
+
+	/// Moves a variable definition with its attached comments after
+	/// another variable definition and its trailing comments.
+	///
+	/// The given index must points to a variable definition token.
+	/// If `after` is not given or not found, the definition is moved to
+	/// the end. After a move, all indexes are invalidated.
+	///
+	/// If other definitions are on the same line, only the definition is
+	/// moved, onto a line of its own.
+	pub fn move_var(&mut self, index: usize, after: Option<&str>) {
+		let Some(block) = self.definition_block(index) else {
+			return;
+		};
+		let mut lines = if block.shared {
+			vec![self.lst_tokens()[index].clone()]
+		} else {
+			self.lst_tokens()[block.range()].to_vec()
+		};
+		if !matches!(lines.last(), Some(lst::Token::Newline)) {
+			lines.push(lst::Token::Newline);
 		}
-		self.lst_tokens_mut().drain(start..=(index + after));
+		self.remove_var(index);
+		let at = after
+			.and_then(|after| self.find_var_index(after))
+			.and_then(|index| self.definition_block(index))
+			.map_or(self.lst_tokens().len(), |block| block.trailing.end);
+		self.insert_lines(at, lines);
+	}
+
+	/// Inserts lines of tokens at the start of a line.
+	fn insert_lines(&mut self, at: usize, lines: Vec<lst::Token<'b>>) {
+		let at = if at == self.lst_tokens().len() {
+			self.ensure_end_newline();
+			self.lst_tokens().len()
+		} else {
+			at
+		};
+		self.lst_tokens_mut().splice(at..at, lines);
+	}
+
+	/// Returns the tokens of a variable definition and its attached
+	/// comments.
+	///
+	/// The given index must points to a variable definition token.
+	pub fn definition_block(
+		&self,
+		index: usize,
+	) -> Option<lst::DefinitionBlock> {
+		self.0
+			.definition_blocks()
+			.into_iter()
+			.find(|block| block.index == index)
+	}
+
+	/// Returns the leading comments of a variable definition.
+	///
+	/// The given index must points to a variable definition token.
+	pub fn leading_comments(&self, index: usize) -> Vec<&str> {
+		self.definition_block(index)
+			.map(|block| self.comments_in(block.leading))
+			.unwrap_or_default()
+	}
+
+	/// Returns the trailing comments of a variable definition, including
+	/// the inline comment.
+	///
+	/// The given index must points to a variable definition token.
+	pub fn trailing_comments(&self, index: usize) -> Vec<&str> {
+		self.definition_block(index)
+			.map(|block| self.comments_in(block.line.start..block.trailing.end))
+			.unwrap_or_default()
+	}
+
+	fn comments_in(&self, range: std::ops::Range<usize>) -> Vec<&str> {
+		self.0.0[range]
+			.iter()
+			.filter_map(|token| {
+				if let lst::Token::Comment(text) = token {
+					Some(text.as_ref())
+				} else {
+					None
+				}
+			})
+			.collect()
 	}
 
 	/// Iterates over all comment lines.
@@ -252,6 +354,570 @@ impl<'b> ApmlEditor<'_, 'b> {
 			}
 		})
 	}
+
+	/// Returns items of a list variable.
+	///
+	/// Only the first definition of the variable is considered.
+	/// Returns [None] if the variable is not defined or its value is not
+	/// a plain array or string.
+	pub fn list_items(&self, name: &str) -> Option<Vec<String>> {
+		let (_, def) = self.find_var(name)?;
+		Some(ListTokens::parse(&def.value)?.items())
+	}
+
+	/// Inserts an item into a list variable at the given position.
+	///
+	/// Spacing, line wrapping and comments of the list are kept, and the
+	/// separator of the new item is inferred from its neighbours.
+	/// If the index is out of bounds, the item is appended.
+	/// If the variable is not defined, it is defined as a string with
+	/// the item at the end.
+	///
+	/// Only the first definition of the variable is edited.
+	/// Returns `false` if its value is not a plain array or string.
+	pub fn insert_list_item(
+		&mut self,
+		name: &str,
+		index: usize,
+		item: &str,
+	) -> bool {
+		if self.find_var_index(name).is_none() {
+			let definition = lst::VariableDefinition {
+				name: name.to_string().into(),
+				op: lst::VariableOp::Assignment,
+				value: lst::VariableValue::String(Arc::new(lst::Text(vec![
+					lst::TextUnit::DoubleQuote(vec![lst::Word::Literal(
+						lst::LiteralPart::escape(item),
+					)]),
+				]))),
+			};
+			let len = self.lst_tokens().len();
+			self.insert_lines(
+				len,
+				vec![lst::Token::Variable(definition), lst::Token::Newline],
+			);
+			return true;
+		}
+		self.edit_list(name, |list| {
+			list.insert(index, item);
+			true
+		})
+	}
+
+	/// Appends an item to a list variable.
+	///
+	/// See [`ApmlEditor::insert_list_item`] for more.
+	pub fn push_list_item(&mut self, name: &str, item: &str) -> bool {
+		self.insert_list_item(name, usize::MAX, item)
+	}
+
+	/// Removes all occurrences of an item from a list variable.
+	///
+	/// An item alone on its line is removed with the whole line, including
+	/// its inline comment. Other comments are kept.
+	///
+	/// Only the first definition of the variable is edited.
+	/// Returns `false` if the item is not found or the value is not a
+	/// plain array or string.
+	pub fn remove_list_item(&mut self, name: &str, item: &str) -> bool {
+		self.edit_list(name, |list| {
+			let mut removed = false;
+			while let Some(index) = list.position(item) {
+				list.remove(index);
+				removed = true;
+			}
+			removed
+		})
+	}
+
+	/// Replaces all occurrences of an item in a list variable.
+	///
+	/// Only the first definition of the variable is edited.
+	/// Returns `false` if the item is not found or the value is not a
+	/// plain array or string.
+	pub fn replace_list_item(
+		&mut self,
+		name: &str,
+		item: &str,
+		new: &str,
+	) -> bool {
+		self.edit_list(name, |list| {
+			let indexes = list
+				.items()
+				.iter()
+				.enumerate()
+				.filter(|(_, value)| *value == item)
+				.map(|(index, _)| index)
+				.collect::<Vec<_>>();
+			for &index in &indexes {
+				list.replace(index, new);
+			}
+			!indexes.is_empty()
+		})
+	}
+
+	/// Edits the list value of the first definition of a variable.
+	///
+	/// The value is only written back if the editing function returns
+	/// `true`.
+	fn edit_list<F>(&mut self, name: &str, edit: F) -> bool
+	where
+		F: FnOnce(&mut ListTokens<'b>) -> bool,
+	{
+		let Some(index) = self.find_var_index(name) else {
+			return false;
+		};
+		let lst::Token::Variable(def) = &mut self.lst_tokens_mut()[index]
+		else {
+			unreachable!()
+		};
+		let Some(mut list) = ListTokens::parse(&def.value) else {
+			return false;
+		};
+		if !edit(&mut list) {
+			return false;
+		}
+		def.value = list.lower();
+		true
+	}
+}
+
+/// Tokens of a list value.
+enum ListTokens<'a> {
+	Array(Vec<lst::ArrayToken<'a>>),
+	String {
+		/// Whether the text was unquoted or empty.
+		unquoted: bool,
+		tokens: Vec<StringListToken<'a>>,
+	},
+}
+
+/// A token in a string delimited with spaces.
+#[derive(Debug, Clone)]
+enum StringListToken<'a> {
+	Space(char),
+	Newline,
+	LineContinuation,
+	Item(Vec<lst::Word<'a>>),
+}
+
+impl<'a> ListTokens<'a> {
+	fn parse(value: &lst::VariableValue<'a>) -> Option<Self> {
+		let text = match value {
+			lst::VariableValue::Array(tokens) => {
+				return Some(Self::Array(tokens.clone()));
+			}
+			lst::VariableValue::String(text) => text,
+		};
+		let (unquoted, words) = match text.0.as_slice() {
+			[] => (true, [].as_slice()),
+			[lst::TextUnit::Unquoted(words)] => (true, words.as_slice()),
+			[lst::TextUnit::DoubleQuote(words)] => (false, words.as_slice()),
+			_ => return None,
+		};
+		let mut tokens = Vec::new();
+		let mut item = Vec::new();
+		let mut flush = |item: &mut Vec<lst::Word<'a>>, token| {
+			if !item.is_empty() {
+				tokens.push(StringListToken::Item(std::mem::take(item)));
+			}
+			if let Some(token) = token {
+				tokens.push(token);
+			}
+		};
+		for word in words {
+			let lst::Word::Literal(parts) = word else {
+				item.push(word.clone());
+				continue;
+			};
+			for part in parts {
+				match part {
+					lst::LiteralPart::String(text) => {
+						for ch in text.chars() {
+							match ch {
+								' ' | '\t' => flush(
+									&mut item,
+									Some(StringListToken::Space(ch)),
+								),
+								'\n' => flush(
+									&mut item,
+									Some(StringListToken::Newline),
+								),
+								_ => push_literal_part(
+									&mut item,
+									lst::LiteralPart::String(
+										ch.to_string().into(),
+									),
+								),
+							}
+						}
+					}
+					lst::LiteralPart::Escaped(_) => {
+						push_literal_part(&mut item, part.clone())
+					}
+					lst::LiteralPart::LineContinuation => flush(
+						&mut item,
+						Some(StringListToken::LineContinuation),
+					),
+				}
+			}
+		}
+		flush(&mut item, None);
+		Some(Self::String { unquoted, tokens })
+	}
+
+	fn lower(self) -> lst::VariableValue<'a> {
+		let (unquoted, tokens) = match self {
+			Self::Array(tokens) => return lst::VariableValue::Array(tokens),
+			Self::String { unquoted, tokens } => (unquoted, tokens),
+		};
+		let mut words = Vec::new();
+		for token in &tokens {
+			match token {
+				StringListToken::Space(ch) => push_literal_part(
+					&mut words,
+					lst::LiteralPart::String(ch.to_string().into()),
+				),
+				StringListToken::Newline => push_literal_part(
+					&mut words,
+					lst::LiteralPart::String("\n".into()),
+				),
+				StringListToken::LineContinuation => push_literal_part(
+					&mut words,
+					lst::LiteralPart::LineContinuation,
+				),
+				StringListToken::Item(item) => {
+					for word in item {
+						if let lst::Word::Literal(parts) = word {
+							for part in parts {
+								push_literal_part(&mut words, part.clone());
+							}
+						} else {
+							words.push(word.clone());
+						}
+					}
+				}
+			}
+		}
+		let text = match tokens.as_slice() {
+			[] if unquoted => lst::Text(vec![]),
+			[StringListToken::Item(_)] if unquoted => {
+				lst::Text(vec![lst::TextUnit::Unquoted(words)])
+			}
+			_ => lst::Text(vec![lst::TextUnit::DoubleQuote(words)]),
+		};
+		lst::VariableValue::String(Arc::new(text))
+	}
+
+	fn items(&self) -> Vec<String> {
+		match self {
+			Self::Array(tokens) => list_items(tokens)
+				.into_iter()
+				.map(|idx| tokens[idx].item_value())
+				.collect(),
+			Self::String { tokens, .. } => list_items(tokens)
+				.into_iter()
+				.map(|idx| tokens[idx].item_value())
+				.collect(),
+		}
+	}
+
+	fn position(&self, item: &str) -> Option<usize> {
+		self.items().iter().position(|value| value == item)
+	}
+
+	fn insert(&mut self, index: usize, item: &str) {
+		match self {
+			Self::Array(tokens) => {
+				list_insert(tokens, index, lst::ArrayToken::new_item(item))
+			}
+			Self::String { tokens, .. } => {
+				list_insert(tokens, index, StringListToken::new_item(item))
+			}
+		}
+	}
+
+	fn remove(&mut self, index: usize) {
+		match self {
+			Self::Array(tokens) => list_remove(tokens, index),
+			Self::String { tokens, .. } => list_remove(tokens, index),
+		}
+	}
+
+	fn replace(&mut self, index: usize, item: &str) {
+		match self {
+			Self::Array(tokens) => {
+				let idx = list_items(tokens)[index];
+				tokens[idx] = lst::ArrayToken::new_item(item);
+			}
+			Self::String { tokens, .. } => {
+				let idx = list_items(tokens)[index];
+				tokens[idx] = StringListToken::new_item(item);
+			}
+		}
+	}
+}
+
+/// Pushes a literal part to words, merging it into the last literal.
+fn push_literal_part<'a>(
+	words: &mut Vec<lst::Word<'a>>,
+	part: lst::LiteralPart<'a>,
+) {
+	let Some(lst::Word::Literal(parts)) = words.last_mut() else {
+		words.push(lst::Word::Literal(vec![part]));
+		return;
+	};
+	if let lst::LiteralPart::String(text) = &part
+		&& let Some(lst::LiteralPart::String(last)) = parts.last_mut()
+	{
+		last.to_mut().push_str(text);
+	} else {
+		parts.push(part);
+	}
+}
+
+/// Returns the unescaped value of words.
+fn words_value(words: &[lst::Word]) -> String {
+	let mut result = String::new();
+	for word in words {
+		let lst::Word::Literal(parts) = word else {
+			result.push_str(&word.to_string());
+			continue;
+		};
+		for part in parts {
+			match part {
+				lst::LiteralPart::String(text) => result.push_str(text),
+				lst::LiteralPart::Escaped(ch) => result.push(*ch),
+				lst::LiteralPart::LineContinuation => {}
+			}
+		}
+	}
+	result
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ListTokenKind {
+	Space,
+	Break,
+	Comment,
+	Item,
+}
+
+/// A token of a list value.
+trait ListToken: Clone {
+	fn kind(&self) -> ListTokenKind;
+
+	fn space() -> Self;
+
+	fn new_item(value: &str) -> Self;
+
+	fn item_value(&self) -> String;
+}
+
+impl ListToken for lst::ArrayToken<'_> {
+	fn kind(&self) -> ListTokenKind {
+		match self {
+			lst::ArrayToken::Spacy(_) => ListTokenKind::Space,
+			lst::ArrayToken::Newline => ListTokenKind::Break,
+			lst::ArrayToken::Comment(_) => ListTokenKind::Comment,
+			lst::ArrayToken::Element(_) => ListTokenKind::Item,
+		}
+	}
+
+	fn space() -> Self {
+		Self::Spacy(' ')
+	}
+
+	fn new_item(value: &str) -> Self {
+		let words = vec![lst::Word::Literal(lst::LiteralPart::escape(value))];
+		// plain words are kept unquoted, like most existing elements
+		let unit = if is_plain_word(value) {
+			lst::TextUnit::Unquoted(words)
+		} else {
+			lst::TextUnit::DoubleQuote(words)
+		};
+		Self::Element(Arc::new(lst::Text(vec![unit])))
+	}
+
+	fn item_value(&self) -> String {
+		let Self::Element(text) = self else {
+			return String::new();
+		};
+		text.0
+			.iter()
+			.map(|unit| match unit {
+				lst::TextUnit::Unquoted(words)
+				| lst::TextUnit::DoubleQuote(words) => words_value(words),
+				lst::TextUnit::SingleQuote(text) => text.to_string(),
+			})
+			.collect()
+	}
+}
+
+impl ListToken for StringListToken<'_> {
+	fn kind(&self) -> ListTokenKind {
+		match self {
+			Self::Space(_) => ListTokenKind::Space,
+			Self::Newline | Self::LineContinuation => ListTokenKind::Break,
+			Self::Item(_) => ListTokenKind::Item,
+		}
+	}
+
+	fn space() -> Self {
+		Self::Space(' ')
+	}
+
+	fn new_item(value: &str) -> Self {
+		Self::Item(vec![lst::Word::Literal(lst::LiteralPart::escape(value))])
+	}
+
+	fn item_value(&self) -> String {
+		if let Self::Item(words) = self {
+			words_value(words)
+		} else {
+			String::new()
+		}
+	}
+}
+
+/// Returns if a value can be written as a unquoted word.
+fn is_plain_word(value: &str) -> bool {
+	!value.is_empty()
+		&& value.chars().all(|ch| {
+			ch.is_ascii_alphanumeric()
+				|| matches!(ch, '+' | '-' | '.' | '_' | '/' | ':' | '@' | ',')
+		})
+}
+
+/// Returns indexes of item tokens.
+fn list_items<T: ListToken>(tokens: &[T]) -> Vec<usize> {
+	(0..tokens.len())
+		.filter(|&idx| tokens[idx].kind() == ListTokenKind::Item)
+		.collect()
+}
+
+/// Returns the separator between two items, without comments and
+/// blank lines.
+fn list_separator<T: ListToken>(separator: &[T]) -> Vec<T> {
+	let Some(newline) = separator
+		.iter()
+		.rposition(|token| token.kind() == ListTokenKind::Break)
+	else {
+		return separator.to_vec();
+	};
+	let start = separator[..newline]
+		.iter()
+		.rposition(|token| token.kind() != ListTokenKind::Space)
+		.map_or(0, |idx| idx + 1);
+	separator[start..].to_vec()
+}
+
+fn list_insert<T: ListToken>(tokens: &mut Vec<T>, index: usize, item: T) {
+	let items = list_items(tokens);
+	let len = items.len();
+	let index = index.min(len);
+	if len == 0 {
+		tokens.insert(0, item);
+		return;
+	}
+	let separator =
+		|idx: usize| list_separator(&tokens[items[idx] + 1..items[idx + 1]]);
+	let separator = if index > 0 && index < len {
+		separator(index - 1)
+	} else if len > 1 && index == 0 {
+		separator(0)
+	} else if len > 1 {
+		separator(len - 2)
+	} else if tokens[..items[0]]
+		.iter()
+		.any(|token| token.kind() == ListTokenKind::Break)
+	{
+		list_separator(&tokens[..items[0]])
+	} else {
+		vec![T::space()]
+	};
+	if index < len {
+		let at = items[index];
+		tokens.splice(at..at, std::iter::once(item).chain(separator));
+	} else {
+		// keep the inline comment of the last item on its line
+		let mut at = items[len - 1] + 1;
+		if separator
+			.iter()
+			.any(|token| token.kind() == ListTokenKind::Break)
+		{
+			let mut end = at;
+			while end < tokens.len()
+				&& tokens[end].kind() == ListTokenKind::Space
+			{
+				end += 1;
+			}
+			if end < tokens.len()
+				&& tokens[end].kind() == ListTokenKind::Comment
+			{
+				at = end + 1;
+			}
+		}
+		tokens.splice(at..at, separator.into_iter().chain([item]));
+	}
+}
+
+fn list_remove<T: ListToken>(tokens: &mut Vec<T>, index: usize) {
+	let kind = |idx: usize| tokens[idx].kind();
+	let pos = list_items(tokens)[index];
+	let mut start = pos;
+	while start > 0 && kind(start - 1) == ListTokenKind::Space {
+		start -= 1;
+	}
+	let mut end = pos + 1;
+	while end < tokens.len() && kind(end) == ListTokenKind::Space {
+		end += 1;
+	}
+	let commented = end < tokens.len() && kind(end) == ListTokenKind::Comment;
+	let line_end = if commented { end + 1 } else { end };
+	let at_line_end =
+		line_end == tokens.len() || kind(line_end) == ListTokenKind::Break;
+	let range = if at_line_end
+		&& line_end < tokens.len()
+		&& (start == 0 || kind(start - 1) == ListTokenKind::Break)
+	{
+		// the item is alone on its line
+		if start > 0 {
+			start..line_end + 1
+		} else if commented {
+			pos..end
+		} else {
+			let mut end = line_end + 1;
+			while end < tokens.len() && kind(end) == ListTokenKind::Space {
+				end += 1;
+			}
+			0..end
+		}
+	} else if at_line_end {
+		// join with the previous item, but never into a comment
+		let mut start = pos;
+		while start > 0 {
+			match kind(start - 1) {
+				ListTokenKind::Space => start -= 1,
+				ListTokenKind::Break
+					if tokens[..start - 1]
+						.iter()
+						.rev()
+						.find(|token| token.kind() != ListTokenKind::Space)
+						.is_none_or(|token| {
+							token.kind() != ListTokenKind::Comment
+						}) =>
+				{
+					start -= 1
+				}
+				_ => break,
+			}
+		}
+		start..pos + 1
+	} else {
+		pos..end
+	};
+	tokens.drain(range);
 }
 
 #[cfg(test)]
@@ -349,6 +1015,48 @@ mod test {
 		let mut editor = ApmlEditor::wrap(&mut lst);
 		editor.remove_var(editor.find_var("b").unwrap().0);
 		assert_eq!(lst.to_string(), "a=b # a\n\n# a\nc=\"$1\"");
+		// leading comments are removed even if a definition follows
+		let mut lst = ApmlLst::parse("a=b\n# b\nb=c\nc=d\n").unwrap();
+		let mut editor = ApmlEditor::wrap(&mut lst);
+		editor.remove_var(editor.find_var("b").unwrap().0);
+		assert_eq!(lst.to_string(), "a=b\nc=d\n");
+	}
+
+	#[test]
+	fn test_shared_line() {
+		let src = "# x\na=1 b=2 # y\nc=3\n";
+		let mut lst = ApmlLst::parse(src).unwrap();
+		let mut editor = ApmlEditor::wrap(&mut lst);
+		let index = editor.find_var_index("a").unwrap();
+		assert_eq!(index, 2);
+		assert_eq!(editor.leading_comments(index), vec![" x"]);
+		editor.remove_var(index);
+		assert_eq!(lst.to_string(), "# x\nb=2 # y\nc=3\n");
+
+		let mut lst = ApmlLst::parse(src).unwrap();
+		let mut editor = ApmlEditor::wrap(&mut lst);
+		editor.remove_var(editor.find_var_index("b").unwrap());
+		assert_eq!(lst.to_string(), "# x\na=1 # y\nc=3\n");
+
+		let mut lst = ApmlLst::parse("a=1 b=2\nc=3").unwrap();
+		let mut editor = ApmlEditor::wrap(&mut lst);
+		assert!(editor.definition_block(0).is_some());
+		editor.remove_var(0);
+		assert_eq!(lst.to_string(), "b=2\nc=3");
+
+		let mut lst = ApmlLst::parse(src).unwrap();
+		let mut editor = ApmlEditor::wrap(&mut lst);
+		editor.move_var(editor.find_var_index("a").unwrap(), Some("c"));
+		assert_eq!(lst.to_string(), "# x\nb=2 # y\nc=3\na=1\n");
+
+		let mut lst = ApmlLst::parse(src).unwrap();
+		let mut editor = ApmlEditor::wrap(&mut lst);
+		editor.append_var_ast(
+			"d".to_string(),
+			&ast::VariableValue::String("4".into()),
+			Some("a"),
+		);
+		assert_eq!(lst.to_string(), "# x\na=1 b=2 # y\nd=\"4\"\nc=3\n");
 	}
 
 	#[test]
@@ -358,4 +1066,105 @@ mod test {
 		let editor = ApmlEditor::wrap(&mut lst);
 		assert_eq!(editor.comments().count(), 4);
 	}
+
+	#[test]
+	fn test_comment_attachment() {
+		let src = "# header\n\n# about a\na=1 # a\n# more a\n\nb=2\n";
+		let mut lst = ApmlLst::parse(src).unwrap();
+		let editor = ApmlEditor::wrap(&mut lst);
+		let index = editor.find_var_index("a").unwrap();
+		assert_eq!(editor.leading_comments(index), vec![" about a"]);
+		assert_eq!(editor.trailing_comments(index), vec![" a", " more a"]);
+		let index = editor.find_var_index("b").unwrap();
+		assert!(editor.leading_comments(index).is_empty());
+
+		let mut lst = ApmlLst::parse(src).unwrap();
+		let mut editor = ApmlEditor::wrap(&mut lst);
+		editor.append_var_ast(
+			"c".to_string(),
+			&ast::VariableValue::String("3".into()),
+			Some("a"),
+		);
+		assert_eq!(
+			lst.to_string(),
+			"# header\n\n# about a\na=1 # a\n# more a\nc=\"3\"\n\nb=2\n"
+		);
+
+		let mut lst = ApmlLst::parse(src).unwrap();
+		let mut editor = ApmlEditor::wrap(&mut lst);
+		editor.remove_var(editor.find_var_index("a").unwrap());
+		assert_eq!(lst.to_string(), "# header\n\nb=2\n");
+
+		let mut lst = ApmlLst::parse("# x\na=1\nb=2").unwrap();
+		let mut editor = ApmlEditor::wrap(&mut lst);
+		editor.remove_var(editor.find_var_index("a").unwrap());
+		assert_eq!(lst.to_string(), "b=2");
+	}
+
+	#[test]
+	fn test_move_var() {
+		let src = "# about a\na=1 # a\n\n# about b\nb=2\n# more b\n\nc=3";
+		let mut lst = ApmlLst::parse(src).unwrap();
+		let mut editor = ApmlEditor::wrap(&mut lst);
+		editor.move_var(editor.find_var_index("a").unwrap(), Some("b"));
+		assert_eq!(
+			lst.to_string(),
+			"# about b\nb=2\n# more b\n# about a\na=1 # a\n\nc=3"
+		);
+
+		let mut lst = ApmlLst::parse(src).unwrap();
+		let mut editor = ApmlEditor::wrap(&mut lst);
+		editor.move_var(editor.find_var_index("b").unwrap(), None);
+		assert_eq!(
+			lst.to_string(),
+			"# about a\na=1 # a\n\nc=3\n# about b\nb=2\n# more b\n"
+		);
+	}
+
+	#[test]
+	fn test_list_items() {
+		let src = "PKGDEP=\"a b \\\n\tc \\\n\td\"\nBUILDDEP=(\n\tx # x\n\t# y\n\ty\n)";
+		let mut lst = ApmlLst::parse(src).unwrap();
+		let mut editor = ApmlEditor::wrap(&mut lst);
+		assert_eq!(
+			editor.list_items("PKGDEP").unwrap(),
+			vec!["a", "b", "c", "d"]
+		);
+		assert_eq!(editor.list_items("BUILDDEP").unwrap(), vec!["x", "y"]);
+		assert!(editor.list_items("ABTYPE").is_none());
+
+		assert!(editor.remove_list_item("PKGDEP", "c"));
+		assert!(!editor.remove_list_item("PKGDEP", "c"));
+		assert!(editor.push_list_item("PKGDEP", "e"));
+		assert!(editor.insert_list_item("PKGDEP", 1, "$f"));
+		assert!(editor.replace_list_item("PKGDEP", "a", "g"));
+		assert_eq!(
+			editor.list_items("PKGDEP").unwrap(),
+			vec!["g", "$f", "b", "d", "e"]
+		);
+		assert!(editor.replace_list_item("PKGDEP", "g", "g"));
+		assert!(!editor.replace_list_item("PKGDEP", "h", "h"));
+		assert!(editor.remove_list_item("BUILDDEP", "x"));
+		assert!(editor.push_list_item("BUILDDEP", "z"));
+		assert!(editor.insert_list_item("BUILDDEP", 0, "w"));
+		assert!(editor.push_list_item("ABTYPE", "self"));
+		assert_eq!(
+			lst.to_string(),
+			"PKGDEP=\"g \\$f b \\\n\td \\\n\te\"\nBUILDDEP=(\n\t# y\n\tw\n\ty\n\tz\n)\nABTYPE=\"self\"\n"
+		);
+
+		let mut lst =
+			ApmlLst::parse("A=\"a b # c\"\nB=(a b # c\n)\nC=a").unwrap();
+		let mut editor = ApmlEditor::wrap(&mut lst);
+		assert_eq!(editor.list_items("A").unwrap(), vec!["a", "b", "#", "c"]);
+		assert!(editor.remove_list_item("B", "b"));
+		assert!(editor.push_list_item("B", "d"));
+		assert!(editor.push_list_item("B", "$e f"));
+		assert!(editor.push_list_item("C", "b"));
+		assert!(editor.remove_list_item("A", "a"));
+		assert_eq!(
+			lst.to_string(),
+			"A=\"b # c\"\nB=(a d \"\\$e f\" # c\n)\nC=\"a b\""
+		);
+	}
 }
@@ -1,17 +1,65 @@
 //! APML expression evaluator.
 
-use std::cmp::min;
+use std::{cmp::min, collections::HashMap, ops::Range};
 
 use thiserror::Error;
 
-use super::{ApmlContext, VariableValue, ast};
+use super::{
+	ApmlContext, VariableValue, ast, lst,
+	pattern::{BashPattern, PatternCache},
+	span::{SourceLocation, Span},
+	subcommand::eval_subcommand,
+};
 
 #[derive(Error, Debug)]
 pub enum EvalError {
 	#[error("Glob-as-regex error: {0}")]
 	RegexError(#[from] regex::Error),
-	#[error("Required variable is unset: {0}")]
-	Unset(String),
+	#[error("Arithmetic error: {0}")]
+	ArithmeticError(String),
+	#[error(
+		"Required variable {name} is unset: {message}{}",
+		display_location(.location)
+	)]
+	Unset {
+		/// Name of the variable.
+		name: String,
+		/// Message given in the `${name:?message}` expansion.
+		message: String,
+		/// Span of the expansion.
+		span: Span,
+		/// Location of the expansion, if resolved.
+		location: Option<SourceLocation>,
+	},
+}
+
+impl EvalError {
+	/// Returns the span of the source causing the error, if available.
+	#[must_use]
+	pub fn span(&self) -> Option<Span> {
+		match self {
+			EvalError::RegexError(_) | EvalError::ArithmeticError(_) => None,
+			EvalError::Unset { span, .. } => Some(*span),
+		}
+	}
+
+	/// Resolves the location of the error in the given source text.
+	#[must_use]
+	pub fn locate(mut self, file: Option<&str>, src: &str) -> Self {
+		if let EvalError::Unset { span, location, .. } = &mut self {
+			let mut resolved = SourceLocation::from_offset(src, span.start);
+			resolved.file = file.map(str::to_string);
+			*location = Some(resolved);
+		}
+		self
+	}
+}
+
+fn display_location(location: &Option<SourceLocation>) -> String {
+	location
+		.as_ref()
+		.map(|location| format!(" (at {location})"))
+		.unwrap_or_default()
 }
 
 type Result<T> = std::result::Result<T, EvalError>;
@@ -24,30 +72,307 @@ pub fn eval_ast(apml: &mut ApmlContext, tree: &ast::ApmlAst) -> Result<()> {
 	Ok(())
 }
 
+/// Evaluates a APML AST, recording provenance of evaluated values.
+///
+/// This is slower than [`eval_ast`] as words are evaluated twice.
+pub fn eval_ast_traced(
+	apml: &mut ApmlContext,
+	tree: &ast::ApmlAst,
+) -> Result<EvalTrace> {
+	let mut trace = EvalTrace::default();
+	let ast::ApmlAst(defs) = tree;
+	for (index, def) in defs.iter().enumerate() {
+		let origin = Origin {
+			def: index,
+			name: def.name.to_string(),
+			span: def.span,
+		};
+		let value_trace =
+			trace_variable_value(apml, &trace, &def.value, &origin)?;
+		eval_variable_def(apml, def)?;
+		trace.variables.insert(origin.name, value_trace);
+	}
+	Ok(trace)
+}
+
+/// A variable definition contributing to a evaluated value.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Origin {
+	/// Index of the variable definition in the AST.
+	///
+	/// As each definition is emitted from one variable token, this is also
+	/// the index among variable tokens of the LST.
+	pub def: usize,
+	/// Name of the defined variable.
+	pub name: String,
+	/// Span of the variable definition.
+	pub span: Span,
+}
+
+impl Origin {
+	/// Returns the index of the variable token in the LST.
+	#[must_use]
+	pub fn token_index(&self, lst: &lst::ApmlLst) -> Option<usize> {
+		lst.0
+			.iter()
+			.enumerate()
+			.filter(|(_, token)| matches!(token, lst::Token::Variable(_)))
+			.nth(self.def)
+			.map(|(index, _)| index)
+	}
+}
+
+/// Provenance of a evaluated variable value.
+///
+/// Values inherited from a parent context or undefined variables
+/// have no origins recorded.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ValueTrace {
+	/// Definitions contributing to the value, in order of contribution.
+	pub origins: Vec<Origin>,
+	/// Origins of byte ranges of a string value.
+	///
+	/// Ranges are sorted and never overlap, but may leave gaps.
+	pub segments: Vec<(Range<usize>, Vec<Origin>)>,
+	/// Origins of each element of an array value.
+	pub elements: Vec<Vec<Origin>>,
+}
+
+impl ValueTrace {
+	/// Returns origins of the byte at the given offset of a string value.
+	#[must_use]
+	pub fn origins_at(&self, offset: usize) -> &[Origin] {
+		self.segments
+			.iter()
+			.find(|(range, _)| range.contains(&offset))
+			.map_or(&[], |(_, origins)| origins.as_slice())
+	}
+
+	/// Returns origins of a byte range of a string value.
+	#[must_use]
+	pub fn origins_in(&self, range: Range<usize>) -> Vec<&Origin> {
+		let mut result = Vec::new();
+		for (segment, origins) in &self.segments {
+			if segment.start < range.end && range.start < segment.end {
+				for origin in origins {
+					if !result.contains(&origin) {
+						result.push(origin);
+					}
+				}
+			}
+		}
+		result
+	}
+
+	/// Returns segments of the value as a string.
+	///
+	/// Array values are joined with spaces, like
+	/// [`VariableValue::as_string`].
+	fn string_segments(
+		&self,
+		value: &VariableValue,
+	) -> Vec<(Range<usize>, Vec<Origin>)> {
+		match value {
+			VariableValue::String(_) => self.segments.clone(),
+			VariableValue::Array(elements) => {
+				let mut result = Vec::new();
+				let mut offset = 0;
+				for (element, origins) in elements.iter().zip(&self.elements) {
+					push_segment(
+						&mut result,
+						offset..offset + element.len(),
+						origins.clone(),
+					);
+					offset += element.len() + 1;
+				}
+				result
+			}
+		}
+	}
+}
+
+/// Provenance of variables evaluated by [`eval_ast_traced`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct EvalTrace {
+	variables: HashMap<String, ValueTrace>,
+}
+
+impl EvalTrace {
+	/// Gets the provenance of a variable.
+	#[must_use]
+	pub fn get(&self, name: &str) -> Option<&ValueTrace> {
+		self.variables.get(name)
+	}
+
+	/// Iterates over provenance of all variables.
+	pub fn iter(&self) -> impl Iterator<Item = (&String, &ValueTrace)> {
+		self.variables.iter()
+	}
+}
+
+fn trace_variable_value(
+	apml: &ApmlContext,
+	trace: &EvalTrace,
+	value: &ast::VariableValue,
+	origin: &Origin,
+) -> Result<ValueTrace> {
+	let mut result = ValueTrace::default();
+	match value {
+		ast::VariableValue::String(text) => {
+			result.segments = trace_text(apml, trace, text, origin)?;
+			for (_, origins) in &result.segments {
+				merge_origins(&mut result.origins, origins);
+			}
+		}
+		ast::VariableValue::Array(elements) => {
+			for element in elements {
+				match element {
+					ast::ArrayElement::ArrayInclusion(name, _) => {
+						let count = apml.read(name.as_ref()).into_array().len();
+						match trace.get(name) {
+							Some(included)
+								if included.elements.len() == count =>
+							{
+								result
+									.elements
+									.extend_from_slice(&included.elements)
+							}
+							Some(included) => {
+								result.elements.extend(std::iter::repeat_n(
+									included.origins.clone(),
+									count,
+								))
+							}
+							None => result
+								.elements
+								.extend(std::iter::repeat_n(Vec::new(), count)),
+						}
+					}
+					ast::ArrayElement::Text(text, _) => {
+						let mut origins = Vec::new();
+						for (_, segment) in
+							trace_text(apml, trace, text, origin)?
+						{
+							merge_origins(&mut origins, &segment);
+						}
+						result.elements.push(origins);
+					}
+				}
+			}
+			for origins in &result.elements {
+				merge_origins(&mut result.origins, origins);
+			}
+		}
+	}
+	Ok(result)
+}
+
+fn trace_text(
+	apml: &ApmlContext,
+	trace: &EvalTrace,
+	text: &ast::Text,
+	origin: &Origin,
+) -> Result<Vec<(Range<usize>, Vec<Origin>)>> {
+	let mut segments = Vec::new();
+	let mut offset = 0;
+	for word in &text.0 {
+		let len = eval_word(apml, word, &mut false)?.len();
+		match word {
+			ast::Word::Variable(expansion) => {
+				let expanded = trace.get(expansion.name.as_ref());
+				if expansion.modifier.is_none() {
+					// origins of the expanded value are kept in place
+					if let Some(expanded) = expanded {
+						let value = apml.read(expansion.name.as_ref());
+						for (range, origins) in expanded.string_segments(&value)
+						{
+							push_segment(
+								&mut segments,
+								range.start + offset..range.end + offset,
+								origins,
+							);
+						}
+					}
+				} else {
+					let mut origins = vec![origin.clone()];
+					if let Some(expanded) = expanded {
+						merge_origins(&mut origins, &expanded.origins);
+					}
+					push_segment(&mut segments, offset..offset + len, origins);
+				}
+			}
+			ast::Word::Literal(..)
+			| ast::Word::Subcommand(..)
+			| ast::Word::Arithmetic(..) => push_segment(
+				&mut segments,
+				offset..offset + len,
+				vec![origin.clone()],
+			),
+		}
+		offset += len;
+	}
+	Ok(segments)
+}
+
+/// Appends a segment, merging it with the previous one if possible.
+fn push_segment(
+	segments: &mut Vec<(Range<usize>, Vec<Origin>)>,
+	range: Range<usize>,
+	origins: Vec<Origin>,
+) {
+	if range.is_empty() {
+		return;
+	}
+	if let Some((last, last_origins)) = segments.last_mut()
+		&& last.end == range.start
+		&& *last_origins == origins
+	{
+		last.end = range.end;
+	} else {
+		segments.push((range, origins));
+	}
+}
+
+/// Appends origins which are not included yet.
+fn merge_origins(origins: &mut Vec<Origin>, other: &[Origin]) {
+	for origin in other {
+		if !origins.contains(origin) {
+			origins.push(origin.clone());
+		}
+	}
+}
+
 #[inline]
 fn eval_variable_def(
 	apml: &mut ApmlContext,
 	def: &ast::VariableDefinition,
 ) -> Result<()> {
 	let name = def.name.to_string();
-	let value = eval_variable_value(apml, &def.value)?;
-	apml.variables.insert(name, value);
+	let mut unevaluable = false;
+	let value = eval_variable_value(apml, &def.value, &mut unevaluable)?;
+	apml.variables.insert(name.clone(), value);
+	apml.mark_unevaluable(&name, unevaluable);
 	Ok(())
 }
 
+/// Evaluates a variable value.
+///
+/// `unevaluable` is set if the value contains sub-commands which are not
+/// evaluated or refers to unevaluable variables.
 #[inline]
 fn eval_variable_value(
 	apml: &ApmlContext,
 	value: &ast::VariableValue,
+	unevaluable: &mut bool,
 ) -> Result<VariableValue> {
 	match value {
-		ast::VariableValue::String(text) => {
-			Ok(VariableValue::String(eval_text(apml, text)?))
-		}
+		ast::VariableValue::String(text) => Ok(VariableValue::String(
+			eval_text_checked(apml, text, unevaluable)?,
+		)),
 		ast::VariableValue::Array(element) => {
 			let mut result = Vec::new();
 			for element in element {
-				eval_array_element(apml, element, &mut result)?;
+				eval_array_element(apml, element, &mut result, unevaluable)?;
 			}
 			Ok(VariableValue::Array(result))
 		}
@@ -59,50 +384,81 @@ fn eval_array_element(
 	apml: &ApmlContext,
 	element: &ast::ArrayElement,
 	values: &mut Vec<String>,
+	unevaluable: &mut bool,
 ) -> Result<()> {
 	match element {
-		ast::ArrayElement::ArrayInclusion(name) => {
+		ast::ArrayElement::ArrayInclusion(name, _) => {
 			// expand array elements
-			values.append(
-				&mut apml
-					.variables
-					.get(name.as_ref())
-					.cloned()
-					.unwrap_or_default()
-					.into_array(),
-			);
+			*unevaluable |= apml.is_unevaluable(name);
+			values.append(&mut apml.read(name.as_ref()).into_array());
 			Ok(())
 		}
-		ast::ArrayElement::Text(text) => {
-			values.push(eval_text(apml, text)?);
+		ast::ArrayElement::Text(text, _) => {
+			values.push(eval_text_checked(apml, text, unevaluable)?);
 			Ok(())
 		}
 	}
 }
 
 pub fn eval_text(apml: &ApmlContext, text: &ast::Text) -> Result<String> {
+	eval_text_checked(apml, text, &mut false)
+}
+
+/// Evaluates a text, setting `unevaluable` if the text contains sub-commands
+/// which are not evaluated or refers to unevaluable variables.
+pub(crate) fn eval_text_checked(
+	apml: &ApmlContext,
+	text: &ast::Text,
+	unevaluable: &mut bool,
+) -> Result<String> {
 	let mut result = String::new();
 	let ast::Text(words) = text;
 	for word in words {
-		result.push_str(&eval_word(apml, word)?);
+		result.push_str(&eval_word(apml, word, unevaluable)?);
 	}
 	Ok(result)
 }
 
 #[inline]
-fn eval_word(apml: &ApmlContext, word: &ast::Word) -> Result<String> {
+fn eval_word(
+	apml: &ApmlContext,
+	word: &ast::Word,
+	unevaluable: &mut bool,
+) -> Result<String> {
 	match word {
-		ast::Word::Literal(text) | ast::Word::Subcommand(text) => {
-			Ok(text.to_string())
+		ast::Word::Literal(text, _) => Ok(text.to_string()),
+		ast::Word::Subcommand(text, _) => {
+			if apml.eval_subcommands()
+				&& let Some(output) = eval_subcommand(apml, text)?
+			{
+				Ok(output)
+			} else {
+				// unevaluable sub-commands are kept as is
+				*unevaluable = true;
+				Ok(text.to_string())
+			}
+		}
+		ast::Word::Arithmetic(expr, _) => {
+			*unevaluable |= expr
+				.variables()
+				.into_iter()
+				.any(|name| apml.is_unevaluable(name));
+			Ok(eval_arithmetic(apml, expr)?.to_string())
 		}
 		ast::Word::Variable(expansion) => {
-			let val = apml
-				.variables
-				.get(expansion.name.as_ref())
-				.cloned()
-				.unwrap_or_default();
+			*unevaluable |= apml.is_unevaluable(&expansion.name);
+			let val = apml.read(expansion.name.as_ref());
 			if let Some(modifier) = &expansion.modifier {
-				apply_expansion_modifier(apml, modifier, val)
+				apply_expansion_modifier(apml, modifier, val, unevaluable)
+					.map_err(|err| match err {
+						EvalError::Unset { message, .. } => EvalError::Unset {
+							name: expansion.name.to_string(),
+							message,
+							span: expansion.span,
+							location: None,
+						},
+						err => err,
+					})
 			} else {
 				Ok(val.into_string())
 			}
@@ -110,44 +466,97 @@ fn eval_word(apml: &ApmlContext, word: &ast::Word) -> Result<String> {
 	}
 }
 
-fn apply_expansion_modifier(
+/// Maximum depth of recursive evaluation of variables in arithmetic
+/// expressions.
+const MAX_ARITHMETIC_DEPTH: usize = 64;
+
+/// Evaluates a arithmetic expression with bash integer semantics.
+///
+/// Values of referred variables are evaluated as arithmetic expressions
+/// recursively, and empty or unset variables are evaluated as zero.
+/// Overflows wrap around.
+pub fn eval_arithmetic(
 	apml: &ApmlContext,
-	modifier: &ast::ExpansionModifier,
-	value: VariableValue,
-) -> Result<String> {
-	struct MatchReplacer(usize);
-	impl regex::Replacer for MatchReplacer {
-		fn replace_append(
-			&mut self,
-			caps: &regex::Captures<'_>,
-			dst: &mut String,
-		) {
-			dst.push_str(&caps[self.0]);
-		}
-	}
+	expr: &ast::ArithmeticExpr,
+) -> Result<i64> {
+	eval_arithmetic_at(apml, expr, 0)
+}
 
-	struct UppercaseReplacer;
-	impl regex::Replacer for UppercaseReplacer {
-		fn replace_append(
-			&mut self,
-			caps: &regex::Captures<'_>,
-			dst: &mut String,
-		) {
-			dst.push_str(&caps[0].to_ascii_uppercase());
+fn eval_arithmetic_at(
+	apml: &ApmlContext,
+	expr: &ast::ArithmeticExpr,
+	depth: usize,
+) -> Result<i64> {
+	let eval = |expr| eval_arithmetic_at(apml, expr, depth);
+	match expr {
+		ast::ArithmeticExpr::Number(num) => Ok(*num),
+		ast::ArithmeticExpr::Variable(name) => {
+			let value = apml.read(name).into_string();
+			let value = value.trim();
+			if value.is_empty() {
+				return Ok(0);
+			}
+			if depth >= MAX_ARITHMETIC_DEPTH {
+				return Err(EvalError::ArithmeticError(
+					"expression recursion level exceeded".to_string(),
+				));
+			}
+			let expr = ast::ArithmeticExpr::parse(value).ok_or_else(|| {
+				EvalError::ArithmeticError(format!(
+					"invalid value of {name}: {value}"
+				))
+			})?;
+			eval_arithmetic_at(apml, &expr, depth + 1)
 		}
-	}
-
-	struct LowercaseReplacer;
-	impl regex::Replacer for LowercaseReplacer {
-		fn replace_append(
-			&mut self,
-			caps: &regex::Captures<'_>,
-			dst: &mut String,
-		) {
-			dst.push_str(&caps[0].to_ascii_lowercase());
+		ast::ArithmeticExpr::Unary(op, expr) => {
+			let value = eval(expr)?;
+			Ok(match op {
+				ast::UnaryOperator::Plus => value,
+				ast::UnaryOperator::Minus => value.wrapping_neg(),
+				ast::UnaryOperator::Not => (value == 0) as i64,
+			})
+		}
+		ast::ArithmeticExpr::Binary(ast::BinaryOperator::And, lhs, rhs) => {
+			Ok((eval(lhs)? != 0 && eval(rhs)? != 0) as i64)
+		}
+		ast::ArithmeticExpr::Binary(ast::BinaryOperator::Or, lhs, rhs) => {
+			Ok((eval(lhs)? != 0 || eval(rhs)? != 0) as i64)
+		}
+		ast::ArithmeticExpr::Binary(op, lhs, rhs) => {
+			let (lhs, rhs) = (eval(lhs)?, eval(rhs)?);
+			Ok(match op {
+				ast::BinaryOperator::Mul => lhs.wrapping_mul(rhs),
+				ast::BinaryOperator::Div | ast::BinaryOperator::Rem
+					if rhs == 0 =>
+				{
+					return Err(EvalError::ArithmeticError(
+						"division by 0".to_string(),
+					));
+				}
+				ast::BinaryOperator::Div => lhs.wrapping_div(rhs),
+				ast::BinaryOperator::Rem => lhs.wrapping_rem(rhs),
+				ast::BinaryOperator::Add => lhs.wrapping_add(rhs),
+				ast::BinaryOperator::Sub => lhs.wrapping_sub(rhs),
+				ast::BinaryOperator::Lt => (lhs < rhs) as i64,
+				ast::BinaryOperator::Le => (lhs <= rhs) as i64,
+				ast::BinaryOperator::Gt => (lhs > rhs) as i64,
+				ast::BinaryOperator::Ge => (lhs >= rhs) as i64,
+				ast::BinaryOperator::Eq => (lhs == rhs) as i64,
+				ast::BinaryOperator::Ne => (lhs != rhs) as i64,
+				ast::BinaryOperator::And | ast::BinaryOperator::Or => {
+					unreachable!()
+				}
+			})
 		}
 	}
+}
 
+fn apply_expansion_modifier(
+	apml: &ApmlContext,
+	modifier: &ast::ExpansionModifier,
+	value: VariableValue,
+	unevaluable: &mut bool,
+) -> Result<String> {
 	match modifier {
 		ast::ExpansionModifier::Substring { offset, length } => {
 			let value = value.into_string();
@@ -164,61 +573,85 @@ fn apply_expansion_modifier(
 				Ok(value[*offset..].to_string())
 			}
 		}
-		ast::ExpansionModifier::StripShortestPrefix(pattern) => Ok(pattern
-			.to_regex("^(?:", ")?(.*)$", false)?
-			.replace(&value.into_string(), MatchReplacer(1))
-			.to_string()),
-		ast::ExpansionModifier::StripLongestPrefix(pattern) => Ok(pattern
-			.to_regex("^(?:", ")?(.*?)$", true)?
-			.replace(&value.into_string(), MatchReplacer(1))
-			.to_string()),
-		ast::ExpansionModifier::StripShortestSuffix(pattern) => Ok(pattern
-			.to_regex("^(.*)(?:", ")$", false)?
-			.replace(&value.into_string(), MatchReplacer(1))
-			.to_string()),
-		ast::ExpansionModifier::StripLongestSuffix(pattern) => Ok(pattern
-			.to_regex("^(.*?)(?:", ")$", true)?
-			.replace(&value.into_string(), MatchReplacer(1))
-			.to_string()),
-		ast::ExpansionModifier::ReplaceOnce { pattern, string } => Ok(pattern
-			.to_regex("", "", true)?
-			.replace(&value.into_string(), &eval_text(apml, string)?)
-			.to_string()),
-		ast::ExpansionModifier::ReplaceAll { pattern, string } => Ok(pattern
-			.to_regex("", "", true)?
-			.replace_all(&value.into_string(), &eval_text(apml, string)?)
-			.to_string()),
+		ast::ExpansionModifier::StripShortestPrefix(pattern) => {
+			Ok(strip_prefix(pattern, value.into_string(), false))
+		}
+		ast::ExpansionModifier::StripLongestPrefix(pattern) => {
+			Ok(strip_prefix(pattern, value.into_string(), true))
+		}
+		ast::ExpansionModifier::StripShortestSuffix(pattern) => {
+			Ok(strip_suffix(pattern, value.into_string(), false))
+		}
+		ast::ExpansionModifier::StripLongestSuffix(pattern) => {
+			Ok(strip_suffix(pattern, value.into_string(), true))
+		}
+		ast::ExpansionModifier::ReplaceOnce { pattern, string } => {
+			let string = eval_text_checked(apml, string, unevaluable)?;
+			Ok(map_matches(pattern, &value.into_string(), false, |_| {
+				string.clone()
+			}))
+		}
+		ast::ExpansionModifier::ReplaceAll { pattern, string } => {
+			let string = eval_text_checked(apml, string, unevaluable)?;
+			Ok(map_matches(pattern, &value.into_string(), true, |_| {
+				string.clone()
+			}))
+		}
 		ast::ExpansionModifier::ReplacePrefix { pattern, string } => {
-			Ok(pattern
-				.to_regex("^", "", true)?
-				.replace_all(&value.into_string(), &eval_text(apml, string)?)
-				.to_string())
+			let value = value.into_string();
+			let matcher = PatternCache::global().get(pattern);
+			Ok(match matcher.prefix_len(&value, true) {
+				Some(len) => {
+					eval_text_checked(apml, string, unevaluable)?
+						+ &value[len..]
+				}
+				None => value,
+			})
 		}
 		ast::ExpansionModifier::ReplaceSuffix { pattern, string } => {
-			Ok(pattern
-				.to_regex("", "$", true)?
-				.replace_all(&value.into_string(), &eval_text(apml, string)?)
-				.to_string())
-		}
-		ast::ExpansionModifier::UpperOnce(pattern) => Ok(pattern
-			.to_regex("", "", true)?
-			.replace(&value.into_string(), UppercaseReplacer)
-			.to_string()),
-		ast::ExpansionModifier::UpperAll(pattern) => Ok(pattern
-			.to_regex("", "", true)?
-			.replace_all(&value.into_string(), UppercaseReplacer)
-			.to_string()),
-		ast::ExpansionModifier::LowerOnce(pattern) => Ok(pattern
-			.to_regex("", "", true)?
-			.replace(&value.into_string(), LowercaseReplacer)
-			.to_string()),
-		ast::ExpansionModifier::LowerAll(pattern) => Ok(pattern
-			.to_regex("", "", true)?
-			.replace_all(&value.into_string(), LowercaseReplacer)
-			.to_string()),
+			let value = value.into_string();
+			let matcher = PatternCache::global().get(pattern);
+			Ok(match matcher.suffix_start(&value, true) {
+				Some(start) => {
+					value[..start].to_string()
+						+ &eval_text_checked(apml, string, unevaluable)?
+				}
+				None => value,
+			})
+		}
+		ast::ExpansionModifier::UpperOnce(pattern) => Ok(map_matches(
+			pattern,
+			&value.into_string(),
+			false,
+			str::to_ascii_uppercase,
+		)),
+		ast::ExpansionModifier::UpperAll(pattern) => Ok(map_matches(
+			pattern,
+			&value.into_string(),
+			true,
+			str::to_ascii_uppercase,
+		)),
+		ast::ExpansionModifier::LowerOnce(pattern) => Ok(map_matches(
+			pattern,
+			&value.into_string(),
+			false,
+			str::to_ascii_lowercase,
+		)),
+		ast::ExpansionModifier::LowerAll(pattern) => Ok(map_matches(
+			pattern,
+			&value.into_string(),
+			true,
+			str::to_ascii_lowercase,
+		)),
 		ast::ExpansionModifier::ErrorOnUnset(text) => {
 			if value.is_empty() {
-				Err(EvalError::Unset(eval_text(apml, text)?))
+				// name and span are filled by the caller
+				Err(EvalError::Unset {
+					name: String::new(),
+					message: eval_text_checked(apml, text, unevaluable)?,
+					span: Span::default(),
+					location: None,
+				})
 			} else {
 				Ok(value.into_string())
 			}
@@ -226,14 +659,14 @@ fn apply_expansion_modifier(
 		ast::ExpansionModifier::Length => Ok(value.len().to_string()),
 		ast::ExpansionModifier::WhenUnset(text) => {
 			if value.is_empty() {
-				eval_text(apml, text)
+				eval_text_checked(apml, text, unevaluable)
 			} else {
 				Ok(value.into_string())
 			}
 		}
 		ast::ExpansionModifier::WhenSet(text) => {
 			if !value.is_empty() {
-				eval_text(apml, text)
+				eval_text_checked(apml, text, unevaluable)
 			} else {
 				Ok(value.into_string())
 			}
@@ -241,15 +674,63 @@ fn apply_expansion_modifier(
 	}
 }
 
+/// Removes the shortest or longest prefix matching a pattern.
+fn strip_prefix(pattern: &BashPattern, value: String, longest: bool) -> String {
+	match PatternCache::global()
+		.get(pattern)
+		.prefix_len(&value, longest)
+	{
+		Some(len) => value[len..].to_string(),
+		None => value,
+	}
+}
+
+/// Removes the shortest or longest suffix matching a pattern.
+fn strip_suffix(pattern: &BashPattern, value: String, longest: bool) -> String {
+	match PatternCache::global()
+		.get(pattern)
+		.suffix_start(&value, longest)
+	{
+		Some(start) => value[..start].to_string(),
+		None => value,
+	}
+}
+
+/// Replaces the first or all matches of a pattern with a mapped string.
+fn map_matches(
+	pattern: &BashPattern,
+	value: &str,
+	all: bool,
+	mut map: impl FnMut(&str) -> String,
+) -> String {
+	let matcher = PatternCache::global().get(pattern);
+	let matches = if all {
+		matcher.find_all(value)
+	} else {
+		matcher.find(value, 0).into_iter().collect()
+	};
+	let mut result = String::with_capacity(value.len());
+	let mut last = 0;
+	for range in matches {
+		result.push_str(&value[last..range.start]);
+		result.push_str(&map(&value[range.clone()]));
+		last = range.end;
+	}
+	result.push_str(&value[last..]);
+	result
+}
+
 #[cfg(test)]
 mod test {
 	use std::sync::Arc;
 
 	use crate::apml::{
-		ApmlContext,
-		ast::{ExpansionModifier, Text, Word},
-		eval::apply_expansion_modifier,
+		ApmlContext, ApmlError,
+		ast::{ApmlAst, AstNode, ExpansionModifier, Text, Word},
+		eval::{EvalError, Origin, apply_expansion_modifier, eval_ast},
+		lst::ApmlLst,
 		pattern::{BashPattern, GlobPart},
+		span::Span,
 	};
 
 	#[test]
@@ -264,7 +745,8 @@ mod test {
 					offset: 0,
 					length: Some(10)
 				},
-				"123".into()
+				"123".into(),
+				&mut false
 			)
 			.unwrap(),
 			"123"
@@ -276,7 +758,8 @@ mod test {
 					offset: 0,
 					length: Some(-1)
 				},
-				"123".into()
+				"123".into(),
+				&mut false
 			)
 			.unwrap(),
 			"12"
@@ -288,7 +771,8 @@ mod test {
 					offset: 1,
 					length: None
 				},
-				"123".into()
+				"123".into(),
+				&mut false
 			)
 			.unwrap(),
 			"23"
@@ -301,12 +785,14 @@ mod test {
 			GlobPart::String("a".into()),
 			GlobPart::AnyChar,
 		]));
-		let text1 = Arc::new(Text(vec![Word::Literal("test".into())]));
+		let text1 =
+			Arc::new(Text(vec![Word::Literal("test".into(), Span::default())]));
 		assert_eq!(
 			apply_expansion_modifier(
 				&ctx,
 				&ExpansionModifier::StripShortestPrefix(pattern1.clone()),
-				"123".into()
+				"123".into(),
+				&mut false
 			)
 			.unwrap(),
 			"123"
@@ -315,7 +801,8 @@ mod test {
 			apply_expansion_modifier(
 				&ctx,
 				&ExpansionModifier::StripShortestPrefix(pattern1.clone()),
-				"a123".into()
+				"a123".into(),
+				&mut false
 			)
 			.unwrap(),
 			"123"
@@ -324,7 +811,8 @@ mod test {
 			apply_expansion_modifier(
 				&ctx,
 				&ExpansionModifier::StripShortestPrefix(pattern1.clone()),
-				"123".into()
+				"123".into(),
+				&mut false
 			)
 			.unwrap(),
 			"123"
@@ -333,7 +821,8 @@ mod test {
 			apply_expansion_modifier(
 				&ctx,
 				&ExpansionModifier::StripShortestSuffix(pattern1.clone()),
-				"a123a123".into()
+				"a123a123".into(),
+				&mut false
 			)
 			.unwrap(),
 			"a123"
@@ -342,7 +831,8 @@ mod test {
 			apply_expansion_modifier(
 				&ctx,
 				&ExpansionModifier::StripLongestPrefix(pattern1.clone()),
-				"123".into()
+				"123".into(),
+				&mut false
 			)
 			.unwrap(),
 			"123"
@@ -351,7 +841,8 @@ mod test {
 			apply_expansion_modifier(
 				&ctx,
 				&ExpansionModifier::StripLongestPrefix(pattern1.clone()),
-				"a123".into()
+				"a123".into(),
+				&mut false
 			)
 			.unwrap(),
 			""
@@ -360,7 +851,8 @@ mod test {
 			apply_expansion_modifier(
 				&ctx,
 				&ExpansionModifier::StripLongestSuffix(pattern1.clone()),
-				"123".into()
+				"123".into(),
+				&mut false
 			)
 			.unwrap(),
 			"123"
@@ -369,7 +861,8 @@ mod test {
 			apply_expansion_modifier(
 				&ctx,
 				&ExpansionModifier::StripLongestSuffix(pattern1.clone()),
-				"a123a123".into()
+				"a123a123".into(),
+				&mut false
 			)
 			.unwrap(),
 			""
@@ -381,7 +874,8 @@ mod test {
 					pattern: pattern1.clone(),
 					string: text1.clone()
 				},
-				"1a123a123".into()
+				"1a123a123".into(),
+				&mut false
 			)
 			.unwrap(),
 			"1test"
@@ -393,7 +887,8 @@ mod test {
 					pattern: pattern2.clone(),
 					string: text1.clone()
 				},
-				"a123a123".into()
+				"a123a123".into(),
+				&mut false
 			)
 			.unwrap(),
 			"test23a123"
@@ -405,7 +900,8 @@ mod test {
 					pattern: pattern1.clone(),
 					string: text1.clone()
 				},
-				"1a123a123".into()
+				"1a123a123".into(),
+				&mut false
 			)
 			.unwrap(),
 			"1test"
@@ -417,7 +913,8 @@ mod test {
 					pattern: pattern2.clone(),
 					string: text1.clone()
 				},
-				"a123a123".into()
+				"a123a123".into(),
+				&mut false
 			)
 			.unwrap(),
 			"test23test23"
@@ -429,7 +926,8 @@ mod test {
 					pattern: pattern1.clone(),
 					string: text1.clone()
 				},
-				"1a123a123".into()
+				"1a123a123".into(),
+				&mut false
 			)
 			.unwrap(),
 			"1a123a123"
@@ -441,7 +939,8 @@ mod test {
 					pattern: pattern1.clone(),
 					string: text1.clone()
 				},
-				"a123a123".into()
+				"a123a123".into(),
+				&mut false
 			)
 			.unwrap(),
 			"test"
@@ -453,7 +952,8 @@ mod test {
 					pattern: pattern1.clone(),
 					string: text1.clone()
 				},
-				"1a123a1231".into()
+				"1a123a1231".into(),
+				&mut false
 			)
 			.unwrap(),
 			"1test"
@@ -465,7 +965,8 @@ mod test {
 					pattern: pattern1.clone(),
 					string: text1.clone()
 				},
-				"a123a123".into()
+				"a123a123".into(),
+				&mut false
 			)
 			.unwrap(),
 			"test"
@@ -474,7 +975,8 @@ mod test {
 			apply_expansion_modifier(
 				&ctx,
 				&ExpansionModifier::UpperOnce(pattern1.clone()),
-				"aa123abc123".into()
+				"aa123abc123".into(),
+				&mut false
 			)
 			.unwrap(),
 			"AA123ABC123"
@@ -483,7 +985,8 @@ mod test {
 			apply_expansion_modifier(
 				&ctx,
 				&ExpansionModifier::UpperOnce(pattern2.clone()),
-				"aa123abc123".into()
+				"aa123abc123".into(),
+				&mut false
 			)
 			.unwrap(),
 			"AA123abc123"
@@ -492,7 +995,8 @@ mod test {
 			apply_expansion_modifier(
 				&ctx,
 				&ExpansionModifier::UpperAll(pattern1.clone()),
-				"aa123abc123".into()
+				"aa123abc123".into(),
+				&mut false
 			)
 			.unwrap(),
 			"AA123ABC123"
@@ -501,7 +1005,8 @@ mod test {
 			apply_expansion_modifier(
 				&ctx,
 				&ExpansionModifier::UpperAll(pattern2.clone()),
-				"aa123abc123".into()
+				"aa123abc123".into(),
+				&mut false
 			)
 			.unwrap(),
 			"AA123ABc123"
@@ -510,7 +1015,8 @@ mod test {
 			apply_expansion_modifier(
 				&ctx,
 				&ExpansionModifier::LowerOnce(pattern1.clone()),
-				"aA123aBC123".into()
+				"aA123aBC123".into(),
+				&mut false
 			)
 			.unwrap(),
 			"aa123abc123"
@@ -519,7 +1025,8 @@ mod test {
 			apply_expansion_modifier(
 				&ctx,
 				&ExpansionModifier::LowerOnce(pattern2.clone()),
-				"aA123aBC123".into()
+				"aA123aBC123".into(),
+				&mut false
 			)
 			.unwrap(),
 			"aa123aBC123"
@@ -528,7 +1035,8 @@ mod test {
 			apply_expansion_modifier(
 				&ctx,
 				&ExpansionModifier::LowerAll(pattern1.clone()),
-				"aA123aBC123".into()
+				"aA123aBC123".into(),
+				&mut false
 			)
 			.unwrap(),
 			"aa123abc123"
@@ -537,7 +1045,8 @@ mod test {
 			apply_expansion_modifier(
 				&ctx,
 				&ExpansionModifier::LowerAll(pattern2.clone()),
-				"aA123aBc123".into()
+				"aA123aBc123".into(),
+				&mut false
 			)
 			.unwrap(),
 			"aa123abc123"
@@ -546,7 +1055,8 @@ mod test {
 			apply_expansion_modifier(
 				&ctx,
 				&ExpansionModifier::ErrorOnUnset(text1.clone()),
-				"test".into()
+				"test".into(),
+				&mut false
 			)
 			.unwrap(),
 			"test"
@@ -555,13 +1065,15 @@ mod test {
 			&ctx,
 			&ExpansionModifier::ErrorOnUnset(text1.clone()),
 			"".into(),
+			&mut false,
 		)
 		.unwrap_err();
 		assert_eq!(
 			apply_expansion_modifier(
 				&ctx,
 				&ExpansionModifier::Length,
-				"test".into()
+				"test".into(),
+				&mut false
 			)
 			.unwrap(),
 			"4"
@@ -570,7 +1082,8 @@ mod test {
 			apply_expansion_modifier(
 				&ctx,
 				&ExpansionModifier::WhenUnset(text1.clone()),
-				"aaa".into()
+				"aaa".into(),
+				&mut false
 			)
 			.unwrap(),
 			"aaa"
@@ -579,7 +1092,8 @@ mod test {
 			apply_expansion_modifier(
 				&ctx,
 				&ExpansionModifier::WhenUnset(text1.clone()),
-				"".into()
+				"".into(),
+				&mut false
 			)
 			.unwrap(),
 			"test"
@@ -588,7 +1102,8 @@ mod test {
 			apply_expansion_modifier(
 				&ctx,
 				&ExpansionModifier::WhenSet(text1.clone()),
-				"aaa".into()
+				"aaa".into(),
+				&mut false
 			)
 			.unwrap(),
 			"test"
@@ -597,10 +1112,153 @@ mod test {
 			apply_expansion_modifier(
 				&ctx,
 				&ExpansionModifier::WhenSet(text1.clone()),
-				"".into()
+				"".into(),
+				&mut false
 			)
 			.unwrap(),
 			""
 		);
 	}
+
+	#[test]
+	fn test_trace() {
+		let src = "BASE=\"a b\"\nPKGDEP=\"${BASE} c\"\nPKGDEP+=\" d\"\n\
+			# comment\nA=(x)\nA+=(\"${BASE}\" y)\nB=\"${BASE/a/z}\"";
+		let lst = ApmlLst::parse(src).unwrap();
+		let ast = ApmlAst::emit_from(&lst).unwrap();
+		let (ctx, trace) = ApmlContext::eval_ast_traced(&ast, None).unwrap();
+		assert_eq!(ctx["PKGDEP"], "a b c d");
+
+		let pkgdep = trace.get("PKGDEP").unwrap();
+		let defs_of = |origins: &[Origin]| {
+			origins.iter().map(|o| o.def).collect::<Vec<_>>()
+		};
+		assert_eq!(defs_of(&pkgdep.origins), vec![0, 1, 2]);
+		assert_eq!(defs_of(pkgdep.origins_at(0)), vec![0]);
+		assert_eq!(defs_of(pkgdep.origins_at(4)), vec![1]);
+		assert_eq!(defs_of(pkgdep.origins_at(6)), vec![2]);
+		assert_eq!(pkgdep.origins_in(3..6).len(), 2);
+		assert_eq!(pkgdep.origins_at(100), &[]);
+		assert!(pkgdep.elements.is_empty());
+		assert_eq!(pkgdep.origins[2].name, "PKGDEP");
+		assert_eq!(pkgdep.origins[2].span.slice(src), Some("PKGDEP+=\" d\""));
+		assert_eq!(pkgdep.origins[2].token_index(&lst), Some(4));
+
+		let array = trace.get("A").unwrap();
+		assert_eq!(
+			array
+				.elements
+				.iter()
+				.map(|origins| defs_of(origins))
+				.collect::<Vec<_>>(),
+			vec![vec![3], vec![0], vec![4]]
+		);
+		let replaced = trace.get("B").unwrap();
+		assert_eq!(defs_of(&replaced.origins), vec![5, 0]);
+	}
+
+	#[test]
+	fn test_subcommand() {
+		let src = "VER=1.2.3\nA=$(echo $VER | cut -d. -f1)\nB=$(uname -m)\n\
+			C=\"${B}-x\"\nD=(a \"${C:-$(true)}\")\nE=${A/1/$(false)}\n\
+			F=${VER:-$(true)}\nG=$(echo $B)";
+		let lst = ApmlLst::parse(src).unwrap();
+		let ast = ApmlAst::emit_from(&lst).unwrap();
+
+		let apml = ApmlContext::eval_ast(&ast).unwrap();
+		assert_eq!(apml["A"], "$(echo $VER | cut -d. -f1)");
+		for name in ["A", "B", "C", "D", "E", "G"] {
+			assert!(apml.is_unevaluable(name));
+		}
+		assert!(!apml.is_unevaluable("VER"));
+		// unused modifier texts are not evaluated
+		assert!(!apml.is_unevaluable("F"));
+
+		let mut apml = ApmlContext::new();
+		apml.set_eval_subcommands(true);
+		eval_ast(&mut apml, &ast).unwrap();
+		assert_eq!(apml["A"], "1");
+		assert_eq!(apml["B"], "$(uname -m)");
+		assert!(!apml.is_unevaluable("A"));
+		assert!(!apml.is_unevaluable("F"));
+		assert_eq!(apml["G"], "$(echo $B)");
+		for name in ["B", "C", "D", "E", "G"] {
+			assert!(apml.is_unevaluable(name));
+		}
+		let child = ApmlContext::with_parent(Arc::new(apml.clone()));
+		assert!(child.eval_subcommands());
+		assert!(child.is_unevaluable("B"));
+		assert!(apml.flatten().is_unevaluable("B"));
+		apml.insert("B".into(), "x86_64".into());
+		assert!(!apml.is_unevaluable("B"));
+	}
+
+	#[test]
+	fn test_arithmetic() {
+		let apml = ApmlContext::eval_source(
+			"A=7\nB=\"A * 2\"\nC=$((A + 1))\nD=\"$(( ($B - 4) / 3 % 3 ))\"\n\
+			E=$((${A} > 5 && -A < 0 || 1 / 0))\nF=$((010 + 0x10 - -1))\n\
+			G=$((A == 7 != 0))\nH=$((UNSET + !0 * 3 >= 4))\nI=$((-7 / 2))",
+		)
+		.unwrap();
+		assert_eq!(apml["C"], "8");
+		assert_eq!(apml["D"], "0");
+		assert_eq!(apml["E"], "1");
+		assert_eq!(apml["F"], "25");
+		assert_eq!(apml["G"], "1");
+		assert_eq!(apml["H"], "0");
+		assert_eq!(apml["I"], "-3");
+
+		// unsupported expressions are kept as is
+		let apml =
+			ApmlContext::eval_source("A=1\nB=$((1<<2))\nC=$((A + 1))").unwrap();
+		assert_eq!(apml["B"], "$((1<<2))");
+		assert!(apml.is_unevaluable("B"));
+		assert_eq!(apml["C"], "2");
+
+		let err = ApmlContext::eval_source("A=$((1 % 0))").unwrap_err();
+		assert_eq!(err.to_string(), "Arithmetic error: division by 0");
+		let err = ApmlContext::eval_source("A=A\nB=$((A))").unwrap_err();
+		assert_eq!(
+			err.to_string(),
+			"Arithmetic error: expression recursion level exceeded"
+		);
+		let err = ApmlContext::eval_source("A=a.b\nB=$((A))").unwrap_err();
+		assert_eq!(
+			err.to_string(),
+			"Arithmetic error: invalid value of A: a.b"
+		);
+	}
+
+	#[test]
+	fn test_glob_modifiers() {
+		let apml = ApmlContext::eval_source(
+			"V=1.2.3rc1\nA=${V%%+([[:alpha:]])+([[:digit:]])}\n\
+			B=${V//[![:digit:]]/_}\nC=${V/!(*.*)/x}\nD=${V^^[a-z]}\n\
+			E=${V#@(0|1).}",
+		)
+		.unwrap();
+		assert_eq!(apml["A"], "1.2.3");
+		assert_eq!(apml["B"], "1_2_3__1");
+		assert_eq!(apml["C"], "x.2.3rc1");
+		assert_eq!(apml["D"], "1.2.3RC1");
+		assert_eq!(apml["E"], "2.3rc1");
+	}
+
+	#[test]
+	fn test_unset_location() {
+		let src = "A=1\nB=\"a ${C:?C is required}\"";
+		let err = ApmlContext::eval_source(src).unwrap_err();
+		let ApmlError::Eval(err) = err else { panic!() };
+		assert_eq!(err.span(), Some(Span::new(9, 28)));
+		assert_eq!(
+			err.to_string(),
+			"Required variable C is unset: C is required (at 2:6)"
+		);
+		let err = err.locate(Some("defines"), src);
+		let EvalError::Unset { location, .. } = &err else {
+			panic!()
+		};
+		assert_eq!(location.as_ref().unwrap().to_string(), "defines:2:6");
+	}
 }
@@ -0,0 +1,500 @@
+//! Canonical APML formatter.
+//!
+//! [`ApmlFormatter`] rewrites a [LST][lst::ApmlLst] into the canonical
+//! style of AOSC OS packages:
+//!
+//! - Each variable definition is placed in its own line, without
+//!   leading or trailing spaces. Inline comments are separated from the
+//!   definition with one space.
+//! - Groups of definitions are separated with exactly one empty line.
+//!   Leading and trailing empty lines are removed and the file ends with
+//!   a newline.
+//! - Texts made up of plain unquoted words are kept unquoted, while all
+//!   other texts are converted into one double-quoted unit.
+//! - Dependency lists, such as `PKGDEP` and `BUILDDEP`, are separated with
+//!   one space, and wrapped with line continuations when they are too long.
+//!   Continued lines are aligned to the opening quote.
+//! - Arrays are placed in one line if they fit and contain no comments,
+//!   or one element per line otherwise.
+//!
+//! Formatting is idempotent and comments are always kept.
+
+use std::{borrow::Cow, mem::take, sync::Arc};
+
+use super::{
+	lst::{self, source_len},
+	parser::ParseError,
+};
+
+/// Variables holding whitespace-separated dependency lists.
+///
+/// Architecture-specific overrides, like `PKGDEP__AMD64`, are included.
+const DEPENDENCY_VARIABLES: &[&str] = &[
+	"PKGDEP", "BUILDDEP", "PKGRECOM", "PKGSUG", "PKGBREAK", "PKGREP",
+	"PKGCONFL", "PKGPROV",
+];
+
+/// Formatter producing canonical APML.
+#[derive(Debug, Clone)]
+pub struct ApmlFormatter {
+	/// Maximum width of lines.
+	max_width: usize,
+}
+
+impl Default for ApmlFormatter {
+	fn default() -> Self {
+		Self { max_width: 80 }
+	}
+}
+
+impl ApmlFormatter {
+	/// Creates a formatter with the default options.
+	pub fn new() -> Self {
+		Default::default()
+	}
+
+	/// Sets the maximum width of lines, which defaults to 80.
+	///
+	/// This only affects the wrapping of dependency lists and arrays.
+	#[must_use]
+	pub fn max_width(mut self, max_width: usize) -> Self {
+		self.max_width = max_width;
+		self
+	}
+
+	/// Formats a LST into the canonical style.
+	pub fn format<'a>(&self, lst: &lst::ApmlLst<'a>) -> lst::ApmlLst<'a> {
+		let mut result = Vec::new();
+		let mut pending_empty_line = false;
+		for line in lst.0.split(|token| matches!(token, lst::Token::Newline)) {
+			let mut defs = Vec::new();
+			let mut comment = None;
+			for token in line {
+				match token {
+					lst::Token::Spacy(_) | lst::Token::Newline => {}
+					lst::Token::Comment(text) => comment = Some(text),
+					lst::Token::Variable(def) => defs.push(def),
+				}
+			}
+			if defs.is_empty() && comment.is_none() {
+				// collapse empty lines and drop leading ones
+				pending_empty_line = !result.is_empty();
+				continue;
+			}
+			if pending_empty_line {
+				result.push(lst::Token::Newline);
+				pending_empty_line = false;
+			}
+			let has_defs = !defs.is_empty();
+			for (idx, def) in defs.into_iter().enumerate() {
+				if idx != 0 {
+					result.push(lst::Token::Newline);
+				}
+				result.push(lst::Token::Variable(self.format_def(def)));
+			}
+			if let Some(comment) = comment {
+				if has_defs {
+					result.push(lst::Token::Spacy(' '));
+				}
+				result.push(lst::Token::Comment(trim_comment(comment)));
+			}
+			result.push(lst::Token::Newline);
+		}
+		lst::ApmlLst(result)
+	}
+
+	/// Parses and formats a APML source.
+	pub fn format_source(&self, src: &str) -> Result<String, ParseError> {
+		Ok(self.format(&lst::ApmlLst::parse(src)?).to_string())
+	}
+
+	/// Returns if a LST is already in the canonical style.
+	pub fn is_formatted(&self, lst: &lst::ApmlLst) -> bool {
+		self.format(lst).to_string() == lst.to_string()
+	}
+
+	fn format_def<'a>(
+		&self,
+		def: &lst::VariableDefinition<'a>,
+	) -> lst::VariableDefinition<'a> {
+		// length of `NAME=`
+		let prefix = def.name.len() + source_len(&def.op);
+		let value = match &def.value {
+			lst::VariableValue::String(text) => {
+				let text = if def.op == lst::VariableOp::Assignment
+					&& is_dependency_variable(&def.name)
+				{
+					// skip the opening quote
+					self.format_list(text, prefix + 1)
+				} else {
+					format_text(text)
+				};
+				lst::VariableValue::String(Arc::new(text))
+			}
+			lst::VariableValue::Array(tokens) => {
+				lst::VariableValue::Array(self.format_array(tokens, prefix))
+			}
+		};
+		lst::VariableDefinition {
+			name: def.name.clone(),
+			op: def.op.clone(),
+			value,
+		}
+	}
+
+	/// Formats a whitespace-separated list into a double-quoted text,
+	/// wrapping it with line continuations.
+	fn format_list<'a>(
+		&self,
+		text: &lst::Text<'a>,
+		indent: usize,
+	) -> lst::Text<'a> {
+		let mut words = Vec::new();
+		let mut width = indent;
+		for (idx, item) in
+			split_items(quote_words(text)).into_iter().enumerate()
+		{
+			let len = item.iter().map(source_len).sum::<usize>();
+			if idx != 0 {
+				// reserve two columns for ` \`
+				if width + 1 + len + 2 > self.max_width {
+					push_part(&mut words, lst::LiteralPart::String(" ".into()));
+					push_part(&mut words, lst::LiteralPart::LineContinuation);
+					push_part(
+						&mut words,
+						lst::LiteralPart::String(" ".repeat(indent).into()),
+					);
+					width = indent;
+				} else {
+					push_part(&mut words, lst::LiteralPart::String(" ".into()));
+					width += 1;
+				}
+			}
+			width += len;
+			for word in item {
+				push_word(&mut words, word);
+			}
+		}
+		lst::Text(vec![lst::TextUnit::DoubleQuote(words)])
+	}
+
+	fn format_array<'a>(
+		&self,
+		tokens: &[lst::ArrayToken<'a>],
+		prefix: usize,
+	) -> Vec<lst::ArrayToken<'a>> {
+		// elements with their inline comments, and standalone comments
+		let mut entries: Vec<(Option<lst::Text>, Option<Cow<str>>)> =
+			Vec::new();
+		let mut element_in_line = false;
+		let mut has_comments = false;
+		for token in tokens {
+			match token {
+				lst::ArrayToken::Spacy(_) => {}
+				lst::ArrayToken::Newline => element_in_line = false,
+				lst::ArrayToken::Comment(text) => {
+					has_comments = true;
+					let comment = Some(trim_comment(text));
+					match entries.last_mut() {
+						Some((Some(_), inline @ None)) if element_in_line => {
+							*inline = comment;
+						}
+						_ => entries.push((None, comment)),
+					}
+					element_in_line = false;
+				}
+				lst::ArrayToken::Element(text) => {
+					entries.push((Some(format_text(text)), None));
+					element_in_line = true;
+				}
+			}
+		}
+
+		let mut result = Vec::new();
+		if !has_comments {
+			for (element, _) in &entries {
+				if let Some(element) = element {
+					result.push(lst::ArrayToken::Element(Arc::new(
+						element.clone(),
+					)));
+					result.push(lst::ArrayToken::Spacy(' '));
+				}
+			}
+			result.pop();
+			// `NAME=(` and `)`
+			let width =
+				prefix + 2 + result.iter().map(source_len).sum::<usize>();
+			if width <= self.max_width {
+				return result;
+			}
+			result.clear();
+		}
+		for (element, comment) in entries {
+			result.push(lst::ArrayToken::Newline);
+			result.push(lst::ArrayToken::Spacy('\t'));
+			if let Some(element) = element {
+				result.push(lst::ArrayToken::Element(Arc::new(element)));
+				if comment.is_some() {
+					result.push(lst::ArrayToken::Spacy(' '));
+				}
+			}
+			if let Some(comment) = comment {
+				result.push(lst::ArrayToken::Comment(comment));
+			}
+		}
+		result.push(lst::ArrayToken::Newline);
+		result
+	}
+}
+
+/// Returns if a variable holds a dependency list.
+fn is_dependency_variable(name: &str) -> bool {
+	let base = name.split_once("__").map_or(name, |(base, _)| base);
+	DEPENDENCY_VARIABLES.contains(&base)
+}
+
+/// Removes trailing spaces of a comment.
+fn trim_comment<'a>(text: &Cow<'a, str>) -> Cow<'a, str> {
+	if text.ends_with(char::is_whitespace) {
+		Cow::Owned(text.trim_end().to_string())
+	} else {
+		text.clone()
+	}
+}
+
+/// Formats a text, keeping plain unquoted texts and double-quoting others.
+fn format_text<'a>(text: &lst::Text<'a>) -> lst::Text<'a> {
+	if is_plain(text) {
+		text.clone()
+	} else {
+		lst::Text(vec![lst::TextUnit::DoubleQuote(quote_words(text))])
+	}
+}
+
+/// Returns if a text is made up of unquoted words without escapes.
+fn is_plain(text: &lst::Text) -> bool {
+	!text.0.is_empty()
+		&& text.0.iter().all(|unit| match unit {
+			lst::TextUnit::Unquoted(words) => {
+				words.iter().all(|word| match word {
+					lst::Word::Literal(parts) => parts.iter().all(|part| {
+						matches!(part, lst::LiteralPart::String(_))
+					}),
+					lst::Word::UnbracedVariable(_)
+					| lst::Word::BracedVariable(_) => true,
+					lst::Word::Subcommand(_) | lst::Word::Arithmetic(_) => {
+						false
+					}
+				})
+			}
+			lst::TextUnit::SingleQuote(_) | lst::TextUnit::DoubleQuote(_) => {
+				false
+			}
+		})
+}
+
+/// Returns if a character must be escaped in double-quoted words.
+fn should_escape(ch: char) -> bool {
+	lst::LiteralPart::should_escape(ch) || ch == '`'
+}
+
+/// Converts all units of a text into words of one double-quoted unit.
+fn quote_words<'a>(text: &lst::Text<'a>) -> Vec<lst::Word<'a>> {
+	let mut result = Vec::new();
+	for unit in &text.0 {
+		match unit {
+			lst::TextUnit::Unquoted(words) => {
+				for word in words {
+					let lst::Word::Literal(parts) = word else {
+						result.push(word.clone());
+						continue;
+					};
+					for part in parts {
+						match part {
+							lst::LiteralPart::String(text) => {
+								push_escaped(&mut result, text)
+							}
+							lst::LiteralPart::Escaped(ch) => {
+								push_escaped(&mut result, &ch.to_string())
+							}
+							lst::LiteralPart::LineContinuation => push_part(
+								&mut result,
+								lst::LiteralPart::LineContinuation,
+							),
+						}
+					}
+				}
+			}
+			lst::TextUnit::SingleQuote(text) => push_escaped(&mut result, text),
+			lst::TextUnit::DoubleQuote(words) => {
+				for word in words {
+					push_word(&mut result, word.clone());
+				}
+			}
+		}
+	}
+	result
+}
+
+/// Splits double-quoted words into whitespace-separated items.
+///
+/// Line continuations are considered as whitespaces.
+fn split_items<'a>(words: Vec<lst::Word<'a>>) -> Vec<Vec<lst::Word<'a>>> {
+	fn flush<'a>(item: &mut Vec<lst::Word<'a>>, buffer: &mut String) {
+		if !buffer.is_empty() {
+			push_part(item, lst::LiteralPart::String(take(buffer).into()));
+		}
+	}
+	fn finish<'a>(
+		items: &mut Vec<Vec<lst::Word<'a>>>,
+		item: &mut Vec<lst::Word<'a>>,
+		buffer: &mut String,
+	) {
+		flush(item, buffer);
+		if !item.is_empty() {
+			items.push(take(item));
+		}
+	}
+
+	let mut items = Vec::new();
+	let mut item = Vec::new();
+	let mut buffer = String::new();
+	for word in words {
+		let lst::Word::Literal(parts) = word else {
+			flush(&mut item, &mut buffer);
+			item.push(word);
+			continue;
+		};
+		for part in parts {
+			match part {
+				lst::LiteralPart::String(text) => {
+					for ch in text.chars() {
+						if ch.is_whitespace() {
+							finish(&mut items, &mut item, &mut buffer);
+						} else {
+							buffer.push(ch);
+						}
+					}
+				}
+				lst::LiteralPart::Escaped(ch) => {
+					flush(&mut item, &mut buffer);
+					push_part(&mut item, lst::LiteralPart::Escaped(ch));
+				}
+				lst::LiteralPart::LineContinuation => {
+					finish(&mut items, &mut item, &mut buffer)
+				}
+			}
+		}
+	}
+	finish(&mut items, &mut item, &mut buffer);
+	items
+}
+
+/// Appends a string to double-quoted words, escaping characters.
+fn push_escaped(words: &mut Vec<lst::Word<'_>>, text: &str) {
+	let mut buffer = String::new();
+	for ch in text.chars() {
+		if should_escape(ch) {
+			if !buffer.is_empty() {
+				push_part(
+					words,
+					lst::LiteralPart::String(take(&mut buffer).into()),
+				);
+			}
+			push_part(words, lst::LiteralPart::Escaped(ch));
+		} else {
+			buffer.push(ch);
+		}
+	}
+	if !buffer.is_empty() {
+		push_part(words, lst::LiteralPart::String(buffer.into()));
+	}
+}
+
+/// Appends a word, merging literal words.
+fn push_word<'a>(words: &mut Vec<lst::Word<'a>>, word: lst::Word<'a>) {
+	if let lst::Word::Literal(parts) = word {
+		for part in parts {
+			push_part(words, part);
+		}
+	} else {
+		words.push(word);
+	}
+}
+
+/// Appends a literal part, merging literal words.
+fn push_part<'a>(words: &mut Vec<lst::Word<'a>>, part: lst::LiteralPart<'a>) {
+	if let Some(lst::Word::Literal(parts)) = words.last_mut() {
+		parts.push(part);
+	} else {
+		words.push(lst::Word::Literal(vec![part]));
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn assert_format(src: &str, expected: &str) {
+		let formatter = ApmlFormatter::new();
+		let result = formatter.format_source(src).unwrap();
+		assert_eq!(result, expected);
+		// idempotent
+		assert_eq!(formatter.format_source(&result).unwrap(), expected);
+		assert!(formatter.is_formatted(&lst::ApmlLst::parse(&result).unwrap()));
+	}
+
+	#[test]
+	fn test_format_layout() {
+		assert_format("", "");
+		assert_format("\n\n", "");
+		assert_format(
+			"\n  A=1  \n\n\n# c  \nB=2 C=3  # d\n\n\n",
+			"A=1\n\n# c\nB=2\nC=3 # d\n",
+		);
+		assert_format("A=1\nB=2", "A=1\nB=2\n");
+	}
+
+	#[test]
+	fn test_format_quoting() {
+		assert_format("A=1.0\nB=$A-${A}", "A=1.0\nB=$A-${A}\n");
+		assert_format("A=\nB=''", "A=\"\"\nB=\"\"\n");
+		assert_format(
+			"A='a b'\"c\"d\\ e\nB='$x`'",
+			"A=\"a bcd e\"\nB=\"\\$x\\`\"\n",
+		);
+		assert_format("A=\"a  \\\n  b\"", "A=\"a  \\\n  b\"\n");
+		assert_format("A=$(true)", "A=\"$(true)\"\n");
+	}
+
+	#[test]
+	fn test_format_dependency() {
+		assert_format(
+			"PKGDEP=\"  a   b\\\n c\"\nPKGDEP__AMD64=d",
+			"PKGDEP=\"a b c\"\nPKGDEP__AMD64=\"d\"\n",
+		);
+		// appending is not normalized
+		assert_format("PKGDEP+=\" a\"", "PKGDEP+=\" a\"\n");
+		assert_format(
+			"PKGDEP=\"x11-lib libdrm expat systemd elfutils libvdpau nettle libva wayland s2tc lm-sensors libglvnd llvm-runtime libclc\"",
+			"PKGDEP=\"x11-lib libdrm expat systemd elfutils libvdpau nettle libva wayland \\
+        s2tc lm-sensors libglvnd llvm-runtime libclc\"\n",
+		);
+		assert_format(
+			"BUILDDEP=\"${PKGDEP} a\\$b\"",
+			"BUILDDEP=\"${PKGDEP} a\\$b\"\n",
+		);
+	}
+
+	#[test]
+	fn test_format_array() {
+		assert_format("A=( a  'b'\n c )", "A=(a \"b\" c)\n");
+		assert_format("A=()", "A=()\n");
+		assert_format("A=(a # a\n# b\n  b)", "A=(\n\ta # a\n\t# b\n\tb\n)\n");
+		let formatter = ApmlFormatter::new().max_width(10);
+		assert_eq!(
+			formatter.format_source("A=(aaa bbb)").unwrap(),
+			"A=(\n\taaa\n\tbbb\n)\n"
+		);
+	}
+}
@@ -20,11 +20,12 @@
 use std::{
 	borrow::Cow,
 	fmt::{Debug, Display, Write},
+	ops::Range,
 	sync::Arc,
 };
 
 use super::{
-	parser::{ParseError, apml_lst},
+	parser::{ParseError, apml_lst, diagnose},
 	pattern::BashPattern,
 };
 
@@ -46,17 +47,151 @@ impl<'a> ApmlLst<'a> {
 	///
 	/// This is a wrapper calling [`apml_lst`] parser combinator,
 	/// while errors produced by the parser are converted into [`ParseError`],
-	/// and [`ParseError::UnexpectedSource`] is produced when
-	/// there are some unparsable texts in the input.
+	/// and [`ParseError::Invalid`] is produced with all syntax errors
+	/// found by [`diagnose`] when there are some unparsable texts in the input.
+	/// If no syntax error can be located, [`ParseError::UnexpectedSource`]
+	/// is produced instead.
 	pub fn parse(src: &'a str) -> Result<Self, ParseError> {
 		let (out, tree) = apml_lst(src)?;
 		if !out.is_empty() {
-			return Err(ParseError::UnexpectedSource {
-				pos: nom::Offset::offset(src, out) + 1,
-			});
+			let diagnostics = diagnose(src);
+			if diagnostics.is_empty() {
+				return Err(ParseError::UnexpectedSource {
+					pos: nom::Offset::offset(src, out) + 1,
+				});
+			}
+			return Err(ParseError::Invalid(diagnostics));
 		}
 		Ok(tree)
 	}
+
+	/// Returns all variable definitions with their attached comments.
+	///
+	/// See [`DefinitionBlock`] for how comments are attached.
+	pub fn definition_blocks(&self) -> Vec<DefinitionBlock> {
+		let lines = self.lines();
+		let mut result = Vec::new();
+		for (line_idx, (line, kind)) in lines.iter().enumerate() {
+			if *kind != LineKind::Definition {
+				continue;
+			}
+			let leading = lines[..line_idx]
+				.iter()
+				.rev()
+				.take_while(|(_, kind)| *kind == LineKind::Comment)
+				.last()
+				.map_or(line.start, |(first, _)| first.start);
+			let following = lines[line_idx + 1..]
+				.iter()
+				.take_while(|(_, kind)| *kind == LineKind::Comment)
+				.collect::<Vec<_>>();
+			let next = lines.get(line_idx + 1 + following.len());
+			let trailing_end = match (following.last(), next) {
+				// comments followed by a definition lead that definition
+				(_, Some((_, LineKind::Definition))) | (None, _) => line.end,
+				(Some((last, _)), _) => last.end,
+			};
+			let indexes = line
+				.clone()
+				.filter(|idx| matches!(self.0[*idx], Token::Variable(_)))
+				.collect::<Vec<_>>();
+			for &index in &indexes {
+				result.push(DefinitionBlock {
+					index,
+					leading: leading..line.start,
+					line: line.clone(),
+					trailing: line.end..trailing_end,
+					shared: indexes.len() > 1,
+				});
+			}
+		}
+		result
+	}
+
+	/// Splits tokens into lines, each including its ending newline.
+	fn lines(&self) -> Vec<(Range<usize>, LineKind)> {
+		let mut result = Vec::new();
+		let mut start = 0;
+		let mut kind = LineKind::Blank;
+		for (idx, token) in self.0.iter().enumerate() {
+			match token {
+				Token::Spacy(_) => {}
+				Token::Newline => {
+					result.push((start..idx + 1, kind));
+					start = idx + 1;
+					kind = LineKind::Blank;
+				}
+				Token::Comment(_) => {
+					if kind == LineKind::Blank {
+						kind = LineKind::Comment;
+					}
+				}
+				Token::Variable(_) => kind = LineKind::Definition,
+			}
+		}
+		if start < self.0.len() {
+			result.push((start..self.0.len(), kind));
+		}
+		result
+	}
+}
+
+/// Token ranges of a variable definition and its attached comments.
+///
+/// Comment-only lines directly above a definition are its leading
+/// comments. Comment-only lines directly below a definition are its
+/// trailing comments, unless they are directly followed by another
+/// definition, in which case they lead that one. Inline comments are a
+/// part of the definition line.
+///
+/// All ranges are made up of whole lines, including their newlines, so
+/// that the whole block can be moved or removed at once. If a line holds
+/// multiple definitions, such as `a=1 b=2`, each of them gets a block
+/// with the same ranges, marked as [shared][Self::shared].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DefinitionBlock {
+	/// Index of the variable definition token.
+	pub index: usize,
+	/// Tokens of leading comment lines.
+	pub leading: Range<usize>,
+	/// Tokens of the line of the definition.
+	pub line: Range<usize>,
+	/// Tokens of trailing comment lines.
+	pub trailing: Range<usize>,
+	/// Whether other definitions are on the same line.
+	pub shared: bool,
+}
+
+impl DefinitionBlock {
+	/// Returns the range of all tokens in the block.
+	#[must_use]
+	pub fn range(&self) -> Range<usize> {
+		self.leading.start..self.trailing.end
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LineKind {
+	Blank,
+	Comment,
+	Definition,
+}
+
+/// Returns the length in bytes of the source text of a LST node.
+///
+/// This is the same as `node.to_string().len()`, without allocating.
+pub fn source_len<T: Display + ?Sized>(node: &T) -> usize {
+	struct Counter(usize);
+	impl Write for Counter {
+		fn write_str(&mut self, s: &str) -> std::fmt::Result {
+			self.0 += s.len();
+			Ok(())
+		}
+	}
+	let mut counter = Counter(0);
+	// writing to the counter never fails
+	let _ = write!(counter, "{node}");
+	counter.0
 }
 
 /// A token in the LST.
@@ -223,6 +358,10 @@ pub enum Word<'a> {
 	BracedVariable(BracedExpansion<'a>),
 	/// A sub-command expansion (`"$(<tokens>)"`).
 	Subcommand(Vec<ArrayToken<'a>>),
+	/// An arithmetic expansion (`"$((<expression>))"`).
+	///
+	/// The expression is kept as is.
+	Arithmetic(Cow<'a, str>),
 }
 
 impl Display for Word<'_> {
@@ -248,6 +387,7 @@ impl Display for Word<'_> {
 				f.write_str(")")?;
 				Ok(())
 			}
+			Word::Arithmetic(expr) => f.write_fmt(format_args!("$(({expr}))")),
 		}
 	}
 }
@@ -537,6 +677,14 @@ mod test {
 		);
 	}
 
+	#[test]
+	fn test_source_len() {
+		let src = "a=\"兔$b\" # c\nd=(${e[@]} 'f')";
+		let tree = ApmlLst::parse(src).unwrap();
+		assert_eq!(source_len(&tree), src.len());
+		assert_eq!(source_len(&tree.0[0]), "a=\"兔$b\"".len());
+	}
+
 	#[test]
 	fn test_literal_part_escape() {
 		assert!(LiteralPart::should_escape('$'));
@@ -559,4 +707,24 @@ mod test {
 			]
 		);
 	}
+
+	#[test]
+	fn test_definition_blocks() {
+		let src = "# header\n\n# a\n  # a2\na=1 # inline\n# a3\n\n\
+			b=2\n# c\nc=3\n# c2\nd=4\n# d2";
+		let lst = ApmlLst::parse(src).unwrap();
+		let blocks = lst.definition_blocks();
+		let text =
+			|range: Range<usize>| ApmlLst(lst.0[range].to_vec()).to_string();
+		assert_eq!(blocks.len(), 4);
+		assert_eq!(text(blocks[0].leading.clone()), "# a\n  # a2\n");
+		assert_eq!(text(blocks[0].line.clone()), "a=1 # inline\n");
+		assert_eq!(text(blocks[0].trailing.clone()), "# a3\n");
+		assert_eq!(text(blocks[1].range()), "b=2\n");
+		assert_eq!(text(blocks[2].range()), "# c\nc=3\n");
+		assert_eq!(text(blocks[3].range()), "# c2\nd=4\n# d2");
+		assert!(
+			matches!(&lst.0[blocks[3].index], Token::Variable(def) if def.name == "d")
+		);
+	}
 }
@@ -1,27 +1,51 @@
 //! ACBS Package Metadata Language (APML) syntax tree and parsers.
 
 use std::{
-	collections::HashMap,
+	collections::{HashMap, HashSet},
 	fmt::{Display, Write},
 	ops::{Add, AddAssign, Index},
+	sync::Arc,
 };
 
 use ast::{ApmlAst, AstNode};
 use lst::ApmlLst;
 use thiserror::Error;
 
+pub mod analysis;
+pub mod arch;
 pub mod ast;
+#[cfg(feature = "serde")]
+pub mod de;
 pub mod editor;
 pub mod eval;
+pub mod formatter;
 pub mod lst;
+pub mod model;
 pub mod parser;
 pub mod pattern;
+#[cfg(feature = "serde")]
+pub mod ser;
+pub mod span;
+pub mod subcommand;
 pub mod value;
+pub mod visit;
 
 /// A evaluated APML context.
+///
+/// Contexts can be layered on top of a parent context. Variables that are
+/// not defined in a context are looked up in its parent, so a `defines`
+/// file can be evaluated in the scope of its `spec`, as Autobuild does.
+///
+/// Sub-command expansions are kept as their source text unless safe
+/// evaluation is enabled with [`ApmlContext::set_eval_subcommands`].
+/// Variables containing sub-commands which are not evaluated are marked
+/// as unevaluable, see [`ApmlContext::is_unevaluable`].
 #[derive(Debug, PartialEq, Eq, Clone, Default)]
 pub struct ApmlContext {
 	variables: HashMap<String, VariableValue>,
+	parent: Option<Arc<ApmlContext>>,
+	unevaluable: HashSet<String>,
+	eval_subcommands: bool,
 }
 
 impl ApmlContext {
@@ -30,6 +54,17 @@ impl ApmlContext {
 		Default::default()
 	}
 
+	/// Creates a empty APML context layered on top of a parent context.
+	///
+	/// Evaluation options are inherited from the parent.
+	pub fn with_parent(parent: Arc<ApmlContext>) -> Self {
+		Self {
+			eval_subcommands: parent.eval_subcommands,
+			parent: Some(parent),
+			..Default::default()
+		}
+	}
+
 	/// Evaluates a APML AST, expanding variables.
 	pub fn eval_ast(ast: &ApmlAst) -> std::result::Result<Self, ApmlError> {
 		let mut apml = ApmlContext::default();
@@ -37,56 +72,192 @@ impl ApmlContext {
 		Ok(apml)
 	}
 
+	/// Evaluates a APML AST on top of a parent context, expanding variables.
+	pub fn eval_ast_with_parent(
+		ast: &ApmlAst,
+		parent: Arc<ApmlContext>,
+	) -> std::result::Result<Self, ApmlError> {
+		let mut apml = ApmlContext::with_parent(parent);
+		eval::eval_ast(&mut apml, ast)?;
+		Ok(apml)
+	}
+
+	/// Evaluates a APML AST, recording provenance of evaluated values.
+	///
+	/// See [`eval::eval_ast_traced`].
+	pub fn eval_ast_traced(
+		ast: &ApmlAst,
+		parent: Option<Arc<ApmlContext>>,
+	) -> std::result::Result<(Self, eval::EvalTrace), ApmlError> {
+		let mut apml = match parent {
+			Some(parent) => ApmlContext::with_parent(parent),
+			None => ApmlContext::new(),
+		};
+		let trace = eval::eval_ast_traced(&mut apml, ast)?;
+		Ok((apml, trace))
+	}
+
 	/// Emits and evaluates a APML LST.
 	pub fn eval_lst(lst: &ApmlLst) -> std::result::Result<Self, ApmlError> {
 		Self::eval_ast(&ApmlAst::emit_from(lst)?)
 	}
 
 	/// Parses a APML source code, expanding variables.
+	///
+	/// Locations of evaluation errors are resolved against the source.
 	pub fn eval_source(src: &str) -> std::result::Result<Self, ApmlError> {
 		Self::eval_lst(&ApmlLst::parse(src)?)
+			.map_err(|err| err.locate(None, src))
+	}
+
+	/// Returns if sub-commands are evaluated.
+	#[must_use]
+	pub fn eval_subcommands(&self) -> bool {
+		self.eval_subcommands
+	}
+
+	/// Enables or disables safe evaluation of sub-commands.
+	///
+	/// See [`subcommand`] for the supported subset of commands.
+	pub fn set_eval_subcommands(&mut self, enabled: bool) {
+		self.eval_subcommands = enabled;
+	}
+
+	/// Returns if the value of a variable contains unevaluated sub-commands.
+	///
+	/// Such values contain the source text of sub-commands and should not
+	/// be considered as real values.
+	#[must_use]
+	pub fn is_unevaluable(&self, name: &str) -> bool {
+		self.layers()
+			.find(|layer| layer.variables.contains_key(name))
+			.is_some_and(|layer| layer.unevaluable.contains(name))
+	}
+
+	/// Marks the value of a variable in this layer as unevaluable or not.
+	pub(crate) fn mark_unevaluable(&mut self, name: &str, unevaluable: bool) {
+		if unevaluable {
+			self.unevaluable.insert(name.to_string());
+		} else {
+			self.unevaluable.remove(name);
+		}
+	}
+
+	/// Returns the parent context.
+	#[must_use]
+	pub fn parent(&self) -> Option<&Arc<ApmlContext>> {
+		self.parent.as_ref()
+	}
+
+	/// Iterates over this context and all of its ancestors, nearest first.
+	fn layers(&self) -> impl Iterator<Item = &ApmlContext> {
+		std::iter::successors(Some(self), |ctx| ctx.parent.as_deref())
+	}
+
+	/// Merges all layers into a single context without parent.
+	#[must_use]
+	pub fn flatten(&self) -> Self {
+		let mut variables = HashMap::new();
+		let mut unevaluable = HashSet::new();
+		for layer in self.layers() {
+			for (name, value) in &layer.variables {
+				if !variables.contains_key(name) {
+					variables.insert(name.clone(), value.clone());
+					if layer.unevaluable.contains(name) {
+						unevaluable.insert(name.clone());
+					}
+				}
+			}
+		}
+		Self {
+			variables,
+			parent: None,
+			unevaluable,
+			eval_subcommands: self.eval_subcommands,
+		}
 	}
 
 	/// Gets a variable value.
 	#[must_use]
 	pub fn get(&self, name: &str) -> Option<&VariableValue> {
+		self.layers().find_map(|layer| layer.variables.get(name))
+	}
+
+	/// Gets a variable value defined in this layer, ignoring the parent.
+	#[must_use]
+	pub fn get_local(&self, name: &str) -> Option<&VariableValue> {
 		self.variables.get(name)
 	}
 
 	/// Gets a variable value or returns a default value if not found.
 	#[must_use]
 	pub fn read(&self, name: &str) -> VariableValue {
-		self.variables.get(name).cloned().unwrap_or_default()
+		self.get(name).cloned().unwrap_or_default()
 	}
 
 	/// Gets a variable value.
+	///
+	/// If the variable is only defined in the parent context, it will be
+	/// copied into this layer first, leaving the parent untouched.
 	#[must_use]
 	pub fn get_mut(&mut self, name: &str) -> Option<&mut VariableValue> {
+		if !self.variables.contains_key(name) {
+			let parent = self.parent.as_ref()?;
+			let value = parent.get(name)?.clone();
+			let unevaluable = parent.is_unevaluable(name);
+			self.variables.insert(name.to_string(), value);
+			self.mark_unevaluable(name, unevaluable);
+		}
 		self.variables.get_mut(name)
 	}
 
-	/// Removes a variable value.
+	/// Removes a variable value from this layer.
+	///
+	/// Values defined in the parent context will be visible again after
+	/// the removal.
 	pub fn remove(&mut self, name: &str) -> Option<VariableValue> {
+		self.unevaluable.remove(name);
 		self.variables.remove(name)
 	}
 
 	/// Inserts a variable.
 	pub fn insert(&mut self, name: String, value: VariableValue) {
+		self.unevaluable.remove(&name);
 		self.variables.insert(name, value);
 	}
 
-	/// Iterates over all variables.
+	/// Iterates over all variables, including ones from the parent.
 	pub fn iter(&self) -> impl Iterator<Item = (&String, &VariableValue)> {
+		self.layers().enumerate().flat_map(move |(depth, layer)| {
+			layer.variables.iter().filter(move |(name, _)| {
+				!self
+					.layers()
+					.take(depth)
+					.any(|shadow| shadow.variables.contains_key(*name))
+			})
+		})
+	}
+
+	/// Iterates over variables defined in this layer only.
+	pub fn iter_local(
+		&self,
+	) -> impl Iterator<Item = (&String, &VariableValue)> {
 		self.variables.iter()
 	}
 
-	/// Iterates over all variable names.
+	/// Iterates over all variable names, including ones from the parent.
 	pub fn keys(&self) -> impl Iterator<Item = &String> {
-		self.variables.keys()
+		self.iter().map(|(name, _)| name)
 	}
 
 	/// Returns if a variable is defined.
 	pub fn contains_var<S: AsRef<str>>(&self, key: S) -> bool {
+		self.layers()
+			.any(|layer| layer.variables.contains_key(key.as_ref()))
+	}
+
+	/// Returns if a variable is defined in this layer.
+	pub fn contains_local_var<S: AsRef<str>>(&self, key: S) -> bool {
 		self.variables.contains_key(key.as_ref())
 	}
 }
@@ -106,7 +277,11 @@ impl IntoIterator for ApmlContext {
 	type IntoIter = <HashMap<String, VariableValue> as IntoIterator>::IntoIter;
 
 	fn into_iter(self) -> Self::IntoIter {
-		self.variables.into_iter()
+		if self.parent.is_some() {
+			self.flatten().variables.into_iter()
+		} else {
+			self.variables.into_iter()
+		}
 	}
 }
 
@@ -120,6 +295,22 @@ pub enum ApmlError {
 	Eval(#[from] eval::EvalError),
 }
 
+impl ApmlError {
+	/// Resolves the location of the error in the given source text.
+	///
+	/// See [`eval::EvalError::locate`] and [`parser::ParseError::with_file`].
+	#[must_use]
+	pub fn locate(self, file: Option<&str>, src: &str) -> Self {
+		match self {
+			ApmlError::Parse(err) if let Some(file) = file => {
+				ApmlError::Parse(err.with_file(file))
+			}
+			ApmlError::Eval(err) => ApmlError::Eval(err.locate(file, src)),
+			err => err,
+		}
+	}
+}
+
 /// Value of variables.
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
 pub enum VariableValue {
@@ -493,4 +684,48 @@ B="${VAR1[*]}"
 			assert_eq!(entries, vec!["A", "B", "VAR1"]);
 		}
 	}
+
+	#[test]
+	fn test_apml_context_layered() {
+		let spec = Arc::new(
+			ApmlContext::eval_source("VER=1.0\nREL=2\nA=(a b)\n").unwrap(),
+		);
+		let ast = ApmlAst::emit_from(
+			&ApmlLst::parse(
+				"PKGVER=\"${VER}-${REL}\"\nREL=3\nA+=(c)\nB=\"${C:-x}\"\n",
+			)
+			.unwrap(),
+		)
+		.unwrap();
+		let mut apml =
+			ApmlContext::eval_ast_with_parent(&ast, spec.clone()).unwrap();
+		assert_eq!(apml.parent(), Some(&spec));
+		assert_eq!(apml["PKGVER"], "1.0-2");
+		assert_eq!(apml["VER"], "1.0");
+		assert_eq!(apml["REL"], "3");
+		assert_eq!(
+			apml["A"],
+			VariableValue::Array(vec!["a".into(), "b".into(), "c".into()])
+		);
+		assert_eq!(spec["REL"], "2");
+		assert_eq!(apml.get_local("VER"), None);
+		assert!(apml.contains_var("VER"));
+		assert!(!apml.contains_local_var("VER"));
+		{
+			let mut keys = apml.keys().collect::<Vec<_>>();
+			keys.sort();
+			assert_eq!(keys, vec!["A", "B", "PKGVER", "REL", "VER"]);
+			assert_eq!(apml.iter_local().count(), 4);
+		}
+		*apml.get_mut("VER").unwrap() += "1";
+		assert_eq!(apml["VER"], "1.01");
+		assert_eq!(spec["VER"], "1.0");
+		assert_eq!(apml.remove("VER"), Some("1.01".into()));
+		assert_eq!(apml["VER"], "1.0");
+		let flat = apml.flatten();
+		assert_eq!(flat.parent(), None);
+		assert_eq!(flat.iter_local().count(), 5);
+		assert_eq!(flat["REL"], "3");
+		assert_eq!(apml.into_iter().count(), 5);
+	}
 }
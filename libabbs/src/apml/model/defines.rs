@@ -0,0 +1,313 @@
+//! Typed view of `defines` files.
+
+use std::{convert::Infallible, fmt::Display, str::FromStr};
+
+use crate::{
+	Architecture,
+	apml::{
+		ApmlContext,
+		pattern::{GlobMatcher, bash_pattern},
+	},
+};
+
+use super::{ModelError, read_bool, read_integer, read_optional, read_string};
+
+/// A typed view of a `defines` file.
+#[derive(Debug, Clone, Default)]
+pub struct Defines {
+	/// Name of the package (`PKGNAME`).
+	pub pkgname: String,
+	/// Section of the package (`PKGSEC`).
+	pub pkgsec: String,
+	/// Description of the package (`PKGDES`).
+	pub pkgdes: String,
+	/// Epoch of the package (`PKGEPOCH`).
+	pub pkgepoch: Option<u32>,
+	/// Runtime dependencies (`PKGDEP`).
+	pub pkgdep: Vec<String>,
+	/// Build-time dependencies (`BUILDDEP`).
+	pub builddep: Vec<String>,
+	/// Recommended packages (`PKGRECOM`).
+	pub pkgrecom: Vec<String>,
+	/// Suggested packages (`PKGSUG`).
+	pub pkgsug: Vec<String>,
+	/// Packages broken by this package (`PKGBREAK`).
+	pub pkgbreak: Vec<String>,
+	/// Packages replaced by this package (`PKGREP`).
+	pub pkgrep: Vec<String>,
+	/// Packages conflicting with this package (`PKGCONFL`).
+	pub pkgconfl: Vec<String>,
+	/// Virtual packages provided by this package (`PKGPROV`).
+	pub pkgprov: Vec<String>,
+	/// Build template (`ABTYPE`), detected automatically if unset.
+	pub abtype: Option<AbType>,
+	/// Host architecture (`ABHOST`), such as `noarch`.
+	pub abhost: Option<String>,
+	/// Architectures the package fails to build on (`FAIL_ARCH`).
+	pub fail_arch: Option<String>,
+	/// Whether Python 2 support is disabled (`NOPYTHON2`).
+	pub nopython2: bool,
+}
+
+impl Defines {
+	/// Returns if the package is architecture-independent.
+	pub fn is_noarch(&self) -> bool {
+		self.abhost.as_deref() == Some("noarch")
+	}
+
+	/// Returns architectures excluded by `FAIL_ARCH`.
+	///
+	/// See [`fail_arch_excludes`].
+	pub fn excluded_archs(&self) -> Result<Vec<Architecture>, ModelError> {
+		match &self.fail_arch {
+			Some(pattern) => fail_arch_excludes(pattern),
+			None => Ok(Vec::new()),
+		}
+	}
+
+	/// Returns architectures the package is built on.
+	///
+	/// Architecture-independent packages are built on
+	/// [`noarch`][Architecture::NoArch] only, in which case `FAIL_ARCH`
+	/// is not used. Otherwise, these are all architectures not excluded
+	/// by `FAIL_ARCH`.
+	pub fn build_archs(&self) -> Result<Vec<Architecture>, ModelError> {
+		if self.is_noarch() {
+			return Ok(vec![Architecture::NoArch]);
+		}
+		let excluded = self.excluded_archs()?;
+		Ok(Architecture::ALL
+			.into_iter()
+			.filter(|arch| !arch.is_noarch() && !excluded.contains(arch))
+			.collect())
+	}
+}
+
+/// Returns architectures matched by a `FAIL_ARCH` pattern, such as
+/// `!(amd64|arm64)`.
+///
+/// The pattern is a bash extglob matched against architecture
+/// identifiers. [`noarch`][Architecture::NoArch] is never included.
+pub fn fail_arch_excludes(
+	pattern: &str,
+) -> Result<Vec<Architecture>, ModelError> {
+	let invalid = || ModelError::InvalidPattern {
+		name: "FAIL_ARCH",
+		value: pattern.to_string(),
+	};
+	let (rest, parsed) = bash_pattern(pattern, "").map_err(|_| invalid())?;
+	if !rest.is_empty() {
+		return Err(invalid());
+	}
+	let matcher = GlobMatcher::new(&parsed);
+	Ok(Architecture::ALL
+		.into_iter()
+		.filter(|arch| !arch.is_noarch() && matcher.is_match(arch.ident()))
+		.collect())
+}
+
+impl TryFrom<&ApmlContext> for Defines {
+	type Error = ModelError;
+
+	fn try_from(ctx: &ApmlContext) -> Result<Self, Self::Error> {
+		let read_array = |name: &str| ctx.read(name).into_array();
+		Ok(Self {
+			pkgname: read_string(ctx, "PKGNAME"),
+			pkgsec: read_string(ctx, "PKGSEC"),
+			pkgdes: read_string(ctx, "PKGDES"),
+			pkgepoch: read_integer(ctx, "PKGEPOCH")?,
+			pkgdep: read_array("PKGDEP"),
+			builddep: read_array("BUILDDEP"),
+			pkgrecom: read_array("PKGRECOM"),
+			pkgsug: read_array("PKGSUG"),
+			pkgbreak: read_array("PKGBREAK"),
+			pkgrep: read_array("PKGREP"),
+			pkgconfl: read_array("PKGCONFL"),
+			pkgprov: read_array("PKGPROV"),
+			abtype: AbType::read(ctx),
+			abhost: read_optional(ctx, "ABHOST"),
+			fail_arch: read_optional(ctx, "FAIL_ARCH"),
+			nopython2: read_bool(ctx, "NOPYTHON2"),
+		})
+	}
+}
+
+/// A build template of Autobuild (`ABTYPE`).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AbType {
+	Autotools,
+	Cmake,
+	CmakeNinja,
+	Dummy,
+	GoMod,
+	Meson,
+	Npm,
+	Perl,
+	PlainMake,
+	Python,
+	Pep517,
+	QtProj,
+	Rust,
+	SelfBuild,
+	Waf,
+	/// A template unknown to libabbs.
+	Other(String),
+}
+
+impl AbType {
+	/// Reads `ABTYPE` from a context, returning [None] if it is unset.
+	///
+	/// Unlike [`Defines::try_from`], this does not fail on malformed
+	/// values of other variables.
+	pub fn read(ctx: &ApmlContext) -> Option<Self> {
+		read_optional(ctx, "ABTYPE")
+			.map(|abtype| AbType::from_str(&abtype).unwrap())
+	}
+
+	/// Returns the identifier of the template.
+	pub fn as_str(&self) -> &str {
+		match self {
+			AbType::Autotools => "autotools",
+			AbType::Cmake => "cmake",
+			AbType::CmakeNinja => "cmakeninja",
+			AbType::Dummy => "dummy",
+			AbType::GoMod => "gomod",
+			AbType::Meson => "meson",
+			AbType::Npm => "npm",
+			AbType::Perl => "perl",
+			AbType::PlainMake => "plainmake",
+			AbType::Python => "python",
+			AbType::Pep517 => "pep517",
+			AbType::QtProj => "qtproj",
+			AbType::Rust => "rust",
+			AbType::SelfBuild => "self",
+			AbType::Waf => "waf",
+			AbType::Other(other) => other,
+		}
+	}
+}
+
+impl FromStr for AbType {
+	type Err = Infallible;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Ok(match s {
+			"autotools" => AbType::Autotools,
+			"cmake" => AbType::Cmake,
+			"cmakeninja" => AbType::CmakeNinja,
+			"dummy" => AbType::Dummy,
+			"gomod" => AbType::GoMod,
+			"meson" => AbType::Meson,
+			"npm" => AbType::Npm,
+			"perl" => AbType::Perl,
+			"plainmake" => AbType::PlainMake,
+			"python" => AbType::Python,
+			"pep517" => AbType::Pep517,
+			"qtproj" => AbType::QtProj,
+			"rust" => AbType::Rust,
+			"self" => AbType::SelfBuild,
+			"waf" => AbType::Waf,
+			other => AbType::Other(other.to_string()),
+		})
+	}
+}
+
+impl Display for AbType {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(self.as_str())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::sync::Arc;
+
+	use super::*;
+	use crate::apml::VariableValue;
+
+	#[test]
+	fn test_defines() {
+		let spec = ApmlContext::eval_source("VER=1").unwrap();
+		let mut ctx = ApmlContext::with_parent(Arc::new(spec));
+		ctx.insert(
+			"PKGNAME".to_string(),
+			VariableValue::String("a".to_string()),
+		);
+		ctx.insert(
+			"PKGDEP".to_string(),
+			VariableValue::String("b c>=1".to_string()),
+		);
+		ctx.insert(
+			"ABTYPE".to_string(),
+			VariableValue::String("cmakeninja".to_string()),
+		);
+		ctx.insert(
+			"ABHOST".to_string(),
+			VariableValue::String("noarch".to_string()),
+		);
+		let defines = Defines::try_from(&ctx).unwrap();
+		assert_eq!(defines.pkgname, "a");
+		assert_eq!(defines.pkgdep, vec!["b", "c>=1"]);
+		assert!(defines.builddep.is_empty());
+		assert_eq!(defines.abtype, Some(AbType::CmakeNinja));
+		assert!(defines.is_noarch());
+		assert_eq!(defines.fail_arch, None);
+		assert!(!defines.nopython2);
+
+		let ctx = ApmlContext::eval_source(
+			"PKGNAME=a\nPKGDEP=(b c)\nABTYPE=foo\nPKGEPOCH=2\nNOPYTHON2=1",
+		)
+		.unwrap();
+		let defines = Defines::try_from(&ctx).unwrap();
+		assert_eq!(defines.pkgdep, vec!["b", "c"]);
+		assert_eq!(defines.abtype, Some(AbType::Other("foo".to_string())));
+		assert_eq!(defines.abtype.as_ref().unwrap().to_string(), "foo");
+		assert_eq!(defines.pkgepoch, Some(2));
+		assert!(!defines.is_noarch());
+		assert!(defines.nopython2);
+
+		let ctx =
+			ApmlContext::eval_source("ABTYPE=python\nPKGEPOCH=x").unwrap();
+		assert!(Defines::try_from(&ctx).is_err());
+		assert_eq!(AbType::read(&ctx), Some(AbType::Python));
+		assert_eq!(AbType::read(&ApmlContext::default()), None);
+	}
+
+	#[test]
+	fn test_fail_arch() {
+		use Architecture::*;
+		assert_eq!(fail_arch_excludes("amd64").unwrap(), vec![Amd64]);
+		assert_eq!(
+			fail_arch_excludes("!(amd64|arm64)").unwrap(),
+			vec![LoongArch64, Riscv, Loongson3, Ppc64el]
+		);
+		assert_eq!(
+			fail_arch_excludes("@(loong*|ppc64el)").unwrap(),
+			vec![LoongArch64, Loongson3, Ppc64el]
+		);
+		assert!(fail_arch_excludes("i486").unwrap().is_empty());
+		assert!(fail_arch_excludes("!(amd64").is_err());
+
+		let defines = Defines {
+			fail_arch: Some("!(amd64|arm64)".to_string()),
+			..Default::default()
+		};
+		assert_eq!(defines.build_archs().unwrap(), vec![Amd64, Arm64]);
+		let defines = Defines {
+			abhost: Some("noarch".to_string()),
+			..defines
+		};
+		assert_eq!(defines.excluded_archs().unwrap().len(), 4);
+		assert_eq!(defines.build_archs().unwrap(), vec![NoArch]);
+		assert!(Defines::default().excluded_archs().unwrap().is_empty());
+		assert_eq!(Defines::default().build_archs().unwrap().len(), 6);
+	}
+
+	#[test]
+	fn test_abtype() {
+		for abtype in ["autotools", "cmakeninja", "pep517", "self", "foo"] {
+			assert_eq!(AbType::from_str(abtype).unwrap().as_str(), abtype);
+		}
+		assert_eq!(AbType::from_str("self").unwrap(), AbType::SelfBuild);
+	}
+}
@@ -0,0 +1,229 @@
+//! Dependency atoms in `PKGDEP`, `BUILDDEP`, `PKGBREAK` and alike.
+//!
+//! An atom is a package name optionally followed by a version
+//! constraint, such as `foo>=1.2` and `bar<=2:3.0-1`. A name with a
+//! trailing underscore, such as `baz_`, requires at least the version of
+//! `baz` installed at build time.
+
+use std::{fmt::Display, str::FromStr};
+
+use super::{ModelError, version::PackageVersion};
+
+/// A dependency atom, such as `foo>=1.2`.
+///
+/// Formatting an atom gives back the text it is parsed from, as long as
+/// the version is written in the canonical form, i.e. without a zero
+/// epoch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DependencyAtom {
+	/// Name of the package.
+	pub name: String,
+	/// Constraint on the version.
+	pub constraint: Constraint,
+}
+
+/// Constraint on the version of a dependency.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Constraint {
+	/// Any version.
+	Any,
+	/// At least the version installed at build time (`name_`).
+	Implicit,
+	/// A explicit version constraint.
+	Version {
+		/// The comparison operator.
+		op: Operator,
+		/// The version to compare with.
+		version: PackageVersion,
+	},
+}
+
+/// Comparison operator of a version constraint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operator {
+	/// `<`
+	Lt,
+	/// `<=`
+	Le,
+	/// `==`
+	Eq,
+	/// `>=`
+	Ge,
+	/// `>`
+	Gt,
+}
+
+impl Operator {
+	/// Returns the textual form of the operator.
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Operator::Lt => "<",
+			Operator::Le => "<=",
+			Operator::Eq => "==",
+			Operator::Ge => ">=",
+			Operator::Gt => ">",
+		}
+	}
+
+	/// Returns if `left op right` holds.
+	pub fn compare(
+		&self,
+		left: &PackageVersion,
+		right: &PackageVersion,
+	) -> bool {
+		match self {
+			Operator::Lt => left < right,
+			Operator::Le => left <= right,
+			Operator::Eq => left == right,
+			Operator::Ge => left >= right,
+			Operator::Gt => left > right,
+		}
+	}
+}
+
+impl Display for Operator {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(self.as_str())
+	}
+}
+
+impl DependencyAtom {
+	/// Creates a atom accepting any version of a package.
+	pub fn new<S: Into<String>>(name: S) -> Self {
+		Self {
+			name: name.into(),
+			constraint: Constraint::Any,
+		}
+	}
+
+	/// Returns if a version of the package satisfies the atom.
+	///
+	/// The build-time version of [`Constraint::Implicit`] is unknown, so
+	/// such constraints are always satisfied.
+	pub fn is_satisfied_by(&self, version: &PackageVersion) -> bool {
+		match &self.constraint {
+			Constraint::Any | Constraint::Implicit => true,
+			Constraint::Version {
+				op,
+				version: required,
+			} => op.compare(version, required),
+		}
+	}
+}
+
+impl FromStr for DependencyAtom {
+	type Err = ModelError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let invalid = || ModelError::InvalidDependency {
+			value: s.to_string(),
+		};
+		let (name, constraint) = match s.find(['<', '>', '=']) {
+			Some(pos) => {
+				let (name, rest) = s.split_at(pos);
+				let (op, version) = [
+					Operator::Le,
+					Operator::Ge,
+					Operator::Eq,
+					Operator::Lt,
+					Operator::Gt,
+				]
+				.into_iter()
+				.find_map(|op| {
+					rest.strip_prefix(op.as_str()).map(|version| (op, version))
+				})
+				.ok_or_else(invalid)?;
+				let version = version.parse().map_err(|_| invalid())?;
+				(name, Constraint::Version { op, version })
+			}
+			None => match s.strip_suffix('_') {
+				Some(name) => (name, Constraint::Implicit),
+				None => (s, Constraint::Any),
+			},
+		};
+		if name.is_empty()
+			|| !name.chars().all(|ch| {
+				ch.is_ascii_alphanumeric()
+					|| matches!(ch, '+' | '-' | '.' | '_')
+			}) {
+			return Err(invalid());
+		}
+		Ok(Self {
+			name: name.to_string(),
+			constraint,
+		})
+	}
+}
+
+impl Display for DependencyAtom {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(&self.name)?;
+		match &self.constraint {
+			Constraint::Any => Ok(()),
+			Constraint::Implicit => f.write_str("_"),
+			Constraint::Version { op, version } => write!(f, "{op}{version}"),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn atom(s: &str) -> DependencyAtom {
+		s.parse().unwrap()
+	}
+
+	#[test]
+	fn test_dependency_atom() {
+		assert_eq!(atom("foo"), DependencyAtom::new("foo"));
+		assert_eq!(
+			atom("baz_"),
+			DependencyAtom {
+				name: "baz".to_string(),
+				constraint: Constraint::Implicit,
+			}
+		);
+		let bar = atom("bar<=2:3.0-1");
+		assert_eq!(bar.name, "bar");
+		assert_eq!(
+			bar.constraint,
+			Constraint::Version {
+				op: Operator::Le,
+				version: "2:3.0-1".parse().unwrap(),
+			}
+		);
+		for s in [
+			"foo",
+			"baz_",
+			"bar<=2:3.0-1",
+			"python-3>=3.11",
+			"a<1",
+			"b>1~rc",
+			"c==1.0",
+			"g++",
+			"lib_a.so",
+		] {
+			assert_eq!(atom(s).to_string(), s);
+		}
+		for s in ["", ">=1", "a=1", "a>=", "a b", "a>=1 2"] {
+			assert!(s.parse::<DependencyAtom>().is_err(), "{s}");
+		}
+	}
+
+	#[test]
+	fn test_satisfaction() {
+		let version = |s: &str| s.parse::<PackageVersion>().unwrap();
+		let py = atom("python-3>=3.11");
+		assert!(py.is_satisfied_by(&version("3.11")));
+		assert!(py.is_satisfied_by(&version("3.12.1-2")));
+		assert!(!py.is_satisfied_by(&version("3.10.9")));
+		assert!(!py.is_satisfied_by(&version("3.11~rc1")));
+		assert!(atom("a<1").is_satisfied_by(&version("0.9")));
+		assert!(!atom("a>1").is_satisfied_by(&version("1")));
+		assert!(atom("a>1").is_satisfied_by(&version("1.0")));
+		assert!(atom("a==1.0").is_satisfied_by(&version("1.00")));
+		assert!(atom("a_").is_satisfied_by(&version("0")));
+		assert!(atom("a").is_satisfied_by(&version("0")));
+	}
+}
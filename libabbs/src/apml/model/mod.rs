@@ -0,0 +1,97 @@
+//! Typed views of common package metadata.
+//!
+//! - [Spec][spec::Spec]: the `spec` file of a package.
+//! - [Defines][defines::Defines]: the `defines` file of a sub-package.
+//! - [Source][source::Source] and [Checksum][source::Checksum]: entries of
+//!   `SRCS` and `CHKSUMS`.
+//! - [PackageVersion][version::PackageVersion]: the full version of a
+//!   package, with dpkg-compatible comparison.
+//! - [DependencyAtom][dependency::DependencyAtom]: entries of `PKGDEP`,
+//!   `BUILDDEP` and alike.
+//!
+//! Views are built from evaluated [`ApmlContext`]s, so they reflect
+//! variables from parent contexts as well.
+
+use thiserror::Error;
+
+use super::{ApmlContext, parser::ParseError};
+
+pub mod defines;
+pub mod dependency;
+pub mod source;
+pub mod spec;
+pub mod version;
+
+/// Error from building typed views.
+#[derive(Debug, Error)]
+pub enum ModelError {
+	#[error("Invalid entry in {name}: {value:?}: {source}")]
+	InvalidUnion {
+		/// Name of the variable.
+		name: &'static str,
+		/// The entry failed to be parsed.
+		value: String,
+		source: ParseError,
+	},
+	#[error("Invalid checksum in CHKSUMS: {value:?}")]
+	InvalidChecksum {
+		/// The entry failed to be parsed.
+		value: String,
+	},
+	#[error("Invalid package version: {value:?}")]
+	InvalidVersion {
+		/// The version failed to be parsed.
+		value: String,
+	},
+	#[error("Invalid dependency: {value:?}")]
+	InvalidDependency {
+		/// The entry failed to be parsed.
+		value: String,
+	},
+	#[error("Invalid pattern in {name}: {value:?}")]
+	InvalidPattern {
+		/// Name of the variable.
+		name: &'static str,
+		/// The value failed to be parsed.
+		value: String,
+	},
+	#[error("Invalid integer in {name}: {value:?}")]
+	InvalidInteger {
+		/// Name of the variable.
+		name: &'static str,
+		/// The value failed to be parsed.
+		value: String,
+	},
+}
+
+/// Reads a variable as a trimmed string.
+fn read_string(ctx: &ApmlContext, name: &str) -> String {
+	ctx.read(name).into_string().trim().to_string()
+}
+
+/// Reads a variable as a string, returning [None] if it is unset or empty.
+fn read_optional(ctx: &ApmlContext, name: &str) -> Option<String> {
+	Some(read_string(ctx, name)).filter(|value| !value.is_empty())
+}
+
+/// Reads a variable as a boolean, following Autobuild's `bool` rules.
+fn read_bool(ctx: &ApmlContext, name: &str) -> bool {
+	matches!(
+		read_string(ctx, name).to_ascii_lowercase().as_str(),
+		"1" | "y" | "yes" | "true"
+	)
+}
+
+/// Reads a variable as an optional integer.
+fn read_integer(
+	ctx: &ApmlContext,
+	name: &'static str,
+) -> Result<Option<u32>, ModelError> {
+	read_optional(ctx, name)
+		.map(|value| {
+			value
+				.parse()
+				.map_err(|_| ModelError::InvalidInteger { name, value })
+		})
+		.transpose()
+}
@@ -0,0 +1,315 @@
+//! Typed view of `SRCS` and `CHKSUMS` entries.
+
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+use kstring::KString;
+
+use crate::apml::{ApmlContext, value::union::Union};
+
+use super::ModelError;
+
+/// A source of a package, parsed from a `SRCS` entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Source {
+	/// Type of the source.
+	pub kind: SourceKind,
+	/// Location of the source, such as the URL, the file name for `file`
+	/// and the package name for `pypi`.
+	///
+	/// It is empty for `none` sources.
+	pub location: String,
+	/// Revision to check out for VCS sources (`commit=`).
+	pub commit: Option<String>,
+	/// Name to save the source as (`rename=`).
+	pub rename: Option<String>,
+	/// Whether to copy the whole repository for VCS sources
+	/// (`copy-repo=`).
+	pub copy_repo: bool,
+	/// Version of the package for `pypi` sources (`version=`).
+	pub version: Option<String>,
+	/// Other properties.
+	pub properties: HashMap<KString, String>,
+}
+
+/// Type of a [`Source`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SourceKind {
+	/// A tarball or other files downloaded from URL (`tbl` or `tarball`).
+	Tarball,
+	/// A Git repository (`git`).
+	Git,
+	/// A Subversion repository (`svn`).
+	Svn,
+	/// A Mercurial repository (`hg`).
+	Hg,
+	/// A Bazaar repository (`bzr`).
+	Bzr,
+	/// A Fossil repository (`fossil`).
+	Fossil,
+	/// A local file (`file`).
+	File,
+	/// A package from PyPI (`pypi`).
+	Pypi,
+	/// No source (`none`).
+	None,
+	/// Other unknown types.
+	Other(String),
+}
+
+impl SourceKind {
+	/// Returns the tag of the source type.
+	pub fn as_str(&self) -> &str {
+		match self {
+			Self::Tarball => "tbl",
+			Self::Git => "git",
+			Self::Svn => "svn",
+			Self::Hg => "hg",
+			Self::Bzr => "bzr",
+			Self::Fossil => "fossil",
+			Self::File => "file",
+			Self::Pypi => "pypi",
+			Self::None => "none",
+			Self::Other(tag) => tag,
+		}
+	}
+
+	/// Returns if the source is a VCS repository.
+	pub fn is_vcs(&self) -> bool {
+		matches!(
+			self,
+			Self::Git | Self::Svn | Self::Hg | Self::Bzr | Self::Fossil
+		)
+	}
+}
+
+impl FromStr for SourceKind {
+	type Err = std::convert::Infallible;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Ok(match s.to_ascii_lowercase().as_str() {
+			"tbl" | "tarball" => Self::Tarball,
+			"git" => Self::Git,
+			"svn" => Self::Svn,
+			"hg" => Self::Hg,
+			"bzr" => Self::Bzr,
+			"fossil" => Self::Fossil,
+			"file" => Self::File,
+			"pypi" => Self::Pypi,
+			"none" => Self::None,
+			_ => Self::Other(s.to_string()),
+		})
+	}
+}
+
+impl Display for SourceKind {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(self.as_str())
+	}
+}
+
+impl Source {
+	/// Parses a `SRCS` entry, treating bare URLs as tarballs.
+	pub fn parse(src: &str) -> Result<Self, ModelError> {
+		let src = src.trim();
+		if src.eq_ignore_ascii_case("none") {
+			return Ok(Self::from(Union::new("none")));
+		}
+		let union = if src.starts_with("https://")
+			|| src.starts_with("http://")
+			|| !src.contains("::")
+		{
+			parse_union("SRCS", format!("tbl::{src}"))?
+		} else {
+			parse_union("SRCS", src.to_string())?
+		};
+		Ok(Self::from(union))
+	}
+}
+
+impl From<Union> for Source {
+	fn from(mut union: Union) -> Self {
+		let mut take = |key: &str| union.properties.remove(key);
+		Self {
+			commit: take("commit"),
+			rename: take("rename"),
+			copy_repo: take("copy-repo")
+				.is_some_and(|value| matches!(value.as_str(), "true" | "1")),
+			version: take("version"),
+			kind: SourceKind::from_str(&union.tag).unwrap(),
+			location: union.argument.unwrap_or_default(),
+			properties: union.properties,
+		}
+	}
+}
+
+impl From<&Source> for Union {
+	fn from(value: &Source) -> Self {
+		let mut properties = value.properties.clone();
+		let mut put = |key: &'static str, value: Option<&String>| {
+			if let Some(value) = value {
+				properties.insert(KString::from_static(key), value.clone());
+			}
+		};
+		put("commit", value.commit.as_ref());
+		put("rename", value.rename.as_ref());
+		put("version", value.version.as_ref());
+		if value.copy_repo {
+			properties
+				.insert(KString::from_static("copy-repo"), "true".to_string());
+		}
+		Self {
+			tag: KString::from_ref(value.kind.as_str()),
+			properties,
+			argument: Some(value.location.clone())
+				.filter(|location| !location.is_empty()),
+		}
+	}
+}
+
+/// A checksum of a source, parsed from a `CHKSUMS` entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Checksum {
+	/// Skips verification (`SKIP`).
+	Skip,
+	/// A hash digest (`<ALGORITHM>::<DIGEST>`), such as `sha256::<HEX>`.
+	Hash {
+		/// Name of the hash algorithm, in lower case.
+		algorithm: String,
+		/// Digest in lower-case hexadecimal.
+		digest: String,
+	},
+}
+
+impl Checksum {
+	/// Parses a `CHKSUMS` entry.
+	pub fn parse(chksum: &str) -> Result<Self, ModelError> {
+		let chksum = chksum.trim();
+		if chksum.eq_ignore_ascii_case("SKIP") {
+			return Ok(Self::Skip);
+		}
+		let union = parse_union("CHKSUMS", chksum.to_string())?;
+		match union.argument {
+			Some(digest) if union.properties.is_empty() => Ok(Self::Hash {
+				algorithm: union.tag.to_ascii_lowercase(),
+				digest: digest.to_ascii_lowercase(),
+			}),
+			_ => Err(ModelError::InvalidChecksum {
+				value: chksum.to_string(),
+			}),
+		}
+	}
+}
+
+impl Display for Checksum {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Skip => f.write_str("SKIP"),
+			Self::Hash { algorithm, digest } => {
+				write!(f, "{algorithm}::{digest}")
+			}
+		}
+	}
+}
+
+/// Reads all sources of a context (`SRCS`).
+pub fn read_sources(ctx: &ApmlContext) -> Result<Vec<Source>, ModelError> {
+	ctx.read("SRCS")
+		.into_array()
+		.iter()
+		.map(|src| Source::parse(src))
+		.collect()
+}
+
+/// Reads all checksums of a context (`CHKSUMS`).
+pub fn read_checksums(ctx: &ApmlContext) -> Result<Vec<Checksum>, ModelError> {
+	ctx.read("CHKSUMS")
+		.into_array()
+		.iter()
+		.map(|chksum| Checksum::parse(chksum))
+		.collect()
+}
+
+/// Pairs sources with their checksums by position.
+///
+/// Sources without a corresponding `CHKSUMS` entry are paired with
+/// [None]. This works on both parsed entries and raw strings, so that
+/// callers may parse only the entries they use.
+pub fn pair_checksums<'a, S, C>(
+	srcs: &'a [S],
+	chksums: &'a [C],
+) -> impl Iterator<Item = (&'a S, Option<&'a C>)> {
+	srcs.iter()
+		.enumerate()
+		.map(|(idx, src)| (src, chksums.get(idx)))
+}
+
+pub(super) fn parse_union(
+	name: &'static str,
+	value: String,
+) -> Result<Union, ModelError> {
+	Union::try_from(value.as_str()).map_err(|source| ModelError::InvalidUnion {
+		name,
+		value,
+		source,
+	})
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_source() {
+		let src = Source::parse("https://a.com/a.tar.gz").unwrap();
+		assert_eq!(src.kind, SourceKind::Tarball);
+		assert_eq!(src.location, "https://a.com/a.tar.gz");
+		let src = Source::parse(
+			"git::commit=tags/v1;copy-repo=true;submodule=recursive::https://a.com/a.git",
+		)
+		.unwrap();
+		assert_eq!(src.kind, SourceKind::Git);
+		assert!(src.kind.is_vcs());
+		assert_eq!(src.commit.as_deref(), Some("tags/v1"));
+		assert!(src.copy_repo);
+		assert_eq!(src.properties["submodule"], "recursive");
+		assert_eq!(
+			Union::from(&src).print(),
+			"git::commit=tags/v1;copy-repo=true;submodule=recursive::https://a.com/a.git"
+		);
+		let src = Source::parse("pypi::version=1.0::foo").unwrap();
+		assert_eq!(src.kind, SourceKind::Pypi);
+		assert_eq!(src.version.as_deref(), Some("1.0"));
+		assert_eq!(src.location, "foo");
+		let src =
+			Source::parse("tarball::rename=b.tgz::https://a.com/a").unwrap();
+		assert_eq!(src.kind, SourceKind::Tarball);
+		assert_eq!(src.rename.as_deref(), Some("b.tgz"));
+		let src = Source::parse("none").unwrap();
+		assert_eq!(src.kind, SourceKind::None);
+		assert!(src.location.is_empty());
+		let src = Source::parse("none::").unwrap_err();
+		assert!(matches!(src, ModelError::InvalidUnion { .. }));
+		let src = Source::parse("cvs::https://a.com").unwrap();
+		assert_eq!(src.kind, SourceKind::Other("cvs".to_string()));
+	}
+
+	#[test]
+	fn test_checksum() {
+		assert_eq!(Checksum::parse("SKIP").unwrap(), Checksum::Skip);
+		assert_eq!(
+			Checksum::parse("SHA256::ABCD").unwrap(),
+			Checksum::Hash {
+				algorithm: "sha256".to_string(),
+				digest: "abcd".to_string(),
+			}
+		);
+		assert_eq!(
+			Checksum::parse("sha256::abcd").unwrap().to_string(),
+			"sha256::abcd"
+		);
+		assert!(matches!(
+			Checksum::parse("sha256").unwrap_err(),
+			ModelError::InvalidChecksum { .. }
+		));
+	}
+}
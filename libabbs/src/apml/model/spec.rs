@@ -0,0 +1,113 @@
+//! Typed view of `spec` files.
+
+use crate::apml::{ApmlContext, value::union::Union};
+
+use super::{
+	ModelError, read_bool, read_integer, read_optional, read_string,
+	source::{
+		Checksum, Source, pair_checksums, parse_union, read_checksums,
+		read_sources,
+	},
+};
+
+/// A typed view of a `spec` file.
+#[derive(Debug, Clone, Default)]
+pub struct Spec {
+	/// Version of the package (`VER`).
+	pub ver: String,
+	/// Revision of the package (`REL`).
+	pub rel: Option<u32>,
+	/// Sources of the package (`SRCS`).
+	///
+	/// Bare URLs are treated as tarballs, i.e. `tbl::<URL>`.
+	pub srcs: Vec<Source>,
+	/// Checksums of sources (`CHKSUMS`), such as `sha256::<HASH>` and
+	/// `SKIP`, in the same order as [`srcs`][Self::srcs].
+	pub chksums: Vec<Checksum>,
+	/// Update checking configuration (`CHKUPDATE`).
+	pub chkupdate: Option<Union>,
+	/// Whether the package has no sources (`DUMMYSRC`).
+	pub dummysrc: bool,
+	/// Sub-directory of sources to build in (`SUBDIR`).
+	pub subdir: Option<String>,
+}
+
+impl TryFrom<&ApmlContext> for Spec {
+	type Error = ModelError;
+
+	fn try_from(ctx: &ApmlContext) -> Result<Self, Self::Error> {
+		Ok(Self {
+			ver: read_string(ctx, "VER"),
+			rel: read_integer(ctx, "REL")?,
+			srcs: read_sources(ctx)?,
+			chksums: read_checksums(ctx)?,
+			chkupdate: read_optional(ctx, "CHKUPDATE")
+				.map(|chkupdate| parse_union("CHKUPDATE", chkupdate))
+				.transpose()?,
+			dummysrc: read_bool(ctx, "DUMMYSRC"),
+			subdir: read_optional(ctx, "SUBDIR"),
+		})
+	}
+}
+
+impl Spec {
+	/// Iterates over sources paired with their checksums.
+	///
+	/// Checksums are paired by position. Sources without a corresponding
+	/// `CHKSUMS` entry are paired with [None].
+	pub fn sources(
+		&self,
+	) -> impl Iterator<Item = (&Source, Option<&Checksum>)> {
+		pair_checksums(&self.srcs, &self.chksums)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::apml::model::source::SourceKind;
+
+	#[test]
+	fn test_spec() {
+		let ctx = ApmlContext::eval_source(
+			"VER=1.2\nREL=3\nSRCS=\"https://a.com/a-$VER.tar.gz \
+			git::commit=tags/v$VER::https://a.com/a.git\"\n\
+			CHKSUMS=\"sha256::abcd SKIP\"\nCHKUPDATE=\"anitya::id=1\"\n\
+			SUBDIR=a",
+		)
+		.unwrap();
+		let spec = Spec::try_from(&ctx).unwrap();
+		assert_eq!(spec.ver, "1.2");
+		assert_eq!(spec.rel, Some(3));
+		assert_eq!(spec.srcs.len(), 2);
+		assert_eq!(spec.srcs[0].kind, SourceKind::Tarball);
+		assert_eq!(spec.srcs[0].location, "https://a.com/a-1.2.tar.gz");
+		assert_eq!(spec.srcs[1].kind, SourceKind::Git);
+		assert_eq!(spec.srcs[1].commit.as_deref(), Some("tags/v1.2"));
+		assert_eq!(
+			spec.chksums[0],
+			Checksum::Hash {
+				algorithm: "sha256".to_string(),
+				digest: "abcd".to_string()
+			}
+		);
+		assert_eq!(spec.chksums[1], Checksum::Skip);
+		let sources = spec.sources().collect::<Vec<_>>();
+		assert_eq!(sources[1], (&spec.srcs[1], Some(&Checksum::Skip)));
+		assert_eq!(spec.chkupdate.unwrap().properties["id"], "1");
+		assert!(!spec.dummysrc);
+		assert_eq!(spec.subdir.as_deref(), Some("a"));
+
+		let ctx = ApmlContext::eval_source("VER=1\nDUMMYSRC=1").unwrap();
+		let spec = Spec::try_from(&ctx).unwrap();
+		assert!(spec.dummysrc);
+		assert!(spec.srcs.is_empty());
+		assert_eq!(spec.rel, None);
+
+		let ctx = ApmlContext::eval_source("REL=a").unwrap();
+		assert_eq!(
+			Spec::try_from(&ctx).unwrap_err().to_string(),
+			"Invalid integer in REL: \"a\""
+		);
+	}
+}
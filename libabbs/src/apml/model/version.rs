@@ -0,0 +1,219 @@
+//! Package versions with dpkg-compatible comparison.
+
+use std::{cmp::Ordering, fmt::Display, str::FromStr};
+
+use super::{ModelError, defines::Defines, spec::Spec};
+
+/// Full version of a package, in form of `epoch:upstream-revision`.
+///
+/// Versions are compared following dpkg rules, where `~` sorts before
+/// anything, even the end of a version. Thus `1.0~rc1` is older than
+/// `1.0`. Equal versions may have different representations, such as
+/// `1.0` and `1.00`, so [`Eq`] follows the ordering instead of the
+/// strings.
+#[derive(Debug, Clone, Default)]
+pub struct PackageVersion {
+	/// Epoch of the version (`PKGEPOCH`), omitted if zero.
+	pub epoch: u32,
+	/// Upstream version (`VER`).
+	pub upstream: String,
+	/// Revision of the version (`REL`), omitted if empty.
+	pub revision: String,
+}
+
+impl PackageVersion {
+	/// Builds the version of a sub-package from its `spec` and `defines`.
+	///
+	/// `REL=0` is treated as no revision.
+	pub fn new(spec: &Spec, defines: &Defines) -> Self {
+		Self {
+			epoch: defines.pkgepoch.unwrap_or_default(),
+			upstream: spec.ver.clone(),
+			revision: spec
+				.rel
+				.filter(|rel| *rel != 0)
+				.map(|rel| rel.to_string())
+				.unwrap_or_default(),
+		}
+	}
+}
+
+impl FromStr for PackageVersion {
+	type Err = ModelError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let invalid = || ModelError::InvalidVersion {
+			value: s.to_string(),
+		};
+		let (epoch, rest) = match s.split_once(':') {
+			Some((epoch, rest)) => {
+				(epoch.parse().map_err(|_| invalid())?, rest)
+			}
+			None => (0, s),
+		};
+		let (upstream, revision) = match rest.rsplit_once('-') {
+			Some((upstream, revision)) if !revision.is_empty() => {
+				(upstream, revision)
+			}
+			Some(_) => return Err(invalid()),
+			None => (rest, ""),
+		};
+		if upstream.is_empty() || upstream.contains(char::is_whitespace) {
+			return Err(invalid());
+		}
+		Ok(Self {
+			epoch,
+			upstream: upstream.to_string(),
+			revision: revision.to_string(),
+		})
+	}
+}
+
+impl Display for PackageVersion {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		if self.epoch != 0 {
+			write!(f, "{}:", self.epoch)?;
+		}
+		f.write_str(&self.upstream)?;
+		if !self.revision.is_empty() {
+			write!(f, "-{}", self.revision)?;
+		}
+		Ok(())
+	}
+}
+
+impl Ord for PackageVersion {
+	fn cmp(&self, other: &Self) -> Ordering {
+		self.epoch
+			.cmp(&other.epoch)
+			.then_with(|| compare_part(&self.upstream, &other.upstream))
+			.then_with(|| compare_part(&self.revision, &other.revision))
+	}
+}
+
+impl PartialOrd for PackageVersion {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl PartialEq for PackageVersion {
+	fn eq(&self, other: &Self) -> bool {
+		self.cmp(other) == Ordering::Equal
+	}
+}
+
+impl Eq for PackageVersion {}
+
+/// Compares upstream versions or revisions, following `verrevcmp` of
+/// dpkg.
+///
+/// Versions are split into alternating non-digit and digit parts.
+/// Non-digit parts are compared by characters, where `~` sorts first,
+/// then the end of part, then letters, then other characters. Digit
+/// parts are compared numerically.
+pub fn compare_part(a: &str, b: &str) -> Ordering {
+	let (mut a, mut b) = (a.as_bytes(), b.as_bytes());
+	while !a.is_empty() || !b.is_empty() {
+		while a.first().is_some_and(|ch| !ch.is_ascii_digit())
+			|| b.first().is_some_and(|ch| !ch.is_ascii_digit())
+		{
+			let (ac, bc) = (char_order(a.first()), char_order(b.first()));
+			if ac != bc {
+				return ac.cmp(&bc);
+			}
+			a = &a[1..];
+			b = &b[1..];
+		}
+		let (a_digits, a_rest) = split_digits(a);
+		let (b_digits, b_rest) = split_digits(b);
+		let ordering = a_digits
+			.len()
+			.cmp(&b_digits.len())
+			.then_with(|| a_digits.cmp(b_digits));
+		if ordering.is_ne() {
+			return ordering;
+		}
+		(a, b) = (a_rest, b_rest);
+	}
+	Ordering::Equal
+}
+
+/// Returns the weight of a character in non-digit parts.
+fn char_order(ch: Option<&u8>) -> i32 {
+	match ch {
+		Some(b'~') => -1,
+		Some(ch) if ch.is_ascii_digit() => 0,
+		Some(ch) if ch.is_ascii_alphabetic() => *ch as i32,
+		Some(ch) => *ch as i32 + 256,
+		None => 0,
+	}
+}
+
+/// Splits the leading digits without leading zeros.
+fn split_digits(s: &[u8]) -> (&[u8], &[u8]) {
+	let end = s.iter().take_while(|ch| ch.is_ascii_digit()).count();
+	let (digits, rest) = s.split_at(end);
+	let zeros = digits.iter().take_while(|ch| **ch == b'0').count();
+	(&digits[zeros..], rest)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::apml::ApmlContext;
+
+	fn version(s: &str) -> PackageVersion {
+		s.parse().unwrap()
+	}
+
+	#[test]
+	fn test_version() {
+		let spec = Spec::try_from(
+			&ApmlContext::eval_source("VER=1.2.3\nREL=2").unwrap(),
+		)
+		.unwrap();
+		let defines = Defines::try_from(
+			&ApmlContext::eval_source("PKGNAME=a\nPKGEPOCH=1").unwrap(),
+		)
+		.unwrap();
+		let ver = PackageVersion::new(&spec, &defines);
+		assert_eq!(ver.to_string(), "1:1.2.3-2");
+		assert_eq!(ver, version("1:1.2.3-2"));
+		let spec = Spec::try_from(
+			&ApmlContext::eval_source("VER=1.2\nREL=0").unwrap(),
+		)
+		.unwrap();
+		let ver = PackageVersion::new(&spec, &Defines::default());
+		assert_eq!(ver.to_string(), "1.2");
+
+		let ver = version("2:1.0-rc1-3");
+		assert_eq!(ver.epoch, 2);
+		assert_eq!(ver.upstream, "1.0-rc1");
+		assert_eq!(ver.revision, "3");
+		assert!("a:1".parse::<PackageVersion>().is_err());
+		assert!("1.0-".parse::<PackageVersion>().is_err());
+		assert!("".parse::<PackageVersion>().is_err());
+	}
+
+	#[test]
+	fn test_compare() {
+		let ordered = [
+			"0.9", "1.0~~", "1.0~rc1", "1.0", "1.0-1", "1.0-1.1", "1.0-2",
+			"1.0a", "1.0+dfsg", "1.1", "1.10", "2", "1:0.1",
+		];
+		for (idx, a) in ordered.iter().enumerate() {
+			for (jdx, b) in ordered.iter().enumerate() {
+				assert_eq!(
+					version(a).cmp(&version(b)),
+					idx.cmp(&jdx),
+					"{a} <=> {b}"
+				);
+			}
+		}
+		assert_eq!(version("1.0"), version("1.00"));
+		assert_eq!(version("0:1.0"), version("1.0"));
+		assert_eq!(compare_part("", "~"), Ordering::Greater);
+		assert_eq!(compare_part("a", ""), Ordering::Greater);
+	}
+}
@@ -1,6 +1,6 @@
 //! Parser combinators to parse APML source code to [LST][super::lst].
 
-use std::{borrow::Cow, sync::Arc};
+use std::{borrow::Cow, fmt::Display, sync::Arc};
 
 use nom::{
 	IResult, Parser,
@@ -15,7 +15,7 @@ use thiserror::Error;
 
 use crate::apml::pattern::{BashPattern, bash_pattern};
 
-use super::lst::*;
+use super::{lst::*, span::SourceLocation};
 
 /// Errors produced while parsing the input source.
 #[derive(Debug, Error)]
@@ -24,6 +24,143 @@ pub enum ParseError {
 	SyntaxError(String),
 	#[error("Unexpected source at char {pos}")]
 	UnexpectedSource { pos: usize },
+	/// The source contains syntax errors, see [`diagnose`].
+	#[error("{}", display_diagnostics(.0))]
+	Invalid(Vec<ParseDiagnostic>),
+}
+
+impl ParseError {
+	/// Returns the diagnostics of syntax errors.
+	pub fn diagnostics(&self) -> &[ParseDiagnostic] {
+		match self {
+			ParseError::Invalid(diagnostics) => diagnostics,
+			_ => &[],
+		}
+	}
+
+	/// Sets the path to the source file in diagnostics.
+	#[must_use]
+	pub fn with_file(mut self, file: &str) -> Self {
+		if let ParseError::Invalid(diagnostics) = &mut self {
+			for diagnostic in diagnostics {
+				diagnostic.location.file = Some(file.to_string());
+			}
+		}
+		self
+	}
+}
+
+fn display_diagnostics(diagnostics: &[ParseDiagnostic]) -> String {
+	diagnostics
+		.iter()
+		.map(ToString::to_string)
+		.collect::<Vec<_>>()
+		.join("\n")
+}
+
+/// A syntax error found in the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseDiagnostic {
+	/// Kind of the error, describing what was expected.
+	pub kind: SyntaxErrorKind,
+	/// Byte offset of the error.
+	pub pos: usize,
+	/// Location of the error.
+	pub location: SourceLocation,
+	/// The source line containing the error.
+	pub excerpt: String,
+}
+
+impl ParseDiagnostic {
+	/// Creates a diagnostic at the given byte offset of the source.
+	pub fn new(src: &str, pos: usize, kind: SyntaxErrorKind) -> Self {
+		let line_start = src[..pos].rfind('\n').map_or(0, |idx| idx + 1);
+		let line_end = src[pos..].find('\n').map_or(src.len(), |idx| pos + idx);
+		Self {
+			kind,
+			pos,
+			location: SourceLocation::from_offset(src, pos),
+			excerpt: src[line_start..line_end].to_string(),
+		}
+	}
+}
+
+impl Display for ParseDiagnostic {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		// keep tabs so that the marker is aligned
+		let marker = self
+			.excerpt
+			.chars()
+			.take(self.location.column - 1)
+			.map(|ch| if ch == '\t' { '\t' } else { ' ' })
+			.collect::<String>();
+		f.write_fmt(format_args!(
+			"{}: {}\n | {}\n | {marker}^",
+			self.location, self.kind, self.excerpt
+		))
+	}
+}
+
+/// Kinds of syntax errors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyntaxErrorKind {
+	/// A variable definition, a comment or a newline was expected.
+	ExpectedStatement,
+	/// A variable operator was expected after the variable name.
+	ExpectedOperator,
+	/// A single-quoted text is not terminated.
+	UnterminatedSingleQuote,
+	/// A double-quoted text is not terminated.
+	UnterminatedDoubleQuote,
+	/// A braced variable expansion is not closed.
+	UnbalancedBrace,
+	/// A sub-command expansion is not closed.
+	UnterminatedSubcommand,
+	/// A arithmetic expansion is not closed.
+	UnterminatedArithmetic,
+	/// A array value is not closed.
+	UnterminatedArray,
+	/// A braced variable expansion is malformed.
+	InvalidExpansion,
+	/// A unexpected character.
+	UnexpectedChar(char),
+}
+
+impl Display for SyntaxErrorKind {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			SyntaxErrorKind::ExpectedStatement => f.write_str(
+				"expected a variable definition, a comment or a newline",
+			),
+			SyntaxErrorKind::ExpectedOperator => {
+				f.write_str("expected `=` or `+=` after variable name")
+			}
+			SyntaxErrorKind::UnterminatedSingleQuote => {
+				f.write_str("unterminated single quote, expected `'`")
+			}
+			SyntaxErrorKind::UnterminatedDoubleQuote => {
+				f.write_str("unterminated double quote, expected `\"`")
+			}
+			SyntaxErrorKind::UnbalancedBrace => {
+				f.write_str("unbalanced `${`, expected `}`")
+			}
+			SyntaxErrorKind::UnterminatedSubcommand => {
+				f.write_str("unterminated `$(`, expected `)`")
+			}
+			SyntaxErrorKind::UnterminatedArithmetic => {
+				f.write_str("unterminated `$((`, expected `))`")
+			}
+			SyntaxErrorKind::UnterminatedArray => {
+				f.write_str("unterminated array, expected `)`")
+			}
+			SyntaxErrorKind::InvalidExpansion => f.write_str(
+				"invalid variable expansion, expected a variable name and an optional modifier",
+			),
+			SyntaxErrorKind::UnexpectedChar(ch) => {
+				f.write_fmt(format_args!("unexpected character {ch:?}"))
+			}
+		}
+	}
 }
 
 impl From<nom::Err<nom::error::Error<&str>>> for ParseError {
@@ -126,6 +263,33 @@ fn array_token(i: &'_ str) -> IResult<&'_ str, ArrayToken<'_>> {
 	.parse(i)
 }
 
+/// Parses an arithmetic expansion (`"$((<expression>))"`).
+fn arithmetic(i: &'_ str) -> IResult<&'_ str, &'_ str> {
+	delimited(tag("$(("), arithmetic_expr, tag("))")).parse(i)
+}
+
+/// Takes an arithmetic expression until the unbalanced `)`.
+fn arithmetic_expr(i: &'_ str) -> IResult<&'_ str, &'_ str> {
+	let mut depth = 0usize;
+	for (idx, ch) in i.char_indices() {
+		match ch {
+			'(' => depth += 1,
+			')' if depth == 0 => return Ok((&i[idx..], &i[..idx])),
+			')' => depth -= 1,
+			_ => {}
+		}
+	}
+	Err(nom::Err::Error(nom::error::Error::new(
+		i,
+		nom::error::ErrorKind::TakeUntil,
+	)))
+}
+
+/// Parses a sub-command expansion (`"$(<tokens>)"`).
+pub fn subcommand(i: &'_ str) -> IResult<&'_ str, Vec<ArrayToken<'_>>> {
+	delimited(tag("$("), many0(array_token), char(')')).parse(i)
+}
+
 #[inline]
 fn text<'a, Cond>(i: &'a str, cond: &Cond) -> IResult<&'a str, Text<'a>>
 where
@@ -202,11 +366,10 @@ where
 		map(preceded(char('$'), variable_name), |name| {
 			Word::UnbracedVariable(Cow::Borrowed(name))
 		}),
+		// arithmetic
+		map(arithmetic, |expr| Word::Arithmetic(Cow::Borrowed(expr))),
 		// subcommand
-		map(
-			delimited(tag("$("), many0(array_token), char(')')),
-			Word::Subcommand,
-		),
+		map(subcommand, Word::Subcommand),
 		// literal
 		map(many1(|s| literal_part(s, cond, escape_cond)), Word::Literal),
 	))
@@ -405,6 +568,336 @@ fn substring_expansion_modifier(
 	.parse(i)
 }
 
+/// Finds all syntax errors in a APML source.
+///
+/// After an error is found, parsing is resumed from the next line,
+/// so that errors in the rest of the source are also reported.
+pub fn diagnose(src: &str) -> Vec<ParseDiagnostic> {
+	let mut result = Vec::new();
+	let mut start = 0;
+	while start < src.len() {
+		let chunk = &src[start..];
+		let pos = match apml_lst(chunk) {
+			Ok(("", _)) => break,
+			Ok((out, _)) => chunk.len() - out.len(),
+			Err(_) => 0,
+		};
+		// prefer the explanation of scanner if it is not beyond the
+		// line where the parser stopped
+		let line_end =
+			chunk[pos..].find('\n').map_or(chunk.len(), |idx| pos + idx);
+		let (pos, kind) = match Scanner::new(chunk).scan() {
+			Err((scan_pos, kind)) if scan_pos <= line_end => (scan_pos, kind),
+			_ => {
+				let kind = if chunk[pos..].starts_with("${") {
+					SyntaxErrorKind::InvalidExpansion
+				} else if let Some(ch) = chunk[pos..].chars().next() {
+					SyntaxErrorKind::UnexpectedChar(ch)
+				} else {
+					SyntaxErrorKind::ExpectedStatement
+				};
+				(pos, kind)
+			}
+		};
+		result.push(ParseDiagnostic::new(src, start + pos, kind));
+		// resume from the line after the parser stopped
+		start += line_end + 1;
+	}
+	result
+}
+
+type ScanResult = Result<(), (usize, SyntaxErrorKind)>;
+
+/// Reports unclosed structures nested in another one as the outer one.
+fn enclose(
+	result: ScanResult,
+	open: usize,
+	kind: SyntaxErrorKind,
+) -> ScanResult {
+	match result {
+		Err((
+			_,
+			SyntaxErrorKind::UnterminatedSingleQuote
+			| SyntaxErrorKind::UnterminatedDoubleQuote
+			| SyntaxErrorKind::UnbalancedBrace
+			| SyntaxErrorKind::UnterminatedSubcommand
+			| SyntaxErrorKind::UnterminatedArithmetic
+			| SyntaxErrorKind::UnterminatedArray,
+		)) => Err((open, kind)),
+		result => result,
+	}
+}
+
+/// A lenient scanner tracking quotes, expansions and arrays.
+///
+/// This is used to explain why the parser stopped. It accepts a superset
+/// of APML and only reports unclosed structures and malformed statements.
+struct Scanner<'a> {
+	src: &'a str,
+	pos: usize,
+}
+
+impl<'a> Scanner<'a> {
+	fn new(src: &'a str) -> Self {
+		Self { src, pos: 0 }
+	}
+
+	fn rest(&self) -> &'a str {
+		&self.src[self.pos..]
+	}
+
+	fn peek(&self) -> Option<char> {
+		self.rest().chars().next()
+	}
+
+	fn bump(&mut self) {
+		if let Some(ch) = self.peek() {
+			self.pos += ch.len_utf8();
+		}
+	}
+
+	fn eat(&mut self, token: &str) -> bool {
+		if self.rest().starts_with(token) {
+			self.pos += token.len();
+			true
+		} else {
+			false
+		}
+	}
+
+	fn name(&mut self) -> bool {
+		let start = self.pos;
+		while let Some(ch) = self.peek()
+			&& (ch.is_alphanumeric() || ch == '_')
+		{
+			self.bump();
+		}
+		self.pos != start
+	}
+
+	fn skip_comment(&mut self) {
+		while let Some(ch) = self.peek()
+			&& ch != '\n'
+		{
+			self.bump();
+		}
+	}
+
+	fn scan(&mut self) -> ScanResult {
+		while let Some(ch) = self.peek() {
+			match ch {
+				' ' | '\t' | '\n' => self.bump(),
+				'#' => self.skip_comment(),
+				_ => self.statement()?,
+			}
+		}
+		Ok(())
+	}
+
+	fn statement(&mut self) -> ScanResult {
+		if !self.name() {
+			return Err((self.pos, SyntaxErrorKind::ExpectedStatement));
+		}
+		if !self.eat("=") && !self.eat("+=") {
+			return Err((self.pos, SyntaxErrorKind::ExpectedOperator));
+		}
+		if self.peek() == Some('(') {
+			self.tokens(self.pos, SyntaxErrorKind::UnterminatedArray)
+		} else {
+			self.text(&|ch| matches!(ch, ' ' | '#' | '\n'))
+		}
+	}
+
+	/// Scans a list of array tokens, including the parentheses.
+	fn tokens(
+		&mut self,
+		open: usize,
+		unterminated: SyntaxErrorKind,
+	) -> ScanResult {
+		self.bump();
+		loop {
+			match self.peek() {
+				None => return Err((open, unterminated)),
+				Some(')') => {
+					self.bump();
+					return Ok(());
+				}
+				Some(' ' | '\t' | '\n') => self.bump(),
+				Some('#') => self.skip_comment(),
+				Some(_) => enclose(
+					self.text(&|ch| matches!(ch, ' ' | '#' | ')' | '\n')),
+					open,
+					unterminated.clone(),
+				)?,
+			}
+		}
+	}
+
+	fn text(&mut self, stop: &dyn Fn(char) -> bool) -> ScanResult {
+		while let Some(ch) = self.peek() {
+			match ch {
+				ch if stop(ch) => break,
+				'\'' => self.single_quote()?,
+				'"' => self.double_quote()?,
+				'$' => self.dollar()?,
+				'\\' => {
+					self.bump();
+					self.bump();
+				}
+				_ => self.bump(),
+			}
+		}
+		Ok(())
+	}
+
+	fn single_quote(&mut self) -> ScanResult {
+		let open = self.pos;
+		self.bump();
+		match self.rest().find('\'') {
+			Some(idx) => {
+				self.pos += idx + 1;
+				Ok(())
+			}
+			None => Err((open, SyntaxErrorKind::UnterminatedSingleQuote)),
+		}
+	}
+
+	fn double_quote(&mut self) -> ScanResult {
+		let open = self.pos;
+		self.bump();
+		loop {
+			match self.peek() {
+				None => {
+					return Err((
+						open,
+						SyntaxErrorKind::UnterminatedDoubleQuote,
+					));
+				}
+				Some('"') => {
+					self.bump();
+					return Ok(());
+				}
+				Some('$') => enclose(
+					self.dollar(),
+					open,
+					SyntaxErrorKind::UnterminatedDoubleQuote,
+				)?,
+				Some('\\') => {
+					self.bump();
+					self.bump();
+				}
+				Some(_) => self.bump(),
+			}
+		}
+	}
+
+	fn dollar(&mut self) -> ScanResult {
+		if self.rest().starts_with("${") {
+			self.braced()
+		} else if self.rest().starts_with("$((") {
+			self.arithmetic()
+		} else if self.rest().starts_with("$(") {
+			let open = self.pos;
+			self.bump();
+			self.tokens(open, SyntaxErrorKind::UnterminatedSubcommand)
+		} else {
+			self.bump();
+			Ok(())
+		}
+	}
+
+	fn arithmetic(&mut self) -> ScanResult {
+		let open = self.pos;
+		self.pos += 3;
+		let mut depth = 0usize;
+		loop {
+			match self.peek() {
+				None => {
+					return Err((
+						open,
+						SyntaxErrorKind::UnterminatedArithmetic,
+					));
+				}
+				Some('(') => depth += 1,
+				Some(')') if depth == 0 => {
+					return if self.eat("))") {
+						Ok(())
+					} else {
+						Err((open, SyntaxErrorKind::UnterminatedArithmetic))
+					};
+				}
+				Some(')') => depth -= 1,
+				Some(_) => {}
+			}
+			self.bump();
+		}
+	}
+
+	fn braced(&mut self) -> ScanResult {
+		let open = self.pos;
+		self.pos += 2;
+		// a `}` in the same line means the expansion is malformed
+		// instead of unclosed
+		let malformed = |rest: &str| {
+			let line = rest.split('\n').next().unwrap_or_default();
+			if line.contains('}') {
+				Err((open, SyntaxErrorKind::InvalidExpansion))
+			} else {
+				Err((open, SyntaxErrorKind::UnbalancedBrace))
+			}
+		};
+		if self.eat("#") && self.name() {
+			// length of variable
+			return if self.eat("}") {
+				Ok(())
+			} else {
+				malformed(self.rest())
+			};
+		}
+		if !self.name() {
+			return malformed(self.rest());
+		}
+		match self.peek() {
+			Some('}') => {
+				self.bump();
+				Ok(())
+			}
+			Some('#' | '%' | '/' | '^' | ',' | ':' | '[') => loop {
+				match self.peek() {
+					None => {
+						return Err((open, SyntaxErrorKind::UnbalancedBrace));
+					}
+					Some('}') => {
+						self.bump();
+						return Ok(());
+					}
+					Some('\'') => enclose(
+						self.single_quote(),
+						open,
+						SyntaxErrorKind::UnbalancedBrace,
+					)?,
+					Some('"') => enclose(
+						self.double_quote(),
+						open,
+						SyntaxErrorKind::UnbalancedBrace,
+					)?,
+					Some('$') => enclose(
+						self.dollar(),
+						open,
+						SyntaxErrorKind::UnbalancedBrace,
+					)?,
+					Some('\\') => {
+						self.bump();
+						self.bump();
+					}
+					Some(_) => self.bump(),
+				}
+			},
+			_ => malformed(self.rest()),
+		}
+	}
+}
+
 #[cfg(test)]
 mod test {
 	use crate::apml::{
@@ -1291,6 +1784,10 @@ MESON_AFTER__AMD64=" \
 				})
 			)
 		);
+		assert_eq!(
+			word("$(( (a) + 1 )) a", &|ch| ch != ' ', &mut anychar).unwrap(),
+			(" a", Word::Arithmetic(Cow::Borrowed(" (a) + 1 ")))
+		);
 		word("${#abc:1} a", &|ch| ch != ' ', &mut anychar).unwrap_err();
 		word("", &|ch| ch != ' ', &mut anychar).unwrap_err();
 		assert_eq!(
@@ -1743,4 +2240,64 @@ MESON_AFTER__AMD64=" \
 		substring_expansion_modifier(":").unwrap_err();
 		substring_expansion_modifier("1").unwrap_err();
 	}
+
+	#[test]
+	fn test_diagnose() {
+		let kinds = |src| {
+			diagnose(src)
+				.into_iter()
+				.map(|diag| (diag.location.to_string(), diag.kind))
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(kinds("A=1\nB=2"), vec![]);
+		assert_eq!(
+			kinds("A=1\nB=\"abc\nC=2"),
+			vec![("2:3".to_string(), SyntaxErrorKind::UnterminatedDoubleQuote)]
+		);
+		assert_eq!(
+			kinds("A='abc"),
+			vec![("1:3".to_string(), SyntaxErrorKind::UnterminatedSingleQuote)]
+		);
+		assert_eq!(
+			kinds("aaa"),
+			vec![("1:4".to_string(), SyntaxErrorKind::ExpectedOperator)]
+		);
+		assert_eq!(
+			kinds("=1"),
+			vec![("1:1".to_string(), SyntaxErrorKind::ExpectedStatement)]
+		);
+		assert_eq!(
+			kinds("A=${B:x}\nB=${C D}"),
+			vec![
+				("1:3".to_string(), SyntaxErrorKind::InvalidExpansion),
+				("2:3".to_string(), SyntaxErrorKind::InvalidExpansion)
+			]
+		);
+		// recovers and reports all errors
+		assert_eq!(
+			kinds("A=${B\nC=${D:-x\nE=(a b\nF=$(true\nG=1"),
+			vec![
+				("1:3".to_string(), SyntaxErrorKind::UnbalancedBrace),
+				("2:3".to_string(), SyntaxErrorKind::UnbalancedBrace),
+				("3:3".to_string(), SyntaxErrorKind::UnterminatedArray),
+				("4:3".to_string(), SyntaxErrorKind::UnterminatedSubcommand),
+			]
+		);
+		assert_eq!(
+			kinds("A=$((1 + 2\nB=1"),
+			vec![("1:3".to_string(), SyntaxErrorKind::UnterminatedArithmetic)]
+		);
+	}
+
+	#[test]
+	fn test_parse_error() {
+		let err = ApmlLst::parse("A=1\n\tB=\"abc").unwrap_err();
+		assert_eq!(err.diagnostics().len(), 1);
+		assert_eq!(err.diagnostics()[0].pos, 7);
+		assert_eq!(err.diagnostics()[0].excerpt, "\tB=\"abc");
+		assert_eq!(
+			err.with_file("spec").to_string(),
+			"spec:2:4: unterminated double quote, expected `\"`\n | \tB=\"abc\n | \t  ^"
+		);
+	}
 }
@@ -2,13 +2,17 @@
 
 use std::{
 	borrow::Cow,
+	collections::HashMap,
 	fmt::{Display, Write},
+	hash::{BuildHasher, RandomState},
+	ops::Range,
+	sync::{Arc, LazyLock, RwLock},
 };
 
 use nom::{
 	IResult, Parser,
 	branch::alt,
-	bytes::complete::{tag, take_until1, take_while1},
+	bytes::complete::tag,
 	character::complete::{anychar, char},
 	combinator::{map, opt, value},
 	multi::{many0, many1},
@@ -179,6 +183,494 @@ impl PatternList<'_> {
 	}
 }
 
+impl BashPattern<'_> {
+	/// Converts a pattern into an owned one.
+	pub fn into_owned(self) -> BashPattern<'static> {
+		BashPattern(self.0.into_iter().map(GlobPart::into_owned).collect())
+	}
+}
+
+impl GlobPart<'_> {
+	/// Converts a pattern part into an owned one.
+	pub fn into_owned(self) -> GlobPart<'static> {
+		match self {
+			GlobPart::String(text) => {
+				GlobPart::String(text.into_owned().into())
+			}
+			GlobPart::Escaped(ch) => GlobPart::Escaped(ch),
+			GlobPart::AnyString => GlobPart::AnyString,
+			GlobPart::AnyChar => GlobPart::AnyChar,
+			GlobPart::Range(range) => {
+				GlobPart::Range(range.into_owned().into())
+			}
+			GlobPart::ZeroOrOneOf(list) => {
+				GlobPart::ZeroOrOneOf(list.into_owned())
+			}
+			GlobPart::ZeroOrMoreOf(list) => {
+				GlobPart::ZeroOrMoreOf(list.into_owned())
+			}
+			GlobPart::OneOrMoreOf(list) => {
+				GlobPart::OneOrMoreOf(list.into_owned())
+			}
+			GlobPart::OneOf(list) => GlobPart::OneOf(list.into_owned()),
+			GlobPart::Not(list) => GlobPart::Not(list.into_owned()),
+		}
+	}
+}
+
+impl PatternList<'_> {
+	/// Converts a pattern list into an owned one.
+	pub fn into_owned(self) -> PatternList<'static> {
+		PatternList(self.0.into_iter().map(BashPattern::into_owned).collect())
+	}
+}
+
+/// A compiled [`BashPattern`] matching strings natively.
+///
+/// Matching simulates all alternatives at once, tracking the set of
+/// positions reachable after each part, so extglob operators never
+/// backtrack exponentially.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GlobMatcher(Vec<Node>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+	Literal(Vec<char>),
+	AnyString,
+	AnyChar,
+	Class(CharClass),
+	ZeroOrOneOf(Vec<GlobMatcher>),
+	ZeroOrMoreOf(Vec<GlobMatcher>),
+	OneOrMoreOf(Vec<GlobMatcher>),
+	OneOf(Vec<GlobMatcher>),
+	Not(Vec<GlobMatcher>),
+}
+
+/// A bracket expression (`"[<range>]"`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CharClass {
+	negated: bool,
+	items: Vec<ClassItem>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ClassItem {
+	Char(char),
+	Range(char, char),
+	Named(NamedClass),
+}
+
+/// A POSIX character class (`"[:<name>:]"`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NamedClass {
+	Alnum,
+	Alpha,
+	Ascii,
+	Blank,
+	Cntrl,
+	Digit,
+	Graph,
+	Lower,
+	Print,
+	Punct,
+	Space,
+	Upper,
+	Word,
+	Xdigit,
+	/// Unknown classes match nothing.
+	Invalid,
+}
+
+impl NamedClass {
+	fn from_name(name: &str) -> Self {
+		match name {
+			"alnum" => Self::Alnum,
+			"alpha" => Self::Alpha,
+			"ascii" => Self::Ascii,
+			"blank" => Self::Blank,
+			"cntrl" => Self::Cntrl,
+			"digit" => Self::Digit,
+			"graph" => Self::Graph,
+			"lower" => Self::Lower,
+			"print" => Self::Print,
+			"punct" => Self::Punct,
+			"space" => Self::Space,
+			"upper" => Self::Upper,
+			"word" => Self::Word,
+			"xdigit" => Self::Xdigit,
+			_ => Self::Invalid,
+		}
+	}
+
+	fn matches(self, ch: char) -> bool {
+		match self {
+			Self::Alnum => ch.is_alphanumeric(),
+			Self::Alpha => ch.is_alphabetic(),
+			Self::Ascii => ch.is_ascii(),
+			Self::Blank => ch == ' ' || ch == '\t',
+			Self::Cntrl => ch.is_control(),
+			Self::Digit => ch.is_ascii_digit(),
+			Self::Graph => !ch.is_control() && !ch.is_whitespace(),
+			Self::Lower => ch.is_lowercase(),
+			Self::Print => !ch.is_control(),
+			Self::Punct => ch.is_ascii_punctuation(),
+			Self::Space => ch.is_whitespace(),
+			Self::Upper => ch.is_uppercase(),
+			Self::Word => ch.is_alphanumeric() || ch == '_',
+			Self::Xdigit => ch.is_ascii_hexdigit(),
+			Self::Invalid => false,
+		}
+	}
+}
+
+impl CharClass {
+	fn parse(range: &str) -> Self {
+		let (negated, range) = match range.strip_prefix(['!', '^']) {
+			Some(range) if !range.is_empty() => (true, range),
+			_ => (false, range),
+		};
+		let mut items = Vec::new();
+		let mut rest = range;
+		while !rest.is_empty() {
+			if let Some(class) = rest.strip_prefix("[:")
+				&& let Some(end) = class.find(":]")
+			{
+				items.push(ClassItem::Named(NamedClass::from_name(
+					&class[..end],
+				)));
+				rest = &class[end + 2..];
+				continue;
+			}
+			let mut chars = rest.chars();
+			let mut start = chars.next().unwrap();
+			if start == '\\'
+				&& let Some(escaped) = chars.next()
+			{
+				start = escaped;
+			}
+			rest = chars.as_str();
+			let mut chars = rest.chars();
+			if chars.next() == Some('-')
+				&& let Some(mut end) = chars.next()
+			{
+				if end == '\\'
+					&& let Some(escaped) = chars.next()
+				{
+					end = escaped;
+				}
+				items.push(ClassItem::Range(start, end));
+				rest = chars.as_str();
+			} else {
+				items.push(ClassItem::Char(start));
+			}
+		}
+		Self { negated, items }
+	}
+
+	fn matches(&self, ch: char) -> bool {
+		let found = self.items.iter().any(|item| match item {
+			ClassItem::Char(expected) => ch == *expected,
+			ClassItem::Range(start, end) => (*start..=*end).contains(&ch),
+			ClassItem::Named(class) => class.matches(ch),
+		});
+		found != self.negated
+	}
+}
+
+/// A string split into characters for matching.
+struct Haystack {
+	chars: Vec<char>,
+	/// Byte offsets of each character, followed by the length.
+	offsets: Vec<usize>,
+}
+
+impl Haystack {
+	fn new(text: &str) -> Self {
+		let (offsets, chars) = text.char_indices().unzip::<_, _, Vec<_>, _>();
+		let mut offsets: Vec<usize> = offsets;
+		offsets.push(text.len());
+		Self { chars, offsets }
+	}
+
+	/// Returns the character index at a byte offset.
+	fn index_of(&self, offset: usize) -> usize {
+		self.offsets.partition_point(|&pos| pos < offset)
+	}
+}
+
+impl GlobMatcher {
+	/// Compiles a pattern.
+	pub fn new(pattern: &BashPattern) -> Self {
+		Self(
+			pattern
+				.0
+				.iter()
+				.map(|part| match part {
+					GlobPart::String(text) => {
+						Node::Literal(text.chars().collect())
+					}
+					GlobPart::Escaped(ch) => Node::Literal(vec![*ch]),
+					GlobPart::AnyString => Node::AnyString,
+					GlobPart::AnyChar => Node::AnyChar,
+					GlobPart::Range(range) => {
+						Node::Class(CharClass::parse(range))
+					}
+					GlobPart::ZeroOrOneOf(list) => {
+						Node::ZeroOrOneOf(Self::compile_list(list))
+					}
+					GlobPart::ZeroOrMoreOf(list) => {
+						Node::ZeroOrMoreOf(Self::compile_list(list))
+					}
+					GlobPart::OneOrMoreOf(list) => {
+						Node::OneOrMoreOf(Self::compile_list(list))
+					}
+					GlobPart::OneOf(list) => {
+						Node::OneOf(Self::compile_list(list))
+					}
+					GlobPart::Not(list) => Node::Not(Self::compile_list(list)),
+				})
+				.collect(),
+		)
+	}
+
+	fn compile_list(list: &PatternList) -> Vec<Self> {
+		list.0.iter().map(Self::new).collect()
+	}
+
+	/// Returns whether the whole string matches.
+	pub fn is_match(&self, text: &str) -> bool {
+		let haystack = Haystack::new(text);
+		self.ends(&haystack.chars, 0)
+			.last()
+			.is_some_and(|&end| end == haystack.chars.len())
+	}
+
+	/// Returns the byte length of the shortest or longest matching prefix.
+	pub fn prefix_len(&self, text: &str, longest: bool) -> Option<usize> {
+		let haystack = Haystack::new(text);
+		let ends = self.ends(&haystack.chars, 0);
+		let end = if longest { ends.last() } else { ends.first() };
+		end.map(|&end| haystack.offsets[end])
+	}
+
+	/// Returns the byte offset of the shortest or longest matching suffix.
+	pub fn suffix_start(&self, text: &str, longest: bool) -> Option<usize> {
+		let haystack = Haystack::new(text);
+		let len = haystack.chars.len();
+		let is_suffix =
+			|start: &usize| self.ends(&haystack.chars, *start).contains(&len);
+		let start = if longest {
+			(0..=len).find(is_suffix)
+		} else {
+			(0..=len).rev().find(is_suffix)
+		};
+		start.map(|start| haystack.offsets[start])
+	}
+
+	/// Finds the leftmost-longest match starting at or after a byte offset.
+	pub fn find(&self, text: &str, from: usize) -> Option<Range<usize>> {
+		let haystack = Haystack::new(text);
+		(haystack.index_of(from)..=haystack.chars.len()).find_map(|start| {
+			self.ends(&haystack.chars, start)
+				.last()
+				.map(|&end| haystack.offsets[start]..haystack.offsets[end])
+		})
+	}
+
+	/// Returns all matches, leftmost-longest and non-overlapping.
+	pub fn find_all(&self, text: &str) -> Vec<Range<usize>> {
+		let haystack = Haystack::new(text);
+		let len = haystack.chars.len();
+		let mut matches = Vec::new();
+		let mut start = 0;
+		while start <= len {
+			match self.ends(&haystack.chars, start).last() {
+				Some(&end) => {
+					matches
+						.push(haystack.offsets[start]..haystack.offsets[end]);
+					// empty matches must still make progress
+					start = if end > start { end } else { end + 1 };
+				}
+				None => start += 1,
+			}
+		}
+		matches
+	}
+
+	/// Returns all sorted end positions of matches starting at `start`.
+	fn ends(&self, text: &[char], start: usize) -> Vec<usize> {
+		let mut positions = vec![start];
+		for node in &self.0 {
+			let mut next = Vec::new();
+			for &pos in &positions {
+				node.ends(text, pos, &mut next);
+			}
+			next.sort_unstable();
+			next.dedup();
+			if next.is_empty() {
+				return next;
+			}
+			positions = next;
+		}
+		positions
+	}
+}
+
+impl Node {
+	/// Appends end positions of matches starting at `pos`.
+	fn ends(&self, text: &[char], pos: usize, out: &mut Vec<usize>) {
+		match self {
+			Node::Literal(chars) => {
+				if text[pos..].starts_with(chars) {
+					out.push(pos + chars.len());
+				}
+			}
+			Node::AnyString => out.extend(pos..=text.len()),
+			Node::AnyChar => {
+				if pos < text.len() {
+					out.push(pos + 1);
+				}
+			}
+			Node::Class(class) => {
+				if let Some(&ch) = text.get(pos)
+					&& class.matches(ch)
+				{
+					out.push(pos + 1);
+				}
+			}
+			Node::ZeroOrOneOf(list) => {
+				out.push(pos);
+				list_ends(list, text, pos, out);
+			}
+			Node::ZeroOrMoreOf(list) => {
+				list_closure(list, text, vec![pos], out);
+			}
+			Node::OneOrMoreOf(list) => {
+				let mut first = Vec::new();
+				list_ends(list, text, pos, &mut first);
+				list_closure(list, text, first, out);
+			}
+			Node::OneOf(list) => list_ends(list, text, pos, out),
+			Node::Not(list) => {
+				let mut excluded = Vec::new();
+				list_ends(list, text, pos, &mut excluded);
+				out.extend(
+					(pos..=text.len()).filter(|end| !excluded.contains(end)),
+				);
+			}
+		}
+	}
+}
+
+/// Appends end positions of any pattern in a list.
+fn list_ends(
+	list: &[GlobMatcher],
+	text: &[char],
+	pos: usize,
+	out: &mut Vec<usize>,
+) {
+	for matcher in list {
+		out.extend(matcher.ends(text, pos));
+	}
+}
+
+/// Appends positions reachable by repeating a list from `start`.
+fn list_closure(
+	list: &[GlobMatcher],
+	text: &[char],
+	start: Vec<usize>,
+	out: &mut Vec<usize>,
+) {
+	let mut seen = start.clone();
+	let mut queue = start;
+	while let Some(pos) = queue.pop() {
+		let mut next = Vec::new();
+		list_ends(list, text, pos, &mut next);
+		for end in next {
+			if !seen.contains(&end) {
+				seen.push(end);
+				queue.push(end);
+			}
+		}
+	}
+	out.extend(seen);
+}
+
+type PatternBuckets =
+	HashMap<u64, Vec<(Arc<BashPattern<'static>>, Arc<GlobMatcher>)>>;
+
+/// A cache of [`GlobMatcher`]s keyed by patterns.
+///
+/// Equal patterns from different AST nodes share one matcher. The cache
+/// is cleared once it grows beyond [`PatternCache::CAPACITY`] entries.
+#[derive(Debug, Default)]
+pub struct PatternCache {
+	hasher: RandomState,
+	buckets: RwLock<PatternBuckets>,
+}
+
+impl PatternCache {
+	/// Maximum number of cached patterns.
+	pub const CAPACITY: usize = 4096;
+
+	/// Creates an empty cache.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Returns the process-wide cache used by evaluation.
+	pub fn global() -> &'static Self {
+		static CACHE: LazyLock<PatternCache> = LazyLock::new(PatternCache::new);
+		&CACHE
+	}
+
+	/// Returns the compiled matcher of a pattern, compiling it if needed.
+	pub fn get(&self, pattern: &BashPattern) -> Arc<GlobMatcher> {
+		let hash = self.hasher.hash_one(pattern);
+		if let Some(matcher) = self
+			.buckets
+			.read()
+			.unwrap()
+			.get(&hash)
+			.and_then(|bucket| {
+				bucket.iter().find(|(key, _)| key.as_ref() == pattern)
+			})
+			.map(|(_, matcher)| matcher.clone())
+		{
+			return matcher;
+		}
+
+		let matcher = Arc::new(GlobMatcher::new(pattern));
+		let mut buckets = self.buckets.write().unwrap();
+		if buckets.values().map(Vec::len).sum::<usize>() >= Self::CAPACITY {
+			buckets.clear();
+		}
+		let bucket = buckets.entry(hash).or_default();
+		if !bucket.iter().any(|(key, _)| key.as_ref() == pattern) {
+			bucket.push((
+				Arc::new(pattern.clone().into_owned()),
+				matcher.clone(),
+			));
+		}
+		matcher
+	}
+
+	/// Returns the number of cached patterns.
+	pub fn len(&self) -> usize {
+		self.buckets.read().unwrap().values().map(Vec::len).sum()
+	}
+
+	/// Returns whether the cache is empty.
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	/// Removes all cached patterns.
+	pub fn clear(&self) {
+		self.buckets.write().unwrap().clear();
+	}
+}
+
 /// Parses a glob pattern.
 #[inline]
 pub fn bash_pattern<'a>(
@@ -225,18 +717,73 @@ fn pattern_part<'a>(
 		// any char
 		value(GlobPart::AnyChar, char('?')),
 		// range
-		map(delimited(char('['), take_until1("]"), char(']')), |range| {
+		map(delimited(char('['), range_body, char(']')), |range| {
 			GlobPart::Range(Cow::Borrowed(range))
 		}),
 		// literal
 		map(
-			take_while1(|ch| !"[*?\\".contains(ch) && !exclude.contains(ch)),
+			|i| literal(i, exclude),
 			|s| GlobPart::String(Cow::Borrowed(s)),
 		),
 	))
 	.parse(i)
 }
 
+/// Parses a literal string, stopping before any extglob operator.
+fn literal<'a>(i: &'a str, exclude: &'static str) -> IResult<&'a str, &'a str> {
+	let len = i
+		.char_indices()
+		.find(|&(idx, ch)| {
+			"[*?\\".contains(ch)
+				|| exclude.contains(ch)
+				|| ("+@!".contains(ch) && i[idx + 1..].starts_with('('))
+		})
+		.map_or(i.len(), |(idx, _)| idx);
+	if len == 0 {
+		return Err(nom::Err::Error(nom::error::Error::new(
+			i,
+			nom::error::ErrorKind::TakeWhile1,
+		)));
+	}
+	Ok((&i[len..], &i[..len]))
+}
+
+/// Parses the inside of a bracket expression.
+///
+/// A leading `]` and `]` inside `[:class:]` do not close the expression.
+fn range_body(i: &str) -> IResult<&str, &str> {
+	let mut rest = i.strip_prefix(['!', '^']).unwrap_or(i);
+	if let Some(after) = rest.strip_prefix(']') {
+		rest = after;
+	}
+	while !rest.starts_with(']') {
+		if let Some(class) = rest.strip_prefix("[:")
+			&& let Some(end) = class.find(":]")
+		{
+			rest = &class[end + 2..];
+		} else if let Some(escaped) = rest.strip_prefix('\\')
+			&& let Some(ch) = escaped.chars().next()
+		{
+			rest = &escaped[ch.len_utf8()..];
+		} else if let Some(ch) = rest.chars().next() {
+			rest = &rest[ch.len_utf8()..];
+		} else {
+			return Err(nom::Err::Error(nom::error::Error::new(
+				rest,
+				nom::error::ErrorKind::TakeUntil,
+			)));
+		}
+	}
+	let len = i.len() - rest.len();
+	if len == 0 {
+		return Err(nom::Err::Error(nom::error::Error::new(
+			i,
+			nom::error::ErrorKind::TakeUntil,
+		)));
+	}
+	Ok((rest, &i[..len]))
+}
+
 #[inline]
 fn pattern_list(i: &'_ str) -> IResult<&'_ str, PatternList<'_>> {
 	many0(terminated(|i| bash_pattern(i, "|)"), opt(char('|'))))
@@ -335,4 +882,99 @@ mod test {
 			.build_regex(&mut result, false);
 		assert_eq!(result, "(abc|LA.?)");
 	}
+
+	fn matcher(src: &str) -> GlobMatcher {
+		let (rest, pattern) = bash_pattern(src, "").unwrap();
+		assert_eq!(rest, "");
+		GlobMatcher::new(&pattern)
+	}
+
+	#[test]
+	fn test_range() {
+		assert_eq!(
+			bash_pattern("[[:alpha:]]x", "").unwrap().1.0[0],
+			GlobPart::Range(Cow::Borrowed("[:alpha:]"))
+		);
+		assert_eq!(
+			bash_pattern("[]a]", "").unwrap().1.0[0],
+			GlobPart::Range(Cow::Borrowed("]a"))
+		);
+		assert_eq!(
+			bash_pattern("[!]a]", "").unwrap().1.0[0],
+			GlobPart::Range(Cow::Borrowed("!]a"))
+		);
+		assert!(bash_pattern("[abc", "").is_err());
+
+		let m = matcher("[[:alpha:]_][[:digit:]]");
+		assert!(m.is_match("a1"));
+		assert!(m.is_match("_9"));
+		assert!(!m.is_match("1a"));
+		let m = matcher("[!a-c]");
+		assert!(m.is_match("d"));
+		assert!(!m.is_match("b"));
+		let m = matcher("[^[:space:]x]");
+		assert!(m.is_match("y"));
+		assert!(!m.is_match(" "));
+		assert!(!m.is_match("x"));
+		assert!(!matcher("[[:nope:]]").is_match("a"));
+		assert!(matcher("[a\\]]").is_match("]"));
+	}
+
+	#[test]
+	fn test_glob_matcher() {
+		let m = matcher("a*b?c");
+		assert!(m.is_match("abxc"));
+		assert!(m.is_match("axxxbyc"));
+		assert!(!m.is_match("abc"));
+		assert!(matcher("*").is_match(""));
+		assert!(matcher("\\*").is_match("*"));
+		assert!(!matcher("\\*").is_match("a"));
+		assert!(matcher("你?").is_match("你好"));
+
+		let m = matcher("a*");
+		assert_eq!(m.prefix_len("aa1a", false), Some(1));
+		assert_eq!(m.prefix_len("aa1a", true), Some(4));
+		assert_eq!(m.prefix_len("1a", true), None);
+		assert_eq!(m.suffix_start("a1a2", false), Some(2));
+		assert_eq!(m.suffix_start("a1a2", true), Some(0));
+		assert_eq!(m.find("1a2", 0), Some(1..3));
+		assert_eq!(matcher("a?").find_all("aa1ab"), vec![0..2, 3..5]);
+		assert_eq!(matcher("?(x)").find_all("ab"), vec![0..0, 1..1, 2..2]);
+	}
+
+	#[test]
+	fn test_extglob() {
+		let m = matcher("?(a|b)c");
+		assert!(m.is_match("c"));
+		assert!(m.is_match("ac"));
+		assert!(!m.is_match("abc"));
+		let m = matcher("*(ab|c)d");
+		assert!(m.is_match("d"));
+		assert!(m.is_match("abcabd"));
+		assert!(!m.is_match("abad"));
+		let m = matcher("+([[:digit:]]).+([[:digit:]])");
+		assert!(m.is_match("1.23"));
+		assert!(!m.is_match(".23"));
+		let m = matcher("@(foo|bar)-*");
+		assert!(m.is_match("bar-1"));
+		assert!(!m.is_match("baz-1"));
+		let m = matcher("!(*.tar).gz");
+		assert!(m.is_match("a.gz"));
+		assert!(!m.is_match("a.tar.gz"));
+		assert!(matcher("*(*(a))b").is_match(&format!("{}b", "a".repeat(64))));
+		assert!(!matcher("*(a|aa)b").is_match(&"a".repeat(64)));
+	}
+
+	#[test]
+	fn test_pattern_cache() {
+		let cache = PatternCache::new();
+		let pattern = Arc::new(bash_pattern("a*", "").unwrap().1);
+		let matcher = cache.get(&pattern);
+		assert!(Arc::ptr_eq(&matcher, &cache.get(&pattern.as_ref().clone())));
+		assert_eq!(cache.len(), 1);
+		cache.get(&bash_pattern("b*", "").unwrap().1);
+		assert_eq!(cache.len(), 2);
+		cache.clear();
+		assert!(cache.is_empty());
+	}
 }
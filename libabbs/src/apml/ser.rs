@@ -0,0 +1,63 @@
+//! Serializing [`ApmlContext`] for external tools.
+//!
+//! A context is serialized as a map from variable names to values,
+//! including variables from parent contexts. Strings are serialized as
+//! strings and arrays as sequences of strings, so a context can be passed
+//! to other tools as JSON:
+//!
+//! ```
+//! use libabbs::apml::ApmlContext;
+//!
+//! let ctx = ApmlContext::eval_source("A=1\nB=(a b)").unwrap();
+//! assert_eq!(
+//!     serde_json::to_string(&ctx).unwrap(),
+//!     r#"{"A":"1","B":["a","b"]}"#
+//! );
+//! ```
+
+use serde::{Serialize, Serializer};
+
+use super::{ApmlContext, VariableValue};
+
+impl Serialize for ApmlContext {
+	fn serialize<S: Serializer>(
+		&self,
+		serializer: S,
+	) -> Result<S::Ok, S::Error> {
+		// sort variables for reproducible outputs
+		let mut variables = self.iter().collect::<Vec<_>>();
+		variables.sort_unstable_by_key(|(name, _)| *name);
+		serializer.collect_map(variables)
+	}
+}
+
+impl Serialize for VariableValue {
+	fn serialize<S: Serializer>(
+		&self,
+		serializer: S,
+	) -> Result<S::Ok, S::Error> {
+		match self {
+			VariableValue::String(text) => serializer.serialize_str(text),
+			VariableValue::Array(els) => serializer.collect_seq(els),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::sync::Arc;
+
+	use super::*;
+
+	#[test]
+	fn test_serialize() {
+		let parent = ApmlContext::eval_source("A=1\nB=2").unwrap();
+		let mut ctx = ApmlContext::with_parent(Arc::new(parent));
+		ctx.insert("B".to_string(), VariableValue::Array(vec!["b".into()]));
+		ctx.insert("C".to_string(), VariableValue::default());
+		assert_eq!(
+			serde_json::to_string(&ctx).unwrap(),
+			r#"{"A":"1","B":["b"],"C":""}"#
+		);
+	}
+}
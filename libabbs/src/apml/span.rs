@@ -0,0 +1,138 @@
+//! Source positions.
+//!
+//! [`Span`] is a byte range in the APML source text. As LST is lossless,
+//! a byte range always maps back to the LST tokens covering it.
+//!
+//! [`SourceLocation`] is a human-readable position, with line and column
+//! numbers, which can be resolved from a byte offset and the source text.
+
+use std::{fmt::Display, ops::Range};
+
+/// A byte range in the source text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+	/// Start offset (inclusive).
+	pub start: usize,
+	/// End offset (exclusive).
+	pub end: usize,
+}
+
+impl Span {
+	/// Creates a span.
+	#[must_use]
+	pub fn new(start: usize, end: usize) -> Self {
+		Self { start, end }
+	}
+
+	/// Returns the span as a range.
+	#[must_use]
+	pub fn range(&self) -> Range<usize> {
+		self.start..self.end
+	}
+
+	/// Returns the length of the span in bytes.
+	#[must_use]
+	pub fn len(&self) -> usize {
+		self.end - self.start
+	}
+
+	/// Returns if the span is empty.
+	#[must_use]
+	pub fn is_empty(&self) -> bool {
+		self.start == self.end
+	}
+
+	/// Returns the source text covered by the span.
+	#[must_use]
+	pub fn slice<'a>(&self, src: &'a str) -> Option<&'a str> {
+		src.get(self.range())
+	}
+}
+
+impl From<Range<usize>> for Span {
+	fn from(value: Range<usize>) -> Self {
+		Self::new(value.start, value.end)
+	}
+}
+
+/// A human-readable position in a source file.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SourceLocation {
+	/// Path to the source file, if known.
+	pub file: Option<String>,
+	/// Line number, starting from 1.
+	pub line: usize,
+	/// Column number in characters, starting from 1.
+	pub column: usize,
+}
+
+impl SourceLocation {
+	/// Resolves the location of a byte offset in the source text.
+	///
+	/// Offsets beyond the end of source are clamped.
+	#[must_use]
+	pub fn from_offset(src: &str, offset: usize) -> Self {
+		let mut offset = offset.min(src.len());
+		while !src.is_char_boundary(offset) {
+			offset -= 1;
+		}
+		let before = &src[..offset];
+		let line = before.matches('\n').count() + 1;
+		let line_start = before.rfind('\n').map_or(0, |pos| pos + 1);
+		let column = before[line_start..].chars().count() + 1;
+		Self {
+			file: None,
+			line,
+			column,
+		}
+	}
+
+	/// Sets the path to the source file.
+	#[must_use]
+	pub fn with_file<S: Into<String>>(mut self, file: S) -> Self {
+		self.file = Some(file.into());
+		self
+	}
+}
+
+impl Display for SourceLocation {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		if let Some(file) = &self.file {
+			f.write_fmt(format_args!("{file}:"))?;
+		}
+		f.write_fmt(format_args!("{}:{}", self.line, self.column))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_span() {
+		let span = Span::new(2, 5);
+		assert_eq!(span.range(), 2..5);
+		assert_eq!(span.len(), 3);
+		assert!(!span.is_empty());
+		assert!(Span::default().is_empty());
+		assert_eq!(span.slice("abcdefg"), Some("cde"));
+		assert_eq!(span.slice("ab"), None);
+		assert_eq!(Span::from(1..2), Span::new(1, 2));
+	}
+
+	#[test]
+	fn test_source_location() {
+		let src = "a=b\n兔=c\nd=e";
+		assert_eq!(SourceLocation::from_offset(src, 0).to_string(), "1:1");
+		assert_eq!(SourceLocation::from_offset(src, 2).to_string(), "1:3");
+		assert_eq!(SourceLocation::from_offset(src, 4).to_string(), "2:1");
+		assert_eq!(SourceLocation::from_offset(src, 7).to_string(), "2:2");
+		assert_eq!(SourceLocation::from_offset(src, 5).to_string(), "2:1");
+		assert_eq!(
+			SourceLocation::from_offset(src, 100)
+				.with_file("defines")
+				.to_string(),
+			"defines:3:4"
+		);
+	}
+}
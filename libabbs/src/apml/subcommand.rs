@@ -0,0 +1,648 @@
+//! Safe evaluation of sub-command expansions.
+//!
+//! A small subset of side-effect-free commands is evaluated natively,
+//! without spawning any process:
+//!
+//! - `echo [-n] [ARGS]`
+//! - `printf FORMAT [ARGS]`, supporting `%s`, `%d` and `%%`
+//! - `tr [-d] SET1 [SET2]`
+//! - `cut -d DELIM -f LIST` and `cut -c LIST`
+//! - `sed [-E] s/PATTERN/REPLACEMENT/[g]`
+//! - `expr` with integer arithmetic
+//!
+//! Commands can be chained with pipes. Any other sub-command, including
+//! ones using redirections, command lists or unsupported options, is
+//! unevaluable. So are sub-commands with arguments referring to unevaluable
+//! values, and ones with unquoted expansions which would be subject to word
+//! splitting or pathname expansion.
+
+use std::{
+	collections::HashMap,
+	sync::{LazyLock, Mutex},
+};
+
+use regex::Regex;
+
+use super::{
+	ApmlContext,
+	ast::{self, AstNode},
+	eval::{EvalError, eval_text_checked},
+	lst, parser,
+};
+
+/// Evaluates a sub-command expansion, including `$(` and `)`.
+///
+/// Returns [None] if the sub-command is unevaluable.
+pub fn eval_subcommand(
+	apml: &ApmlContext,
+	src: &str,
+) -> Result<Option<String>, EvalError> {
+	let Ok(("", tokens)) = parser::subcommand(src) else {
+		return Ok(None);
+	};
+	let mut stages = vec![Vec::new()];
+	for token in &tokens {
+		match token {
+			lst::ArrayToken::Spacy(_) => {}
+			lst::ArrayToken::Newline | lst::ArrayToken::Comment(_) => {
+				return Ok(None);
+			}
+			lst::ArrayToken::Element(text) => {
+				let unquoted = unquoted_literal(text);
+				if unquoted.as_deref() == Some("|") {
+					stages.push(Vec::new());
+					continue;
+				}
+				if unquoted.is_some_and(|text| text.contains(is_metachar)) {
+					return Ok(None);
+				}
+				let Some(arg) = eval_arg(apml, text)? else {
+					return Ok(None);
+				};
+				stages.last_mut().expect("stages are never empty").push(arg);
+			}
+		}
+	}
+
+	let mut output = None;
+	for args in stages {
+		let Some(stdout) = run(&args, output.as_deref()) else {
+			return Ok(None);
+		};
+		output = Some(stdout);
+	}
+	Ok(output.map(|output| output.trim_end_matches('\n').to_string()))
+}
+
+/// Evaluates a command argument.
+///
+/// Returns [None] if the argument is unevaluable or would not be kept as one
+/// argument by the shell.
+fn eval_arg(
+	apml: &ApmlContext,
+	text: &lst::Text,
+) -> Result<Option<String>, EvalError> {
+	let mut result = String::new();
+	let mut unevaluable = false;
+	for unit in &text.0 {
+		let lst::TextUnit::Unquoted(words) = unit else {
+			let Some(value) =
+				eval_lst_text(apml, vec![unit.clone()], &mut unevaluable)?
+			else {
+				return Ok(None);
+			};
+			result.push_str(&value);
+			continue;
+		};
+		for word in words {
+			let unit = lst::TextUnit::Unquoted(vec![word.clone()]);
+			let Some(value) =
+				eval_lst_text(apml, vec![unit], &mut unevaluable)?
+			else {
+				return Ok(None);
+			};
+			// unquoted expansions are split into fields and globbed
+			if !matches!(word, lst::Word::Literal(_))
+				&& (value.is_empty() || value.contains(is_split_char))
+			{
+				return Ok(None);
+			}
+			result.push_str(&value);
+		}
+	}
+	Ok((!unevaluable).then_some(result))
+}
+
+/// Evaluates text units, returning [None] if they cannot be emitted.
+fn eval_lst_text(
+	apml: &ApmlContext,
+	units: Vec<lst::TextUnit>,
+	unevaluable: &mut bool,
+) -> Result<Option<String>, EvalError> {
+	let Ok(text) = ast::Text::emit_from(&lst::Text(units)) else {
+		return Ok(None);
+	};
+	eval_text_checked(apml, &text, unevaluable).map(Some)
+}
+
+/// Returns unquoted literal strings of a text.
+///
+/// Returns [None] if the text contains no such string.
+fn unquoted_literal(text: &lst::Text) -> Option<String> {
+	let mut result = None;
+	for unit in &text.0 {
+		let lst::TextUnit::Unquoted(words) = unit else {
+			continue;
+		};
+		for word in words {
+			let lst::Word::Literal(parts) = word else {
+				continue;
+			};
+			for part in parts {
+				if let lst::LiteralPart::String(text) = part {
+					result.get_or_insert_with(String::new).push_str(text);
+				}
+			}
+		}
+	}
+	result
+}
+
+/// Returns if a character is a shell metacharacter.
+fn is_metachar(ch: char) -> bool {
+	matches!(ch, '|' | '&' | ';' | '<' | '>' | '`' | '(' | ')')
+}
+
+/// Returns if a character in an unquoted expansion would cause the value
+/// to be split into fields or expanded as a pathname pattern.
+fn is_split_char(ch: char) -> bool {
+	matches!(ch, ' ' | '\t' | '\n' | '*' | '?' | '[')
+}
+
+/// Runs a command with the given input, returning the output.
+fn run(args: &[String], input: Option<&str>) -> Option<String> {
+	let (command, args) = args.split_first()?;
+	match command.as_str() {
+		"echo" => echo(args),
+		"printf" => printf(args),
+		"tr" => tr(args, input?),
+		"cut" => cut(args, input?),
+		"sed" => sed(args, input?),
+		"expr" => expr(args),
+		_ => None,
+	}
+}
+
+fn echo(mut args: &[String]) -> Option<String> {
+	let mut newline = true;
+	while let Some((flag, rest)) = args.split_first() {
+		match flag.as_str() {
+			"-n" => newline = false,
+			"-E" => {}
+			"-e" if args.iter().all(|arg| !arg.contains('\\')) => {}
+			"-e" => return None,
+			_ => break,
+		}
+		args = rest;
+	}
+	let mut result = args.join(" ");
+	if newline {
+		result.push('\n');
+	}
+	Some(result)
+}
+
+fn printf(args: &[String]) -> Option<String> {
+	let (format, args) = args.split_first()?;
+	let mut args = args.iter();
+	let mut result = String::new();
+	loop {
+		let remaining = args.len();
+		let mut chars = format.chars();
+		while let Some(ch) = chars.next() {
+			match ch {
+				'\\' => result.push(unescape(chars.next()?)?),
+				'%' => match chars.next()? {
+					'%' => result.push('%'),
+					's' => {
+						result.push_str(args.next().map_or("", String::as_str))
+					}
+					'd' => {
+						let arg = args.next().map_or("0", String::as_str);
+						result.push_str(&arg.parse::<i64>().ok()?.to_string());
+					}
+					_ => return None,
+				},
+				ch => result.push(ch),
+			}
+		}
+		// the format is reused until all arguments are consumed
+		if args.len() == 0 || args.len() == remaining {
+			break;
+		}
+	}
+	Some(result)
+}
+
+fn tr(args: &[String], input: &str) -> Option<String> {
+	let (delete, sets) = match args.split_first()? {
+		(flag, sets) if flag == "-d" => (true, sets),
+		(flag, _) if flag.starts_with('-') && flag.len() > 1 => return None,
+		_ => (false, args),
+	};
+	match (delete, sets) {
+		(true, [set]) => {
+			let set = expand_set(set)?;
+			Some(input.chars().filter(|ch| !set.contains(ch)).collect())
+		}
+		(false, [from, to]) => {
+			let from = expand_set(from)?;
+			let to = expand_set(to)?;
+			let last = *to.last()?;
+			Some(
+				input
+					.chars()
+					.map(|ch| match from.iter().rposition(|&c| c == ch) {
+						Some(idx) => to.get(idx).copied().unwrap_or(last),
+						None => ch,
+					})
+					.collect(),
+			)
+		}
+		_ => None,
+	}
+}
+
+/// Expands a `tr` character set.
+fn expand_set(set: &str) -> Option<Vec<char>> {
+	let mut result = Vec::new();
+	let mut rest = set;
+	while !rest.is_empty() {
+		if let Some(class) = rest.strip_prefix("[:") {
+			let (name, after) = class.split_once(":]")?;
+			result.extend(match name {
+				"lower" => ('a'..='z').collect::<Vec<_>>(),
+				"upper" => ('A'..='Z').collect(),
+				"digit" => ('0'..='9').collect(),
+				"alpha" => ('A'..='Z').chain('a'..='z').collect(),
+				"alnum" => {
+					('0'..='9').chain('A'..='Z').chain('a'..='z').collect()
+				}
+				"space" => vec![' ', '\t', '\n', '\r', '\x0b', '\x0c'],
+				"blank" => vec![' ', '\t'],
+				_ => return None,
+			});
+			rest = after;
+			continue;
+		}
+		let mut chars = rest.chars();
+		let mut ch = chars.next()?;
+		if ch == '\\' {
+			ch = unescape(chars.next()?)?;
+		}
+		rest = chars.as_str();
+		if let Some(range) = rest.strip_prefix('-')
+			&& let Some(end) = range.chars().next()
+		{
+			if end < ch {
+				return None;
+			}
+			result.extend(ch..=end);
+			rest = &range[end.len_utf8()..];
+		} else {
+			result.push(ch);
+		}
+	}
+	Some(result)
+}
+
+fn cut(args: &[String], input: &str) -> Option<String> {
+	let mut delim = '\t';
+	let mut fields = None;
+	let mut chars = None;
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+		let (flag, value) = arg.split_at_checked(2)?;
+		let value = if value.is_empty() {
+			args.next()?.as_str()
+		} else {
+			value
+		};
+		match flag {
+			"-d" => {
+				let mut value = value.chars();
+				delim = value.next()?;
+				if value.next().is_some() {
+					return None;
+				}
+			}
+			"-f" => fields = Some(parse_list(value)?),
+			"-c" => chars = Some(parse_list(value)?),
+			_ => return None,
+		}
+	}
+
+	let mut result = String::new();
+	for line in input.lines() {
+		match (&fields, &chars) {
+			(Some(list), None) => {
+				if line.contains(delim) {
+					let selected = line
+						.split(delim)
+						.enumerate()
+						.filter(|(idx, _)| in_list(list, idx + 1))
+						.map(|(_, field)| field)
+						.collect::<Vec<_>>();
+					result.push_str(&selected.join(&delim.to_string()));
+				} else {
+					result.push_str(line);
+				}
+			}
+			(None, Some(list)) => result.extend(
+				line.chars()
+					.enumerate()
+					.filter(|(idx, _)| in_list(list, idx + 1))
+					.map(|(_, ch)| ch),
+			),
+			_ => return None,
+		}
+		result.push('\n');
+	}
+	Some(result)
+}
+
+/// Parses a `cut` list into 1-based inclusive ranges.
+fn parse_list(list: &str) -> Option<Vec<(usize, usize)>> {
+	let mut result = Vec::new();
+	for item in list.split(',') {
+		let range = match item.split_once('-') {
+			Some(("", "")) => return None,
+			Some((start, "")) => (start.parse().ok()?, usize::MAX),
+			Some(("", end)) => (1, end.parse().ok()?),
+			Some((start, end)) => (start.parse().ok()?, end.parse().ok()?),
+			None => {
+				let pos = item.parse().ok()?;
+				(pos, pos)
+			}
+		};
+		if range.0 == 0 {
+			return None;
+		}
+		result.push(range);
+	}
+	Some(result)
+}
+
+fn in_list(list: &[(usize, usize)], pos: usize) -> bool {
+	list.iter()
+		.any(|(start, end)| (*start..=*end).contains(&pos))
+}
+
+fn sed(args: &[String], input: &str) -> Option<String> {
+	let mut extended = false;
+	let mut script = None;
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"-E" | "-r" => extended = true,
+			"-e" if script.is_none() => script = Some(args.next()?),
+			_ if arg.starts_with('-') => return None,
+			_ if script.is_none() => script = Some(arg),
+			// input files
+			_ => return None,
+		}
+	}
+
+	let mut script = script?.chars();
+	if script.next()? != 's' {
+		return None;
+	}
+	let delim = script.next()?;
+	if delim == '\\' || delim == '\n' {
+		return None;
+	}
+	let pattern = sed_part(&mut script, delim)?;
+	let replacement = sed_part(&mut script, delim)?;
+	let global = match script.as_str() {
+		"" => false,
+		"g" => true,
+		_ => return None,
+	};
+	let pattern = if extended {
+		pattern
+	} else {
+		bre_to_regex(&pattern)?
+	};
+	let regex = sed_regex(&pattern)?;
+	let replacement = sed_replacement(&replacement)?;
+
+	let mut result = String::new();
+	for line in input.lines() {
+		if global {
+			result.push_str(&regex.replace_all(line, &replacement));
+		} else {
+			result.push_str(&regex.replace(line, &replacement));
+		}
+		result.push('\n');
+	}
+	Some(result)
+}
+
+/// Maximum number of cached `sed` regular expressions.
+const SED_CACHE_CAPACITY: usize = 256;
+
+/// Returns the compiled regular expression of a pattern, compiling it if
+/// needed.
+fn sed_regex(pattern: &str) -> Option<Regex> {
+	static CACHE: LazyLock<Mutex<HashMap<String, Regex>>> =
+		LazyLock::new(Mutex::default);
+	let mut cache = CACHE.lock().unwrap();
+	if let Some(regex) = cache.get(pattern) {
+		return Some(regex.clone());
+	}
+	let regex = Regex::new(pattern).ok()?;
+	if cache.len() >= SED_CACHE_CAPACITY {
+		cache.clear();
+	}
+	cache.insert(pattern.to_string(), regex.clone());
+	Some(regex)
+}
+
+/// Reads a part of `s` command until the delimiter.
+fn sed_part(chars: &mut std::str::Chars, delim: char) -> Option<String> {
+	let mut result = String::new();
+	loop {
+		match chars.next()? {
+			'\\' => {
+				let ch = chars.next()?;
+				if ch != delim {
+					result.push('\\');
+				}
+				result.push(ch);
+			}
+			ch if ch == delim => return Some(result),
+			ch => result.push(ch),
+		}
+	}
+}
+
+/// Converts a POSIX basic regular expression into [regex] syntax.
+fn bre_to_regex(pattern: &str) -> Option<String> {
+	let mut result = String::new();
+	let mut chars = pattern.chars().peekable();
+	while let Some(ch) = chars.next() {
+		match ch {
+			'\\' => match chars.next()? {
+				ch @ ('(' | ')' | '{' | '}' | '+' | '?' | '|') => {
+					result.push(ch)
+				}
+				'n' => result.push_str("\\n"),
+				// back-references are not supported
+				'0'..='9' => return None,
+				ch => {
+					result.push('\\');
+					result.push(ch);
+				}
+			},
+			'(' | ')' | '{' | '}' | '+' | '?' | '|' => {
+				result.push('\\');
+				result.push(ch);
+			}
+			'[' => {
+				// bracket expressions are copied verbatim
+				result.push('[');
+				if let Some(&ch @ ('^' | ']')) = chars.peek() {
+					result.push(ch);
+					chars.next();
+					if ch == '^' && chars.peek() == Some(&']') {
+						result.push(']');
+						chars.next();
+					}
+				}
+				loop {
+					let ch = chars.next()?;
+					result.push(ch);
+					if ch == '[' && chars.peek() == Some(&':') {
+						// character classes
+						loop {
+							let ch = chars.next()?;
+							result.push(ch);
+							if ch == ']' {
+								break;
+							}
+						}
+					} else if ch == ']' {
+						break;
+					}
+				}
+			}
+			ch => result.push(ch),
+		}
+	}
+	Some(result)
+}
+
+/// Converts a replacement of `s` command into [regex] syntax.
+fn sed_replacement(replacement: &str) -> Option<String> {
+	let mut result = String::new();
+	let mut chars = replacement.chars();
+	while let Some(ch) = chars.next() {
+		match ch {
+			'\\' => match chars.next()? {
+				ch @ '0'..='9' => {
+					result.push_str("${");
+					result.push(ch);
+					result.push('}');
+				}
+				'n' => result.push('\n'),
+				'$' => result.push_str("$$"),
+				ch => result.push(ch),
+			},
+			'&' => result.push_str("${0}"),
+			'$' => result.push_str("$$"),
+			ch => result.push(ch),
+		}
+	}
+	Some(result)
+}
+
+fn expr(args: &[String]) -> Option<String> {
+	let result = match args {
+		[value] => value.parse::<i64>().ok()?,
+		[lhs, op, rhs] => {
+			let lhs = lhs.parse::<i64>().ok()?;
+			let rhs = rhs.parse::<i64>().ok()?;
+			match op.as_str() {
+				"+" => lhs.checked_add(rhs)?,
+				"-" => lhs.checked_sub(rhs)?,
+				"*" => lhs.checked_mul(rhs)?,
+				"/" => lhs.checked_div(rhs)?,
+				"%" => lhs.checked_rem(rhs)?,
+				_ => return None,
+			}
+		}
+		_ => return None,
+	};
+	Some(format!("{result}\n"))
+}
+
+/// Resolves a backslash escape sequence.
+fn unescape(ch: char) -> Option<char> {
+	match ch {
+		'n' => Some('\n'),
+		't' => Some('\t'),
+		'r' => Some('\r'),
+		'\\' | '\'' | '"' | '-' | '[' | ']' => Some(ch),
+		_ => None,
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn eval(src: &str) -> Option<String> {
+		let mut apml = ApmlContext::new();
+		apml.insert("VER".into(), "1.2.3-beta".into());
+		apml.insert("WORDS".into(), "a b".into());
+		apml.insert("GLOB".into(), "*.c".into());
+		eval_subcommand(&apml, src).unwrap()
+	}
+
+	#[test]
+	fn test_eval_subcommand() {
+		assert_eq!(eval("$(echo a  b)").unwrap(), "a b");
+		assert_eq!(eval("$(echo -n \"${VER}\")").unwrap(), "1.2.3-beta");
+		assert_eq!(eval("$(printf '%s-%d%%\\n' a 1 b)").unwrap(), "a-1%\nb-0%");
+		assert_eq!(eval("$(echo $VER | tr - _)").unwrap(), "1.2.3_beta");
+		assert_eq!(
+			eval("$(echo $VER | tr '[:lower:]' '[:upper:]')").unwrap(),
+			"1.2.3-BETA"
+		);
+		assert_eq!(eval("$(echo $VER | tr -d a-z.)").unwrap(), "123-");
+		assert_eq!(eval("$(echo $VER | cut -d. -f1-2)").unwrap(), "1.2");
+		assert_eq!(eval("$(echo $VER | cut -d . -f 2,3)").unwrap(), "2.3-beta");
+		assert_eq!(eval("$(echo $VER | cut -c3-)").unwrap(), "2.3-beta");
+		assert_eq!(
+			eval("$(echo $VER | sed 's/\\./_/g')").unwrap(),
+			"1_2_3-beta"
+		);
+		assert_eq!(
+			eval("$(echo $VER | sed -e 's|\\([0-9]\\)-|\\1~|')").unwrap(),
+			"1.2.3~beta"
+		);
+		assert_eq!(
+			eval("$(echo $VER | sed -E 's/([a-z]+)/[&]/')").unwrap(),
+			"1.2.3-[beta]"
+		);
+		assert_eq!(eval("$(expr 6 '*' 7)").unwrap(), "42");
+		assert_eq!(
+			eval("$(echo $VER | cut -d- -f1 | tr . ' ')").unwrap(),
+			"1 2 3"
+		);
+	}
+
+	#[test]
+	fn test_unevaluable() {
+		assert_eq!(eval("$(true)"), None);
+		assert_eq!(eval("$(rm -rf /)"), None);
+		assert_eq!(eval("$(echo a > b)"), None);
+		assert_eq!(eval("$(echo a; echo b)"), None);
+		assert_eq!(eval("$(echo a|tr a b)"), None);
+		assert_eq!(eval("$(tr a b)"), None);
+		assert_eq!(eval("$(echo a | sed 's/a/b/' file)"), None);
+		assert_eq!(eval("$(echo a | sed 's/\\(a\\)\\1/b/')"), None);
+		assert_eq!(eval("$(echo a | sed 'y/a/b/')"), None);
+		assert_eq!(eval("$(echo a | cut -s -f1)"), None);
+		assert_eq!(eval("$(expr 1 / 0)"), None);
+		assert_eq!(eval("$(printf '%5s' a)"), None);
+		assert_eq!(eval("$(echo | )"), None);
+		assert_eq!(eval("$(echo $(true))"), None);
+		assert_eq!(eval("$(printf '%s' $A)"), None);
+		assert_eq!(eval("$(printf '%s' $WORDS)"), None);
+		assert_eq!(eval("$(printf '%s' ${VER/-/ })"), None);
+		assert_eq!(eval("$(echo $GLOB)"), None);
+		assert_eq!(eval("$(printf '%s' \"$WORDS\")").unwrap(), "a b");
+		assert_eq!(eval("$(printf '%s' x$VER)").unwrap(), "x1.2.3-beta");
+	}
+}
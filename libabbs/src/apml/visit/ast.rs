@@ -0,0 +1,207 @@
+//! Visitor of AST by reference.
+
+use crate::apml::{
+	ast::{
+		ApmlAst, ArithmeticExpr, ArrayElement, ExpansionModifier, Text,
+		VariableDefinition, VariableExpansion, VariableValue, Word,
+	},
+	pattern::{BashPattern, GlobPart},
+};
+
+/// A visitor of AST by reference.
+///
+/// See [the module documentation][super] for details.
+pub trait AstVisitor<'a> {
+	fn visit_ast(&mut self, ast: &ApmlAst<'a>) {
+		walk_ast(self, ast);
+	}
+
+	fn visit_variable_definition(&mut self, def: &VariableDefinition<'a>) {
+		walk_variable_definition(self, def);
+	}
+
+	fn visit_variable_value(&mut self, value: &VariableValue<'a>) {
+		walk_variable_value(self, value);
+	}
+
+	fn visit_array_element(&mut self, element: &ArrayElement<'a>) {
+		walk_array_element(self, element);
+	}
+
+	fn visit_text(&mut self, text: &Text<'a>) {
+		walk_text(self, text);
+	}
+
+	fn visit_word(&mut self, word: &Word<'a>) {
+		walk_word(self, word);
+	}
+
+	fn visit_variable_expansion(&mut self, exp: &VariableExpansion<'a>) {
+		walk_variable_expansion(self, exp);
+	}
+
+	fn visit_expansion_modifier(&mut self, modifier: &ExpansionModifier<'a>) {
+		walk_expansion_modifier(self, modifier);
+	}
+
+	fn visit_arithmetic(&mut self, _expr: &ArithmeticExpr) {}
+
+	fn visit_pattern(&mut self, pattern: &BashPattern<'a>) {
+		walk_pattern(self, pattern);
+	}
+
+	fn visit_glob_part(&mut self, part: &GlobPart<'a>) {
+		walk_glob_part(self, part);
+	}
+}
+
+pub fn walk_ast<'a, V: AstVisitor<'a> + ?Sized>(v: &mut V, ast: &ApmlAst<'a>) {
+	for def in &ast.0 {
+		v.visit_variable_definition(def);
+	}
+}
+
+pub fn walk_variable_definition<'a, V: AstVisitor<'a> + ?Sized>(
+	v: &mut V,
+	def: &VariableDefinition<'a>,
+) {
+	v.visit_variable_value(&def.value);
+}
+
+pub fn walk_variable_value<'a, V: AstVisitor<'a> + ?Sized>(
+	v: &mut V,
+	value: &VariableValue<'a>,
+) {
+	match value {
+		VariableValue::String(text) => v.visit_text(text),
+		VariableValue::Array(elements) => {
+			for element in elements {
+				v.visit_array_element(element);
+			}
+		}
+	}
+}
+
+pub fn walk_array_element<'a, V: AstVisitor<'a> + ?Sized>(
+	v: &mut V,
+	element: &ArrayElement<'a>,
+) {
+	match element {
+		ArrayElement::ArrayInclusion(..) => {}
+		ArrayElement::Text(text, _) => v.visit_text(text),
+	}
+}
+
+pub fn walk_text<'a, V: AstVisitor<'a> + ?Sized>(v: &mut V, text: &Text<'a>) {
+	for word in &text.0 {
+		v.visit_word(word);
+	}
+}
+
+pub fn walk_word<'a, V: AstVisitor<'a> + ?Sized>(v: &mut V, word: &Word<'a>) {
+	match word {
+		Word::Literal(..) | Word::Subcommand(..) => {}
+		Word::Variable(exp) => v.visit_variable_expansion(exp),
+		Word::Arithmetic(expr, _) => v.visit_arithmetic(expr),
+	}
+}
+
+pub fn walk_variable_expansion<'a, V: AstVisitor<'a> + ?Sized>(
+	v: &mut V,
+	exp: &VariableExpansion<'a>,
+) {
+	if let Some(modifier) = &exp.modifier {
+		v.visit_expansion_modifier(modifier);
+	}
+}
+
+pub fn walk_expansion_modifier<'a, V: AstVisitor<'a> + ?Sized>(
+	v: &mut V,
+	modifier: &ExpansionModifier<'a>,
+) {
+	match modifier {
+		ExpansionModifier::StripShortestPrefix(pattern)
+		| ExpansionModifier::StripLongestPrefix(pattern)
+		| ExpansionModifier::StripShortestSuffix(pattern)
+		| ExpansionModifier::StripLongestSuffix(pattern)
+		| ExpansionModifier::UpperOnce(pattern)
+		| ExpansionModifier::UpperAll(pattern)
+		| ExpansionModifier::LowerOnce(pattern)
+		| ExpansionModifier::LowerAll(pattern) => v.visit_pattern(pattern),
+		ExpansionModifier::ReplaceOnce { pattern, string }
+		| ExpansionModifier::ReplaceAll { pattern, string }
+		| ExpansionModifier::ReplacePrefix { pattern, string }
+		| ExpansionModifier::ReplaceSuffix { pattern, string } => {
+			v.visit_pattern(pattern);
+			v.visit_text(string);
+		}
+		ExpansionModifier::ErrorOnUnset(text)
+		| ExpansionModifier::WhenUnset(text)
+		| ExpansionModifier::WhenSet(text) => v.visit_text(text),
+		ExpansionModifier::Substring { .. } | ExpansionModifier::Length => {}
+	}
+}
+
+pub fn walk_pattern<'a, V: AstVisitor<'a> + ?Sized>(
+	v: &mut V,
+	pattern: &BashPattern<'a>,
+) {
+	for part in &pattern.0 {
+		v.visit_glob_part(part);
+	}
+}
+
+pub fn walk_glob_part<'a, V: AstVisitor<'a> + ?Sized>(
+	v: &mut V,
+	part: &GlobPart<'a>,
+) {
+	match part {
+		GlobPart::ZeroOrOneOf(list)
+		| GlobPart::ZeroOrMoreOf(list)
+		| GlobPart::OneOrMoreOf(list)
+		| GlobPart::OneOf(list)
+		| GlobPart::Not(list) => {
+			for pattern in &list.0 {
+				v.visit_pattern(pattern);
+			}
+		}
+		GlobPart::String(_)
+		| GlobPart::Escaped(_)
+		| GlobPart::AnyString
+		| GlobPart::AnyChar
+		| GlobPart::Range(_) => {}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::apml::{ast::AstNode, lst::ApmlLst};
+
+	#[derive(Default)]
+	struct Replacements(Vec<String>);
+
+	impl<'a> AstVisitor<'a> for Replacements {
+		fn visit_expansion_modifier(
+			&mut self,
+			modifier: &ExpansionModifier<'a>,
+		) {
+			if let ExpansionModifier::ReplaceAll { pattern, .. } = modifier {
+				self.0.push(pattern.to_string());
+			}
+			walk_expansion_modifier(self, modifier);
+		}
+	}
+
+	#[test]
+	fn test_ast_visitor() {
+		let lst = ApmlLst::parse(
+			"A=\"${B//a/${C//b/c}}\"\nD=(\"${E:-${F//d}}\" ${G[@]})",
+		)
+		.unwrap();
+		let ast = ApmlAst::emit_from(&lst).unwrap();
+		let mut visitor = Replacements::default();
+		visitor.visit_ast(&ast);
+		assert_eq!(visitor.0, vec!["a", "b", "d"]);
+	}
+}
@@ -0,0 +1,271 @@
+//! Visitor of LST by reference.
+
+use crate::apml::{
+	lst::{
+		ApmlLst, ArrayToken, BracedExpansion, ExpansionModifier, LiteralPart,
+		Text, TextUnit, Token, VariableDefinition, VariableValue, Word,
+	},
+	pattern::{BashPattern, GlobPart},
+};
+
+/// A visitor of LST by reference.
+///
+/// See [the module documentation][super] for details.
+pub trait LstVisitor<'a> {
+	fn visit_lst(&mut self, lst: &ApmlLst<'a>) {
+		walk_lst(self, lst);
+	}
+
+	fn visit_token(&mut self, token: &Token<'a>) {
+		walk_token(self, token);
+	}
+
+	fn visit_comment(&mut self, _comment: &str) {}
+
+	fn visit_variable_definition(&mut self, def: &VariableDefinition<'a>) {
+		walk_variable_definition(self, def);
+	}
+
+	fn visit_variable_value(&mut self, value: &VariableValue<'a>) {
+		walk_variable_value(self, value);
+	}
+
+	fn visit_array_token(&mut self, token: &ArrayToken<'a>) {
+		walk_array_token(self, token);
+	}
+
+	fn visit_text(&mut self, text: &Text<'a>) {
+		walk_text(self, text);
+	}
+
+	fn visit_text_unit(&mut self, unit: &TextUnit<'a>) {
+		walk_text_unit(self, unit);
+	}
+
+	fn visit_word(&mut self, word: &Word<'a>) {
+		walk_word(self, word);
+	}
+
+	fn visit_literal_part(&mut self, _part: &LiteralPart<'a>) {}
+
+	fn visit_braced_expansion(&mut self, exp: &BracedExpansion<'a>) {
+		walk_braced_expansion(self, exp);
+	}
+
+	fn visit_expansion_modifier(&mut self, modifier: &ExpansionModifier<'a>) {
+		walk_expansion_modifier(self, modifier);
+	}
+
+	fn visit_pattern(&mut self, pattern: &BashPattern<'a>) {
+		walk_pattern(self, pattern);
+	}
+
+	fn visit_glob_part(&mut self, part: &GlobPart<'a>) {
+		walk_glob_part(self, part);
+	}
+}
+
+pub fn walk_lst<'a, V: LstVisitor<'a> + ?Sized>(v: &mut V, lst: &ApmlLst<'a>) {
+	for token in &lst.0 {
+		v.visit_token(token);
+	}
+}
+
+pub fn walk_token<'a, V: LstVisitor<'a> + ?Sized>(
+	v: &mut V,
+	token: &Token<'a>,
+) {
+	match token {
+		Token::Spacy(_) | Token::Newline => {}
+		Token::Comment(comment) => v.visit_comment(comment),
+		Token::Variable(def) => v.visit_variable_definition(def),
+	}
+}
+
+pub fn walk_variable_definition<'a, V: LstVisitor<'a> + ?Sized>(
+	v: &mut V,
+	def: &VariableDefinition<'a>,
+) {
+	v.visit_variable_value(&def.value);
+}
+
+pub fn walk_variable_value<'a, V: LstVisitor<'a> + ?Sized>(
+	v: &mut V,
+	value: &VariableValue<'a>,
+) {
+	match value {
+		VariableValue::String(text) => v.visit_text(text),
+		VariableValue::Array(tokens) => {
+			for token in tokens {
+				v.visit_array_token(token);
+			}
+		}
+	}
+}
+
+pub fn walk_array_token<'a, V: LstVisitor<'a> + ?Sized>(
+	v: &mut V,
+	token: &ArrayToken<'a>,
+) {
+	match token {
+		ArrayToken::Spacy(_) | ArrayToken::Newline => {}
+		ArrayToken::Comment(comment) => v.visit_comment(comment),
+		ArrayToken::Element(text) => v.visit_text(text),
+	}
+}
+
+pub fn walk_text<'a, V: LstVisitor<'a> + ?Sized>(v: &mut V, text: &Text<'a>) {
+	for unit in &text.0 {
+		v.visit_text_unit(unit);
+	}
+}
+
+pub fn walk_text_unit<'a, V: LstVisitor<'a> + ?Sized>(
+	v: &mut V,
+	unit: &TextUnit<'a>,
+) {
+	match unit {
+		TextUnit::Unquoted(words) | TextUnit::DoubleQuote(words) => {
+			for word in words {
+				v.visit_word(word);
+			}
+		}
+		TextUnit::SingleQuote(_) => {}
+	}
+}
+
+pub fn walk_word<'a, V: LstVisitor<'a> + ?Sized>(v: &mut V, word: &Word<'a>) {
+	match word {
+		Word::Literal(parts) => {
+			for part in parts {
+				v.visit_literal_part(part);
+			}
+		}
+		Word::BracedVariable(exp) => v.visit_braced_expansion(exp),
+		Word::Subcommand(tokens) => {
+			for token in tokens {
+				v.visit_array_token(token);
+			}
+		}
+		Word::UnbracedVariable(_) | Word::Arithmetic(_) => {}
+	}
+}
+
+pub fn walk_braced_expansion<'a, V: LstVisitor<'a> + ?Sized>(
+	v: &mut V,
+	exp: &BracedExpansion<'a>,
+) {
+	if let Some(modifier) = &exp.modifier {
+		v.visit_expansion_modifier(modifier);
+	}
+}
+
+pub fn walk_expansion_modifier<'a, V: LstVisitor<'a> + ?Sized>(
+	v: &mut V,
+	modifier: &ExpansionModifier<'a>,
+) {
+	match modifier {
+		ExpansionModifier::StripShortestPrefix(pattern)
+		| ExpansionModifier::StripLongestPrefix(pattern)
+		| ExpansionModifier::StripShortestSuffix(pattern)
+		| ExpansionModifier::StripLongestSuffix(pattern)
+		| ExpansionModifier::UpperOnce(pattern)
+		| ExpansionModifier::UpperAll(pattern)
+		| ExpansionModifier::LowerOnce(pattern)
+		| ExpansionModifier::LowerAll(pattern) => v.visit_pattern(pattern),
+		ExpansionModifier::ReplaceOnce { pattern, string }
+		| ExpansionModifier::ReplaceAll { pattern, string }
+		| ExpansionModifier::ReplacePrefix { pattern, string }
+		| ExpansionModifier::ReplaceSuffix { pattern, string } => {
+			v.visit_pattern(pattern);
+			if let Some(string) = string {
+				v.visit_text(string);
+			}
+		}
+		ExpansionModifier::ErrorOnUnset(text)
+		| ExpansionModifier::WhenUnset(text)
+		| ExpansionModifier::WhenSet(text) => v.visit_text(text),
+		ExpansionModifier::Substring { .. }
+		| ExpansionModifier::Length
+		| ExpansionModifier::ArrayElements
+		| ExpansionModifier::SingleWordElements => {}
+	}
+}
+
+pub fn walk_pattern<'a, V: LstVisitor<'a> + ?Sized>(
+	v: &mut V,
+	pattern: &BashPattern<'a>,
+) {
+	for part in &pattern.0 {
+		v.visit_glob_part(part);
+	}
+}
+
+pub fn walk_glob_part<'a, V: LstVisitor<'a> + ?Sized>(
+	v: &mut V,
+	part: &GlobPart<'a>,
+) {
+	match part {
+		GlobPart::ZeroOrOneOf(list)
+		| GlobPart::ZeroOrMoreOf(list)
+		| GlobPart::OneOrMoreOf(list)
+		| GlobPart::OneOf(list)
+		| GlobPart::Not(list) => {
+			for pattern in &list.0 {
+				v.visit_pattern(pattern);
+			}
+		}
+		GlobPart::String(_)
+		| GlobPart::Escaped(_)
+		| GlobPart::AnyString
+		| GlobPart::AnyChar
+		| GlobPart::Range(_) => {}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[derive(Default)]
+	struct Counter {
+		comments: usize,
+		literals: usize,
+		expansions: usize,
+		patterns: usize,
+	}
+
+	impl<'a> LstVisitor<'a> for Counter {
+		fn visit_comment(&mut self, _comment: &str) {
+			self.comments += 1;
+		}
+
+		fn visit_literal_part(&mut self, _part: &LiteralPart<'a>) {
+			self.literals += 1;
+		}
+
+		fn visit_braced_expansion(&mut self, exp: &BracedExpansion<'a>) {
+			self.expansions += 1;
+			walk_braced_expansion(self, exp);
+		}
+
+		fn visit_pattern(&mut self, pattern: &BashPattern<'a>) {
+			self.patterns += 1;
+			walk_pattern(self, pattern);
+		}
+	}
+
+	#[test]
+	fn test_lst_visitor() {
+		let lst = ApmlLst::parse(
+			"# a\nA=\"a${B/@(x|y)/${C:-c}}\"\nD=(a # d\n$(e ${F}))",
+		)
+		.unwrap();
+		let mut counter = Counter::default();
+		counter.visit_lst(&lst);
+		assert_eq!(counter.comments, 2);
+		assert_eq!(counter.literals, 4);
+		assert_eq!(counter.expansions, 3);
+		assert_eq!(counter.patterns, 3);
+	}
+}
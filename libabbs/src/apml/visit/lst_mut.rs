@@ -0,0 +1,278 @@
+//! Visitor of LST by mutable reference.
+
+use std::{borrow::Cow, sync::Arc};
+
+use crate::apml::{
+	lst::{
+		ApmlLst, ArrayToken, BracedExpansion, ExpansionModifier, LiteralPart,
+		Text, TextUnit, Token, VariableDefinition, VariableValue, Word,
+	},
+	pattern::{BashPattern, GlobPart},
+};
+
+/// A visitor of LST by mutable reference.
+///
+/// See [the module documentation][super] for details.
+pub trait LstVisitorMut<'a> {
+	fn visit_lst_mut(&mut self, lst: &mut ApmlLst<'a>) {
+		walk_lst_mut(self, lst);
+	}
+
+	fn visit_token_mut(&mut self, token: &mut Token<'a>) {
+		walk_token_mut(self, token);
+	}
+
+	fn visit_comment_mut(&mut self, _comment: &mut Cow<'a, str>) {}
+
+	fn visit_variable_definition_mut(
+		&mut self,
+		def: &mut VariableDefinition<'a>,
+	) {
+		walk_variable_definition_mut(self, def);
+	}
+
+	fn visit_variable_value_mut(&mut self, value: &mut VariableValue<'a>) {
+		walk_variable_value_mut(self, value);
+	}
+
+	fn visit_array_token_mut(&mut self, token: &mut ArrayToken<'a>) {
+		walk_array_token_mut(self, token);
+	}
+
+	fn visit_text_mut(&mut self, text: &mut Text<'a>) {
+		walk_text_mut(self, text);
+	}
+
+	fn visit_text_unit_mut(&mut self, unit: &mut TextUnit<'a>) {
+		walk_text_unit_mut(self, unit);
+	}
+
+	fn visit_word_mut(&mut self, word: &mut Word<'a>) {
+		walk_word_mut(self, word);
+	}
+
+	fn visit_literal_part_mut(&mut self, _part: &mut LiteralPart<'a>) {}
+
+	fn visit_braced_expansion_mut(&mut self, exp: &mut BracedExpansion<'a>) {
+		walk_braced_expansion_mut(self, exp);
+	}
+
+	fn visit_expansion_modifier_mut(
+		&mut self,
+		modifier: &mut ExpansionModifier<'a>,
+	) {
+		walk_expansion_modifier_mut(self, modifier);
+	}
+
+	fn visit_pattern_mut(&mut self, pattern: &mut BashPattern<'a>) {
+		walk_pattern_mut(self, pattern);
+	}
+
+	fn visit_glob_part_mut(&mut self, part: &mut GlobPart<'a>) {
+		walk_glob_part_mut(self, part);
+	}
+}
+
+pub fn walk_lst_mut<'a, V: LstVisitorMut<'a> + ?Sized>(
+	v: &mut V,
+	lst: &mut ApmlLst<'a>,
+) {
+	for token in &mut lst.0 {
+		v.visit_token_mut(token);
+	}
+}
+
+pub fn walk_token_mut<'a, V: LstVisitorMut<'a> + ?Sized>(
+	v: &mut V,
+	token: &mut Token<'a>,
+) {
+	match token {
+		Token::Spacy(_) | Token::Newline => {}
+		Token::Comment(comment) => v.visit_comment_mut(comment),
+		Token::Variable(def) => v.visit_variable_definition_mut(def),
+	}
+}
+
+pub fn walk_variable_definition_mut<'a, V: LstVisitorMut<'a> + ?Sized>(
+	v: &mut V,
+	def: &mut VariableDefinition<'a>,
+) {
+	v.visit_variable_value_mut(&mut def.value);
+}
+
+pub fn walk_variable_value_mut<'a, V: LstVisitorMut<'a> + ?Sized>(
+	v: &mut V,
+	value: &mut VariableValue<'a>,
+) {
+	match value {
+		VariableValue::String(text) => v.visit_text_mut(Arc::make_mut(text)),
+		VariableValue::Array(tokens) => {
+			for token in tokens {
+				v.visit_array_token_mut(token);
+			}
+		}
+	}
+}
+
+pub fn walk_array_token_mut<'a, V: LstVisitorMut<'a> + ?Sized>(
+	v: &mut V,
+	token: &mut ArrayToken<'a>,
+) {
+	match token {
+		ArrayToken::Spacy(_) | ArrayToken::Newline => {}
+		ArrayToken::Comment(comment) => v.visit_comment_mut(comment),
+		ArrayToken::Element(text) => v.visit_text_mut(Arc::make_mut(text)),
+	}
+}
+
+pub fn walk_text_mut<'a, V: LstVisitorMut<'a> + ?Sized>(
+	v: &mut V,
+	text: &mut Text<'a>,
+) {
+	for unit in &mut text.0 {
+		v.visit_text_unit_mut(unit);
+	}
+}
+
+pub fn walk_text_unit_mut<'a, V: LstVisitorMut<'a> + ?Sized>(
+	v: &mut V,
+	unit: &mut TextUnit<'a>,
+) {
+	match unit {
+		TextUnit::Unquoted(words) | TextUnit::DoubleQuote(words) => {
+			for word in words {
+				v.visit_word_mut(word);
+			}
+		}
+		TextUnit::SingleQuote(_) => {}
+	}
+}
+
+pub fn walk_word_mut<'a, V: LstVisitorMut<'a> + ?Sized>(
+	v: &mut V,
+	word: &mut Word<'a>,
+) {
+	match word {
+		Word::Literal(parts) => {
+			for part in parts {
+				v.visit_literal_part_mut(part);
+			}
+		}
+		Word::BracedVariable(exp) => v.visit_braced_expansion_mut(exp),
+		Word::Subcommand(tokens) => {
+			for token in tokens {
+				v.visit_array_token_mut(token);
+			}
+		}
+		Word::UnbracedVariable(_) | Word::Arithmetic(_) => {}
+	}
+}
+
+pub fn walk_braced_expansion_mut<'a, V: LstVisitorMut<'a> + ?Sized>(
+	v: &mut V,
+	exp: &mut BracedExpansion<'a>,
+) {
+	if let Some(modifier) = &mut exp.modifier {
+		v.visit_expansion_modifier_mut(modifier);
+	}
+}
+
+pub fn walk_expansion_modifier_mut<'a, V: LstVisitorMut<'a> + ?Sized>(
+	v: &mut V,
+	modifier: &mut ExpansionModifier<'a>,
+) {
+	match modifier {
+		ExpansionModifier::StripShortestPrefix(pattern)
+		| ExpansionModifier::StripLongestPrefix(pattern)
+		| ExpansionModifier::StripShortestSuffix(pattern)
+		| ExpansionModifier::StripLongestSuffix(pattern)
+		| ExpansionModifier::UpperOnce(pattern)
+		| ExpansionModifier::UpperAll(pattern)
+		| ExpansionModifier::LowerOnce(pattern)
+		| ExpansionModifier::LowerAll(pattern) => {
+			v.visit_pattern_mut(Arc::make_mut(pattern))
+		}
+		ExpansionModifier::ReplaceOnce { pattern, string }
+		| ExpansionModifier::ReplaceAll { pattern, string }
+		| ExpansionModifier::ReplacePrefix { pattern, string }
+		| ExpansionModifier::ReplaceSuffix { pattern, string } => {
+			v.visit_pattern_mut(Arc::make_mut(pattern));
+			if let Some(string) = string {
+				v.visit_text_mut(Arc::make_mut(string));
+			}
+		}
+		ExpansionModifier::ErrorOnUnset(text)
+		| ExpansionModifier::WhenUnset(text)
+		| ExpansionModifier::WhenSet(text) => v.visit_text_mut(Arc::make_mut(text)),
+		ExpansionModifier::Substring { .. }
+		| ExpansionModifier::Length
+		| ExpansionModifier::ArrayElements
+		| ExpansionModifier::SingleWordElements => {}
+	}
+}
+
+pub fn walk_pattern_mut<'a, V: LstVisitorMut<'a> + ?Sized>(
+	v: &mut V,
+	pattern: &mut BashPattern<'a>,
+) {
+	for part in &mut pattern.0 {
+		v.visit_glob_part_mut(part);
+	}
+}
+
+pub fn walk_glob_part_mut<'a, V: LstVisitorMut<'a> + ?Sized>(
+	v: &mut V,
+	part: &mut GlobPart<'a>,
+) {
+	match part {
+		GlobPart::ZeroOrOneOf(list)
+		| GlobPart::ZeroOrMoreOf(list)
+		| GlobPart::OneOrMoreOf(list)
+		| GlobPart::OneOf(list)
+		| GlobPart::Not(list) => {
+			for pattern in &mut list.0 {
+				v.visit_pattern_mut(pattern);
+			}
+		}
+		GlobPart::String(_)
+		| GlobPart::Escaped(_)
+		| GlobPart::AnyString
+		| GlobPart::AnyChar
+		| GlobPart::Range(_) => {}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	struct Rename;
+
+	impl<'a> LstVisitorMut<'a> for Rename {
+		fn visit_comment_mut(&mut self, comment: &mut Cow<'a, str>) {
+			*comment = Cow::Owned(comment.to_uppercase());
+		}
+
+		fn visit_braced_expansion_mut(
+			&mut self,
+			exp: &mut BracedExpansion<'a>,
+		) {
+			if exp.name == "A" {
+				exp.name = Cow::Borrowed("B");
+			}
+			walk_braced_expansion_mut(self, exp);
+		}
+	}
+
+	#[test]
+	fn test_lst_visitor_mut() {
+		let mut lst =
+			ApmlLst::parse("# a\nA=\"${A:-${A%%a}}\"\nC=(${A[@]} # c\n)")
+				.unwrap();
+		Rename.visit_lst_mut(&mut lst);
+		assert_eq!(
+			lst.to_string(),
+			"# A\nA=\"${B:-${B%%a}}\"\nC=(${B[@]} # C\n)"
+		);
+	}
+}
@@ -0,0 +1,43 @@
+//! Visitors of APML syntax trees.
+//!
+//! - [`LstVisitor`]: visits a LST by reference.
+//! - [`LstVisitorMut`]: visits a LST by mutable reference.
+//! - [`AstVisitor`]: visits a AST by reference.
+//!
+//! Every `visit_*` method of visitors defaults to the corresponding
+//! `walk_*` function, which visits all children of the node. To handle a
+//! kind of nodes, override its `visit_*` method and call the `walk_*`
+//! function in it if children should be visited too.
+//!
+//! For example, this collects all variables replaced with `${X/..}`:
+//!
+//! ```
+//! use libabbs::apml::{
+//!     lst::{ApmlLst, BracedExpansion, ExpansionModifier},
+//!     visit::{LstVisitor, lst::walk_braced_expansion},
+//! };
+//!
+//! struct Replaced(Vec<String>);
+//!
+//! impl<'a> LstVisitor<'a> for Replaced {
+//!     fn visit_braced_expansion(&mut self, exp: &BracedExpansion<'a>) {
+//!         if let Some(ExpansionModifier::ReplaceOnce { .. }) = exp.modifier {
+//!             self.0.push(exp.name.to_string());
+//!         }
+//!         walk_braced_expansion(self, exp);
+//!     }
+//! }
+//!
+//! let lst = ApmlLst::parse("A=\"${B/a/${C/b}}\"").unwrap();
+//! let mut visitor = Replaced(vec![]);
+//! visitor.visit_lst(&lst);
+//! assert_eq!(visitor.0, vec!["B", "C"]);
+//! ```
+
+pub mod ast;
+pub mod lst;
+pub mod lst_mut;
+
+pub use ast::AstVisitor;
+pub use lst::LstVisitor;
+pub use lst_mut::LstVisitorMut;
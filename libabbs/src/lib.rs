@@ -7,7 +7,7 @@ pub mod apml;
 pub mod tree;
 
 /// An ISA supported by AOSC OS.
-#[derive(PartialEq, Eq, Clone, Copy, Hash)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
 pub enum Architecture {
 	NoArch,
 	Amd64,
@@ -19,6 +19,17 @@ pub enum Architecture {
 }
 
 impl Architecture {
+	/// All supported architectures, including [`noarch`][Self::NoArch].
+	pub const ALL: [Self; 7] = [
+		Self::NoArch,
+		Self::Amd64,
+		Self::Arm64,
+		Self::LoongArch64,
+		Self::Riscv,
+		Self::Loongson3,
+		Self::Ppc64el,
+	];
+
 	/// Returns the identifier for the architecture.
 	pub fn ident(&self) -> &'static str {
 		match self {
@@ -46,8 +57,54 @@ impl Architecture {
 		}
 	}
 
+	/// Returns the dpkg architecture name, e.g. `all` for
+	/// [`noarch`][Self::NoArch] and `riscv64` for [`riscv`][Self::Riscv].
+	pub fn dpkg_name(&self) -> &'static str {
+		match self {
+			Architecture::NoArch => "all",
+			Architecture::Riscv => "riscv64",
+			_ => self.ident(),
+		}
+	}
+
+	/// Recognizes a dpkg architecture name.
+	pub fn from_dpkg_name(name: &str) -> Option<Self> {
+		match name {
+			"all" => Some(Self::NoArch),
+			"riscv64" => Some(Self::Riscv),
+			"noarch" | "riscv" => None,
+			_ => Self::from_ident(name),
+		}
+	}
+
 	/// Returns if the architecture is [`noarch`][Self::NoArch].
 	pub fn is_noarch(&self) -> bool {
 		matches!(self, Self::NoArch)
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_architecture() {
+		assert_eq!(
+			Architecture::from_ident("riscv"),
+			Some(Architecture::Riscv)
+		);
+		assert_eq!(Architecture::Riscv.dpkg_name(), "riscv64");
+		assert_eq!(Architecture::NoArch.dpkg_name(), "all");
+		assert_eq!(Architecture::Amd64.dpkg_name(), "amd64");
+		assert_eq!(
+			Architecture::from_dpkg_name("all"),
+			Some(Architecture::NoArch)
+		);
+		assert_eq!(
+			Architecture::from_dpkg_name("loongarch64"),
+			Some(Architecture::LoongArch64)
+		);
+		assert_eq!(Architecture::from_dpkg_name("riscv"), None);
+		assert_eq!(Architecture::from_dpkg_name("i386"), None);
+	}
+}
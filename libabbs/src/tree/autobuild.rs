@@ -0,0 +1,421 @@
+//! Auxiliary files of sub-packages read by Autobuild.
+//!
+//! Besides `defines` and build scripts, Autobuild reads maintainer
+//! scripts, such as `postinst`, and files describing the package, such
+//! as `conffiles`. Like `defines`, these files may carry a modifier
+//! suffix, e.g. `postinst.stage2`. The `overrides` directory holds files
+//! copied into the package as is.
+//!
+//! Structured files have typed parsers:
+//!
+//! - `conffiles`: one path of a configuration file per line.
+//! - `alternatives`: `alternative <link> <path> <priority>` lines, where
+//!   multiple triples may follow a single `alternative`.
+//! - `usergroup`: `user <name> <uid> <group> <home> <shell> [<groups>]`
+//!   and `group <name> <gid>` lines, where the primary group is a name or
+//!   a ID, and groups are separated by commas.
+//!
+//! Empty lines and lines starting with `#` are ignored by all parsers.
+
+use std::{
+	fmt::Display,
+	fs,
+	path::{Path, PathBuf},
+};
+
+use kstring::KString;
+
+use super::{AbbsError, AbbsResult, AbbsSubPackage};
+
+/// Kind of a auxiliary file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AuxFileKind {
+	/// Script run before installation (`preinst`).
+	Preinst,
+	/// Script run after installation (`postinst`).
+	Postinst,
+	/// Script run before removal (`prerm`).
+	Prerm,
+	/// Script run after removal (`postrm`).
+	Postrm,
+	/// List of configuration files (`conffiles`).
+	Conffiles,
+	/// Alternatives to register (`alternatives`).
+	Alternatives,
+	/// dpkg triggers (`triggers`).
+	Triggers,
+	/// System users and groups to create (`usergroup`).
+	Usergroup,
+	/// Directory of files copied into the package (`overrides`).
+	Overrides,
+}
+
+impl AuxFileKind {
+	/// All kinds of auxiliary files.
+	pub const ALL: [Self; 9] = [
+		Self::Preinst,
+		Self::Postinst,
+		Self::Prerm,
+		Self::Postrm,
+		Self::Conffiles,
+		Self::Alternatives,
+		Self::Triggers,
+		Self::Usergroup,
+		Self::Overrides,
+	];
+
+	/// Returns the file name.
+	pub fn file_name(&self) -> &'static str {
+		match self {
+			AuxFileKind::Preinst => "preinst",
+			AuxFileKind::Postinst => "postinst",
+			AuxFileKind::Prerm => "prerm",
+			AuxFileKind::Postrm => "postrm",
+			AuxFileKind::Conffiles => "conffiles",
+			AuxFileKind::Alternatives => "alternatives",
+			AuxFileKind::Triggers => "triggers",
+			AuxFileKind::Usergroup => "usergroup",
+			AuxFileKind::Overrides => "overrides",
+		}
+	}
+
+	/// Returns if the file is a maintainer script.
+	pub fn is_script(&self) -> bool {
+		matches!(
+			self,
+			AuxFileKind::Preinst
+				| AuxFileKind::Postinst
+				| AuxFileKind::Prerm
+				| AuxFileKind::Postrm
+		)
+	}
+
+	/// Returns if the file may carry a modifier suffix.
+	///
+	/// Only the `overrides` directory does not.
+	pub fn has_suffix(&self) -> bool {
+		!matches!(self, AuxFileKind::Overrides)
+	}
+}
+
+impl Display for AuxFileKind {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(self.file_name())
+	}
+}
+
+/// A auxiliary file of a sub-package.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuxFile {
+	/// Kind of the file.
+	pub kind: AuxFileKind,
+	/// Modifier suffix, e.g. `""` and `".stage2"`.
+	pub suffix: KString,
+	/// Path of the file or directory.
+	pub path: PathBuf,
+}
+
+/// A entry of `alternatives`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alternative {
+	/// Path of the generic name, e.g. `/usr/bin/editor`.
+	pub link: String,
+	/// Path of the alternative, e.g. `/usr/bin/vim`.
+	pub path: String,
+	/// Priority of the alternative.
+	pub priority: i32,
+}
+
+/// A entry of `usergroup`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UserGroup {
+	/// A system user.
+	User {
+		/// Login name.
+		name: String,
+		/// User ID.
+		uid: u32,
+		/// Primary group, as a name or a ID, like `useradd -g`.
+		group: String,
+		/// Home directory.
+		home: String,
+		/// Login shell.
+		shell: String,
+		/// Supplementary groups.
+		groups: Vec<String>,
+	},
+	/// A system group.
+	Group {
+		/// Group name.
+		name: String,
+		/// Group ID.
+		gid: u32,
+	},
+}
+
+impl AbbsSubPackage {
+	/// Returns a auxiliary file with a modifier suffix, if it exists.
+	///
+	/// The suffix is ignored for [`AuxFileKind::Overrides`].
+	pub fn aux_file(&self, kind: AuxFileKind, suffix: &str) -> Option<AuxFile> {
+		let suffix = if kind.has_suffix() { suffix } else { "" };
+		let path = self.join(format!("{}{}", kind.file_name(), suffix));
+		let exists = if kind == AuxFileKind::Overrides {
+			path.is_dir()
+		} else {
+			path.is_file()
+		};
+		exists.then(|| AuxFile {
+			kind,
+			suffix: KString::from_ref(suffix),
+			path,
+		})
+	}
+
+	/// Returns all existing auxiliary files for all modifier suffixes.
+	///
+	/// Files are ordered by suffixes, then by kinds.
+	pub fn aux_files(&self) -> AbbsResult<Vec<AuxFile>> {
+		let mut suffixes = self.modifier_suffixes()?;
+		suffixes.sort_unstable();
+		let mut result = Vec::new();
+		for suffix in &suffixes {
+			for kind in AuxFileKind::ALL {
+				if (kind.has_suffix() || suffix.is_empty())
+					&& let Some(file) = self.aux_file(kind, suffix)
+				{
+					result.push(file);
+				}
+			}
+		}
+		Ok(result)
+	}
+
+	/// Returns paths of files in the `overrides` directory, relative to
+	/// it and sorted.
+	pub fn overrides(&self) -> AbbsResult<Vec<PathBuf>> {
+		let root = self.join("overrides");
+		let mut result = Vec::new();
+		if root.is_dir() {
+			collect_files(&root, &root, &mut result)?;
+		}
+		result.sort_unstable();
+		Ok(result)
+	}
+
+	/// Parses `conffiles`, returning [None] if it does not exist.
+	pub fn conffiles(&self, suffix: &str) -> AbbsResult<Option<Vec<String>>> {
+		self.parse_aux(AuxFileKind::Conffiles, suffix, |line| {
+			Some(line.to_string())
+		})
+	}
+
+	/// Parses `alternatives`, returning [None] if it does not exist.
+	pub fn alternatives(
+		&self,
+		suffix: &str,
+	) -> AbbsResult<Option<Vec<Alternative>>> {
+		let Some(lines) =
+			self.parse_aux(AuxFileKind::Alternatives, suffix, |line| {
+				let mut fields = line.split_whitespace();
+				if fields.next() != Some("alternative") {
+					return None;
+				}
+				let fields = fields.collect::<Vec<_>>();
+				if fields.is_empty() || fields.len() % 3 != 0 {
+					return None;
+				}
+				fields
+					.chunks(3)
+					.map(|triple| {
+						Some(Alternative {
+							link: triple[0].to_string(),
+							path: triple[1].to_string(),
+							priority: triple[2].parse().ok()?,
+						})
+					})
+					.collect::<Option<Vec<_>>>()
+			})?
+		else {
+			return Ok(None);
+		};
+		Ok(Some(lines.into_iter().flatten().collect()))
+	}
+
+	/// Parses `usergroup`, returning [None] if it does not exist.
+	pub fn usergroups(
+		&self,
+		suffix: &str,
+	) -> AbbsResult<Option<Vec<UserGroup>>> {
+		self.parse_aux(AuxFileKind::Usergroup, suffix, |line| {
+			let fields = line.split_whitespace().collect::<Vec<_>>();
+			match fields.as_slice() {
+				["user", name, uid, group, home, shell, rest @ ..]
+					if rest.len() <= 1 =>
+				{
+					Some(UserGroup::User {
+						name: name.to_string(),
+						uid: uid.parse().ok()?,
+						group: group.to_string(),
+						home: home.to_string(),
+						shell: shell.to_string(),
+						groups: rest
+							.first()
+							.map(|groups| {
+								groups.split(',').map(str::to_string).collect()
+							})
+							.unwrap_or_default(),
+					})
+				}
+				["group", name, gid] => Some(UserGroup::Group {
+					name: name.to_string(),
+					gid: gid.parse().ok()?,
+				}),
+				_ => None,
+			}
+		})
+	}
+
+	/// Parses a line-based auxiliary file, skipping empty lines and
+	/// comments.
+	fn parse_aux<T>(
+		&self,
+		kind: AuxFileKind,
+		suffix: &str,
+		parse: impl Fn(&str) -> Option<T>,
+	) -> AbbsResult<Option<Vec<T>>> {
+		let Some(file) = self.aux_file(kind, suffix) else {
+			return Ok(None);
+		};
+		let mut result = Vec::new();
+		for (idx, line) in fs::read_to_string(&file.path)?.lines().enumerate() {
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+			result.push(parse(line).ok_or_else(|| AbbsError::InvalidLine {
+				path: file.path.clone(),
+				line: idx + 1,
+			})?);
+		}
+		Ok(Some(result))
+	}
+}
+
+fn collect_files(
+	root: &Path,
+	dir: &Path,
+	result: &mut Vec<PathBuf>,
+) -> AbbsResult<()> {
+	for entry in dir.read_dir()? {
+		let entry = entry?;
+		let path = entry.path();
+		if entry.file_type()?.is_dir() {
+			collect_files(root, &path, result)?;
+		} else {
+			result.push(
+				path.strip_prefix(root)
+					.expect("file must be in the root directory")
+					.to_owned(),
+			);
+		}
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::tree::AbbsTree;
+
+	#[test]
+	fn test_aux_files() {
+		let tree = AbbsTree::new("testrepo");
+		let host = tree.find_subpackage("test2-host").unwrap();
+		assert_eq!(
+			host.aux_files()
+				.unwrap()
+				.iter()
+				.map(|file| (file.kind, file.suffix.as_str()))
+				.collect::<Vec<_>>(),
+			vec![
+				(AuxFileKind::Postinst, ""),
+				(AuxFileKind::Conffiles, ""),
+				(AuxFileKind::Alternatives, ""),
+				(AuxFileKind::Usergroup, ""),
+				(AuxFileKind::Overrides, ""),
+				(AuxFileKind::Prerm, ".stage2"),
+				(AuxFileKind::Usergroup, ".stage2"),
+			]
+		);
+		assert!(host.aux_file(AuxFileKind::Triggers, "").is_none());
+		assert_eq!(
+			host.aux_file(AuxFileKind::Overrides, ".stage2")
+				.unwrap()
+				.suffix,
+			""
+		);
+		assert_eq!(
+			host.overrides().unwrap(),
+			vec![PathBuf::from("b"), PathBuf::from("usr/share/test2/a")]
+		);
+
+		let guest = tree.find_subpackage("test2-guest").unwrap();
+		assert!(guest.aux_files().unwrap().is_empty());
+		assert!(guest.overrides().unwrap().is_empty());
+		assert!(guest.conffiles("").unwrap().is_none());
+	}
+
+	#[test]
+	fn test_parsers() {
+		let tree = AbbsTree::new("testrepo");
+		let host = tree.find_subpackage("test2-host").unwrap();
+		assert_eq!(
+			host.conffiles("").unwrap().unwrap(),
+			vec!["/etc/test2/a.conf", "/etc/test2/b.conf"]
+		);
+		let alternative = |link: &str, path: &str, priority| Alternative {
+			link: link.to_string(),
+			path: path.to_string(),
+			priority,
+		};
+		assert_eq!(
+			host.alternatives("").unwrap().unwrap(),
+			vec![
+				alternative("/usr/bin/editor", "/usr/bin/test2", 20),
+				alternative("/usr/bin/vi", "/usr/bin/test2", 10),
+				alternative("/usr/bin/view", "/usr/bin/test2-view", 10),
+			]
+		);
+		assert_eq!(
+			host.usergroups("").unwrap().unwrap(),
+			vec![
+				UserGroup::Group {
+					name: "test2".to_string(),
+					gid: 900,
+				},
+				UserGroup::User {
+					name: "test2".to_string(),
+					uid: 900,
+					group: "900".to_string(),
+					home: "/var/lib/test2".to_string(),
+					shell: "/bin/false".to_string(),
+					groups: vec![],
+				},
+				UserGroup::User {
+					name: "test2-daemon".to_string(),
+					uid: 901,
+					group: "test2".to_string(),
+					home: "/var/lib/test2".to_string(),
+					shell: "/bin/false".to_string(),
+					groups: vec!["test2".to_string(), "video".to_string()],
+				},
+			]
+		);
+		assert!(matches!(
+			host.usergroups(".stage2"),
+			Err(AbbsError::InvalidLine { line: 1, .. })
+		));
+		assert!(host.alternatives(".stage2").unwrap().is_none());
+	}
+}
@@ -0,0 +1,180 @@
+//! Git-aware operations of trees.
+//!
+//! Trees are usually git repositories, and git history is useful for
+//! selecting packages to process, such as packages touched by a pull
+//! request. Operations here invoke the `git` executable.
+
+use std::{collections::BTreeMap, process::Command};
+
+use super::{AbbsError, AbbsResult, AbbsSourcePackage, AbbsTree, SectionName};
+
+impl AbbsTree {
+	/// Lists source packages with files changed between two revisions.
+	///
+	/// If `until` is [None], changes are collected from `since` to the
+	/// working tree, including uncommitted and untracked files.
+	///
+	/// Both the old and the new location of moved files are considered.
+	/// Packages which no longer exist are skipped. Packages are returned
+	/// in order of paths.
+	pub fn changed_packages(
+		&self,
+		since: &str,
+		until: Option<&str>,
+	) -> AbbsResult<Vec<AbbsSourcePackage>> {
+		let mut args = vec!["diff", "--name-status", "-z", "-M", "--relative"];
+		// revisions starting with `-` must not be parsed as options
+		args.push("--end-of-options");
+		args.push(since);
+		args.extend(until);
+		args.push("--");
+		let mut paths = parse_name_status(&self.git(&args)?);
+		if until.is_none() {
+			let untracked = self.git(&[
+				"ls-files",
+				"--others",
+				"--exclude-standard",
+				"-z",
+			])?;
+			paths.extend(
+				untracked
+					.split('\0')
+					.filter(|path| !path.is_empty())
+					.map(str::to_string),
+			);
+		}
+
+		let mut result = BTreeMap::new();
+		for path in paths {
+			let mut components = path.split('/');
+			let (Some(section), Some(package), Some(_)) =
+				(components.next(), components.next(), components.next())
+			else {
+				continue;
+			};
+			if section.starts_with('.') || !section.contains('-') {
+				continue;
+			}
+			if let Some(package) =
+				self.package(&SectionName::from_ref(section), package)
+			{
+				result.insert(format!("{section}/{}", package.name()), package);
+			}
+		}
+		Ok(result.into_values().collect())
+	}
+
+	/// Runs a git command in the tree, returning the standard output.
+	fn git(&self, args: &[&str]) -> AbbsResult<String> {
+		let output = Command::new("git")
+			.arg("-C")
+			.arg(self.as_path())
+			.args(args)
+			.output()?;
+		if !output.status.success() {
+			return Err(AbbsError::GitError(
+				String::from_utf8_lossy(&output.stderr).trim().to_string(),
+			));
+		}
+		Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+	}
+}
+
+/// Parses the output of `git diff --name-status -z`, returning all
+/// paths involved.
+fn parse_name_status(output: &str) -> Vec<String> {
+	let mut result = Vec::new();
+	let mut fields = output.split('\0').filter(|field| !field.is_empty());
+	while let Some(status) = fields.next() {
+		// renames and copies have both the source and the destination
+		let count = if status.starts_with(['R', 'C']) { 2 } else { 1 };
+		result.extend(fields.by_ref().take(count).map(str::to_string));
+	}
+	result
+}
+
+#[cfg(test)]
+mod test {
+	use std::{fs, path::Path};
+
+	use super::*;
+
+	fn git(dir: &Path, args: &[&str]) {
+		let status = Command::new("git")
+			.arg("-C")
+			.arg(dir)
+			.args(["-c", "user.name=test", "-c", "user.email=test@test"])
+			.args(args)
+			.status()
+			.unwrap();
+		assert!(status.success());
+	}
+
+	#[test]
+	fn test_parse_name_status() {
+		assert_eq!(
+			parse_name_status("M\0a/b/spec\0R100\0a/c/spec\0a/d/spec\0"),
+			vec!["a/b/spec", "a/c/spec", "a/d/spec"]
+		);
+		assert!(parse_name_status("").is_empty());
+	}
+
+	#[test]
+	fn test_changed_packages() {
+		let dir = tempfile::tempdir().unwrap();
+		let dir = dir.path();
+		let section = dir.join("app-admin");
+		for name in ["a", "b", "c"] {
+			fs::create_dir_all(section.join(name).join("autobuild")).unwrap();
+			fs::write(section.join(name).join("spec"), "VER=1\n").unwrap();
+			fs::write(
+				section.join(name).join("autobuild/defines"),
+				format!("PKGNAME={name}\n"),
+			)
+			.unwrap();
+		}
+		git(dir, &["init", "-q"]);
+		git(dir, &["add", "."]);
+		git(dir, &["commit", "-q", "-m", "init"]);
+
+		fs::write(section.join("a/autobuild/defines"), "PKGNAME=a2\n").unwrap();
+		git(dir, &["mv", "app-admin/b", "app-admin/d"]);
+		git(dir, &["commit", "-q", "-a", "-m", "change"]);
+
+		let tree = AbbsTree::new(dir);
+		let names = |packages: Vec<AbbsSourcePackage>| {
+			packages
+				.iter()
+				.map(|pkg| pkg.name().to_string())
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(
+			names(tree.changed_packages("HEAD~1", Some("HEAD")).unwrap()),
+			vec!["a", "d"]
+		);
+		assert!(tree.changed_packages("HEAD", None).unwrap().is_empty());
+
+		fs::write(section.join("c/spec"), "VER=2\n").unwrap();
+		fs::create_dir_all(section.join("e")).unwrap();
+		fs::write(section.join("e/spec"), "VER=1\n").unwrap();
+		assert_eq!(
+			names(tree.changed_packages("HEAD", None).unwrap()),
+			vec!["c", "e"]
+		);
+		assert_eq!(
+			names(tree.changed_packages("HEAD~1", None).unwrap()),
+			vec!["a", "c", "d", "e"]
+		);
+		assert!(matches!(
+			tree.changed_packages("no-such-rev", None),
+			Err(AbbsError::GitError(_))
+		));
+		let output = dir.join("output");
+		let option = format!("--output={}", output.display());
+		assert!(matches!(
+			tree.changed_packages(&option, None),
+			Err(AbbsError::GitError(_))
+		));
+		assert!(!output.exists());
+	}
+}
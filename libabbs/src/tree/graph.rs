@@ -0,0 +1,752 @@
+//! Dependency graph of sub-packages in a tree.
+//!
+//! [`DependencyGraph`] records relations declared by `defines` files,
+//! such as `PKGDEP` and `BUILDDEP`, between sub-packages of a tree. It
+//! supports queries of direct and transitive dependencies in both
+//! directions, detection of dependency cycles and exporting to DOT.
+//! With the `serde` feature, the graph can also be serialized, for
+//! example to JSON.
+//!
+//! The graph can be built for a certain architecture, in which case
+//! arch-overrides like `PKGDEP__AMD64` are resolved, see
+//! [`arch`][crate::apml::arch].
+
+use std::{
+	collections::{BTreeSet, HashMap},
+	fmt::{Display, Write},
+	fs,
+	path::{Path, PathBuf},
+	str::FromStr,
+	sync::Arc,
+	vec,
+};
+
+use crate::{
+	Architecture,
+	apml::{
+		ApmlContext,
+		arch::resolve_context,
+		ast::{ApmlAst, AstNode},
+		lst::ApmlLst,
+		model::{defines::Defines, dependency::DependencyAtom},
+	},
+};
+
+use super::{AbbsError, AbbsResult, AbbsSubPackage, AbbsTree};
+
+/// Kind of a relation between packages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DependencyKind {
+	/// Runtime dependency (`PKGDEP`).
+	Runtime,
+	/// Build-time dependency (`BUILDDEP`).
+	Build,
+	/// Recommended package (`PKGRECOM`).
+	Recommends,
+	/// Package broken by the package (`PKGBREAK`).
+	Breaks,
+	/// Package replaced by the package (`PKGREP`).
+	Replaces,
+}
+
+impl DependencyKind {
+	/// All kinds of relations.
+	pub const ALL: [Self; 5] = [
+		Self::Runtime,
+		Self::Build,
+		Self::Recommends,
+		Self::Breaks,
+		Self::Replaces,
+	];
+
+	/// Kinds of relations which require rebuilding dependents, i.e.
+	/// runtime and build-time dependencies.
+	pub const REBUILD: [Self; 2] = [Self::Runtime, Self::Build];
+
+	/// Returns the name of the variable declaring the relation.
+	pub fn var_name(&self) -> &'static str {
+		match self {
+			DependencyKind::Runtime => "PKGDEP",
+			DependencyKind::Build => "BUILDDEP",
+			DependencyKind::Recommends => "PKGRECOM",
+			DependencyKind::Breaks => "PKGBREAK",
+			DependencyKind::Replaces => "PKGREP",
+		}
+	}
+
+	/// Returns the entries of the relation in a `defines` file.
+	fn entries<'a>(&self, defines: &'a Defines) -> &'a [String] {
+		match self {
+			DependencyKind::Runtime => &defines.pkgdep,
+			DependencyKind::Build => &defines.builddep,
+			DependencyKind::Recommends => &defines.pkgrecom,
+			DependencyKind::Breaks => &defines.pkgbreak,
+			DependencyKind::Replaces => &defines.pkgrep,
+		}
+	}
+}
+
+impl Display for DependencyKind {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(self.var_name())
+	}
+}
+
+/// A sub-package in a [`DependencyGraph`].
+#[derive(Debug, Clone)]
+pub struct Node {
+	/// Name of the package (`PKGNAME`).
+	pub name: String,
+	/// The sub-package.
+	pub package: AbbsSubPackage,
+	/// Virtual packages provided by the package (`PKGPROV`).
+	pub provides: Vec<String>,
+}
+
+/// A relation between two nodes in a [`DependencyGraph`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Edge {
+	/// Index of the declaring node.
+	pub from: usize,
+	/// Index of the target node.
+	pub to: usize,
+	/// Kind of the relation.
+	pub kind: DependencyKind,
+}
+
+/// A relation whose target is not found in the tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unresolved {
+	/// Index of the declaring node.
+	pub from: usize,
+	/// The declared entry, such as `foo>=1.0`.
+	pub entry: String,
+	/// Kind of the relation.
+	pub kind: DependencyKind,
+}
+
+/// A graph of relations between sub-packages.
+///
+/// With the `serde` feature, the graph is serialized as a object with
+/// following fields:
+///
+/// - `arch`: identifier of the architecture, or `null`.
+/// - `nodes`: array of objects with `name`, `section`, `package`
+///   and `dir` of sub-packages.
+/// - `edges`: array of objects with `from` and `to` as package names,
+///   and `kind` as the variable declaring the relation.
+/// - `unresolved`: array of objects with `from`, `entry` and `kind`.
+#[derive(Debug, Clone, Default)]
+pub struct DependencyGraph {
+	arch: Option<Architecture>,
+	nodes: Vec<Node>,
+	edges: Vec<Edge>,
+	unresolved: Vec<Unresolved>,
+	/// Package names and provided names to nodes.
+	names: HashMap<String, usize>,
+	/// Outgoing edges of each node, by index of edges.
+	outgoing: Vec<Vec<usize>>,
+	/// Incoming edges of each node, by index of edges.
+	incoming: Vec<Vec<usize>>,
+}
+
+impl DependencyGraph {
+	/// Builds the dependency graph of all sub-packages in a tree.
+	///
+	/// If a architecture is given, arch-overrides for it and for the
+	/// arch-groups in `groups` are resolved. Otherwise only the plain
+	/// variables are read.
+	///
+	/// Packages failing to be evaluated are skipped. Paths of the failed
+	/// files and errors are returned along with the graph.
+	pub fn build(
+		tree: &AbbsTree,
+		arch: Option<Architecture>,
+		groups: &[&str],
+	) -> AbbsResult<(Self, Vec<(PathBuf, AbbsError)>)> {
+		let mut entries = Vec::new();
+		let mut failures = Vec::new();
+		for package in tree.all_packages()? {
+			let spec_path = package.join("spec");
+			if !spec_path.is_file() {
+				continue;
+			}
+			let spec = match eval_file(&spec_path, None) {
+				Ok(spec) => Arc::new(spec),
+				Err(err) => {
+					failures.push((spec_path, err));
+					continue;
+				}
+			};
+			for subpkg in package.subpackages()? {
+				let defines_path = subpkg.join("defines");
+				if !defines_path.is_file() {
+					continue;
+				}
+				let defines = eval_file(&defines_path, Some(spec.clone()))
+					.map(|ctx| match arch {
+						Some(arch) => resolve_context(&ctx, arch, groups),
+						None => ctx,
+					})
+					.and_then(|ctx| Ok(Defines::try_from(&ctx)?));
+				match defines {
+					Ok(defines) => entries.push((subpkg, defines)),
+					Err(err) => failures.push((defines_path, err)),
+				}
+			}
+		}
+		Ok((Self::from_defines(arch, entries), failures))
+	}
+
+	/// Builds a dependency graph from sub-packages and their `defines`.
+	///
+	/// Relations are resolved by package names first, and then by names
+	/// of provided virtual packages.
+	pub fn from_defines(
+		arch: Option<Architecture>,
+		entries: impl IntoIterator<Item = (AbbsSubPackage, Defines)>,
+	) -> Self {
+		let mut graph = Self {
+			arch,
+			..Default::default()
+		};
+		let entries = entries.into_iter().collect::<Vec<_>>();
+		for (idx, (package, defines)) in entries.iter().enumerate() {
+			graph.names.entry(defines.pkgname.clone()).or_insert(idx);
+			graph.nodes.push(Node {
+				name: defines.pkgname.clone(),
+				package: package.clone(),
+				provides: defines.pkgprov.clone(),
+			});
+		}
+		for (idx, (_, defines)) in entries.iter().enumerate() {
+			for name in &defines.pkgprov {
+				graph.names.entry(atom_name(name)).or_insert(idx);
+			}
+		}
+		graph.outgoing = vec![Vec::new(); graph.nodes.len()];
+		graph.incoming = vec![Vec::new(); graph.nodes.len()];
+		for (from, (_, defines)) in entries.iter().enumerate() {
+			for kind in DependencyKind::ALL {
+				for entry in kind.entries(defines) {
+					match graph.names.get(&atom_name(entry)) {
+						Some(&to) => {
+							graph.outgoing[from].push(graph.edges.len());
+							graph.incoming[to].push(graph.edges.len());
+							graph.edges.push(Edge { from, to, kind });
+						}
+						None => graph.unresolved.push(Unresolved {
+							from,
+							entry: entry.clone(),
+							kind,
+						}),
+					}
+				}
+			}
+		}
+		graph
+	}
+
+	/// Returns the architecture the graph is built for.
+	pub fn arch(&self) -> Option<Architecture> {
+		self.arch
+	}
+
+	/// Returns all nodes.
+	pub fn nodes(&self) -> &[Node] {
+		&self.nodes
+	}
+
+	/// Returns all edges.
+	pub fn edges(&self) -> &[Edge] {
+		&self.edges
+	}
+
+	/// Returns relations whose targets are not found.
+	pub fn unresolved(&self) -> &[Unresolved] {
+		&self.unresolved
+	}
+
+	/// Returns the index of a node by package name or provided name.
+	pub fn node<S: AsRef<str>>(&self, name: S) -> Option<usize> {
+		self.names.get(name.as_ref()).copied()
+	}
+
+	/// Returns names of packages a package directly depends on.
+	pub fn dependencies<S: AsRef<str>>(
+		&self,
+		name: S,
+		kinds: &[DependencyKind],
+	) -> Vec<&str> {
+		self.collect(name, kinds, false, false)
+	}
+
+	/// Returns names of packages a package depends on, directly or
+	/// transitively.
+	pub fn transitive_dependencies<S: AsRef<str>>(
+		&self,
+		name: S,
+		kinds: &[DependencyKind],
+	) -> Vec<&str> {
+		self.collect(name, kinds, false, true)
+	}
+
+	/// Returns names of packages directly depending on a package.
+	pub fn reverse_dependencies<S: AsRef<str>>(
+		&self,
+		name: S,
+		kinds: &[DependencyKind],
+	) -> Vec<&str> {
+		self.collect(name, kinds, true, false)
+	}
+
+	/// Returns names of packages depending on a package, directly or
+	/// transitively.
+	///
+	/// With [`DependencyKind::REBUILD`], these are packages to be rebuilt
+	/// after a ABI-breaking change of the package.
+	pub fn transitive_reverse_dependencies<S: AsRef<str>>(
+		&self,
+		name: S,
+		kinds: &[DependencyKind],
+	) -> Vec<&str> {
+		self.collect(name, kinds, true, true)
+	}
+
+	/// Collects neighbours of a node, returning sorted package names.
+	///
+	/// The node itself is excluded unless it is in a cycle.
+	fn collect<S: AsRef<str>>(
+		&self,
+		name: S,
+		kinds: &[DependencyKind],
+		reverse: bool,
+		transitive: bool,
+	) -> Vec<&str> {
+		let Some(start) = self.node(name) else {
+			return Vec::new();
+		};
+		let mut visited = BTreeSet::new();
+		let mut queue = vec![start];
+		while let Some(node) = queue.pop() {
+			for next in self.neighbours(node, kinds, reverse) {
+				if visited.insert(next) && transitive {
+					queue.push(next);
+				}
+			}
+		}
+		visited
+			.into_iter()
+			.map(|node| self.nodes[node].name.as_str())
+			.collect::<BTreeSet<_>>()
+			.into_iter()
+			.collect()
+	}
+
+	/// Iterates over nodes connected to a node with given kinds of edges.
+	fn neighbours(
+		&self,
+		node: usize,
+		kinds: &[DependencyKind],
+		reverse: bool,
+	) -> impl Iterator<Item = usize> {
+		let edges = if reverse {
+			&self.incoming[node]
+		} else {
+			&self.outgoing[node]
+		};
+		edges
+			.iter()
+			.map(|&edge| self.edges[edge])
+			.filter(|edge| kinds.contains(&edge.kind))
+			.map(move |edge| if reverse { edge.from } else { edge.to })
+	}
+
+	/// Returns dependency cycles formed by given kinds of edges.
+	///
+	/// Each cycle is a strongly connected component of the graph,
+	/// returned as sorted package names. Packages depending on themselves
+	/// form single-package cycles.
+	pub fn cycles(&self, kinds: &[DependencyKind]) -> Vec<Vec<&str>> {
+		let mut tarjan = Tarjan {
+			graph: self,
+			kinds,
+			index: vec![None; self.nodes.len()],
+			lowlink: vec![0; self.nodes.len()],
+			on_stack: vec![false; self.nodes.len()],
+			stack: Vec::new(),
+			next: 0,
+			components: Vec::new(),
+		};
+		for node in 0..self.nodes.len() {
+			if tarjan.index[node].is_none() {
+				tarjan.visit(node);
+			}
+		}
+		let mut cycles = tarjan
+			.components
+			.into_iter()
+			.filter(|component| {
+				component.len() > 1
+					|| self
+						.neighbours(component[0], kinds, false)
+						.any(|next| next == component[0])
+			})
+			.map(|component| {
+				let mut names = component
+					.into_iter()
+					.map(|node| self.nodes[node].name.as_str())
+					.collect::<Vec<_>>();
+				names.sort_unstable();
+				names
+			})
+			.collect::<Vec<_>>();
+		cycles.sort_unstable();
+		cycles
+	}
+
+	/// Exports the graph in Graphviz DOT format.
+	///
+	/// Edges are labeled with the variable declaring them.
+	pub fn to_dot(&self) -> String {
+		let mut out = String::from("digraph dependencies {\n");
+		for node in &self.nodes {
+			writeln!(out, "\t{};", dot_id(&node.name)).unwrap();
+		}
+		for edge in &self.edges {
+			writeln!(
+				out,
+				"\t{} -> {} [label={}];",
+				dot_id(&self.nodes[edge.from].name),
+				dot_id(&self.nodes[edge.to].name),
+				dot_id(edge.kind.var_name())
+			)
+			.unwrap();
+		}
+		out.push_str("}\n");
+		out
+	}
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for DependencyGraph {
+	fn serialize<S: serde::Serializer>(
+		&self,
+		serializer: S,
+	) -> Result<S::Ok, S::Error> {
+		use serde::ser::SerializeStruct;
+
+		let name = |node: usize| self.nodes[node].name.as_str();
+		let edges = self
+			.edges
+			.iter()
+			.map(|edge| Relation {
+				from: name(edge.from),
+				target: ("to", name(edge.to)),
+				kind: edge.kind,
+			})
+			.collect::<Vec<_>>();
+		let unresolved = self
+			.unresolved
+			.iter()
+			.map(|unresolved| Relation {
+				from: name(unresolved.from),
+				target: ("entry", &unresolved.entry),
+				kind: unresolved.kind,
+			})
+			.collect::<Vec<_>>();
+
+		let mut state = serializer.serialize_struct("DependencyGraph", 4)?;
+		state.serialize_field("arch", &self.arch.map(|arch| arch.ident()))?;
+		state.serialize_field("nodes", &self.nodes)?;
+		state.serialize_field("edges", &edges)?;
+		state.serialize_field("unresolved", &unresolved)?;
+		state.end()
+	}
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Node {
+	fn serialize<S: serde::Serializer>(
+		&self,
+		serializer: S,
+	) -> Result<S::Ok, S::Error> {
+		use serde::ser::SerializeStruct;
+
+		let source = self.package.source_package();
+		let mut state = serializer.serialize_struct("Node", 4)?;
+		state.serialize_field("name", &self.name)?;
+		state.serialize_field("section", source.section().as_str())?;
+		state.serialize_field("package", source.name())?;
+		state.serialize_field("dir", self.package.dir_name())?;
+		state.end()
+	}
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for DependencyKind {
+	fn serialize<S: serde::Serializer>(
+		&self,
+		serializer: S,
+	) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(self.var_name())
+	}
+}
+
+/// A relation with package names, as serialized in a graph.
+#[cfg(feature = "serde")]
+struct Relation<'a> {
+	from: &'a str,
+	/// Name and value of the target field.
+	target: (&'static str, &'a str),
+	kind: DependencyKind,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Relation<'_> {
+	fn serialize<S: serde::Serializer>(
+		&self,
+		serializer: S,
+	) -> Result<S::Ok, S::Error> {
+		use serde::ser::SerializeStruct;
+
+		let mut state = serializer.serialize_struct("Relation", 3)?;
+		state.serialize_field("from", self.from)?;
+		state.serialize_field(self.target.0, self.target.1)?;
+		state.serialize_field("kind", &self.kind)?;
+		state.end()
+	}
+}
+
+/// Tarjan's algorithm for strongly connected components.
+struct Tarjan<'a> {
+	graph: &'a DependencyGraph,
+	kinds: &'a [DependencyKind],
+	index: Vec<Option<usize>>,
+	lowlink: Vec<usize>,
+	on_stack: Vec<bool>,
+	stack: Vec<usize>,
+	next: usize,
+	components: Vec<Vec<usize>>,
+}
+
+impl Tarjan<'_> {
+	fn visit(&mut self, root: usize) {
+		// nodes being visited and their unvisited neighbours, kept on heap
+		// as dependency chains can be too long for recursion
+		let mut calls = vec![self.enter(root)];
+		while let Some((node, neighbours)) = calls.last_mut() {
+			let node = *node;
+			if let Some(next) = neighbours.next() {
+				match self.index[next] {
+					None => calls.push(self.enter(next)),
+					Some(index) if self.on_stack[next] => {
+						self.lowlink[node] = self.lowlink[node].min(index);
+					}
+					Some(_) => {}
+				}
+				continue;
+			}
+
+			calls.pop();
+			if Some(self.lowlink[node]) == self.index[node] {
+				let mut component = Vec::new();
+				while let Some(top) = self.stack.pop() {
+					self.on_stack[top] = false;
+					component.push(top);
+					if top == node {
+						break;
+					}
+				}
+				self.components.push(component);
+			}
+			if let Some(&(parent, _)) = calls.last() {
+				self.lowlink[parent] =
+					self.lowlink[parent].min(self.lowlink[node]);
+			}
+		}
+	}
+
+	/// Assigns an index to a node, returning it with its neighbours.
+	fn enter(&mut self, node: usize) -> (usize, vec::IntoIter<usize>) {
+		self.index[node] = Some(self.next);
+		self.lowlink[node] = self.next;
+		self.next += 1;
+		self.stack.push(node);
+		self.on_stack[node] = true;
+
+		let neighbours = self
+			.graph
+			.neighbours(node, self.kinds, false)
+			.collect::<Vec<_>>();
+		(node, neighbours.into_iter())
+	}
+}
+
+/// Returns the package name of a dependency entry, such as `foo` for
+/// `foo>=1.0`.
+///
+/// Malformed entries are used as names as is, so they are reported as
+/// unresolved.
+fn atom_name(entry: &str) -> String {
+	DependencyAtom::from_str(entry)
+		.map(|atom| atom.name)
+		.unwrap_or_else(|_| entry.to_string())
+}
+
+/// Evaluates a APML file.
+fn eval_file(
+	path: &Path,
+	parent: Option<Arc<ApmlContext>>,
+) -> AbbsResult<ApmlContext> {
+	let src = fs::read_to_string(path)?;
+	let eval = || {
+		let ast = ApmlAst::emit_from(&ApmlLst::parse(&src)?)?;
+		match parent {
+			Some(parent) => ApmlContext::eval_ast_with_parent(&ast, parent),
+			None => ApmlContext::eval_ast(&ast),
+		}
+	};
+	Ok(
+		eval()
+			.map_err(|err| err.locate(Some(&path.to_string_lossy()), &src))?,
+	)
+}
+
+/// Quotes a string as a DOT identifier.
+fn dot_id(id: &str) -> String {
+	format!("\"{}\"", id.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn defines(src: &str) -> (AbbsSubPackage, Defines) {
+		let ctx = ApmlContext::eval_source(src).unwrap();
+		let defines = Defines::try_from(&ctx).unwrap();
+		let package = AbbsSubPackage::new(format!(
+			"tree/app-admin/{}/autobuild",
+			defines.pkgname
+		));
+		(package, defines)
+	}
+
+	#[test]
+	fn test_graph() {
+		let graph = DependencyGraph::from_defines(
+			None,
+			[
+				defines("PKGNAME=a\nPKGDEP=\"b c>=1\"\nPKGBREAK=\"x<=1\""),
+				defines("PKGNAME=b\nPKGDEP=\"d\"\nBUILDDEP=\"e_\""),
+				defines("PKGNAME=c\nPKGDEP=\"a\""),
+				defines("PKGNAME=d\nPKGPROV=\"vd\""),
+				defines("PKGNAME=e\nPKGDEP=\"e vd\"\nPKGRECOM=\"f\""),
+			],
+		);
+		assert_eq!(graph.nodes().len(), 5);
+		assert_eq!(graph.edges().len(), 7);
+		assert_eq!(graph.node("vd"), graph.node("d"));
+		assert_eq!(
+			graph.unresolved(),
+			&[
+				Unresolved {
+					from: 0,
+					entry: "x<=1".to_string(),
+					kind: DependencyKind::Breaks,
+				},
+				Unresolved {
+					from: 4,
+					entry: "f".to_string(),
+					kind: DependencyKind::Recommends,
+				},
+			]
+		);
+
+		let runtime = &[DependencyKind::Runtime];
+		assert_eq!(graph.dependencies("a", runtime), vec!["b", "c"]);
+		assert_eq!(
+			graph.transitive_dependencies("a", runtime),
+			vec!["a", "b", "c", "d"]
+		);
+		assert_eq!(
+			graph.transitive_dependencies("b", &DependencyKind::REBUILD),
+			vec!["d", "e"]
+		);
+		assert_eq!(graph.reverse_dependencies("d", runtime), vec!["b", "e"]);
+		assert_eq!(
+			graph.transitive_reverse_dependencies("d", runtime),
+			vec!["a", "b", "c", "e"]
+		);
+		assert_eq!(
+			graph.transitive_reverse_dependencies("e", runtime),
+			vec!["e"]
+		);
+		assert!(graph.dependencies("z", runtime).is_empty());
+
+		assert_eq!(graph.cycles(runtime), vec![vec!["a", "c"], vec!["e"]]);
+		assert!(graph.cycles(&[DependencyKind::Build]).is_empty());
+
+		let dot = graph.to_dot();
+		assert!(dot.starts_with("digraph dependencies {\n\t\"a\";\n"));
+		assert!(dot.contains("\t\"a\" -> \"b\" [label=\"PKGDEP\"];\n"));
+
+		#[cfg(feature = "serde")]
+		{
+			let json = serde_json::to_string(&graph).unwrap();
+			assert!(json.starts_with(
+				"{\"arch\":null,\"nodes\":[{\"name\":\"a\",\"section\":\
+				\"app-admin\",\"package\":\"a\",\"dir\":\"autobuild\"},"
+			));
+			assert!(
+				json.contains(
+					"{\"from\":\"a\",\"to\":\"b\",\"kind\":\"PKGDEP\"}"
+				)
+			);
+			assert!(json.ends_with(
+				"\"unresolved\":[{\"from\":\"a\",\"entry\":\"x<=1\",\"kind\":\
+				\"PKGBREAK\"},{\"from\":\"e\",\"entry\":\"f\",\"kind\":\
+				\"PKGRECOM\"}]}"
+			));
+			let json = serde_json::to_value(&graph).unwrap();
+			assert_eq!(json["edges"].as_array().unwrap().len(), 7);
+		}
+	}
+
+	#[test]
+	fn test_long_chain() {
+		// deep enough to overflow the stack of test threads with recursion
+		let len = 100_000;
+		let graph = DependencyGraph::from_defines(
+			None,
+			(0..len).map(|idx| {
+				let defines = Defines {
+					pkgname: format!("p{idx}"),
+					pkgdep: vec![format!("p{}", (idx + 1) % len)],
+					..Default::default()
+				};
+				let package = AbbsSubPackage::new(format!(
+					"tree/app-admin/p{idx}/autobuild"
+				));
+				(package, defines)
+			}),
+		);
+		let cycles = graph.cycles(&[DependencyKind::Runtime]);
+		assert_eq!(cycles.len(), 1);
+		assert_eq!(cycles[0].len(), len);
+		assert!(graph.cycles(&[DependencyKind::Build]).is_empty());
+	}
+
+	#[test]
+	fn test_build() {
+		let tree = AbbsTree::new("testrepo");
+		let (graph, failures) =
+			DependencyGraph::build(&tree, Some(Architecture::Amd64), &[])
+				.unwrap();
+		assert!(failures.is_empty());
+		assert_eq!(graph.arch(), Some(Architecture::Amd64));
+		assert_eq!(graph.nodes().len(), 3);
+		assert!(graph.node("test2-host").is_some());
+		assert!(graph.edges().is_empty());
+	}
+}
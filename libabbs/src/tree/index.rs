@@ -0,0 +1,464 @@
+//! Persistent index of packages in a tree.
+//!
+//! Looking up a package by name requires scanning all sections, and
+//! looking up a sub-package by `PKGNAME` requires evaluating every
+//! `defines` file in the tree. [`PackageIndex`] records the results and
+//! can be saved to disk, so following lookups only touch a few files.
+//!
+//! The index is invalidated by modification times: sections, source
+//! package directories and `defines` files that have been modified since
+//! the last update are rescanned by [`PackageIndex::update`].
+//!
+//! The on-disk format is a private line-based text format and may change
+//! between versions. Index files in unknown formats are discarded.
+
+use std::{
+	collections::{BTreeMap, HashMap},
+	env, fs,
+	io::Write,
+	path::{Path, PathBuf},
+	time::UNIX_EPOCH,
+};
+
+use super::{
+	AbbsResult, AbbsSourcePackage, AbbsSubPackage, AbbsTree, SectionName,
+};
+
+/// Header of index files, including the format version.
+const HEADER: &str = "libabbs-index 1";
+
+/// An index of source packages and sub-packages in a tree.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PackageIndex {
+	sections: BTreeMap<SectionName, SectionEntry>,
+	/// Source package name to section.
+	packages: HashMap<String, SectionName>,
+	/// `PKGNAME` to section, source package and directory.
+	subpackages: HashMap<String, (SectionName, String, String)>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct SectionEntry {
+	mtime: Option<u128>,
+	packages: BTreeMap<String, PackageEntry>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct PackageEntry {
+	mtime: Option<u128>,
+	subpackages: BTreeMap<String, SubPackageEntry>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct SubPackageEntry {
+	/// Modification time of the `defines` file.
+	mtime: Option<u128>,
+	/// `PKGNAME` of the sub-package, if it could be evaluated.
+	name: Option<String>,
+}
+
+impl PackageIndex {
+	/// Creates an empty index.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Builds the index of a tree.
+	pub fn build(tree: &AbbsTree) -> AbbsResult<Self> {
+		let mut index = Self::new();
+		index.update(tree)?;
+		Ok(index)
+	}
+
+	/// Returns the default location of the index file of a tree.
+	///
+	/// The index is placed in the `.git` directory of the tree if exists,
+	/// or in the user cache directory otherwise.
+	pub fn default_path<P: AsRef<Path>>(tree: P) -> Option<PathBuf> {
+		let tree = tree.as_ref();
+		let git = tree.join(".git");
+		if git.is_dir() {
+			return Some(git.join("libabbs-index"));
+		}
+		let cache = env::var_os("XDG_CACHE_HOME")
+			.map(PathBuf::from)
+			.filter(|path| path.is_absolute())
+			.or_else(|| {
+				env::var_os("HOME")
+					.map(|home| PathBuf::from(home).join(".cache"))
+			})?;
+		let tree = fs::canonicalize(tree).unwrap_or_else(|_| tree.to_owned());
+		// FNV-1a, which is stable across builds
+		let hash = tree
+			.to_string_lossy()
+			.bytes()
+			.fold(0xcbf29ce484222325u64, |hash, byte| {
+				(hash ^ byte as u64).wrapping_mul(0x100000001b3)
+			});
+		Some(cache.join("libabbs").join(format!("index-{hash:016x}")))
+	}
+
+	/// Loads an index file.
+	///
+	/// Returns [None] if the file does not exist or is in an unknown
+	/// format.
+	pub fn load<P: AsRef<Path>>(path: P) -> AbbsResult<Option<Self>> {
+		let src = match fs::read_to_string(path) {
+			Ok(src) => src,
+			Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+				return Ok(None);
+			}
+			Err(err) => return Err(err.into()),
+		};
+		Ok(Self::parse(&src))
+	}
+
+	fn parse(src: &str) -> Option<Self> {
+		let mut lines = src.lines();
+		if lines.next()? != HEADER {
+			return None;
+		}
+		let mut sections = BTreeMap::<SectionName, SectionEntry>::new();
+		let mut section = None;
+		let mut package = None;
+		for line in lines {
+			let fields = line.split('\t').collect::<Vec<_>>();
+			match fields.as_slice() {
+				["S", name, mtime] => {
+					let name = SectionName::from_ref(name);
+					sections.insert(
+						name.clone(),
+						SectionEntry {
+							mtime: parse_mtime(mtime)?,
+							packages: BTreeMap::new(),
+						},
+					);
+					section = Some(name);
+					package = None;
+				}
+				["P", name, mtime] => {
+					sections.get_mut(section.as_ref()?)?.packages.insert(
+						name.to_string(),
+						PackageEntry {
+							mtime: parse_mtime(mtime)?,
+							subpackages: BTreeMap::new(),
+						},
+					);
+					package = Some(name.to_string());
+				}
+				["D", dir, mtime, name] => {
+					sections
+						.get_mut(section.as_ref()?)?
+						.packages
+						.get_mut(package.as_ref()?)?
+						.subpackages
+						.insert(
+							dir.to_string(),
+							SubPackageEntry {
+								mtime: parse_mtime(mtime)?,
+								name: Some(name.to_string())
+									.filter(|name| !name.is_empty()),
+							},
+						);
+				}
+				_ => return None,
+			}
+		}
+		let mut index = Self {
+			sections,
+			..Default::default()
+		};
+		index.rebuild_lookup();
+		Some(index)
+	}
+
+	/// Saves the index to a file.
+	///
+	/// Parent directories are created if needed.
+	pub fn save<P: AsRef<Path>>(&self, path: P) -> AbbsResult<()> {
+		let path = path.as_ref();
+		let mut out = String::from(HEADER);
+		out.push('\n');
+		for (name, section) in &self.sections {
+			out.push_str(&format!(
+				"S\t{}\t{}\n",
+				name,
+				print_mtime(section.mtime)
+			));
+			for (name, package) in &section.packages {
+				out.push_str(&format!(
+					"P\t{}\t{}\n",
+					name,
+					print_mtime(package.mtime)
+				));
+				for (dir, subpkg) in &package.subpackages {
+					out.push_str(&format!(
+						"D\t{}\t{}\t{}\n",
+						dir,
+						print_mtime(subpkg.mtime),
+						subpkg.name.as_deref().unwrap_or_default()
+					));
+				}
+			}
+		}
+		let parent = path
+			.parent()
+			.filter(|parent| !parent.as_os_str().is_empty())
+			.unwrap_or(Path::new("."));
+		fs::create_dir_all(parent)?;
+		// write to a unique temporary file first to avoid partial index,
+		// which is removed on failures
+		let mut temp = tempfile::NamedTempFile::new_in(parent)?;
+		temp.write_all(out.as_bytes())?;
+		temp.persist(path).map_err(|err| err.error)?;
+		Ok(())
+	}
+
+	/// Updates the index, rescanning modified parts of the tree.
+	///
+	/// Returns if the index is changed.
+	pub fn update(&mut self, tree: &AbbsTree) -> AbbsResult<bool> {
+		let mut changed = false;
+		let mut sections = BTreeMap::new();
+		for name in tree.sections()? {
+			let path = tree.join(name.as_str());
+			let mtime = mtime_of(&path);
+			let mut section = self.sections.remove(&name).unwrap_or_default();
+			if section.mtime != mtime || mtime.is_none() {
+				let mut packages = BTreeMap::new();
+				for package in tree.section_packages(&name)? {
+					let package = package.name().to_string();
+					let entry =
+						section.packages.remove(&package).unwrap_or_default();
+					packages.insert(package, entry);
+				}
+				section = SectionEntry { mtime, packages };
+				changed = true;
+			}
+			for (package, entry) in &mut section.packages {
+				changed |= entry.update(&path.join(package))?;
+			}
+			sections.insert(name, section);
+		}
+		changed |= !self.sections.is_empty();
+		self.sections = sections;
+		if changed {
+			self.rebuild_lookup();
+		}
+		Ok(changed)
+	}
+
+	fn rebuild_lookup(&mut self) {
+		self.packages.clear();
+		self.subpackages.clear();
+		for (section_name, section) in &self.sections {
+			for (package_name, package) in &section.packages {
+				self.packages
+					.entry(package_name.clone())
+					.or_insert_with(|| section_name.clone());
+				for (dir, subpkg) in &package.subpackages {
+					let Some(name) = &subpkg.name else {
+						continue;
+					};
+					let value = (
+						section_name.clone(),
+						package_name.clone(),
+						dir.clone(),
+					);
+					// prefer the source package with the same name
+					if name == package_name {
+						self.subpackages.insert(name.clone(), value);
+					} else {
+						self.subpackages.entry(name.clone()).or_insert(value);
+					}
+				}
+			}
+		}
+	}
+
+	/// Returns the number of source packages in the index.
+	pub fn len(&self) -> usize {
+		self.sections
+			.values()
+			.map(|section| section.packages.len())
+			.sum()
+	}
+
+	/// Returns if the index contains no source packages.
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	/// Finds a source package.
+	///
+	/// Returns [None] if the package is not indexed or no longer exists.
+	pub fn find_package<S: AsRef<str>>(
+		&self,
+		tree: &AbbsTree,
+		name: S,
+	) -> Option<AbbsSourcePackage> {
+		let section = self.packages.get(name.as_ref())?;
+		tree.package(section, name)
+	}
+
+	/// Finds a sub-package by its `PKGNAME`.
+	///
+	/// Returns [None] if the sub-package is not indexed or its `defines`
+	/// has been modified since the last update.
+	pub fn find_subpackage<S: AsRef<str>>(
+		&self,
+		tree: &AbbsTree,
+		name: S,
+	) -> Option<AbbsSubPackage> {
+		let (section, package, dir) = self.subpackages.get(name.as_ref())?;
+		let entry = self
+			.sections
+			.get(section)?
+			.packages
+			.get(package)?
+			.subpackages
+			.get(dir)?;
+		let path = tree.join(section.as_str()).join(package).join(dir);
+		let mtime = mtime_of(&path.join("defines"));
+		(mtime.is_some() && mtime == entry.mtime)
+			.then(|| AbbsSubPackage::new(path))
+	}
+}
+
+impl PackageEntry {
+	fn update(&mut self, path: &Path) -> AbbsResult<bool> {
+		let mut changed = false;
+		let mtime = mtime_of(path);
+		if self.mtime != mtime || mtime.is_none() {
+			let mut subpackages = BTreeMap::new();
+			if path.is_dir() {
+				for entry in path.read_dir()? {
+					let entry = entry?;
+					if entry.file_type()?.is_dir()
+						&& let Some(dir) = entry.file_name().to_str()
+					{
+						let subpkg =
+							self.subpackages.remove(dir).unwrap_or_default();
+						subpackages.insert(dir.to_string(), subpkg);
+					}
+				}
+			}
+			*self = Self { mtime, subpackages };
+			changed = true;
+		}
+		for (dir, subpkg) in &mut self.subpackages {
+			let path = path.join(dir);
+			let mtime = mtime_of(&path.join("defines"));
+			if subpkg.mtime != mtime || mtime.is_none() {
+				let name = match mtime {
+					Some(_) => AbbsSubPackage::new(path).name().ok(),
+					None => None,
+				};
+				changed |= subpkg.mtime != mtime || subpkg.name != name;
+				*subpkg = SubPackageEntry { mtime, name };
+			}
+		}
+		Ok(changed)
+	}
+}
+
+/// Returns the modification time of a file in nanoseconds.
+fn mtime_of(path: &Path) -> Option<u128> {
+	fs::metadata(path)
+		.and_then(|metadata| metadata.modified())
+		.ok()?
+		.duration_since(UNIX_EPOCH)
+		.ok()
+		.map(|duration| duration.as_nanos())
+}
+
+fn parse_mtime(mtime: &str) -> Option<Option<u128>> {
+	if mtime.is_empty() {
+		Some(None)
+	} else {
+		mtime.parse().ok().map(Some)
+	}
+}
+
+fn print_mtime(mtime: Option<u128>) -> String {
+	mtime.map(|mtime| mtime.to_string()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+	use std::{fs::File, time::SystemTime};
+
+	use super::*;
+
+	fn copy_dir(from: &Path, to: &Path) {
+		fs::create_dir_all(to).unwrap();
+		for entry in from.read_dir().unwrap() {
+			let entry = entry.unwrap();
+			if entry.file_type().unwrap().is_dir() {
+				copy_dir(&entry.path(), &to.join(entry.file_name()));
+			} else {
+				fs::copy(entry.path(), to.join(entry.file_name())).unwrap();
+			}
+		}
+	}
+
+	#[test]
+	fn test_index() {
+		let dir = tempfile::tempdir().unwrap();
+		let dir = dir.path();
+		copy_dir(Path::new("testrepo"), &dir.join("tree"));
+		let index_path = dir.join("index");
+		let tree = AbbsTree::new(dir.join("tree"))
+			.with_index_path(Some(index_path.clone()));
+
+		let index = PackageIndex::build(&tree).unwrap();
+		assert_eq!(index.len(), 3);
+		assert_eq!(index.find_package(&tree, "test1").unwrap().name(), "test1");
+		assert!(index.find_package(&tree, "test3").is_none());
+		assert_eq!(
+			index
+				.find_subpackage(&tree, "test2-guest")
+				.unwrap()
+				.dir_name(),
+			"02-guest"
+		);
+		assert!(index.find_subpackage(&tree, "test2").is_none());
+
+		// lookups through the tree save the index
+		assert_eq!(
+			tree.find_subpackage("test1").unwrap().dir_name(),
+			"autobuild"
+		);
+		assert_eq!(PackageIndex::load(&index_path).unwrap().unwrap(), index);
+		assert!(PackageIndex::load(dir.join("missing")).unwrap().is_none());
+		fs::write(dir.join("invalid"), "libabbs-index 0\n").unwrap();
+		assert!(PackageIndex::load(dir.join("invalid")).unwrap().is_none());
+
+		// modified defines invalidate the entry
+		let defines = dir.join("tree/app-admin/test2/01-host/defines");
+		fs::write(&defines, "PKGNAME=test2-renamed\n").unwrap();
+		File::options()
+			.write(true)
+			.open(&defines)
+			.unwrap()
+			.set_modified(SystemTime::now() + std::time::Duration::from_secs(1))
+			.unwrap();
+		let mut index = index;
+		assert!(index.find_subpackage(&tree, "test2-host").is_none());
+		assert!(index.update(&tree).unwrap());
+		assert!(!index.update(&tree).unwrap());
+		assert_eq!(
+			index
+				.find_subpackage(&tree, "test2-renamed")
+				.unwrap()
+				.dir_name(),
+			"01-host"
+		);
+		assert_eq!(
+			tree.find_subpackage("test2-renamed").unwrap().dir_name(),
+			"01-host"
+		);
+		assert!(tree.find_subpackage("test2-host").is_err());
+		assert_eq!(PackageIndex::load(&index_path).unwrap().unwrap(), index);
+	}
+}
@@ -5,30 +5,79 @@ use std::{
 	fs,
 	ops::Deref,
 	path::{Path, PathBuf},
+	sync::{Arc, Mutex},
 };
 
 use kstring::KString;
 use thiserror::Error;
 
-use crate::apml::{ApmlContext, ApmlError};
+use crate::apml::{ApmlContext, ApmlError, model::ModelError};
+
+pub mod autobuild;
+pub mod git;
+pub mod graph;
+pub mod index;
+pub mod patch;
+
+use index::PackageIndex;
 
 #[derive(Debug, Clone)]
-pub struct AbbsTree(PathBuf);
+pub struct AbbsTree {
+	path: PathBuf,
+	/// Package index shared between clones of the tree.
+	index: Arc<Mutex<IndexState>>,
+}
+
+#[derive(Debug)]
+struct IndexState {
+	/// Path of the index file, or [None] to keep the index in memory.
+	path: Option<PathBuf>,
+	/// The index, loaded lazily.
+	index: Option<PackageIndex>,
+}
 
 impl AbbsTree {
 	/// Opens a ABBS tree at the given path.
+	///
+	/// The package index is kept in memory only. Use
+	/// [`with_persistent_index`][Self::with_persistent_index] to store it
+	/// on disk.
 	pub fn new<P: AsRef<Path>>(path: P) -> Self {
-		Self(path.as_ref().to_owned())
+		Self {
+			path: path.as_ref().to_owned(),
+			index: Arc::new(Mutex::new(IndexState {
+				path: None,
+				index: None,
+			})),
+		}
+	}
+
+	/// Stores the package index at [`PackageIndex::default_path`].
+	#[must_use]
+	pub fn with_persistent_index(self) -> Self {
+		let path = PackageIndex::default_path(&self.path);
+		self.with_index_path(path)
+	}
+
+	/// Sets the path of the package index file.
+	///
+	/// If [None] is given, the index will be kept in memory only.
+	#[must_use]
+	pub fn with_index_path(self, path: Option<PathBuf>) -> Self {
+		Self {
+			path: self.path,
+			index: Arc::new(Mutex::new(IndexState { path, index: None })),
+		}
 	}
 
 	/// Returns the path of tree.
 	pub fn as_path(&self) -> &Path {
-		&self.0
+		&self.path
 	}
 
 	/// Returns the path of tree.
 	pub fn into_path(self) -> PathBuf {
-		self.0
+		self.path
 	}
 
 	/// Creates an owned [`PathBuf`] with `path` adjoined to `self`.
@@ -100,54 +149,138 @@ impl AbbsTree {
 	}
 
 	/// Finds a source package and creates an accessor for it.
+	///
+	/// The lookup is backed by the package index, which is updated
+	/// if the package is not found in it.
 	pub fn find_package<S: AsRef<str>>(
 		&self,
 		name: S,
 	) -> AbbsResult<AbbsSourcePackage> {
-		self.sections()?
-			.iter()
-			.find_map(|section| self.package(section, name.as_ref()))
-			.ok_or_else(|| {
-				AbbsError::PackageNotFound(name.as_ref().to_string())
-			})
+		let name = name.as_ref();
+		self.lookup_index(|index| index.find_package(self, name))?
+			.ok_or_else(|| AbbsError::PackageNotFound(name.to_string()))
 	}
 
 	/// Returns a subpackage with the given name.
 	///
-	/// This will first try to find the package in the source package
-	/// with the same name. If failed to do so, it will fallback to
-	/// search the whole tree.
+	/// If multiple sub-packages have the same name, the one in the
+	/// source package with the same name is preferred.
 	///
-	/// Thus this is a very high-cost operation and you must use it
-	/// carefully.
+	/// The lookup is backed by the package index. Updating the index
+	/// requires evaluating all modified defines files, so the first
+	/// lookup in a tree is a high-cost operation.
 	pub fn find_subpackage<S: AsRef<str>>(
 		&self,
 		name: S,
 	) -> AbbsResult<AbbsSubPackage> {
 		let name = name.as_ref();
-		match self.find_package(name) {
-			Ok(package) => {
-				for subpkg in package.subpackages()? {
-					if let Ok(subpkg_name) = subpkg.name()
-						&& subpkg_name == name
-					{
-						return Ok(subpkg);
-					}
-				}
+		self.lookup_index(|index| index.find_subpackage(self, name))?
+			.ok_or_else(|| AbbsError::PackageNotFound(name.to_string()))
+	}
+
+	/// Lists names of package groups in the `groups` directory.
+	pub fn groups(&self) -> AbbsResult<Vec<String>> {
+		let path = self.join("groups");
+		if !path.is_dir() {
+			return Ok(Vec::new());
+		}
+		let mut result = Vec::new();
+		for entry in path.read_dir()? {
+			let entry = entry?;
+			if entry.file_type()?.is_file()
+				&& let Some(name) = entry.file_name().to_str()
+				&& !name.starts_with('.')
+			{
+				result.push(name.to_string());
 			}
-			Err(AbbsError::PackageNotFound(_)) => {}
-			Err(err) => return Err(err),
 		}
-		for package in self.all_packages()? {
-			for subpkg in package.subpackages()? {
-				if let Ok(subpkg_name) = subpkg.name()
-					&& subpkg_name == name
-				{
-					return Ok(subpkg);
-				}
+		result.sort_unstable();
+		Ok(result)
+	}
+
+	/// Returns source packages in a package group.
+	///
+	/// Group files list one package per line, either as a path like
+	/// `app-admin/foo` or as a bare package name. Lines in form of
+	/// `groups/<name>` include packages of another group. Comments start
+	/// with `#`.
+	///
+	/// The name may be given with or without the `groups/` prefix.
+	/// Packages are returned in order of first appearance.
+	pub fn group<S: AsRef<str>>(
+		&self,
+		name: S,
+	) -> AbbsResult<Vec<AbbsSourcePackage>> {
+		let mut result = Vec::new();
+		self.collect_group(name.as_ref(), &mut Vec::new(), &mut result)?;
+		Ok(result)
+	}
+
+	fn collect_group(
+		&self,
+		name: &str,
+		visited: &mut Vec<String>,
+		result: &mut Vec<AbbsSourcePackage>,
+	) -> AbbsResult<()> {
+		let name = name.strip_prefix("groups/").unwrap_or(name);
+		if visited.iter().any(|group| group == name) {
+			return Ok(());
+		}
+		visited.push(name.to_string());
+		let path = self.join("groups").join(name);
+		if name.is_empty() || !path.is_file() {
+			return Err(AbbsError::GroupNotFound(name.to_string()));
+		}
+		for line in fs::read_to_string(path)?.lines() {
+			let line = line.split('#').next().unwrap_or_default().trim();
+			if line.is_empty() {
+				continue;
 			}
+			if let Some(group) = line.strip_prefix("groups/") {
+				self.collect_group(group, visited, result)?;
+				continue;
+			}
+			let package = match line.trim_end_matches('/').split_once('/') {
+				Some((section, package)) => self
+					.package(&SectionName::from_ref(section), package)
+					.ok_or_else(|| {
+						AbbsError::PackageNotFound(line.to_string())
+					})?,
+				None => self.find_package(line)?,
+			};
+			if !result.contains(&package) {
+				result.push(package);
+			}
+		}
+		Ok(())
+	}
+
+	/// Looks up the package index, updating it on misses.
+	fn lookup_index<T>(
+		&self,
+		lookup: impl Fn(&PackageIndex) -> Option<T>,
+	) -> AbbsResult<Option<T>> {
+		let mut state =
+			self.index.lock().unwrap_or_else(|err| err.into_inner());
+		let IndexState { path, index } = &mut *state;
+		let index = match index {
+			Some(index) => index,
+			None => index.insert(
+				path.as_ref()
+					.and_then(|path| PackageIndex::load(path).ok().flatten())
+					.unwrap_or_default(),
+			),
+		};
+		if let Some(result) = lookup(index) {
+			return Ok(Some(result));
+		}
+		if index.update(self)?
+			&& let Some(path) = path
+		{
+			// the index is only a cache, so failing to save it is fine
+			_ = index.save(path);
 		}
-		Err(AbbsError::PackageNotFound(name.to_string()))
+		Ok(lookup(index))
 	}
 }
 
@@ -157,8 +290,16 @@ pub enum AbbsError {
 	IoError(#[from] std::io::Error),
 	#[error("Package not found: {0}")]
 	PackageNotFound(String),
+	#[error("Package group not found: {0}")]
+	GroupNotFound(String),
+	#[error("Git error: {0}")]
+	GitError(String),
+	#[error("Invalid line {line} in {path:?}")]
+	InvalidLine { path: PathBuf, line: usize },
 	#[error(transparent)]
 	ApmlError(#[from] ApmlError),
+	#[error(transparent)]
+	ModelError(#[from] ModelError),
 }
 
 pub type AbbsResult<T> = Result<T, AbbsError>;
@@ -392,11 +533,12 @@ impl AbbsSubPackage {
 	/// and is a high-cost operation. The caller should cache the name
 	/// as much as possible.
 	pub fn name(&self) -> AbbsResult<String> {
-		Ok(ApmlContext::eval_source(&fs::read_to_string(
-			self.join("defines"),
-		)?)?
-		.read("PKGNAME")
-		.into_string())
+		let path = self.join("defines");
+		let src = fs::read_to_string(&path)?;
+		Ok(ApmlContext::eval_source(&src)
+			.map_err(|err| err.locate(Some(&path.to_string_lossy()), &src))?
+			.read("PKGNAME")
+			.into_string())
 	}
 
 	/// Returns the source package.
@@ -486,6 +628,25 @@ mod test {
 		);
 	}
 
+	#[test]
+	fn test_groups() {
+		let tree = AbbsTree::new("testrepo");
+		assert_eq!(tree.groups().unwrap(), vec!["test", "test-nested"]);
+		let names = |group: &str| {
+			tree.group(group)
+				.unwrap()
+				.iter()
+				.map(|pkg| pkg.name().to_string())
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(names("test"), vec!["test1", "test2"]);
+		assert_eq!(names("groups/test-nested"), vec!["test2", "test1"]);
+		assert!(matches!(
+			tree.group("missing"),
+			Err(AbbsError::GroupNotFound(_))
+		));
+	}
+
 	#[test]
 	fn test_section_name() {
 		let sec = SectionName::from_ref("app");
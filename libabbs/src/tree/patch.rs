@@ -0,0 +1,342 @@
+//! Patches of sub-packages.
+//!
+//! Patches are placed in the `patches` directory of a sub-package, such
+//! as `autobuild/patches`. If a `series` file exists, patches are applied
+//! in the order listed there. Otherwise, all `*.patch` and `*.diff` files
+//! are applied in order of file names.
+
+use std::{
+	fs,
+	path::{Path, PathBuf},
+};
+
+use super::{AbbsResult, AbbsSubPackage};
+
+/// A patch file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Patch {
+	/// Path of the patch relative to the patches directory.
+	pub name: String,
+	/// Path of the patch file.
+	pub path: PathBuf,
+	/// Metadata parsed from the patch.
+	pub info: PatchInfo,
+}
+
+/// Metadata of a patch.
+///
+/// Patches generated by `git format-patch` carry mail headers, which are
+/// parsed into [`from`][Self::from] and [`subject`][Self::subject].
+/// Other text before the diff is treated as the description.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PatchInfo {
+	/// Author of the patch (`From:` header).
+	pub from: Option<String>,
+	/// Subject of the patch (`Subject:` header), without `[PATCH]`
+	/// prefixes.
+	pub subject: Option<String>,
+	/// Text before the diff, excluding mail headers and diff statistics.
+	pub description: String,
+	/// Paths of files touched by the patch.
+	pub files: Vec<String>,
+	/// Whether the patch contains CRLF line endings.
+	pub crlf: bool,
+}
+
+impl PatchInfo {
+	/// Parses metadata of a patch.
+	pub fn parse(src: &str) -> Self {
+		let mut info = Self {
+			crlf: src.contains("\r\n"),
+			..Default::default()
+		};
+		let mut lines = src.lines().map(|line| line.trim_end_matches('\r'));
+		let mut description = Vec::new();
+		let mut header = None::<(&str, String)>;
+		let mut in_headers = false;
+		let mut in_diff = false;
+		for line in lines.by_ref() {
+			if in_headers {
+				if let Some((_, value)) = &mut header
+					&& line.starts_with([' ', '\t'])
+				{
+					value.push(' ');
+					value.push_str(line.trim());
+					continue;
+				}
+				if let Some((name, value)) = header.take() {
+					info.set_header(name, value);
+				}
+				if line.is_empty() {
+					in_headers = false;
+				} else if let Some((name, value)) = line.split_once(": ")
+					&& !name.contains(' ')
+				{
+					header = Some((name, value.trim().to_string()));
+				} else {
+					in_headers = false;
+					description.push(line);
+				}
+				continue;
+			}
+			if description.is_empty() && info.from.is_none() {
+				if line.starts_with("From ") {
+					// mbox separator of git format-patch
+					in_headers = true;
+					continue;
+				}
+				if let Some((name, value)) = line.split_once(": ")
+					&& matches!(name, "From" | "Subject")
+				{
+					in_headers = true;
+					header = Some((name, value.trim().to_string()));
+					continue;
+				}
+			}
+			if line == "---" {
+				// diff statistics follow
+				break;
+			}
+			if is_diff_start(line) {
+				in_diff = true;
+				info.add_file(line);
+				break;
+			}
+			description.push(line);
+		}
+		for line in lines {
+			if !in_diff && !is_diff_start(line) {
+				continue;
+			}
+			in_diff = true;
+			info.add_file(line);
+		}
+		info.description = description.join("\n").trim().to_string();
+		info
+	}
+
+	fn set_header(&mut self, name: &str, value: String) {
+		match name {
+			"From" => self.from = Some(value),
+			"Subject" => {
+				let mut subject = value.as_str();
+				while let Some(rest) = subject.strip_prefix('[')
+					&& let Some((_, rest)) = rest.split_once(']')
+				{
+					subject = rest.trim_start();
+				}
+				self.subject = Some(subject.to_string());
+			}
+			_ => {}
+		}
+	}
+
+	/// Records a file touched by a diff header line.
+	fn add_file(&mut self, line: &str) {
+		let path = match line.strip_prefix("diff --git ") {
+			Some(paths) => paths.rsplit_once(' ').map(|(_, new)| new),
+			None => line
+				.strip_prefix("+++ ")
+				.map(|path| path.split('\t').next().unwrap_or_default()),
+		};
+		let Some(path) = path.filter(|path| *path != "/dev/null") else {
+			return;
+		};
+		// strip the first component, like `patch -p1`
+		let path = path.split_once('/').map_or(path, |(_, path)| path);
+		if !self.files.iter().any(|file| file == path) {
+			self.files.push(path.to_string());
+		}
+	}
+
+	/// Returns whether the patch has a description, either as a subject
+	/// or as text before the diff.
+	pub fn has_description(&self) -> bool {
+		self.subject
+			.as_ref()
+			.is_some_and(|subject| !subject.is_empty())
+			|| !self.description.is_empty()
+	}
+}
+
+/// Returns whether a line starts a diff.
+fn is_diff_start(line: &str) -> bool {
+	line.starts_with("diff ")
+		|| line.starts_with("+++ ")
+		|| line.starts_with("--- ")
+		|| line.starts_with("Index: ")
+}
+
+impl Patch {
+	/// Reads a patch file.
+	pub fn open<P: AsRef<Path>>(name: String, path: P) -> AbbsResult<Self> {
+		let path = path.as_ref().to_owned();
+		let src = fs::read(&path)?;
+		Ok(Self {
+			name,
+			info: PatchInfo::parse(&String::from_utf8_lossy(&src)),
+			path,
+		})
+	}
+}
+
+impl AbbsSubPackage {
+	/// Returns the path of the patches directory.
+	pub fn patches_dir(&self) -> PathBuf {
+		self.join("patches")
+	}
+
+	/// Returns entries of the `series` file, or [None] if it does not
+	/// exist.
+	///
+	/// Comments and options after patch names are stripped.
+	pub fn patch_series(&self) -> AbbsResult<Option<Vec<String>>> {
+		let path = self.patches_dir().join("series");
+		if !path.is_file() {
+			return Ok(None);
+		}
+		Ok(Some(
+			fs::read_to_string(path)?
+				.lines()
+				.filter_map(|line| {
+					line.split('#')
+						.next()
+						.unwrap_or_default()
+						.split_whitespace()
+						.next()
+				})
+				.map(str::to_string)
+				.collect(),
+		))
+	}
+
+	/// Returns names of `*.patch` and `*.diff` files in the patches
+	/// directory, sorted by names.
+	pub fn patch_files(&self) -> AbbsResult<Vec<String>> {
+		let path = self.patches_dir();
+		if !path.is_dir() {
+			return Ok(Vec::new());
+		}
+		let mut result = Vec::new();
+		for entry in path.read_dir()? {
+			let entry = entry?;
+			if entry.file_type()?.is_file()
+				&& let Some(name) = entry.file_name().to_str()
+				&& (name.ends_with(".patch") || name.ends_with(".diff"))
+			{
+				result.push(name.to_string());
+			}
+		}
+		result.sort_unstable();
+		Ok(result)
+	}
+
+	/// Returns patches in order of application.
+	///
+	/// If the `series` file exists, patches are returned in order of it,
+	/// skipping entries without a file. Otherwise, all patch files are
+	/// returned in order of names.
+	pub fn patches(&self) -> AbbsResult<Vec<Patch>> {
+		let dir = self.patches_dir();
+		let names = match self.patch_series()? {
+			Some(series) => series
+				.into_iter()
+				.filter(|name| dir.join(name).is_file())
+				.collect(),
+			None => self.patch_files()?,
+		};
+		names
+			.into_iter()
+			.map(|name| {
+				let path = dir.join(&name);
+				Patch::open(name, path)
+			})
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::tree::AbbsTree;
+
+	#[test]
+	fn test_patch_info() {
+		let info = PatchInfo::parse(
+			"From 1234567890abcdef Mon Sep 17 00:00:00 2001\n\
+			From: A <a@example.com>\n\
+			Date: Thu, 1 Jan 2025 00:00:00 +0800\n\
+			Subject: [PATCH 1/2] [foo] fix a very long\n \
+			subject\n\
+			\n\
+			Some details.\n\
+			---\n \
+			src/a.c | 2 +-\n \
+			1 file changed\n\
+			\n\
+			diff --git a/src/a.c b/src/a.c\n\
+			--- a/src/a.c\n\
+			+++ b/src/a.c\n\
+			@@ -1 +1 @@\n\
+			-a\n\
+			+b\n\
+			diff --git a/b.txt b/b.txt\n\
+			deleted file mode 100644\n\
+			--- a/b.txt\n\
+			+++ /dev/null\n",
+		);
+		assert_eq!(info.from.as_deref(), Some("A <a@example.com>"));
+		assert_eq!(info.subject.as_deref(), Some("fix a very long subject"));
+		assert_eq!(info.description, "Some details.");
+		assert_eq!(info.files, vec!["src/a.c", "b.txt"]);
+		assert!(!info.crlf);
+		assert!(info.has_description());
+
+		let info = PatchInfo::parse(
+			"Description: fix build\r\n\r\n--- a/x\r\n+++ b/x\r\n",
+		);
+		assert_eq!(info.from, None);
+		assert_eq!(info.description, "Description: fix build");
+		assert_eq!(info.files, vec!["x"]);
+		assert!(info.crlf);
+
+		let info = PatchInfo::parse("--- x.orig\n+++ x\n@@ -1 +1 @@\n");
+		assert_eq!(info.files, vec!["x"]);
+		assert!(!info.has_description());
+	}
+
+	#[test]
+	fn test_patches() {
+		let tree = AbbsTree::new("testrepo");
+		let test1 = tree.find_subpackage("test1").unwrap();
+		assert_eq!(
+			test1.patch_series().unwrap().unwrap(),
+			vec!["0002-b.patch", "0001-a.patch", "missing.patch"]
+		);
+		assert_eq!(
+			test1.patch_files().unwrap(),
+			vec!["0001-a.patch", "0002-b.patch", "0003-c.diff"]
+		);
+		let patches = test1.patches().unwrap();
+		assert_eq!(
+			patches.iter().map(|patch| &patch.name).collect::<Vec<_>>(),
+			vec!["0002-b.patch", "0001-a.patch"]
+		);
+		assert_eq!(patches[1].info.subject.as_deref(), Some("Add a"));
+		assert_eq!(patches[1].info.files, vec!["a"]);
+
+		let host = tree.find_subpackage("test2-host").unwrap();
+		assert!(host.patch_series().unwrap().is_none());
+		assert!(host.patches().unwrap().is_empty());
+		let guest = tree.find_subpackage("test2-guest").unwrap();
+		assert_eq!(
+			guest
+				.patches()
+				.unwrap()
+				.iter()
+				.map(|patch| &patch.name)
+				.collect::<Vec<_>>(),
+			vec!["a.diff", "b.patch"]
+		);
+	}
+}
@@ -2,7 +2,11 @@
 
 use anyhow::Result;
 use async_trait::async_trait;
-use libabbs::apml::{lst, value::array::StringArray};
+use libabbs::apml::{
+	lst,
+	model::defines::{AbType, Defines},
+	value::array::StringArray,
+};
 use libpfu::{
 	Linter, Session, declare_lint, declare_linter,
 	message::{LintMessage, Snippet},
@@ -49,38 +53,39 @@ impl Linter for PythonDepsLinter {
 
 		for mut apml in walk_defines(sess) {
 			debug!("Checking Python dependencies for {apml:?}");
-			let abtype = apml.with_upgraded(|apml| {
-				apml.ctx()
-					.map(|ctx| ctx.get("ABTYPE").map(|val| val.as_string()))
+			let defines = apml.with_upgraded(|apml| {
+				apml.effective_ctx().map(Defines::try_from)
 			})?;
-			if let Some(abtype) = abtype
-				&& abtype != "pep517" && abtype != "python" {
-					debug!(
-						"Explicit ABTYPE '{abtype}' is not Python, skipping PEP-517 lints"
-					);
+			let defines = match defines {
+				Ok(defines) => defines,
+				Err(err) => {
+					debug!("Failed to read {apml:?}, skipping: {err}");
 					continue;
 				}
+			};
+			if let Some(abtype) = &defines.abtype
+				&& !matches!(abtype, AbType::Pep517 | AbType::Python)
+			{
+				debug!(
+					"Explicit ABTYPE '{abtype}' is not Python, skipping PEP-517 lints"
+				);
+				continue;
+			}
 
-			let [pkgdep, builddep] = ["PKGDEP", "BUILDDEP"].map(|var| {
-				apml.with_upgraded(|apml| {
-					apml.ctx().map(|ctx| {
-						ctx.get(var)
-							.map(|val| val.as_string())
-							.unwrap_or_default()
-					})
-				})
-				.map(StringArray::from)
-			});
-			let (mut pkgdep, mut builddep) = (pkgdep?, builddep?);
+			let mut pkgdep = StringArray::new(defines.pkgdep);
+			let mut builddep = StringArray::new(defines.builddep);
 			let mut pkgdep_dirty = false;
+			let (mut added_pkgdep, mut added_builddep) = (vec![], vec![]);
 
 			for dep in &mut py_deps {
 				if let Some(prov_pkg) =
 					depsolver::find_system_package(dep, &pkgdep, &builddep)
 						.await?
 				{
-					if pkgdep.contains(&prov_pkg)
-						|| (dep.build_dep && builddep.contains(&prov_pkg))
+					let present = |deps: &StringArray| {
+						depsolver::contains_package(deps, &prov_pkg)
+					};
+					if present(&pkgdep) || (dep.build_dep && present(&builddep))
 					{
 						continue;
 					}
@@ -110,8 +115,10 @@ impl Linter for PythonDepsLinter {
 						if !sess.dry {
 							if !dep.build_dep {
 								pkgdep.push(prov_pkg.clone());
+								added_pkgdep.push(prov_pkg.clone());
 							} else {
 								builddep.push(prov_pkg.clone());
+								added_builddep.push(prov_pkg.clone());
 							}
 							pkgdep_dirty = true;
 						}
@@ -121,14 +128,24 @@ impl Linter for PythonDepsLinter {
 			if pkgdep_dirty {
 				apml.with_upgraded(|apml| {
 					apml.with_editor(|apml| {
-						apml.replace_var_lst(
-							"PKGDEP",
-							lst::VariableValue::String(pkgdep.print().into()),
-						);
-						apml.replace_var_lst(
-							"BUILDDEP",
-							lst::VariableValue::String(builddep.print().into()),
-						);
+						for (var, deps, added) in [
+							("PKGDEP", &pkgdep, &added_pkgdep),
+							("BUILDDEP", &builddep, &added_builddep),
+						] {
+							// edit in place to keep the original formatting
+							if !added.is_empty()
+								&& !added
+									.iter()
+									.all(|dep| apml.push_list_item(var, dep))
+							{
+								apml.replace_var_lst(
+									var,
+									lst::VariableValue::String(
+										deps.print().into(),
+									),
+								);
+							}
+						}
 					})
 				});
 			}
@@ -2,7 +2,9 @@ use std::fmt::Display;
 
 use anyhow::Result;
 use kstring::KString;
-use libabbs::apml::value::array::StringArray;
+use libabbs::apml::{
+	model::dependency::DependencyAtom, value::array::StringArray,
+};
 use libpfu::Session;
 use log::{debug, error};
 use serde::Deserialize;
@@ -163,6 +165,15 @@ struct PyprojectBuildSystem {
 	requires: Vec<String>,
 }
 
+/// Checks if a list of dependency atoms contains a package, regardless of
+/// version constraints.
+pub fn contains_package(deps: &StringArray, pkg: &str) -> bool {
+	deps.iter().any(|dep| {
+		dep.parse::<DependencyAtom>()
+			.map_or(dep == pkg, |atom| atom.name == pkg)
+	})
+}
+
 /// Finds the system package which provides a certain Python package.
 pub async fn find_system_package(
 	dep: &Dependency,
@@ -170,14 +181,14 @@ pub async fn find_system_package(
 	builddep: &StringArray,
 ) -> Result<Option<String>> {
 	let find_dep = |pkg: &str| {
-		if pkgdep.iter().any(|dep| dep == pkg) {
+		if contains_package(pkgdep, pkg) {
 			debug!(
 				"Matched Python dependency package in PKGDEP: {} -> {}",
 				dep.name, pkg
 			);
 			return true;
 		}
-		if dep.build_dep && builddep.iter().any(|dep| dep == pkg) {
+		if dep.build_dep && contains_package(builddep, pkg) {
 			debug!(
 				"Matched Python dependency package in BUILDDEP: {} -> {}",
 				dep.name, pkg
@@ -240,6 +251,15 @@ pub async fn find_system_package(
 mod test {
 	use super::*;
 
+	#[test]
+	fn test_contains_package() {
+		let deps = StringArray::from("python-3>=3.11 python-build_ foo");
+		assert!(contains_package(&deps, "python-3"));
+		assert!(contains_package(&deps, "python-build"));
+		assert!(contains_package(&deps, "foo"));
+		assert!(!contains_package(&deps, "python"));
+	}
+
 	#[test]
 	fn test_extract_name_from_req() {
 		assert!(
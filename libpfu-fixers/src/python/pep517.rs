@@ -2,7 +2,11 @@
 
 use anyhow::Result;
 use async_trait::async_trait;
-use libabbs::apml::{ast, lst, value::array::StringArray};
+use libabbs::apml::{
+	ast, lst,
+	model::defines::{AbType, Defines},
+	value::array::StringArray,
+};
 use libpfu::{
 	Linter, Session, declare_lint, declare_linter,
 	message::{LintMessage, Snippet},
@@ -61,12 +65,18 @@ impl Linter for Pep517Linter {
 			);
 
 			for mut apml in walk_defines(sess) {
-				let abtype = apml.with_upgraded(|apml| {
-					apml.ctx()
-						.map(|ctx| ctx.get("ABTYPE").map(|val| val.as_string()))
+				let defines = apml.with_upgraded(|apml| {
+					apml.effective_ctx().map(Defines::try_from)
 				})?;
-				if let Some(abtype) = abtype {
-					if abtype == "python" {
+				let defines = match defines {
+					Ok(defines) => defines,
+					Err(err) => {
+						debug!("Failed to read {apml:?}, skipping: {err}");
+						continue;
+					}
+				};
+				if let Some(abtype) = &defines.abtype {
+					if *abtype == AbType::Python {
 						apml.with_upgraded(|apml| {
 							LintMessage::new(UPGRADE_TO_PEP517_LINT)
 								.note("remove ABTYPE=python to allow automatic template detection".to_string())
@@ -80,7 +90,7 @@ impl Linter for Pep517Linter {
 								})
 							}
 						})
-					} else if abtype != "pep517" {
+					} else if *abtype != AbType::Pep517 {
 						debug!(
 							"Explicit ABTYPE '{abtype}' is not pep517, skipping PEP-517 lints"
 						);
@@ -88,11 +98,7 @@ impl Linter for Pep517Linter {
 					}
 				}
 
-				let nopy2 = apml.with_upgraded(|apml| {
-					apml.ctx()
-						.map(|ctx| ctx.read("NOPYTHON2").into_string() == "1")
-				})?;
-				if !nopy2 {
+				if !defines.nopython2 {
 					LintMessage::new(PEP517_NOPYTHON2_LINT)
 						.snippet(Snippet::new_index(sess, &apml, 0))
 						.emit(sess);
@@ -111,15 +117,8 @@ impl Linter for Pep517Linter {
 					}
 				}
 
-				let pkgdep = apml.with_upgraded(|apml| {
-					apml.ctx().map(|ctx| {
-						ctx.get("PKGDEP")
-							.map(|val| val.as_string())
-							.unwrap_or_default()
-					})
-				})?;
-				let mut pkgdep = StringArray::from(pkgdep);
-				let mut pkgdep_dirty = false;
+				let mut pkgdep = StringArray::new(defines.pkgdep);
+				let (mut remove_python2, mut add_python3) = (false, false);
 
 				if pkgdep.iter().any(|dep| dep == "python-2") {
 					apml.with_upgraded(|apml| {
@@ -135,7 +134,7 @@ impl Linter for Pep517Linter {
 							.position(|dep| dep == "python-2")
 							.unwrap();
 						pkgdep.remove(pos);
-						pkgdep_dirty = true;
+						remove_python2 = true;
 					}
 				}
 				if !pkgdep.iter().any(|dep| dep == "python-3") {
@@ -148,18 +147,26 @@ impl Linter for Pep517Linter {
 					});
 					if !sess.dry {
 						pkgdep.push("python-3".to_string());
-						pkgdep_dirty = true;
+						add_python3 = true;
 					}
 				}
-				if pkgdep_dirty {
+				if remove_python2 || add_python3 {
 					apml.with_upgraded(|apml| {
 						apml.with_editor(|apml| {
-							apml.replace_var_lst(
-								"PKGDEP",
-								lst::VariableValue::String(
-									pkgdep.print().into(),
-								),
-							);
+							// edit in place to keep the original formatting
+							let edited = (!remove_python2
+								|| apml.remove_list_item("PKGDEP", "python-2"))
+								&& (!add_python3
+									|| apml
+										.push_list_item("PKGDEP", "python-3"));
+							if !edited {
+								apml.replace_var_lst(
+									"PKGDEP",
+									lst::VariableValue::String(
+										pkgdep.print().into(),
+									),
+								);
+							}
 						})
 					});
 				}
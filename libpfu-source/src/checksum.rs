@@ -0,0 +1,34 @@
+//! Checksum verification of downloaded sources.
+
+use anyhow::{Result, bail};
+use libabbs::apml::model::source::Checksum;
+use log::{debug, warn};
+use sha2::{Digest, Sha224, Sha256, Sha384, Sha512};
+
+/// Verifies data against a declared checksum.
+///
+/// Returns an error if the digest mismatches. Checksums with unsupported
+/// algorithms are skipped with a warning.
+pub fn verify(name: &str, checksum: &Checksum, data: &[u8]) -> Result<()> {
+	let Checksum::Hash { algorithm, digest } = checksum else {
+		debug!("Skipped checksum verification of {name}");
+		return Ok(());
+	};
+	let actual = match algorithm.as_str() {
+		"sha224" => format!("{:x}", Sha224::digest(data)),
+		"sha256" => format!("{:x}", Sha256::digest(data)),
+		"sha384" => format!("{:x}", Sha384::digest(data)),
+		"sha512" => format!("{:x}", Sha512::digest(data)),
+		_ => {
+			warn!("unsupported checksum algorithm for {name}: {algorithm}");
+			return Ok(());
+		}
+	};
+	if &actual != digest {
+		bail!(
+			"checksum mismatch for {name}: expected {algorithm}::{digest}, got {algorithm}::{actual}"
+		);
+	}
+	debug!("Verified {algorithm} checksum of {name}");
+	Ok(())
+}
@@ -7,7 +7,7 @@ use bytes::Buf;
 use futures::executor::block_on;
 use libabbs::apml::{
 	ApmlContext,
-	value::{array::StringArray, union::Union},
+	model::source::{Checksum, Source, SourceKind, pair_checksums},
 };
 use log::{debug, info, warn};
 use opendal::{
@@ -19,6 +19,7 @@ use regex::Regex;
 use reqwest::ClientBuilder;
 use tempfile::tempfile;
 
+pub mod checksum;
 pub mod pypi;
 
 static REGEX_GH_URL: LazyLock<Regex> = LazyLock::new(|| {
@@ -30,53 +31,76 @@ static REGEX_GH_URL: LazyLock<Regex> = LazyLock::new(|| {
 
 /// Initializes the source code access for a context.
 pub async fn open(ctx: ApmlContext) -> Result<Operator> {
-	let srcs = ctx.read("SRCS").into_string();
+	let srcs = ctx.read("SRCS").into_array();
+	let chksums = ctx.read("CHKSUMS").into_array();
 	let version = ctx.read("VER").into_string();
-	let srcs = StringArray::from(srcs);
 
-	if srcs.len() == 1 {
-		let src = srcs[0].clone();
-		let un = if src.starts_with("https://") || src.starts_with("http://") {
-			Union::try_from(format!("tbl::{src}").as_str())?
-		} else {
-			Union::try_from(src.as_str())?
-		};
-
-		match un.tag.as_str() {
-			"tarball" | "tbl" => {
-				if let Some(url) = un.argument {
-					if let Some(fs) = find_alt_fs(&url).await? {
-						return Ok(fs);
-					}
-					return fetch_tarball(url).await;
+	// only the used entries are parsed, so unrelated malformed entries
+	// do not prevent accessing the source
+	let sources = pair_checksums(&srcs, &chksums).collect::<Vec<_>>();
+	if let [(src, chksum)] = sources.as_slice() {
+		match Source::parse(src) {
+			Ok(src) => {
+				let chksum = chksum.and_then(|chksum| {
+					Checksum::parse(chksum)
+						.inspect_err(|err| warn!("invalid checksum: {err}"))
+						.ok()
+				});
+				if let Some(fs) =
+					open_source(&src, chksum.as_ref(), &version).await?
+				{
+					return Ok(fs);
 				}
+				warn!("failed to recognize source provider: {}", &src.location);
 			}
-			"git" => {
-				if let Some(url) = un.argument
-					&& let Some(fs) = find_alt_fs(&url).await? {
-						return Ok(fs);
-					}
-			}
-			"pypi" => {
-				if let Some(package) = un.argument {
-					return pypi::load(
-						&package,
-						un.properties.get("version").unwrap_or(&version),
-					)
-					.await;
-				}
-			}
-			_ => {
-				warn!("unsupported source type: {}", un.tag);
-			}
+			Err(err) => warn!("invalid source: {err}"),
 		}
-		warn!("failed to recognize source provider: {}", &src);
 	} else {
 		warn!("multiple sources are not supported yet");
 	}
 	Ok(Operator::new(Memory::default())?.finish())
 }
 
+/// Creates a FS for a source, if the source type is supported.
+async fn open_source(
+	src: &Source,
+	chksum: Option<&Checksum>,
+	version: &str,
+) -> Result<Option<Operator>> {
+	match src.kind {
+		SourceKind::Tarball => {
+			if !src.location.is_empty() {
+				if let Some(fs) = find_alt_fs(&src.location).await? {
+					return Ok(Some(fs));
+				}
+				return fetch_tarball(src.location.clone(), chksum)
+					.await
+					.map(Some);
+			}
+		}
+		SourceKind::Git => {
+			if let Some(fs) = find_alt_fs(&src.location).await? {
+				return Ok(Some(fs));
+			}
+		}
+		SourceKind::Pypi => {
+			if !src.location.is_empty() {
+				return pypi::load(
+					&src.location,
+					src.version.as_deref().unwrap_or(version),
+					chksum,
+				)
+				.await
+				.map(Some);
+			}
+		}
+		_ => {
+			warn!("unsupported source type: {}", src.kind);
+		}
+	}
+	Ok(None)
+}
+
 /// Attempts to create alternative FS from the given URL.
 ///
 /// For example, this will attempt to extract GitHub repository information
@@ -109,7 +133,13 @@ fn http_client() -> Result<reqwest::Client> {
 }
 
 /// Fetches a compressed tarball and loads it into a memory FS.
-async fn fetch_tarball(url: String) -> Result<Operator> {
+///
+/// If a checksum is given, the tarball is verified against it before
+/// being loaded.
+async fn fetch_tarball(
+	url: String,
+	checksum: Option<&Checksum>,
+) -> Result<Operator> {
 	info!("Downloading tarball: {url}");
 	let client = http_client()?;
 	let resp = client
@@ -117,7 +147,11 @@ async fn fetch_tarball(url: String) -> Result<Operator> {
 		.await?
 		.error_for_status()?;
 
-	let reader = resp.bytes().await?.reader();
+	let data = resp.bytes().await?;
+	if let Some(checksum) = checksum {
+		crate::checksum::verify(&url, checksum, &data)?;
+	}
+	let reader = data.reader();
 	let fs = block_on(async { load_compressed_tarball(&url, reader).await })?;
 	Ok(fs)
 }
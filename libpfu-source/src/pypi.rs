@@ -3,13 +3,18 @@
 use std::collections::HashMap;
 
 use anyhow::{Result, anyhow};
+use libabbs::apml::model::source::Checksum;
 use log::debug;
 use opendal::Operator;
 use serde::Deserialize;
 
 use crate::{fetch_tarball, find_alt_fs, http_client};
 
-pub async fn load(package: &str, version: &str) -> Result<Operator> {
+pub async fn load(
+	package: &str,
+	version: &str,
+	checksum: Option<&Checksum>,
+) -> Result<Operator> {
 	let hints = collect_alt_hints(package).await?;
 	for hint in hints {
 		if let Some(fs) = find_alt_fs(&hint).await? {
@@ -24,7 +29,7 @@ pub async fn load(package: &str, version: &str) -> Result<Operator> {
 	let url = format!(
 		"https://pypi.io/packages/source/{prefix}/{package}/{package}-{version}.tar.gz"
 	);
-	fetch_tarball(url).await
+	fetch_tarball(url, checksum).await
 }
 
 async fn collect_alt_hints(package: &str) -> Result<Vec<String>> {
@@ -5,7 +5,7 @@ use std::collections::HashMap;
 use anyhow::Result;
 use async_trait::async_trait;
 use kstring::KString;
-use libabbs::apml::ast;
+use libabbs::apml::{ast, span::Span};
 use libpfu::{
 	Linter, Session, declare_lint, declare_linter,
 	message::{LintMessage, Snippet},
@@ -72,7 +72,7 @@ impl Linter for ArchGroupLinter {
 						ast::VariableValue::String(text) => {
 							for word in &text.0 {
 								match word {
-									ast::Word::Literal(text)
+									ast::Word::Literal(text, _)
 										if text.trim().is_empty() => {}
 									ast::Word::Variable(exp)
 										if exp.modifier.is_none() =>
@@ -95,7 +95,7 @@ impl Linter for ArchGroupLinter {
 						ast::VariableValue::Array(elements) => {
 							for element in elements {
 								match element {
-									ast::ArrayElement::ArrayInclusion(name) => {
+									ast::ArrayElement::ArrayInclusion(name, _) => {
 										included_vars.push(name.to_string());
 									}
 									_ => {
@@ -226,9 +226,9 @@ impl Linter for ArchGroupLinter {
 												target.to_ascii_uppercase()
 											);
 										let value = if is_array {
-											ast::VariableValue::Array(vec![ast::ArrayElement::ArrayInclusion(var_name.to_string().into())])
+											ast::VariableValue::Array(vec![ast::ArrayElement::ArrayInclusion(var_name.to_string().into(), Span::default())])
 										} else {
-											ast::VariableValue::String(ast::Text(vec![ast::Word::Variable(ast::VariableExpansion{ name: var_name.to_string().into(), modifier: None })]))
+											ast::VariableValue::String(ast::Text(vec![ast::Word::Variable(ast::VariableExpansion::new(var_name.to_string().into(), None))]))
 										};
 										editor.append_var_ast(
 											name,
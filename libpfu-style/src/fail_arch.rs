@@ -0,0 +1,140 @@
+//! `FAIL_ARCH` checks.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use libabbs::{Architecture, apml::model::defines::Defines};
+use libpfu::{
+	Linter, Session, declare_lint, declare_linter,
+	message::{LintMessage, Snippet},
+	walk_defines,
+};
+use log::debug;
+
+declare_linter! {
+	pub FAIL_ARCH_LINTER,
+	FailArchLinter,
+	[
+		"invalid-fail-arch",
+		"fail-arch-matches-nothing",
+		"fail-arch-excludes-all",
+		"fail-arch-with-noarch",
+	]
+}
+
+declare_lint! {
+	pub INVALID_FAIL_ARCH_LINT,
+	"invalid-fail-arch",
+	Error,
+	"FAIL_ARCH is not a valid pattern"
+}
+
+declare_lint! {
+	pub FAIL_ARCH_MATCHES_NOTHING_LINT,
+	"fail-arch-matches-nothing",
+	Warning,
+	"FAIL_ARCH matches no known architecture"
+}
+
+declare_lint! {
+	pub FAIL_ARCH_EXCLUDES_ALL_LINT,
+	"fail-arch-excludes-all",
+	Error,
+	"FAIL_ARCH excludes every architecture"
+}
+
+declare_lint! {
+	pub FAIL_ARCH_WITH_NOARCH_LINT,
+	"fail-arch-with-noarch",
+	Warning,
+	"FAIL_ARCH is ignored for noarch packages"
+}
+
+#[async_trait]
+impl Linter for FailArchLinter {
+	async fn apply(&self, sess: &Session) -> Result<()> {
+		for mut apml in walk_defines(sess) {
+			debug!("Checking FAIL_ARCH in {apml:?}");
+			apml.with_upgraded(|apml| {
+				if !apml.ctx()?.contains_var("FAIL_ARCH") {
+					return Ok(());
+				}
+				let defines = Defines::try_from(apml.effective_ctx()?)?;
+
+				if defines.is_noarch() {
+					LintMessage::new(FAIL_ARCH_WITH_NOARCH_LINT)
+						.note(
+							"noarch packages are built only once for all architectures"
+								.to_string(),
+						)
+						.snippet(Snippet::new_variable(sess, apml, "FAIL_ARCH"))
+						.emit(sess);
+					return Ok(());
+				}
+
+				let excluded = match defines.excluded_archs() {
+					Ok(excluded) => excluded,
+					Err(err) => {
+						LintMessage::new(INVALID_FAIL_ARCH_LINT)
+							.note(err.to_string())
+							.snippet(Snippet::new_variable(
+								sess,
+								apml,
+								"FAIL_ARCH",
+							))
+							.emit(sess);
+						return Ok(());
+					}
+				};
+				let known = Architecture::ALL
+					.iter()
+					.filter(|arch| !arch.is_noarch())
+					.map(|arch| arch.ident())
+					.collect::<Vec<_>>();
+				if excluded.is_empty() {
+					LintMessage::new(FAIL_ARCH_MATCHES_NOTHING_LINT)
+						.note(format!(
+							"known architectures: {}",
+							known.join(", ")
+						))
+						.snippet(Snippet::new_variable(sess, apml, "FAIL_ARCH"))
+						.emit(sess);
+				} else if excluded.len() == known.len() {
+					LintMessage::new(FAIL_ARCH_EXCLUDES_ALL_LINT)
+						.note(
+							"use ABHOST=noarch if the package is architecture-independent"
+								.to_string(),
+						)
+						.snippet(Snippet::new_variable(sess, apml, "FAIL_ARCH"))
+						.emit(sess);
+				}
+				Ok::<_, anyhow::Error>(())
+			})?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn lint(defines: &str) -> Vec<&'static str> {
+		crate::test::lint(&FailArchLinter, "VER=1\n", defines)
+	}
+
+	#[test]
+	fn test_fail_arch_linter() {
+		assert!(lint("PKGNAME=test\n").is_empty());
+		assert!(lint("FAIL_ARCH=\"!(amd64|arm64)\"\n").is_empty());
+		assert_eq!(lint("FAIL_ARCH=\"!(amd64\"\n"), vec!["invalid-fail-arch"]);
+		assert_eq!(
+			lint("FAIL_ARCH=\"i486\"\n"),
+			vec!["fail-arch-matches-nothing"]
+		);
+		assert_eq!(lint("FAIL_ARCH=\"*\"\n"), vec!["fail-arch-excludes-all"]);
+		assert_eq!(
+			lint("ABHOST=\" noarch\"\nFAIL_ARCH=\"amd64\"\n"),
+			vec!["fail-arch-with-noarch"]
+		);
+	}
+}
@@ -3,5 +3,71 @@
 pub mod archgroup;
 pub mod chkupd;
 pub mod empty_line;
+pub mod fail_arch;
+pub mod patches;
 pub mod sources;
 pub mod spacing;
+
+#[cfg(test)]
+mod test {
+	use std::fs;
+
+	use futures::executor::block_on;
+	use libabbs::{apml::formatter::ApmlFormatter, tree::AbbsTree};
+	use libpfu::{Linter, Session};
+
+	use crate::{empty_line::EmptyLineLinter, spacing::ExtraSpacesLinter};
+
+	/// Applies a linter in dry mode to a package made of the given `spec`
+	/// and `defines`, returning identifiers of emitted lints.
+	pub(crate) fn lint(
+		linter: &dyn Linter,
+		spec: &str,
+		defines: &str,
+	) -> Vec<&'static str> {
+		let dir = tempfile::tempdir().unwrap();
+		let package = dir.path().join("app-admin/test");
+		fs::create_dir_all(package.join("autobuild")).unwrap();
+		fs::write(package.join("spec"), spec).unwrap();
+		fs::write(package.join("autobuild/defines"), defines).unwrap();
+
+		let tree = AbbsTree::new(dir.path());
+		let package = tree.find_package("test").unwrap();
+		let mut sess = Session::new(tree, package, None).unwrap();
+		sess.dry = true;
+		block_on(linter.apply(&sess)).unwrap();
+		sess.take_messages()
+			.into_iter()
+			.map(|message| message.lint.ident)
+			.collect()
+	}
+
+	#[test]
+	fn test_formatted_passes_style_lints() {
+		let formatter = ApmlFormatter::new();
+		let linters: [&dyn Linter; 2] = [&EmptyLineLinter, &ExtraSpacesLinter];
+		for (spec, defines) in [
+			("  VER=1  \n\n\n\n\nREL=2", "PKGNAME=test\n\n\n"),
+			(
+				"\n\nVER=1 # version  \n  # comment\n",
+				"PKGNAME=test  # name\n\n\n\n\tPKGDEP=\"a   b\"\nPKGDES=x ",
+			),
+		] {
+			let messages = linters
+				.iter()
+				.flat_map(|linter| lint(*linter, spec, defines))
+				.collect::<Vec<_>>();
+			assert!(!messages.is_empty());
+
+			let spec = formatter.format_source(spec).unwrap();
+			let defines = formatter.format_source(defines).unwrap();
+			for linter in linters {
+				assert_eq!(
+					lint(linter, &spec, &defines),
+					Vec::<&str>::new(),
+					"{spec:?} {defines:?}"
+				);
+			}
+		}
+	}
+}
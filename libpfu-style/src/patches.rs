@@ -0,0 +1,102 @@
+//! Patches checks.
+
+use std::path::Path;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use libpfu::{
+	Linter, Session, declare_lint, declare_linter,
+	message::{LintMessage, Snippet},
+};
+use log::debug;
+
+declare_linter! {
+	pub PATCHES_LINTER,
+	PatchesLinter,
+	[
+		"patch-not-in-series",
+		"missing-series-patch",
+		"crlf-patch",
+		"patch-without-description",
+	]
+}
+
+declare_lint! {
+	pub PATCH_NOT_IN_SERIES_LINT,
+	"patch-not-in-series",
+	Warning,
+	"patch is not listed in series and will not be applied"
+}
+
+declare_lint! {
+	pub MISSING_SERIES_PATCH_LINT,
+	"missing-series-patch",
+	Error,
+	"patch listed in series does not exist"
+}
+
+declare_lint! {
+	pub CRLF_PATCH_LINT,
+	"crlf-patch",
+	Warning,
+	"patch contains CRLF line endings"
+}
+
+declare_lint! {
+	pub PATCH_WITHOUT_DESCRIPTION_LINT,
+	"patch-without-description",
+	Note,
+	"patch has no description"
+}
+
+#[async_trait]
+impl Linter for PatchesLinter {
+	async fn apply(&self, sess: &Session) -> Result<()> {
+		let snippet = |path: &Path| {
+			Snippet::new_file(
+				path.strip_prefix(sess.tree.as_path()).unwrap_or(path),
+			)
+		};
+		for subpkg in &sess.subpackages {
+			let abbs = &subpkg.abbs;
+			debug!("Checking patches of {abbs:?}");
+			let dir = abbs.patches_dir();
+
+			if let Some(series) = abbs.patch_series()? {
+				for name in abbs.patch_files()? {
+					if !series.contains(&name) {
+						LintMessage::new(PATCH_NOT_IN_SERIES_LINT)
+							.snippet(snippet(&dir.join(&name)))
+							.emit(sess);
+					}
+				}
+				for name in &series {
+					if !dir.join(name).is_file() {
+						LintMessage::new(MISSING_SERIES_PATCH_LINT)
+							.message(format!("'{name}' does not exist"))
+							.snippet(snippet(&dir.join("series")))
+							.emit(sess);
+					}
+				}
+			}
+
+			for patch in abbs.patches()? {
+				if patch.info.crlf {
+					LintMessage::new(CRLF_PATCH_LINT)
+						.snippet(snippet(&patch.path))
+						.emit(sess);
+				}
+				if !patch.info.has_description() {
+					LintMessage::new(PATCH_WITHOUT_DESCRIPTION_LINT)
+						.note(
+							"describe the patch with a Subject header or text before the diff"
+								.to_string(),
+						)
+						.snippet(snippet(&patch.path))
+						.emit(sess);
+				}
+			}
+		}
+		Ok(())
+	}
+}
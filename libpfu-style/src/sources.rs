@@ -6,7 +6,8 @@ use anyhow::Result;
 use async_trait::async_trait;
 use libabbs::apml::{
 	lst,
-	value::{array::StringArray, union::Union},
+	model::source::{Source, SourceKind},
+	value::array::StringArray,
 };
 use libpfu::{
 	Linter, Session, declare_lint, declare_linter,
@@ -94,155 +95,144 @@ impl Linter for SrcsLinter {
 			let srcs = apml.with_upgraded(|apml| {
 				apml.ctx().map(|ctx| ctx.read("SRCS").into_string())
 			});
-			let mut srcs = StringArray::from(srcs?);
+			let srcs = StringArray::from(srcs?);
 
-			for (idx, src) in srcs.iter_mut().enumerate() {
-				let un = if src.starts_with("https://")
-					|| src.starts_with("http://")
-					|| !src.contains("::")
-				{
-					Union::try_from(format!("tbl::{src}").as_str())?
-				} else {
-					Union::try_from(src.as_str())?
-				};
+			for (idx, src) in srcs.iter().enumerate() {
+				let src = Source::parse(src)?;
 
-				if let Some(url) = &un.argument
-					&& let Some(domain_path) = url.strip_prefix("http://") {
-						let mut https_valid = true;
+				let url = &src.location;
+				if let Some(domain_path) = url.strip_prefix("http://") {
+					let mut https_valid = true;
 
-						if !sess.offline {
-							let https_url = url.replace("http://", "https://");
-							debug!("Checking HTTPS URL: {https_url}");
-							let client = sess.http_client()?;
-							if let Ok(status) = client
-								.head(https_url)
-								.send()
-								.await
-								.map(|resp| resp.status())
-							{
-								https_valid = status.is_success()
-									|| status.is_redirection();
-							} else {
-								https_valid = false;
-							}
+					if !sess.offline {
+						let https_url = url.replace("http://", "https://");
+						debug!("Checking HTTPS URL: {https_url}");
+						let client = sess.http_client()?;
+						if let Ok(status) = client
+							.head(https_url)
+							.send()
+							.await
+							.map(|resp| resp.status())
+						{
+							https_valid = status.is_success()
+								|| status.is_redirection();
+						} else {
+							https_valid = false;
 						}
+					}
+
+					if https_valid {
+						apml.with_upgraded(|apml| {
+							LintMessage::new(INSECURE_SRC_URL_LINT)
+								.note(format!(
+									"source {idx} should use https://"
+								))
+								.snippet(Snippet::new_variable(
+									sess, apml, "SRCS",
+								))
+								.emit(sess);
+							if !sess.dry {
+								apml.with_text(|text| {
+									let domain = domain_path
+										.split_once('/')
+										.unzip()
+										.0
+										.unwrap_or(domain_path);
+									text.replace(
+										&format!("http://{domain}"),
+										&format!("https://{domain}"),
+									)
+								})?;
+							}
+							Ok::<(), anyhow::Error>(())
+						})?;
+					} else {
+						apml.with_upgraded(|apml| {
+							LintMessage::new(HTTPS_UNSUPPORTED_SRC_LINT)
+								.note(format!(
+									"source {idx} does not support https://"
+								))
+								.snippet(Snippet::new_variable(
+									sess, apml, "SRCS",
+								))
+								.emit(sess);
+						});
+					}
+				}
 
-						if https_valid {
+				match &src.kind {
+					SourceKind::Tarball => {
+						let arg = &src.location;
+						if let Some(cap) = REGEX_PYPI.captures(arg) {
 							apml.with_upgraded(|apml| {
-								LintMessage::new(INSECURE_SRC_URL_LINT)
-									.note(format!(
-										"source {idx} should use https://"
-									))
-									.snippet(Snippet::new_variable(
-										sess, apml, "SRCS",
-									))
-									.emit(sess);
-								if !sess.dry {
+								LintMessage::new(
+									PREFER_SPECIFIC_SRC_HANDLER_LINT,
+								)
+								.note(format!(
+									"source {} should be replaced with pypi::{}",
+									idx, &cap["name"],
+								))
+								.snippet(Snippet::new_variable(
+									sess, apml, "SRCS",
+								))
+								.emit(sess);
+							});
+							if !sess.dry {
+								apml.with_upgraded(|apml| {
 									apml.with_text(|text| {
-										let domain = domain_path
-											.split_once('/')
-											.unzip()
-											.0
-											.unwrap_or(domain_path);
-										text.replace(
-											&format!("http://{domain}"),
-											&format!("https://{domain}"),
-										)
-									})?;
-								}
-								Ok::<(), anyhow::Error>(())
-							})?;
-						} else {
+										REGEX_PYPI_FULL
+											.replace(
+												&text,
+												"pypi::version=${version}::${name}",
+											)
+											.to_string()
+									})
+								})?;
+							}
+						} else if let Some(cap) =
+							REGEX_GH_TAR.captures(arg)
+						{
 							apml.with_upgraded(|apml| {
-								LintMessage::new(HTTPS_UNSUPPORTED_SRC_LINT)
-									.note(format!(
-										"source {idx} does not support https://"
-									))
-									.snippet(Snippet::new_variable(
-										sess, apml, "SRCS",
-									))
-									.emit(sess);
+								LintMessage::new(
+									PREFER_SPECIFIC_SRC_HANDLER_LINT,
+								)
+								.note(format!(
+									"source {} should be replaced with git::https://github.com/{}/{}.git",
+									idx, &cap["user"], &cap["repo"],
+								))
+								.snippet(Snippet::new_variable(
+									sess, apml, "SRCS",
+								))
+								.emit(sess);
 							});
-						}
-					}
-
-				match un.tag.to_ascii_lowercase().as_str() {
-					"tarball" | "tbl" => {
-						if let Some(arg) = un.argument {
-							if let Some(cap) = REGEX_PYPI.captures(&arg) {
-								apml.with_upgraded(|apml| {
-									LintMessage::new(
-										PREFER_SPECIFIC_SRC_HANDLER_LINT,
-									)
-									.note(format!(
-										"source {} should be replaced with pypi::{}",
-										idx, &cap["name"],
-									))
-									.snippet(Snippet::new_variable(
-										sess, apml, "SRCS",
-									))
-									.emit(sess);
-								});
-								if !sess.dry {
-									apml.with_upgraded(|apml| {
-										apml.with_text(|text| {
-											REGEX_PYPI_FULL
-												.replace(
-													&text,
-													"pypi::version=${version}::${name}",
-												)
-												.to_string()
-										})
-									})?;
-								}
-							} else if let Some(cap) =
-								REGEX_GH_TAR.captures(&arg)
-							{
+							if !sess.dry {
 								apml.with_upgraded(|apml| {
-									LintMessage::new(
-										PREFER_SPECIFIC_SRC_HANDLER_LINT,
-									)
-									.note(format!(
-										"source {} should be replaced with git::https://github.com/{}/{}.git",
-										idx, &cap["user"], &cap["repo"],
-									))
-									.snippet(Snippet::new_variable(
-										sess, apml, "SRCS",
-									))
-									.emit(sess);
-								});
-								if !sess.dry {
-									apml.with_upgraded(|apml| {
-										apml.with_text(|text| {
-											REGEX_GH_TAR_FULL
-												.replace(
-													&text,
-													"git::commit=tags/${version}::https://github.com/${user}/${repo}.git",
-												)
-												.to_string()
-										})?;
-										let mut chksums = StringArray::from(apml.ctx()?.read("CHKSUMS").into_string());
-										match chksums.get_mut(idx) {
-											Some(chksum) => *chksum = "SKIP".to_string(),
-											None => warn!("failed to replace CHKSUMS entry"),
-										}
-										apml.with_editor(|editor| {
-											editor.replace_var_lst("CHKSUMS", lst::VariableValue::String(chksums.print_expanded().into()));
-										});
-										Ok::<_, anyhow::Error>(())
+									apml.with_text(|text| {
+										REGEX_GH_TAR_FULL
+											.replace(
+												&text,
+												"git::commit=tags/${version}::https://github.com/${user}/${repo}.git",
+											)
+											.to_string()
 									})?;
-								}
+									let mut chksums = StringArray::from(apml.ctx()?.read("CHKSUMS").into_string());
+									match chksums.get_mut(idx) {
+										Some(chksum) => *chksum = "SKIP".to_string(),
+										None => warn!("failed to replace CHKSUMS entry"),
+									}
+									apml.with_editor(|editor| {
+										editor.replace_var_lst("CHKSUMS", lst::VariableValue::String(chksums.print_expanded().into()));
+									});
+									Ok::<_, anyhow::Error>(())
+								})?;
 							}
 						}
 					}
-					"git" | "svn" | "bzr" | "hg" | "fossil" | "file"
-					| "pypi" | "none" => {}
-					_ => {
+					SourceKind::Other(tag) => {
 						apml.with_upgraded(|apml| {
 							LintMessage::new(UNKNOWN_FETCH_TAG_LINT)
 								.note(format!(
-									"source {} with tag {} is unsupported",
-									idx, un.tag
+									"source {idx} with tag {tag} is unsupported"
 								))
 								.snippet(Snippet::new_variable(
 									sess, apml, "SRCS",
@@ -250,6 +240,7 @@ impl Linter for SrcsLinter {
 								.emit(sess);
 						});
 					}
+					_ => {}
 				}
 			}
 		}
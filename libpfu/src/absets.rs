@@ -1,15 +1,26 @@
 use std::{
 	collections::{HashMap, HashSet},
-	fs,
+	fmt, fs,
 };
 
 use anyhow::Result;
 use kstring::KString;
+use libabbs::{
+	Architecture,
+	apml::{ApmlContext, VariableValue, arch},
+};
+use serde::{
+	Deserialize, Deserializer,
+	de::{MapAccess, Visitor},
+};
 
 /// Static data of Autobuild4, from /usr/lib/autobuild4/sets.
 #[derive(Debug, Default, PartialEq, Eq)]
 pub struct Autobuild4Data {
 	pub arch_groups: HashMap<KString, HashSet<KString>>,
+	/// Names of arch-groups in order of definition, which is also the
+	/// order of precedence of their arch-overrides.
+	pub arch_group_order: Vec<KString>,
 }
 
 const ARCH_GROUPS_PATH: &str = "/usr/lib/autobuild4/sets/arch_groups.json";
@@ -20,8 +31,143 @@ impl Autobuild4Data {
 		if !fs::exists(ARCH_GROUPS_PATH)? {
 			return Ok(None);
 		}
-		let arch_groups =
-			serde_json::from_str(&fs::read_to_string(ARCH_GROUPS_PATH)?)?;
-		Ok(Some(Self { arch_groups }))
+		Ok(Some(Self::parse(&fs::read_to_string(ARCH_GROUPS_PATH)?)?))
+	}
+
+	/// Parses the content of `arch_groups.json`.
+	pub fn parse(arch_groups: &str) -> Result<Self> {
+		let OrderedGroups(groups) = serde_json::from_str(arch_groups)?;
+		Ok(Self {
+			arch_group_order: groups
+				.iter()
+				.map(|(group, _)| group.clone())
+				.collect(),
+			arch_groups: groups.into_iter().collect(),
+		})
+	}
+
+	/// Returns names of arch-groups including an architecture, in order
+	/// of precedence.
+	///
+	/// Groups are ordered as defined by Autobuild4. Groups missing from
+	/// [`arch_group_order`][Self::arch_group_order] follow, sorted by
+	/// name.
+	pub fn groups_of(&self, arch: Architecture) -> Vec<&str> {
+		let includes = |group: &str| {
+			self.arch_groups
+				.get(group)
+				.is_some_and(|targets| targets.contains(arch.ident()))
+		};
+		let mut groups = self
+			.arch_group_order
+			.iter()
+			.map(KString::as_str)
+			.filter(|group| includes(group))
+			.collect::<Vec<_>>();
+		let mut rest = self
+			.arch_groups
+			.keys()
+			.map(KString::as_str)
+			.filter(|group| {
+				includes(group)
+					&& !self.arch_group_order.iter().any(|name| name == group)
+			})
+			.collect::<Vec<_>>();
+		rest.sort_unstable();
+		groups.append(&mut rest);
+		groups
+	}
+
+	/// Returns the effective value of a variable for an architecture,
+	/// resolving arch-overrides.
+	///
+	/// See [`arch`] for the precedence rules.
+	pub fn resolve_var<'a>(
+		&self,
+		ctx: &'a ApmlContext,
+		name: &str,
+		arch: Architecture,
+	) -> Option<&'a VariableValue> {
+		arch::resolve_var(ctx, name, arch, &self.groups_of(arch))
+	}
+
+	/// Returns the effective context for an architecture, resolving
+	/// arch-overrides.
+	///
+	/// See [`arch::resolve_context`] for more.
+	pub fn resolve_context(
+		&self,
+		ctx: &ApmlContext,
+		arch: Architecture,
+	) -> ApmlContext {
+		arch::resolve_context(ctx, arch, &self.groups_of(arch))
+	}
+}
+
+/// Arch-groups deserialized in order of definition.
+struct OrderedGroups(Vec<(KString, HashSet<KString>)>);
+
+impl<'de> Deserialize<'de> for OrderedGroups {
+	fn deserialize<D: Deserializer<'de>>(
+		deserializer: D,
+	) -> Result<Self, D::Error> {
+		struct GroupsVisitor;
+
+		impl<'de> Visitor<'de> for GroupsVisitor {
+			type Value = OrderedGroups;
+
+			fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+				f.write_str("a map of arch-groups")
+			}
+
+			fn visit_map<A: MapAccess<'de>>(
+				self,
+				mut map: A,
+			) -> Result<Self::Value, A::Error> {
+				let mut groups = Vec::new();
+				while let Some(entry) = map.next_entry()? {
+					groups.push(entry);
+				}
+				Ok(OrderedGroups(groups))
+			}
+		}
+
+		deserializer.deserialize_map(GroupsVisitor)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_groups_of() {
+		let data = Autobuild4Data::parse(
+			r#"{"retro": ["loongson3"], "ocs2": ["loongson3", "amd64"],
+			"mainline": ["amd64"]}"#,
+		)
+		.unwrap();
+		assert_eq!(
+			data.arch_group_order
+				.iter()
+				.map(KString::as_str)
+				.collect::<Vec<_>>(),
+			vec!["retro", "ocs2", "mainline"]
+		);
+		assert_eq!(
+			data.groups_of(Architecture::Loongson3),
+			vec!["retro", "ocs2"]
+		);
+		assert_eq!(
+			data.groups_of(Architecture::Amd64),
+			vec!["ocs2", "mainline"]
+		);
+
+		let mut data = data;
+		data.arch_group_order.clear();
+		assert_eq!(
+			data.groups_of(Architecture::Amd64),
+			vec!["mainline", "ocs2"]
+		);
 	}
 }
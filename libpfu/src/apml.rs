@@ -22,6 +22,12 @@
 //! will be set to true, indicating that the caller (user) needs to call
 //! [write][ApmlFileAccess::write] to save changes to disk (or by themselves).
 //!
+//! Accessors can optionally be opened with a parent context via
+//! [open_with_parent][ApmlFileAccess::open_with_parent]. In that case,
+//! [effective_ctx][ApmlFileAccess::effective_ctx] evaluates the file on top
+//! of the parent context, e.g. a `defines` file in the scope of its `spec`,
+//! while [ctx][ApmlFileAccess::ctx] still evaluates the file in isolation.
+//!
 //! Accessors also guarantee that modifications to LST will be immediately
 //! reflected on AST and context. This is implemented by clearing the AST
 //! and context cache and re-emitting or re-evaluating them on the next access.
@@ -30,11 +36,12 @@ use std::{
 	fmt::Debug,
 	fs,
 	path::{Path, PathBuf},
+	sync::Arc,
 };
 
 use anyhow::Result;
 use libabbs::apml::{
-	ApmlContext,
+	ApmlContext, ApmlError,
 	ast::{ApmlAst, AstNode},
 	editor::ApmlEditor,
 	lst::ApmlLst,
@@ -46,6 +53,10 @@ pub struct ApmlFileAccess {
 	path: PathBuf,
 	/// Evaluate APML context.
 	ctx: Option<ApmlContext>,
+	/// Parent APML context.
+	parent: Option<Arc<ApmlContext>>,
+	/// Evaluated APML context on top of the parent context.
+	effective_ctx: Option<ApmlContext>,
 	/// Inner self-referencing wrapper.
 	inner: ApmlFileAccessInner,
 	/// Dirty mark.
@@ -73,12 +84,27 @@ struct ApmlFileAccessInner {
 impl ApmlFileAccess {
 	/// Opens a APML file accessor.
 	pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+		Self::open_with_parent(path, None)
+	}
+
+	/// Opens a APML file accessor, evaluating on top of a parent context.
+	///
+	/// If a parent context is given, the effective context is evaluated
+	/// eagerly instead of the isolated one.
+	pub fn open_with_parent<P: AsRef<Path>>(
+		path: P,
+		parent: Option<Arc<ApmlContext>>,
+	) -> Result<Self> {
 		let path = path.as_ref().to_owned();
 		let text = fs::read_to_string(&path)?;
 		// construct inner LST
 		let mut inner = ApmlFileAccessInner::try_new(
 			text,
-			|text| Ok::<_, anyhow::Error>(Some(ApmlLst::parse(text.as_str())?)),
+			|text| {
+				let lst = ApmlLst::parse(text.as_str())
+					.map_err(|err| err.with_file(&path.to_string_lossy()))?;
+				Ok::<_, anyhow::Error>(Some(lst))
+			},
 			|_| Ok(None),
 		)?;
 		// construct inner AST
@@ -89,10 +115,25 @@ impl ApmlFileAccess {
 			Ok::<_, anyhow::Error>(())
 		})?;
 		// construct context
-		let ctx = ApmlContext::eval_ast(inner.borrow_ast().as_ref().unwrap())?;
+		let ast = inner.borrow_ast().as_ref().unwrap();
+		let locate = |err: ApmlError| {
+			err.locate(Some(&path.to_string_lossy()), inner.borrow_orig_text())
+		};
+		let (ctx, effective_ctx) = match &parent {
+			Some(parent) => (
+				None,
+				Some(
+					ApmlContext::eval_ast_with_parent(ast, parent.clone())
+						.map_err(locate)?,
+				),
+			),
+			None => (Some(ApmlContext::eval_ast(ast).map_err(locate)?), None),
+		};
 		Ok(Self {
 			path,
-			ctx: Some(ctx),
+			ctx,
+			parent,
+			effective_ctx,
 			inner,
 			dirty: false,
 		})
@@ -103,6 +144,17 @@ impl ApmlFileAccess {
 		&self.path
 	}
 
+	/// Returns the parent context.
+	pub fn parent(&self) -> Option<&Arc<ApmlContext>> {
+		self.parent.as_ref()
+	}
+
+	/// Replaces the parent context.
+	pub fn set_parent(&mut self, parent: Option<Arc<ApmlContext>>) {
+		self.parent = parent;
+		self.effective_ctx = None;
+	}
+
 	/// Returns the dirty mark.
 	pub fn is_dirty(&self) -> bool {
 		self.dirty
@@ -151,6 +203,7 @@ impl ApmlFileAccess {
 		F: FnOnce(&mut ApmlLst<'_>) -> T,
 	{
 		self.ctx = None;
+		self.effective_ctx = None;
 		self.mark_dirty();
 		self.inner.with_mut(move |inner| {
 			*inner.ast = None;
@@ -174,6 +227,7 @@ impl ApmlFileAccess {
 		F: FnOnce(&mut ApmlEditor<'_, '_>) -> T,
 	{
 		self.ctx = None;
+		self.effective_ctx = None;
 		self.mark_dirty();
 		self.inner.with_mut(move |inner| {
 			*inner.ast = None;
@@ -215,6 +269,7 @@ impl ApmlFileAccess {
 		F: FnOnce(String) -> String,
 	{
 		self.ctx = None;
+		self.effective_ctx = None;
 		self.mark_dirty();
 		let text = self.inner.with_mut(move |inner| {
 			*inner.ast = None;
@@ -225,9 +280,14 @@ impl ApmlFileAccess {
 			let text = lst.to_string();
 			f(text)
 		});
+		let path = self.path.to_string_lossy();
 		self.inner = ApmlFileAccessInner::try_new(
 			text,
-			|text| Ok::<_, anyhow::Error>(Some(ApmlLst::parse(text.as_str())?)),
+			|text| {
+				let lst = ApmlLst::parse(text.as_str())
+					.map_err(|err| err.with_file(&path))?;
+				Ok::<_, anyhow::Error>(Some(lst))
+			},
 			|_| Ok(None),
 		)?;
 		Ok(())
@@ -252,11 +312,33 @@ impl ApmlFileAccess {
 	/// Gets a read reference to APML context.
 	pub fn ctx(&mut self) -> Result<&ApmlContext> {
 		if self.ctx.is_none() {
-			let ctx = ApmlContext::eval_ast(self.ast()?)?;
+			let ctx = ApmlContext::eval_ast(self.ast()?);
+			let ctx = ctx.map_err(|err| self.locate_error(err))?;
 			self.ctx = Some(ctx);
 		}
 		Ok(self.ctx.as_ref().unwrap())
 	}
+
+	/// Gets a read reference to APML context evaluated on top of the
+	/// parent context.
+	///
+	/// If no parent context is set, this is the same as [ctx][Self::ctx].
+	pub fn effective_ctx(&mut self) -> Result<&ApmlContext> {
+		let Some(parent) = self.parent.clone() else {
+			return self.ctx();
+		};
+		if self.effective_ctx.is_none() {
+			let ctx = ApmlContext::eval_ast_with_parent(self.ast()?, parent);
+			let ctx = ctx.map_err(|err| self.locate_error(err))?;
+			self.effective_ctx = Some(ctx);
+		}
+		Ok(self.effective_ctx.as_ref().unwrap())
+	}
+
+	/// Resolves the location of an evaluation error in the current source.
+	fn locate_error(&self, err: ApmlError) -> ApmlError {
+		err.locate(Some(&self.path.to_string_lossy()), &self.lst().to_string())
+	}
 }
 
 impl Debug for ApmlFileAccess {
@@ -271,6 +353,10 @@ impl Debug for ApmlFileAccess {
 
 #[cfg(test)]
 mod test {
+	use std::sync::Arc;
+
+	use libabbs::apml::ApmlContext;
+
 	use super::ApmlFileAccess;
 
 	#[test]
@@ -280,4 +366,19 @@ mod test {
 		let _ = access.ast();
 		let _ = access.ctx();
 	}
+
+	#[test]
+	fn test_access_with_parent() {
+		let parent =
+			Arc::new(ApmlContext::eval_source("A=parent\nD=\"$A\"").unwrap());
+		let mut access =
+			ApmlFileAccess::open_with_parent("testdata/example", Some(parent))
+				.unwrap();
+		assert!(access.parent().is_some());
+		assert_eq!(access.effective_ctx().unwrap()["C"], "value1");
+		assert_eq!(access.effective_ctx().unwrap()["D"], "parent");
+		assert!(!access.ctx().unwrap().contains_var("D"));
+		access.set_parent(None);
+		assert!(!access.effective_ctx().unwrap().contains_var("D"));
+	}
 }
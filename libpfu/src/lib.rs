@@ -11,6 +11,7 @@ use std::{
 use anyhow::Result;
 use apml::ApmlFileAccess;
 use async_trait::async_trait;
+use libabbs::tree::autobuild::{AuxFile, AuxFileKind};
 
 pub mod absets;
 pub mod apml;
@@ -164,6 +165,44 @@ pub fn walk_build_scripts(sess: &Session) -> Vec<PathBuf> {
 	result
 }
 
+/// Collects existing auxiliary files of a kind in all sub-packages.
+///
+/// Files are looked up for the modifier suffix of every recipe, except
+/// for [`AuxFileKind::Overrides`], which is looked up once per
+/// sub-package.
+pub fn walk_aux_files(sess: &Session, kind: AuxFileKind) -> Vec<AuxFile> {
+	let mut result = vec![];
+	for subpkg in &sess.subpackages {
+		if !kind.has_suffix() {
+			result.extend(subpkg.abbs.aux_file(kind, ""));
+			continue;
+		}
+		for recipe in &subpkg.recipes {
+			result.extend(subpkg.abbs.aux_file(kind, &recipe.suffix));
+		}
+	}
+	result
+}
+
+/// Collects paths of existing maintainer scripts, such as `postinst`, in
+/// all recipes of all sub-packages.
+pub fn walk_maintainer_scripts(sess: &Session) -> Vec<PathBuf> {
+	let mut result = vec![];
+	for subpkg in &sess.subpackages {
+		for recipe in &subpkg.recipes {
+			for kind in AuxFileKind::ALL {
+				if kind.is_script()
+					&& let Some(file) =
+						subpkg.abbs.aux_file(kind, &recipe.suffix)
+				{
+					result.push(file.path);
+				}
+			}
+		}
+	}
+	result
+}
+
 /// Wrapper for [RwLockUpgradableReadGuard] to make it [Send].
 ///
 /// This struct is not a part of stable API.
@@ -189,3 +228,39 @@ impl<T: Debug> Debug for ReadGuardWrapper<'_, T> {
 		Debug::fmt(&self.0, f)
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use libabbs::tree::AbbsTree;
+
+	use super::*;
+
+	#[test]
+	fn test_walk_aux_files() {
+		let tree = AbbsTree::new("../libabbs/testrepo");
+		let package = tree.find_package("test2").unwrap();
+		let sess = Session::new(tree, package, None).unwrap();
+		let host = sess.package.join("01-host");
+
+		let mut usergroups = walk_aux_files(&sess, AuxFileKind::Usergroup)
+			.into_iter()
+			.map(|file| file.path)
+			.collect::<Vec<_>>();
+		usergroups.sort();
+		assert_eq!(
+			usergroups,
+			vec![host.join("usergroup"), host.join("usergroup.stage2")]
+		);
+		let overrides = walk_aux_files(&sess, AuxFileKind::Overrides);
+		assert_eq!(overrides.len(), 1);
+		assert_eq!(overrides[0].path, host.join("overrides"));
+		assert!(walk_aux_files(&sess, AuxFileKind::Triggers).is_empty());
+
+		let mut scripts = walk_maintainer_scripts(&sess);
+		scripts.sort();
+		assert_eq!(
+			scripts,
+			vec![host.join("postinst"), host.join("prerm.stage2")]
+		);
+	}
+}
@@ -2,7 +2,10 @@
 
 use std::{borrow::Cow, path::Path};
 
-use libabbs::apml::lst;
+use libabbs::apml::{
+	lst,
+	span::{SourceLocation, Span},
+};
 use log::debug;
 
 use crate::{LintMetadata, Session, apml::ApmlFileAccess};
@@ -56,6 +59,7 @@ impl LintMessage {
 pub struct Snippet {
 	pub path: String,
 	pub line: Option<usize>,
+	pub column: Option<usize>,
 	pub source: Option<String>,
 }
 
@@ -64,6 +68,7 @@ impl Snippet {
 		Self {
 			path: path.to_string_lossy().into_owned(),
 			line: None,
+			column: None,
 			source: None,
 		}
 	}
@@ -92,7 +97,12 @@ impl Snippet {
 				Some(token.to_string())
 			}
 		};
-		Self { path, line, source }
+		Self {
+			path,
+			line,
+			column: None,
+			source,
+		}
 	}
 
 	pub fn new_variable(
@@ -106,11 +116,17 @@ impl Snippet {
 			.unwrap_or(apml.path())
 			.to_string_lossy()
 			.to_string();
-		if let Some((index, source)) = apml.read_with_editor(|editor| {
+		let span = apml.ast().ok().and_then(|ast| {
+			ast.0.iter().find(|def| def.name == var).map(|def| def.span)
+		});
+		if let Some(span) = span {
+			Self::new_span(sess, apml, span)
+		} else if let Some((index, source)) = apml.read_with_editor(|editor| {
 			editor
 				.find_var(var)
 				.map(|(index, token)| (index, token.to_string()))
 		}) {
+			// the file cannot be emitted as AST, fallback to LST
 			let lst = apml.lst();
 			let line = lst.0[0..index]
 				.iter()
@@ -119,6 +135,7 @@ impl Snippet {
 			Self {
 				path,
 				line: Some(line),
+				column: None,
 				source: Some(source),
 			}
 		} else {
@@ -128,11 +145,32 @@ impl Snippet {
 			Self {
 				path,
 				line: None,
+				column: None,
 				source: None,
 			}
 		}
 	}
 
+	/// Creates a snippet pointing to a span of the APML source,
+	/// such as a [variable definition][libabbs::apml::ast::VariableDefinition]
+	/// or a [variable expansion][libabbs::apml::ast::VariableExpansion].
+	pub fn new_span(sess: &Session, apml: &ApmlFileAccess, span: Span) -> Self {
+		let path = apml
+			.path()
+			.strip_prefix(sess.tree.as_path())
+			.unwrap_or(apml.path())
+			.to_string_lossy()
+			.to_string();
+		let text = apml.lst().to_string();
+		let location = SourceLocation::from_offset(&text, span.start);
+		Self {
+			path,
+			line: Some(location.line),
+			column: Some(location.column),
+			source: span.slice(&text).map(str::to_string),
+		}
+	}
+
 	pub fn new_index(
 		sess: &Session,
 		apml: &ApmlFileAccess,
@@ -152,6 +190,7 @@ impl Snippet {
 		Self {
 			path,
 			line: Some(line),
+			column: None,
 			source: None,
 		}
 	}
@@ -8,7 +8,10 @@ use std::sync::{Arc, OnceLock};
 use anyhow::{Result, bail};
 use futures::executor::block_on;
 use kstring::KString;
-use libabbs::tree::{AbbsSourcePackage, AbbsSubPackage, AbbsTree};
+use libabbs::{
+	apml::ApmlContext,
+	tree::{AbbsSourcePackage, AbbsSubPackage, AbbsTree},
+};
 use log::debug;
 use parking_lot::{Mutex, RwLock};
 
@@ -47,10 +50,12 @@ impl Session {
 		package: AbbsSourcePackage,
 		ab4_data: Option<Arc<Autobuild4Data>>,
 	) -> Result<Self> {
-		let spec = ApmlFileAccess::open(package.join("spec"))?;
+		let mut spec = ApmlFileAccess::open(package.join("spec"))?;
+		let spec_ctx = Arc::new(spec.ctx()?.clone());
 		let mut subpackages = Vec::new();
 		for subpackage in package.subpackages()? {
-			subpackages.push(SubpackageSession::new(subpackage)?);
+			subpackages
+				.push(SubpackageSession::new(subpackage, spec_ctx.clone())?);
 		}
 		debug!(
 			"Loaded {} sub-packages for {}",
@@ -129,10 +134,14 @@ pub struct SubpackageSession {
 }
 
 impl SubpackageSession {
-	pub fn new(abbs: AbbsSubPackage) -> Result<Self> {
+	/// Creates a sub-package context.
+	///
+	/// `spec` is the evaluated spec context, which defines files are
+	/// evaluated on top of.
+	pub fn new(abbs: AbbsSubPackage, spec: Arc<ApmlContext>) -> Result<Self> {
 		let mut recipes = Vec::new();
 		for suffix in abbs.modifier_suffixes()? {
-			recipes.push(RecipeSession::new(&abbs, suffix)?);
+			recipes.push(RecipeSession::new(&abbs, suffix, spec.clone())?);
 		}
 		debug!(
 			"Loaded {} recipes for {}/{}",
@@ -153,12 +162,27 @@ pub struct RecipeSession {
 }
 
 impl RecipeSession {
-	pub fn new(abbs: &AbbsSubPackage, suffix: KString) -> Result<Self> {
-		let defines =
-			ApmlFileAccess::open(abbs.join(format!("defines{suffix}")))?;
+	pub fn new(
+		abbs: &AbbsSubPackage,
+		suffix: KString,
+		spec: Arc<ApmlContext>,
+	) -> Result<Self> {
+		let defines = ApmlFileAccess::open_with_parent(
+			abbs.join(format!("defines{suffix}")),
+			Some(spec),
+		)?;
 		Ok(Self {
 			suffix,
 			defines: RwLock::new(defines),
 		})
 	}
+
+	/// Returns the effective context of the recipe.
+	///
+	/// The defines file is evaluated in the scope of the spec context
+	/// captured when the session is created, so spec variables such as
+	/// `$VER` are visible like they are to Autobuild.
+	pub fn effective_ctx(&self) -> Result<ApmlContext> {
+		self.defines.write().effective_ctx().cloned()
+	}
 }
@@ -7,7 +7,8 @@ use libpfu_fixers::{
 };
 use libpfu_style::{
 	archgroup::ARCH_GROUP_LINTER, chkupd::CHKUPDATE_LINTER,
-	empty_line::EMPTY_LINE_LINTER, sources::SRCS_LINTER,
+	empty_line::EMPTY_LINE_LINTER, fail_arch::FAIL_ARCH_LINTER,
+	patches::PATCHES_LINTER, sources::SRCS_LINTER,
 	spacing::EXTRA_SPACES_LINTER,
 };
 
@@ -37,6 +38,8 @@ pub static FULL_LINTERS: LinterPreset = &[
 	PEP517_LINTER,
 	PYTHON_DEPS_LINTER,
 	ARCH_GROUP_LINTER,
+	FAIL_ARCH_LINTER,
+	PATCHES_LINTER,
 ];
 pub static BASELINE_LINTERS: LinterPreset = &[
 	EXTRA_SPACES_LINTER,
@@ -45,6 +48,8 @@ pub static BASELINE_LINTERS: LinterPreset = &[
 	CHKUPDATE_LINTER,
 	FISH_SHELL_LINTER,
 	ARCH_GROUP_LINTER,
+	FAIL_ARCH_LINTER,
+	PATCHES_LINTER,
 ];
 pub static EXTRA_LINTERS: LinterPreset = &[PEP517_LINTER, PYTHON_DEPS_LINTER];
 pub static PEDANTIC_LINTERS: LinterPreset = &[];
@@ -101,6 +101,9 @@ impl LintReporter {
 			write!(to, "       {}{}", style("--> ").blue(), snippet.path)?;
 			if let Some(line) = snippet.line {
 				write!(to, ":{line}")?;
+				if let Some(column) = snippet.column {
+					write!(to, ":{column}")?;
+				}
 			}
 			if let Some(source) = snippet.source {
 				write!(to, ": {source}")?;
@@ -1,11 +1,17 @@
-use std::{path::PathBuf, sync::Arc, time::SystemTime};
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc, time::SystemTime};
 
 use anyhow::{Context, Result, bail};
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use console::style;
-use libabbs::tree::AbbsTree;
+use libabbs::{
+	apml::formatter::ApmlFormatter,
+	tree::{
+		AbbsSourcePackage, AbbsTree,
+		graph::{DependencyGraph, DependencyKind},
+	},
+};
 use libpfu::{Session, absets::Autobuild4Data, walk_apml};
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use logger::LintReporter;
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use regex::Regex;
@@ -18,26 +24,19 @@ pub mod selector;
 #[derive(Parser, Debug)]
 #[command(
 	version,
-	about = "PackFixerUpper: bring up AOSC OS packages magically"
+	about = "PackFixerUpper: bring up AOSC OS packages magically",
+	subcommand_negates_reqs = true
 )]
 struct Args {
+	#[command(subcommand)]
+	command: Option<Command>,
 	/// Path of ABBS tree.
-	#[arg(short = 'C', env = "ABBS_TREE")]
+	#[arg(short = 'C', env = "ABBS_TREE", global = true)]
 	tree: Option<PathBuf>,
-	/// Package name.
-	#[arg(required_unless_present_any = ["section", "regex", "world"])]
-	name: Vec<String>,
-	/// Process all packages in a section.
-	#[arg(short, long)]
-	section: Option<String>,
-	/// Process all packages matching the given regex.
-	#[arg(short, long)]
-	regex: Option<Regex>,
-	/// Process all packages in the tree.
-	#[arg(long)]
-	world: bool,
+	#[command(flatten)]
+	packages: PackageSelection,
 	/// Dry run.
-	#[arg(short, long)]
+	#[arg(short, long, global = true)]
 	dry: bool,
 	/// Run without network.
 	#[arg(long, env = "NO_NETWORK")]
@@ -47,13 +46,131 @@ struct Args {
 	directives: Vec<String>,
 	/// Enable more logging.
 	#[cfg(debug_assertions)]
-	#[arg(long)]
+	#[arg(long, global = true)]
 	debug: bool,
 	/// Enable less logging.
-	#[arg(short, long)]
+	#[arg(short, long, global = true)]
 	quiet: bool,
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+	/// Rewrite APML files into the canonical style.
+	///
+	/// With --dry, files are only checked and not modified.
+	Fmt {
+		#[command(flatten)]
+		packages: PackageSelection,
+	},
+}
+
+#[derive(clap::Args, Debug)]
+struct PackageSelection {
+	/// Package name.
+	#[arg(required_unless_present_any = [
+		"section",
+		"group",
+		"regex",
+		"changed_since",
+		"world",
+	])]
+	name: Vec<String>,
+	/// Process all packages in a section.
+	#[arg(short, long)]
+	section: Option<String>,
+	/// Process all packages in a package group.
+	#[arg(short, long)]
+	group: Option<String>,
+	/// Process all packages matching the given regex.
+	#[arg(short, long)]
+	regex: Option<Regex>,
+	/// Process all packages changed since a git revision, including
+	/// uncommitted changes.
+	#[arg(long, value_name = "REV")]
+	changed_since: Option<String>,
+	/// Process all packages in the tree.
+	#[arg(long)]
+	world: bool,
+	/// Also process all packages depending on selected packages.
+	#[arg(long)]
+	rdeps: bool,
+}
+
+impl PackageSelection {
+	fn select(self, abbs: &AbbsTree) -> Result<Vec<AbbsSourcePackage>> {
+		let rdeps = self.rdeps;
+		let packages = self.select_packages(abbs)?;
+		if rdeps {
+			with_reverse_dependencies(abbs, packages)
+		} else {
+			Ok(packages)
+		}
+	}
+
+	fn select_packages(
+		self,
+		abbs: &AbbsTree,
+	) -> Result<Vec<AbbsSourcePackage>> {
+		if !self.name.is_empty() {
+			let mut packages = Vec::new();
+			// TODO: replace with try_collect
+			for name in self.name {
+				packages.push(abbs.find_package(name)?);
+			}
+			Ok(packages)
+		} else if let Some(section) = self.section {
+			Ok(abbs.section_packages(&section.into())?)
+		} else if let Some(group) = self.group {
+			Ok(abbs.group(group)?)
+		} else if let Some(regex) = self.regex {
+			Ok(abbs
+				.all_packages()?
+				.into_par_iter()
+				.filter(|pkg| regex.is_match(pkg.name()))
+				.collect())
+		} else if let Some(rev) = self.changed_since {
+			Ok(abbs.changed_packages(&rev, None)?)
+		} else if self.world {
+			Ok(abbs.all_packages()?)
+		} else {
+			bail!("Package name must be specified")
+		}
+	}
+}
+
+/// Extends packages with their transitive reverse dependencies.
+fn with_reverse_dependencies(
+	abbs: &AbbsTree,
+	mut packages: Vec<AbbsSourcePackage>,
+) -> Result<Vec<AbbsSourcePackage>> {
+	let (graph, failures) = DependencyGraph::build(abbs, None, &[])?;
+	for (path, err) in failures {
+		warn!("Failed to evaluate {}: {err}", path.display());
+	}
+	let mut rdeps = BTreeMap::new();
+	for node in graph.nodes() {
+		if !packages.contains(&node.package.source_package()) {
+			continue;
+		}
+		for name in graph.transitive_reverse_dependencies(
+			&node.name,
+			&DependencyKind::REBUILD,
+		) {
+			let Some(rdep) = graph.node(name) else {
+				continue;
+			};
+			let package = graph.nodes()[rdep].package.source_package();
+			rdeps.insert(package.name().to_string(), package);
+		}
+	}
+	for package in rdeps.into_values() {
+		if !packages.contains(&package) {
+			packages.push(package);
+		}
+	}
+	Ok(packages)
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
 	let args = Args::parse();
@@ -65,29 +182,16 @@ async fn main() -> Result<()> {
 	let abbs = AbbsTree::new(
 		args.tree
 			.unwrap_or_else(|| std::env::current_dir().unwrap()),
-	);
+	)
+	.with_persistent_index();
 
 	info!("PackFixerUpper {}", env!("CARGO_PKG_VERSION"));
 
-	let packages = if !args.name.is_empty() {
-		let mut packages = Vec::new();
-		// TODO: replace with try_collect
-		for name in args.name {
-			packages.push(abbs.find_package(name)?);
-		}
-		packages
-	} else if let Some(section) = args.section {
-		abbs.section_packages(&section.into())?
-	} else if let Some(regex) = args.regex {
-		abbs.all_packages()?
-			.into_par_iter()
-			.filter(|pkg| regex.is_match(pkg.name()))
-			.collect()
-	} else if args.world {
-		abbs.all_packages()?
-	} else {
-		bail!("Package name must be specified")
-	};
+	if let Some(Command::Fmt { packages }) = args.command {
+		let packages = packages.select(&abbs)?;
+		return format(&abbs, packages, args.dry, args.quiet);
+	}
+	let packages = args.packages.select(&abbs)?;
 
 	let mut linters = LinterSelector::default();
 	for directive in args.directives {
@@ -126,7 +230,7 @@ async fn main() -> Result<()> {
 				Ok(sess) => sess,
 				Err(err) => {
 					error!(
-						"Session initialization failed for {:?}: {:#?}",
+						"Session initialization failed for {:?}: {:#}",
 						&package, err
 					);
 					continue;
@@ -192,3 +296,72 @@ async fn main() -> Result<()> {
 
 	Ok(())
 }
+
+/// Rewrites APML files of packages into the canonical style.
+fn format(
+	abbs: &AbbsTree,
+	packages: Vec<AbbsSourcePackage>,
+	dry: bool,
+	quiet: bool,
+) -> Result<()> {
+	let formatter = ApmlFormatter::new();
+	let total_packages = packages.len();
+	let mut unformatted = 0;
+
+	let start_time = SystemTime::now();
+	for (index, package) in packages.into_iter().enumerate() {
+		if !quiet {
+			eprintln!(
+				"{} [{}/{}] {}/{}",
+				style("  Formatting").green().bold(),
+				index + 1,
+				total_packages,
+				package.section(),
+				package.name()
+			);
+		}
+		let sess = match Session::new(abbs.clone(), package.clone(), None) {
+			Ok(sess) => sess,
+			Err(err) => {
+				error!(
+					"Session initialization failed for {:?}: {:#}",
+					&package, err
+				);
+				continue;
+			}
+		};
+		for mut apml in walk_apml(&sess) {
+			if formatter.is_formatted(apml.lst()) {
+				continue;
+			}
+			unformatted += 1;
+			if dry {
+				warn!("{} is not formatted", apml.path().display());
+				continue;
+			}
+			apml.with_upgraded(|apml| {
+				apml.with_lst(|lst| {
+					let formatted = formatter.format(lst);
+					*lst = formatted;
+				});
+				apml.save()
+			})
+			.with_context(|| format!("saving {apml:?}"))?;
+		}
+	}
+
+	let elapsed = start_time.elapsed()?;
+	eprintln!(
+		"{} {} packages, {} files {} in {}s",
+		style("    Finished").green().bold(),
+		total_packages,
+		unformatted,
+		if dry { "unformatted" } else { "reformatted" },
+		elapsed.as_secs(),
+	);
+	if dry && unformatted != 0 {
+		bail!("{unformatted} APML files are not formatted");
+	}
+
+	Ok(())
+}